@@ -0,0 +1,21 @@
+-- Tables owned by the marketplace live in their own schema so they never
+-- collide with the cardano-db-sync tables sharing the same database.
+CREATE SCHEMA IF NOT EXISTS marketplace;
+
+CREATE TABLE marketplace.mint_phase (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    start_slot BIGINT NOT NULL,
+    end_slot BIGINT NOT NULL,
+    mint_limit INTEGER NOT NULL,
+    CHECK (start_slot < end_slot),
+    CHECK (mint_limit > 0)
+);
+
+CREATE TABLE marketplace.mint_allowlist (
+    phase_id INTEGER NOT NULL REFERENCES marketplace.mint_phase (id) ON DELETE CASCADE,
+    address TEXT NOT NULL,
+    mint_limit INTEGER NOT NULL,
+    minted INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (phase_id, address)
+);
@@ -0,0 +1,29 @@
+CREATE TABLE marketplace.nft_drop (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    price BIGINT NOT NULL,
+    supply INTEGER NOT NULL,
+    start_slot BIGINT NOT NULL,
+    per_address_limit INTEGER,
+    payment_address TEXT NOT NULL,
+    policy_id BYTEA NOT NULL,
+    policy_skey BYTEA NOT NULL,
+    policy_lock_slot BIGINT NOT NULL,
+    CHECK (price >= 0),
+    CHECK (supply > 0),
+    CHECK (start_slot < policy_lock_slot)
+);
+
+-- An item is taken while its reservation is live, or for good once the asset
+-- shows up as minted in db-sync.
+CREATE TABLE marketplace.drop_item (
+    id SERIAL PRIMARY KEY,
+    drop_id INTEGER NOT NULL REFERENCES marketplace.nft_drop (id) ON DELETE CASCADE,
+    asset_name TEXT NOT NULL,
+    metadata JSONB NOT NULL,
+    reserved_by TEXT,
+    reserved_until BIGINT,
+    UNIQUE (drop_id, asset_name)
+);
+
+CREATE INDEX drop_item_reserved_by_idx ON marketplace.drop_item (drop_id, reserved_by);
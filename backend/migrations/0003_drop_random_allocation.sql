@@ -0,0 +1,2 @@
+ALTER TABLE marketplace.nft_drop
+    ADD COLUMN random_allocation BOOLEAN NOT NULL DEFAULT false;
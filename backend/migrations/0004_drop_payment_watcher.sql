@@ -0,0 +1,15 @@
+ALTER TABLE marketplace.nft_drop ADD COLUMN payment_skey BYTEA;
+
+-- Payments received at watched drop addresses, recorded once handled so the
+-- watcher does not act twice on a UTxO db-sync has not yet seen spent.
+CREATE TABLE marketplace.drop_payment (
+    tx_hash BYTEA NOT NULL,
+    tx_index SMALLINT NOT NULL,
+    drop_id INTEGER NOT NULL REFERENCES marketplace.nft_drop (id) ON DELETE CASCADE,
+    payer TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    outcome TEXT NOT NULL,
+    result_tx_hash TEXT,
+    processed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (tx_hash, tx_index)
+);
@@ -0,0 +1,8 @@
+-- Off-chain royalty configuration, takes precedence over CIP-27 metadata
+CREATE TABLE marketplace.royalty_split (
+    policy_id BYTEA NOT NULL,
+    address TEXT NOT NULL,
+    rate_bps INTEGER NOT NULL,
+    PRIMARY KEY (policy_id, address),
+    CHECK (rate_bps > 0 AND rate_bps <= 10000)
+);
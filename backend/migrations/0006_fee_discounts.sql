@@ -0,0 +1,7 @@
+-- Holders of an asset under `policy_id` pay `fee_bps` instead of the standard
+-- marketplace fee when buying.
+CREATE TABLE marketplace.fee_discount (
+    policy_id BYTEA PRIMARY KEY,
+    fee_bps INTEGER NOT NULL,
+    CHECK (fee_bps >= 0 AND fee_bps <= 10000)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE marketplace.coupon (
+    code TEXT PRIMARY KEY,
+    -- Subtracted from the marketplace fee, which never drops below the
+    -- configured floor
+    discount_bps INTEGER NOT NULL,
+    single_use BOOLEAN NOT NULL DEFAULT true,
+    expires_at TIMESTAMPTZ,
+    redemptions INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK (discount_bps > 0 AND discount_bps <= 10000)
+);
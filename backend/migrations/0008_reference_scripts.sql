@@ -0,0 +1,8 @@
+-- Where deployed reference scripts live on chain
+CREATE TABLE marketplace.reference_script (
+    name TEXT PRIMARY KEY,
+    script_hash BYTEA NOT NULL,
+    tx_hash BYTEA NOT NULL,
+    tx_index SMALLINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
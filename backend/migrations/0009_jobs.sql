@@ -0,0 +1,17 @@
+-- Background work, retried with backoff until it succeeds or runs out of
+-- attempts, at which point it is dead-lettered for an admin to look at.
+CREATE TABLE marketplace.job (
+    id BIGSERIAL PRIMARY KEY,
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL DEFAULT '{}',
+    -- pending, running, done or dead
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 5,
+    run_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX job_pending_run_at ON marketplace.job (run_at) WHERE status = 'pending';
@@ -0,0 +1,7 @@
+-- Flat fee in lovelace taken from sales of a project's assets, overriding the
+-- configured default. Kept above the minimum ADA of the revenue output.
+CREATE TABLE marketplace.project_fee (
+    policy_id BYTEA PRIMARY KEY,
+    fee_lovelace BIGINT NOT NULL,
+    CHECK (fee_lovelace >= 1000000)
+);
@@ -0,0 +1,11 @@
+-- Launch projects registered at runtime through the admin API, loaded next to
+-- the configured projects on startup.
+CREATE TABLE marketplace.project (
+    slug TEXT PRIMARY KEY,
+    holder_skey BYTEA NOT NULL,
+    holder_address TEXT NOT NULL,
+    revenue_address TEXT NOT NULL,
+    fee_lovelace BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK (fee_lovelace >= 1000000)
+);
@@ -0,0 +1,24 @@
+-- Primary sale windows of a project's assets. While a phase is open the
+-- phase price replaces the listed price, and each wallet may buy at most
+-- `wallet_limit` assets in it.
+CREATE TABLE marketplace.project_sale_phase (
+    id SERIAL PRIMARY KEY,
+    policy_id BYTEA NOT NULL,
+    name TEXT NOT NULL,
+    price BIGINT NOT NULL,
+    start_slot BIGINT NOT NULL,
+    end_slot BIGINT NOT NULL,
+    wallet_limit INTEGER,
+    CHECK (start_slot < end_slot),
+    CHECK (price > 0),
+    CHECK (wallet_limit IS NULL OR wallet_limit > 0)
+);
+
+CREATE INDEX project_sale_phase_policy_idx ON marketplace.project_sale_phase (policy_id, start_slot);
+
+CREATE TABLE marketplace.project_sale_purchase (
+    phase_id INTEGER NOT NULL REFERENCES marketplace.project_sale_phase (id) ON DELETE CASCADE,
+    address TEXT NOT NULL,
+    purchased INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (phase_id, address)
+);
@@ -0,0 +1,16 @@
+-- Transactions needing witnesses from several parties. Witnesses are merged
+-- into `transaction` as they arrive and the transaction is submitted once
+-- every required signer has signed.
+CREATE TABLE marketplace.tx_session (
+    id BIGSERIAL PRIMARY KEY,
+    transaction BYTEA NOT NULL,
+    -- Hex key hashes
+    required_signers TEXT[] NOT NULL,
+    signed_by TEXT[] NOT NULL DEFAULT '{}',
+    -- collecting, signed or submitted
+    status TEXT NOT NULL DEFAULT 'collecting',
+    tx_hash TEXT,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
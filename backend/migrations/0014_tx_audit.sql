@@ -0,0 +1,20 @@
+-- Every transaction the service built or submitted, kept for audits and
+-- dispute resolution
+CREATE TABLE marketplace.tx_audit (
+    id BIGSERIAL PRIMARY KEY,
+    -- sell, buy, cancel, project_buy, mint, drop_mint, transfer, ...
+    purpose TEXT NOT NULL,
+    -- built or submitted
+    stage TEXT NOT NULL,
+    tx_hash TEXT NOT NULL,
+    -- Bech32 addresses of the parties and outputs
+    addresses TEXT[] NOT NULL,
+    policy_id TEXT,
+    asset_name TEXT,
+    price BIGINT,
+    cbor BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX tx_audit_tx_hash_idx ON marketplace.tx_audit (tx_hash);
+CREATE INDEX tx_audit_addresses_idx ON marketplace.tx_audit USING GIN (addresses);
@@ -0,0 +1,16 @@
+-- Sell transactions accepted by the node but not yet indexed by db-sync.
+-- They are listed as pending until db-sync catches up or their TTL passes.
+CREATE TABLE marketplace.pending_sale (
+    tx_hash TEXT PRIMARY KEY,
+    -- Bech32 address the NFT was sent to
+    holder TEXT NOT NULL,
+    seller TEXT NOT NULL,
+    policy_id BYTEA NOT NULL,
+    asset_name BYTEA NOT NULL,
+    -- Sale metadata under label 888, as db-sync would store it
+    sale_json JSONB NOT NULL,
+    ttl BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX pending_sale_holder_idx ON marketplace.pending_sale (holder);
@@ -0,0 +1,11 @@
+-- Asset names claimed under a managed policy, by the mint transaction
+-- handed out for them. A claim whose transaction expired without being
+-- minted can be taken over.
+CREATE TABLE marketplace.minted_name (
+    policy_id BYTEA NOT NULL,
+    asset_name TEXT NOT NULL,
+    tx_hash TEXT NOT NULL,
+    valid_until BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (policy_id, asset_name)
+);
@@ -0,0 +1,4 @@
+-- A batch listing is one transaction escrowing several NFTs, each of them
+-- pending on its own.
+ALTER TABLE marketplace.pending_sale DROP CONSTRAINT pending_sale_pkey;
+ALTER TABLE marketplace.pending_sale ADD PRIMARY KEY (tx_hash, policy_id, asset_name);
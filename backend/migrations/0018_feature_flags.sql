@@ -0,0 +1,7 @@
+-- Features switched on or off at runtime. Features without a row fall back
+-- to DISABLED_FEATURES.
+CREATE TABLE marketplace.feature_flag (
+    name TEXT PRIMARY KEY,
+    enabled BOOLEAN NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,10 @@
+-- Secondary sale fee for assets under `policy_id`, replacing the standard
+-- marketplace fee and its minimum. Zero exempts the policy entirely.
+CREATE TABLE marketplace.policy_fee (
+    policy_id BYTEA PRIMARY KEY,
+    fee_bps INTEGER NOT NULL,
+    CHECK (fee_bps >= 0 AND fee_bps <= 10000)
+);
+
+-- Fee rate applied to a sale, for buys
+ALTER TABLE marketplace.tx_audit ADD COLUMN fee_bps INTEGER;
@@ -0,0 +1,8 @@
+-- Listings taken off the marketplace pages pending review. The escrow is
+-- untouched, so the seller can still cancel.
+CREATE TABLE marketplace.hidden_listing (
+    -- Hex hash of the sell transaction
+    tx_hash TEXT PRIMARY KEY,
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
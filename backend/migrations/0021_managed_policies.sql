@@ -0,0 +1,10 @@
+-- Policies of single mints the backend keeps, so their creators can mint
+-- more under the same policy
+CREATE TABLE marketplace.managed_policy (
+    policy_id BYTEA PRIMARY KEY,
+    policy_skey BYTEA NOT NULL,
+    policy_lock_slot BIGINT NOT NULL,
+    -- Bech32 address of the minter the policy was created for
+    owner_address TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,10 @@
+-- Collections minted into repeatedly under a policy kept here. Keys are
+-- sealed with the keystore secret, the ones kept before that are not. The key
+-- is dropped once the creator locks the collection.
+ALTER TABLE marketplace.managed_policy
+    ADD COLUMN name TEXT,
+    ADD COLUMN sealed BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN locked_at TIMESTAMPTZ,
+    ALTER COLUMN policy_skey DROP NOT NULL;
+
+CREATE INDEX managed_policy_owner_idx ON marketplace.managed_policy (owner_address);
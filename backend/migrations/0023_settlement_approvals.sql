@@ -0,0 +1,19 @@
+-- Purchases over the approval threshold, waiting for an admin before the
+-- holder signs them
+CREATE TABLE marketplace.settlement_approval (
+    id BIGSERIAL PRIMARY KEY,
+    tx_hash TEXT NOT NULL,
+    -- Bech32 address of the holder that signs once approved
+    holder_address TEXT NOT NULL,
+    buyer_address TEXT NOT NULL,
+    price BIGINT NOT NULL,
+    -- Without the holder signature until approved
+    transaction BYTEA NOT NULL,
+    -- pending, approved or rejected
+    status TEXT NOT NULL DEFAULT 'pending',
+    decided_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX settlement_approval_pending_idx
+ON marketplace.settlement_approval (holder_address) WHERE status = 'pending';
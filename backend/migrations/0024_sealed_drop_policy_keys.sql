@@ -0,0 +1,4 @@
+-- Drop policy keys are sealed with the keystore secret, the ones stored
+-- before that are not.
+ALTER TABLE marketplace.nft_drop
+    ADD COLUMN policy_skey_sealed BOOLEAN NOT NULL DEFAULT false;
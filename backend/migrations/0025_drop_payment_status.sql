@@ -0,0 +1,9 @@
+-- Payment keys of watched drops are sealed with the keystore secret, the
+-- ones stored before that are not.
+ALTER TABLE marketplace.nft_drop
+    ADD COLUMN payment_skey_sealed BOOLEAN NOT NULL DEFAULT false;
+
+-- Payments are recorded as pending before the transaction serving them is
+-- submitted, and done once the submission went through.
+ALTER TABLE marketplace.drop_payment
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'done';
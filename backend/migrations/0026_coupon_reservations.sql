@@ -0,0 +1,10 @@
+-- Coupons applied to a buy are held for its transaction, keyed by the body
+-- hash, and count as redeemed once it is submitted. Reservations of
+-- transactions that were never submitted run out with their validity.
+CREATE TABLE marketplace.coupon_reservation (
+    tx_hash TEXT PRIMARY KEY,
+    code TEXT NOT NULL REFERENCES marketplace.coupon (code) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX coupon_reservation_code_idx ON marketplace.coupon_reservation (code);
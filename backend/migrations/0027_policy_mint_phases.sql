@@ -0,0 +1,17 @@
+-- Phases gate mints under their own policy only. Phases created before have
+-- no policy and gate nothing.
+ALTER TABLE marketplace.mint_phase ADD COLUMN policy_id BYTEA;
+
+CREATE INDEX mint_phase_policy_idx ON marketplace.mint_phase (policy_id);
+
+-- Mints counted against a phase, by the transaction minting them. A claim
+-- counts once its transaction is on chain, or while it still can be.
+-- mint_allowlist.minted keeps the mints counted at build time before.
+CREATE TABLE marketplace.mint_phase_claim (
+    tx_hash TEXT PRIMARY KEY,
+    phase_id INTEGER NOT NULL REFERENCES marketplace.mint_phase (id) ON DELETE CASCADE,
+    address TEXT NOT NULL,
+    valid_until BIGINT NOT NULL
+);
+
+CREATE INDEX mint_phase_claim_address_idx ON marketplace.mint_phase_claim (phase_id, address);
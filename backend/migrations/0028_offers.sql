@@ -0,0 +1,21 @@
+-- Bids on NFTs, the bid ADA escrowed at the holder address until the owner
+-- accepts it, the bidder withdraws it or it expires and is refunded. A bid
+-- counts once its escrow output is on chain and for as long as it is unspent.
+CREATE TABLE marketplace.offer (
+    -- Transaction paying the bid to the holder
+    tx_hash TEXT PRIMARY KEY,
+    -- Its output escrowing the bid
+    output_index INTEGER NOT NULL,
+    -- Bech32 address of the holder escrowing the bid
+    holder TEXT NOT NULL,
+    bidder TEXT NOT NULL,
+    policy_id BYTEA NOT NULL,
+    asset_name BYTEA NOT NULL,
+    price BIGINT NOT NULL,
+    -- Slot from which the bid can no longer be accepted and is refunded
+    expires_at BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX offer_asset_idx ON marketplace.offer (holder, policy_id, asset_name);
+CREATE INDEX offer_bidder_idx ON marketplace.offer (holder, bidder);
@@ -0,0 +1,192 @@
+// Log of every transaction the service built or submitted
+
+use crate::Result;
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::utils::hash_transaction;
+use cardano_serialization_lib::{AssetName, PolicyID, Transaction};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    pub id: i64,
+    pub purpose: String,
+    pub stage: String,
+    pub tx_hash: String,
+    pub addresses: Vec<String>,
+    pub policy_id: Option<String>,
+    pub asset_name: Option<String>,
+    pub price: Option<i64>,
+    pub fee_bps: Option<i32>,
+    #[serde(with = "hex")]
+    pub cbor: Vec<u8>,
+    pub created_at: DateTime<Utc>,
+}
+
+const AUDIT_COLUMNS: &str = r#"
+    id, purpose, stage, tx_hash, addresses, policy_id, asset_name, price,
+    fee_bps, cbor, created_at
+"#;
+
+/// What a transaction was for. The addresses of its outputs are added when
+/// it is recorded.
+pub struct AuditEntry {
+    purpose: &'static str,
+    addresses: Vec<String>,
+    policy_id: Option<String>,
+    asset_name: Option<String>,
+    price: Option<u64>,
+    fee_bps: Option<u64>,
+}
+
+impl AuditEntry {
+    pub fn new(purpose: &'static str) -> AuditEntry {
+        AuditEntry {
+            purpose,
+            addresses: vec![],
+            policy_id: None,
+            asset_name: None,
+            price: None,
+            fee_bps: None,
+        }
+    }
+
+    pub fn address(mut self, address: &Address) -> AuditEntry {
+        self.addresses.push(bech32(address));
+        self
+    }
+
+    pub fn asset(mut self, policy_id: &PolicyID, asset_name: &AssetName) -> AuditEntry {
+        self.policy_id = Some(hex::encode(policy_id.to_bytes()));
+        self.asset_name = Some(
+            String::from_utf8(asset_name.name()).unwrap_or_else(|_| hex::encode(asset_name.name())),
+        );
+        self
+    }
+
+    pub fn price(mut self, price: u64) -> AuditEntry {
+        self.price = Some(price);
+        self
+    }
+
+    /// The marketplace fee rate applied to a sale
+    pub fn fee_bps(mut self, fee_bps: u64) -> AuditEntry {
+        self.fee_bps = Some(fee_bps);
+        self
+    }
+
+    /// Records a transaction handed out for signing
+    pub async fn built(self, pool: &PgPool, tx: &Transaction) -> Result<()> {
+        let tx_hash = hex::encode(hash_transaction(&tx.body()).to_bytes());
+        self.record(pool, "built", tx, &tx_hash).await
+    }
+
+    /// Records a transaction accepted by the node. Failures are only logged,
+    /// as the transaction is on its way regardless.
+    pub async fn submitted(self, pool: &PgPool, tx: &Transaction, tx_hash: &str) {
+        if let Err(e) = self.record(pool, "submitted", tx, tx_hash).await {
+            println!("Failed to audit submitted transaction {}: {}", tx_hash, e);
+        }
+    }
+
+    async fn record(
+        mut self,
+        pool: &PgPool,
+        stage: &str,
+        tx: &Transaction,
+        tx_hash: &str,
+    ) -> Result<()> {
+        let outputs = tx.body().outputs();
+        for i in 0..outputs.len() {
+            self.addresses.push(bech32(&outputs.get(i).address()));
+        }
+        self.addresses.sort();
+        self.addresses.dedup();
+
+        sqlx::query(
+            r#"
+            INSERT INTO marketplace.tx_audit
+                (purpose, stage, tx_hash, addresses, policy_id, asset_name, price, fee_bps, cbor)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+        )
+        .bind(self.purpose)
+        .bind(stage)
+        .bind(tx_hash)
+        .bind(self.addresses)
+        .bind(self.policy_id)
+        .bind(self.asset_name)
+        .bind(self.price.map(|price| price as i64))
+        .bind(self.fee_bps.map(|fee_bps| fee_bps as i32))
+        .bind(tx.to_bytes())
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+fn bech32(address: &Address) -> String {
+    address
+        .to_bech32(None)
+        .unwrap_or_else(|_| hex::encode(address.to_bytes()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditFilter {
+    pub tx_hash: Option<String>,
+    pub address: Option<String>,
+    pub purpose: Option<String>,
+    /// Only entries with a smaller id, for paging backwards
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Newest entries first, at most 100 at a time
+pub async fn get_audit_entries(pool: &PgPool, filter: &AuditFilter) -> Result<Vec<AuditRecord>> {
+    Ok(sqlx::query_as::<_, AuditRecord>(&format!(
+        r#"
+        SELECT {} FROM marketplace.tx_audit
+        WHERE ($1::TEXT IS NULL OR tx_hash = $1)
+          AND ($2::TEXT IS NULL OR $2 = ANY(addresses))
+          AND ($3::TEXT IS NULL OR purpose = $3)
+          AND ($4::BIGINT IS NULL OR id < $4)
+        ORDER BY id DESC
+        LIMIT $5
+        "#,
+        AUDIT_COLUMNS
+    ))
+    .bind(&filter.tx_hash)
+    .bind(&filter.address)
+    .bind(&filter.purpose)
+    .bind(filter.before)
+    .bind(filter.limit.unwrap_or(100).clamp(1, 100))
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Whether the node accepted a transaction submitted through the service
+pub async fn was_submitted(pool: &PgPool, tx_hash: &str) -> Result<bool> {
+    Ok(
+        sqlx::query(
+            "SELECT 1 FROM marketplace.tx_audit WHERE tx_hash = $1 AND stage = 'submitted'",
+        )
+        .bind(tx_hash)
+        .fetch_optional(pool)
+        .await?
+        .is_some(),
+    )
+}
+
+/// Deletes entries older than `retention_days`
+pub async fn prune_audit_entries(pool: &PgPool, retention_days: u32) -> Result<u64> {
+    let res = sqlx::query(
+        "DELETE FROM marketplace.tx_audit WHERE created_at < now() - make_interval(days => $1)",
+    )
+    .bind(retention_days as i32)
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected())
+}
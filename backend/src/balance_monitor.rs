@@ -0,0 +1,83 @@
+// Watches the ADA the holder wallets have to spend and alerts when it runs low
+
+use crate::cardano_db_sync::query_user_address_utxo;
+use crate::jobs::webhook::enqueue_webhook;
+use crate::rest::metrics::Metrics;
+use crate::Result;
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::utils::from_bignum;
+use serde_json::json;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A hot wallet checked against its threshold
+pub struct WatchedBalance {
+    /// Name used in alerts and as the metric label
+    pub name: &'static str,
+    pub address: Address,
+    pub min_lovelace: u64,
+}
+
+/// Checks every `interval_minutes`. Each balance is published as a metric,
+/// and an alert is queued for `webhook_url` when one drops below its
+/// threshold and again once it recovers.
+pub fn spawn(
+    pool: PgPool,
+    balances: Vec<WatchedBalance>,
+    metrics: Arc<Metrics>,
+    webhook_url: Option<String>,
+    interval_minutes: u64,
+) {
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_minutes * 60));
+        let mut low = vec![false; balances.len()];
+        loop {
+            interval.tick().await;
+            for (watched, low) in balances.iter().zip(low.iter_mut()) {
+                let lovelace = match spendable_lovelace(&pool, &watched.address).await {
+                    Ok(lovelace) => lovelace,
+                    Err(e) => {
+                        println!("Failed to check the {} balance: {}", watched.name, e);
+                        continue;
+                    }
+                };
+                metrics.record_balance(watched.name, lovelace);
+                let below = lovelace < watched.min_lovelace;
+                if below == *low {
+                    continue;
+                }
+                *low = below;
+                let status = if below { "low" } else { "recovered" };
+                println!(
+                    "The {} balance is {}: {} lovelace, threshold {}",
+                    watched.name, status, lovelace, watched.min_lovelace
+                );
+                if let Some(webhook_url) = &webhook_url {
+                    let alert = json!({
+                        "holder": watched.name,
+                        "address": watched.address.to_bech32(None).ok(),
+                        "status": status,
+                        "lovelace": lovelace,
+                        "thresholdLovelace": watched.min_lovelace,
+                    });
+                    if let Err(e) = enqueue_webhook(&pool, webhook_url, alert).await {
+                        println!("Failed to queue the {} balance alert: {}", watched.name, e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// ADA in the UTxOs without assets. The ADA escrowed with NFTs belongs to
+/// their sellers and cannot pay for anything.
+async fn spendable_lovelace(pool: &PgPool, address: &Address) -> Result<u64> {
+    Ok(query_user_address_utxo(pool, address)
+        .await?
+        .iter()
+        .map(|utxo| utxo.output().amount())
+        .filter(|amount| amount.multiasset().map_or(true, |ma| ma.len() == 0))
+        .map(|amount| from_bignum(&amount.coin()))
+        .sum())
+}
@@ -0,0 +1,27 @@
+// Caps how many transactions are built at the same time
+
+use crate::{Error, Result};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Building a transaction runs several UTxO queries. Past the limit
+/// requests are turned away, instead of queueing up for database connections.
+pub struct BuildLimit {
+    semaphore: Semaphore,
+    retry_after_seconds: u64,
+}
+
+impl BuildLimit {
+    pub fn new(max_concurrent: usize, retry_after_seconds: u64) -> Self {
+        Self {
+            semaphore: Semaphore::new(max_concurrent),
+            retry_after_seconds,
+        }
+    }
+
+    /// Held for as long as the transaction is being built
+    pub fn acquire(&self) -> Result<SemaphorePermit<'_>> {
+        self.semaphore
+            .try_acquire()
+            .map_err(|_| Error::TooManyRequests(self.retry_after_seconds))
+    }
+}
@@ -0,0 +1,79 @@
+// Burning of NFTs held by a wallet
+
+use crate::chain::ChainQuery;
+use crate::coin::{
+    build_transaction_body, BuildSettings, LargestFirst, TransactionWitnessSetParams,
+};
+use crate::marketplace::{create_value_with_single_nft, find_nft};
+use crate::nft::PolicyWitness;
+use crate::{Error, Result};
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::utils::Int;
+use cardano_serialization_lib::{
+    AssetName, Mint, MintAssets, PolicyID, Transaction, TransactionOutput,
+};
+
+const ONE_HOUR: u32 = 3600;
+
+/// Builds a transaction burning one unit of the asset held by `owner`. Other
+/// assets sharing the UTxO go back to the owner, the ADA freed by the burn
+/// joins the change. The owner still has to sign, as do the policy keys of a
+/// user supplied script.
+pub async fn burn_nft<C: ChainQuery>(
+    chain: &C,
+    owner: &Address,
+    policy_id: &PolicyID,
+    asset_name: &AssetName,
+    policy: PolicyWitness,
+    build: &BuildSettings,
+) -> Result<Transaction> {
+    let slot = chain.slot_number().await?;
+    let ttl = policy.ttl(policy_id, slot, slot + ONE_HOUR)?;
+
+    let owner_utxos = chain.address_utxos(owner).await?;
+    let (nft_utxo, owner_utxos) = find_nft(owner_utxos, policy_id, asset_name)
+        .map_err(|_| Error::Message("Address does not hold this NFT".to_string()))?;
+
+    let nft_value = create_value_with_single_nft(policy_id, asset_name);
+    let mut outputs = vec![];
+    let mut rest = nft_utxo.output().amount();
+    let remaining_assets = rest
+        .multiasset()
+        .unwrap()
+        .sub(&nft_value.multiasset().unwrap());
+    if remaining_assets.len() > 0 {
+        // Assets besides the NFT stay with the owner
+        rest.set_multiasset(&remaining_assets);
+        outputs.push(TransactionOutput::new(owner, &rest));
+    }
+
+    let mut mint_assets = MintAssets::new();
+    mint_assets.insert(asset_name, Int::new_i32(-1));
+    let mut mint = Mint::new();
+    mint.insert(policy_id, &mint_assets);
+
+    let native_scripts = policy.native_scripts();
+    let protocol_params = chain.protocol_params().await?;
+    let tx_witness_params = TransactionWitnessSetParams {
+        vkey_count: 2,
+        native_scripts: Some(&native_scripts),
+        ..Default::default()
+    };
+    let tx_body = build_transaction_body(
+        owner_utxos,
+        vec![nft_utxo],
+        outputs,
+        ttl,
+        &protocol_params,
+        None,
+        Some(mint),
+        &tx_witness_params,
+        None,
+        &LargestFirst,
+        None,
+        build,
+    )?;
+
+    let tx_witness_set = policy.witness_set(&tx_body);
+    Ok(Transaction::new(&tx_body, &tx_witness_set, None))
+}
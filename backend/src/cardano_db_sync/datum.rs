@@ -0,0 +1,95 @@
+use cardano_serialization_lib::crypto::DataHash;
+use cardano_serialization_lib::plutus::{ConstrPlutusData, PlutusData, PlutusList, PlutusMap};
+use cardano_serialization_lib::utils::{hash_plutus_data, to_bignum, BigInt};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Datum {
+    pub hash: String,
+    /// `None` when re-encoding the JSON does not give back the hashed bytes
+    pub cbor: Option<String>,
+    pub json: Value,
+}
+
+#[derive(sqlx::FromRow)]
+struct PgDatum {
+    hash: Vec<u8>,
+    value: Value,
+}
+
+/// db-sync 11 keeps datums only as their detailed schema JSON, the CBOR is
+/// re-encoded from it.
+pub async fn query_datum(pool: &PgPool, hash: &DataHash) -> crate::Result<Option<Datum>> {
+    let datum = sqlx::query_as::<_, PgDatum>(
+        r#"
+        SELECT hash, value
+        FROM datum
+        WHERE hash = $1
+        LIMIT 1
+        "#,
+    )
+    .bind(hash.to_bytes())
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(datum.map(|datum| Datum {
+        cbor: datum_cbor(&datum.hash, &datum.value),
+        hash: hex::encode(datum.hash),
+        json: datum.value,
+    }))
+}
+
+/// The CBOR of a datum given as detailed schema JSON, as long as it hashes
+/// to `hash`. Encodings the serialization library does not reproduce, such
+/// as indefinite length lists, give none rather than different bytes.
+pub fn datum_cbor(hash: &[u8], json: &Value) -> Option<String> {
+    let data = plutus_data(json)?;
+    if hash_plutus_data(&data).to_bytes() != hash {
+        return None;
+    }
+    Some(hex::encode(data.to_bytes()))
+}
+
+fn plutus_data(json: &Value) -> Option<PlutusData> {
+    let json = json.as_object()?;
+    if let Some(constructor) = json.get("constructor") {
+        let fields = plutus_list(json.get("fields")?)?;
+        return Some(PlutusData::new_constr_plutus_data(&ConstrPlutusData::new(
+            &to_bignum(constructor.as_u64()?),
+            &fields,
+        )));
+    }
+    if let Some(entries) = json.get("map") {
+        let mut map = PlutusMap::new();
+        for entry in entries.as_array()? {
+            map.insert(
+                &plutus_data(entry.get("k")?)?,
+                &plutus_data(entry.get("v")?)?,
+            );
+        }
+        return Some(PlutusData::new_map(&map));
+    }
+    if let Some(list) = json.get("list") {
+        return Some(PlutusData::new_list(&plutus_list(list)?));
+    }
+    if let Some(int) = json.get("int") {
+        return BigInt::from_str(&int.to_string())
+            .ok()
+            .map(|int| PlutusData::new_integer(&int));
+    }
+    if let Some(bytes) = json.get("bytes") {
+        return hex::decode(bytes.as_str()?).ok().map(PlutusData::new_bytes);
+    }
+    None
+}
+
+fn plutus_list(json: &Value) -> Option<PlutusList> {
+    let mut list = PlutusList::new();
+    for item in json.as_array()? {
+        list.add(&plutus_data(item)?);
+    }
+    Some(list)
+}
@@ -0,0 +1,105 @@
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionMetadata {
+    pub hash: String,
+    pub json: Option<Value>,
+}
+
+/// Metadata under `label`, newest first. With a policy, only transactions
+/// minting or moving an asset of it are considered, narrowed down further to
+/// a single asset when a name is given.
+pub async fn query_metadata_by_label(
+    pool: &PgPool,
+    label: u64,
+    policy: Option<Vec<u8>>,
+    asset_name: Option<Vec<u8>>,
+    page: u32,
+) -> crate::Result<Vec<TransactionMetadata>> {
+    let offset = page.saturating_sub(1) as i64 * 16;
+    Ok(sqlx::query_as::<_, TransactionMetadata>(
+        r#"
+        SELECT
+            encode(tx.hash, 'hex') AS hash,
+            tx_metadata.json
+        FROM tx_metadata
+        INNER JOIN tx ON tx.id = tx_metadata.tx_id
+        WHERE tx_metadata.key = $1
+        AND (
+            $2::bytea IS NULL
+            OR EXISTS (
+                SELECT 1 FROM ma_tx_mint
+                WHERE ma_tx_mint.tx_id = tx.id
+                AND ma_tx_mint.policy = $2
+                AND ($3::bytea IS NULL OR ma_tx_mint.name = $3)
+            )
+            OR EXISTS (
+                SELECT 1 FROM tx_out
+                INNER JOIN ma_tx_out ON ma_tx_out.tx_out_id = tx_out.id
+                WHERE tx_out.tx_id = tx.id
+                AND ma_tx_out.policy = $2
+                AND ($3::bytea IS NULL OR ma_tx_out.name = $3)
+            )
+        )
+        ORDER BY tx.id DESC
+        LIMIT 16
+        OFFSET $4
+        "#,
+    )
+    .bind(label as i64)
+    .bind(policy)
+    .bind(asset_name)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Assets put in outputs of transactions carrying some metadata label, along
+/// with how many of them are still unspent
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelUsage {
+    /// Bech32 address or hex policy id, by what is grouped
+    pub key: String,
+    pub listings: i64,
+    pub active: i64,
+}
+
+/// Label usage per output address and per policy, the 100 busiest of each
+pub async fn query_label_usage(
+    pool: &PgPool,
+    label: u64,
+) -> crate::Result<(Vec<LabelUsage>, Vec<LabelUsage>)> {
+    let query = |key: &str| {
+        format!(
+            r#"
+            SELECT
+                {} AS key,
+                COUNT(*) AS listings,
+                COUNT(*) FILTER (WHERE tx_in.id IS NULL) AS active
+            FROM tx_metadata
+            INNER JOIN tx_out ON tx_out.tx_id = tx_metadata.tx_id
+            INNER JOIN ma_tx_out ON ma_tx_out.tx_out_id = tx_out.id
+            LEFT JOIN tx_in
+            ON tx_in.tx_out_id = tx_out.tx_id AND tx_in.tx_out_index = tx_out.index
+            WHERE tx_metadata.key = $1
+            GROUP BY 1
+            ORDER BY listings DESC
+            LIMIT 100
+            "#,
+            key
+        )
+    };
+    let by_address = sqlx::query_as::<_, LabelUsage>(&query("tx_out.address"))
+        .bind(label as i64)
+        .fetch_all(pool)
+        .await?;
+    let by_policy = sqlx::query_as::<_, LabelUsage>(&query("encode(ma_tx_out.policy, 'hex')"))
+        .bind(label as i64)
+        .fetch_all(pool)
+        .await?;
+    Ok((by_address, by_policy))
+}
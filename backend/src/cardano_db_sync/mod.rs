@@ -1,9 +1,37 @@
+mod datum;
+mod metadata;
 mod nft;
 /// Schema for the database can be found at
 /// https://github.com/input-output-hk/cardano-db-sync/blob/master/doc/schema.md
+mod project;
 mod protocol;
+mod revenue;
+mod royalty;
+mod script;
+mod search;
+mod tx;
 mod utxo;
 
-pub use nft::{query_if_nft_minted, query_single_nft, query_user_address_nfts, NftMetadata};
-pub use protocol::{get_protocol_params, get_slot_number, ProtocolParams};
-pub use utxo::{query_user_address_utxo, UtxoJson};
+pub use datum::{query_datum, Datum};
+pub use metadata::{query_label_usage, query_metadata_by_label, LabelUsage, TransactionMetadata};
+pub use nft::{
+    query_collection_metadata, query_duplicate_assets, query_if_nft_minted, query_single_nft,
+    query_user_address_nfts, CollectionAsset, DuplicateAsset, NftMetadata,
+};
+pub use project::{query_project_stats, ProjectStats};
+pub use protocol::{get_protocol_params, get_slot_number, Era, ProtocolParams};
+pub use revenue::{query_revenue, RevenueAddresses, RevenueSource};
+pub use royalty::query_royalty_metadata;
+pub use script::{
+    is_script_address, query_reference_script_hash, query_script_address_utxo, script_hash,
+    ScriptDatum, ScriptUtxo, ScriptUtxoJson,
+};
+pub use search::{
+    query_asset_address, query_assets_by_name, query_handle_address, query_policies_by_prefix,
+    query_transaction_exists, AssetMatch,
+};
+pub use tx::{query_transaction_depth, query_transaction_senders, TransactionDepth};
+pub use utxo::{
+    query_user_address_utxo, query_user_address_utxo_page, query_utxo_unspent, UtxoJson,
+    MAX_ADDRESS_UTXOS,
+};
@@ -124,3 +124,77 @@ pub async fn query_single_nft(
 
     Ok(res)
 }
+
+/// An already minted asset with the same name as a new one
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateAsset {
+    pub policy_id: String,
+    pub asset_name: String,
+    /// Minted under one of `watched_policies`, rather than by the user
+    pub watched_policy: bool,
+}
+
+/// Assets named `asset_name` minted under one of `watched_policies` (hex), or
+/// minted into `address` before
+pub async fn query_duplicate_assets(
+    pool: &PgPool,
+    asset_name: &str,
+    watched_policies: &[String],
+    address: &Address,
+) -> crate::Result<Vec<DuplicateAsset>> {
+    Ok(sqlx::query_as::<_, DuplicateAsset>(
+        r#"
+        SELECT DISTINCT
+            encode(ma_tx_mint.policy, 'hex') AS policy_id,
+            convert_from(ma_tx_mint.name, 'utf-8') AS asset_name,
+            encode(ma_tx_mint.policy, 'hex') = ANY($2) AS watched_policy
+        FROM ma_tx_mint
+        WHERE ma_tx_mint.name = $1
+        AND ma_tx_mint.quantity > 0
+        AND (
+            encode(ma_tx_mint.policy, 'hex') = ANY($2)
+            OR EXISTS (
+                SELECT 1 FROM tx_out
+                WHERE tx_out.tx_id = ma_tx_mint.tx_id AND tx_out.address = $3
+            )
+        )
+        LIMIT 20
+        "#,
+    )
+    .bind(asset_name.as_bytes())
+    .bind(watched_policies)
+    .bind(address.to_bech32(None)?)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// The CIP-25 metadata an asset was last minted with
+#[derive(sqlx::FromRow)]
+pub struct CollectionAsset {
+    pub name: Vec<u8>,
+    pub json: Value,
+}
+
+/// Every asset minted under a policy along with its latest CIP-25 metadata
+pub async fn query_collection_metadata(
+    pool: &PgPool,
+    policy_id: &[u8],
+) -> crate::Result<Vec<CollectionAsset>> {
+    Ok(sqlx::query_as::<_, CollectionAsset>(
+        r#"
+        SELECT DISTINCT ON (ma_tx_mint.name)
+            ma_tx_mint.name,
+            tx_metadata.json
+        FROM ma_tx_mint
+        INNER JOIN tx_metadata
+        ON ma_tx_mint.tx_id = tx_metadata.tx_id AND tx_metadata.key = 721
+        WHERE ma_tx_mint.policy = $1
+        AND ma_tx_mint.quantity > 0
+        ORDER BY ma_tx_mint.name, ma_tx_mint.tx_id DESC
+        "#,
+    )
+    .bind(policy_id)
+    .fetch_all(pool)
+    .await?)
+}
@@ -0,0 +1,79 @@
+use bigdecimal::ToPrimitive;
+use serde::Serialize;
+use sqlx::types::BigDecimal;
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectStats {
+    pub units_sold: u64,
+    pub remaining: u64,
+    pub revenue: u64,
+    pub unique_buyers: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct PgProjectStats {
+    units_sold: Option<BigDecimal>,
+    remaining: Option<BigDecimal>,
+    revenue: Option<BigDecimal>,
+    unique_buyers: i64,
+}
+
+/// Sales figures of a project from the history of its holder address. Every
+/// transaction spending the holder is a sale, the assets it sends elsewhere
+/// are the units sold and the ADA reaching `revenue_address` the revenue.
+pub async fn query_project_stats(
+    pool: &PgPool,
+    holder_address: &str,
+    revenue_address: &str,
+) -> crate::Result<ProjectStats> {
+    let stats = sqlx::query_as::<_, PgProjectStats>(
+        r#"
+        WITH sales AS (
+            SELECT DISTINCT tx_in.tx_in_id AS tx_id
+            FROM tx_in
+            INNER JOIN tx_out AS spent
+            ON spent.tx_id = tx_in.tx_out_id AND spent.index = tx_in.tx_out_index
+            WHERE spent.address = $1
+        ),
+        sold AS (
+            SELECT tx_out.address, ma_tx_out.quantity
+            FROM sales
+            INNER JOIN tx_out ON tx_out.tx_id = sales.tx_id
+            INNER JOIN ma_tx_out ON ma_tx_out.tx_out_id = tx_out.id
+            WHERE tx_out.address <> $1
+        )
+        SELECT
+            (SELECT SUM(quantity) FROM sold) AS units_sold,
+            (SELECT COUNT(DISTINCT address) FROM sold) AS unique_buyers,
+            (
+                SELECT SUM(tx_out.value)
+                FROM sales
+                INNER JOIN tx_out ON tx_out.tx_id = sales.tx_id
+                WHERE tx_out.address = $2
+            ) AS revenue,
+            (
+                SELECT SUM(ma_tx_out.quantity)
+                FROM tx_out
+                INNER JOIN ma_tx_out ON ma_tx_out.tx_out_id = tx_out.id
+                LEFT JOIN tx_in
+                ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
+                WHERE tx_out.address = $1
+                AND tx_in.id IS NULL
+            ) AS remaining
+        "#,
+    )
+    .bind(holder_address)
+    .bind(revenue_address)
+    .fetch_one(pool)
+    .await?;
+
+    let to_u64 = |n: Option<BigDecimal>| n.and_then(|n| n.to_u64()).unwrap_or(0);
+    Ok(ProjectStats {
+        units_sold: to_u64(stats.units_sold),
+        remaining: to_u64(stats.remaining),
+        revenue: to_u64(stats.revenue),
+        unique_buyers: stats.unique_buyers,
+    })
+}
@@ -1,6 +1,8 @@
 use bigdecimal::ToPrimitive;
 use cardano_serialization_lib::fees::LinearFee;
-use cardano_serialization_lib::utils::{to_bignum, Coin};
+use cardano_serialization_lib::plutus::{CostModel, Costmdls, ExUnits, Language};
+use cardano_serialization_lib::utils::{to_bignum, Coin, Int};
+use cardano_serialization_lib::UnitInterval;
 use sqlx::types::BigDecimal;
 use sqlx::PgPool;
 
@@ -9,21 +11,70 @@ const MAX_VAL_SIZE: u32 = 5000;
 const POOL_DEPOSIT: u64 = 500000000;
 const KEY_DEPOSIT: u64 = 2000000;
 const COINS_PER_UTXO_WORD: u64 = 34482;
+const COLLATERAL_PERCENT: u32 = 150;
+const MAX_COLLATERAL_INPUTS: u32 = 3;
+/// Words of an ADA-only UTxO in the Alonzo min-ADA rule, which the Mary rule
+/// used by the serialization library is scaled to
+const ADA_ONLY_UTXO_WORDS: u64 = 29;
+const BYTES_PER_WORD: u64 = 8;
+/// Prices are stored as floating point, this many decimals are kept
+const PRICE_DENOMINATOR: u64 = 1_000_000_000;
+
+/// Ledger eras, by the protocol version they start at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Era {
+    Shelley,
+    Allegra,
+    Mary,
+    Alonzo,
+    Babbage,
+    Conway,
+}
+
+impl Era {
+    /// None for versions before Shelley or after the last known era, rather
+    /// than building with the rules of a guessed one
+    fn from_protocol_major(major: i32) -> Option<Era> {
+        match major {
+            2 => Some(Era::Shelley),
+            3 => Some(Era::Allegra),
+            4 => Some(Era::Mary),
+            5..=6 => Some(Era::Alonzo),
+            7..=8 => Some(Era::Babbage),
+            9..=10 => Some(Era::Conway),
+            _ => None,
+        }
+    }
+}
 
 // There is a version in cardano_serialization_lib but always returns Option when trying to retrieve.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProtocolParams {
+    pub era: Era,
     pub linear_fee: LinearFee,
+    /// Minimum UTxO value for the Mary min-ADA rule. Later eras price UTxOs
+    /// by size instead, their parameter is converted to the equivalent.
     pub minimum_utxo_value: Coin,
     pub pool_deposit: Coin,
     pub key_deposit: Coin,
     pub max_tx_size: u32,
     pub max_value_size: u32,
+    /// Per 8 bytes from Babbage on, where the parameter is per byte
     pub coins_per_utxo_word: Coin,
+    /// Collateral of a script transaction, as a percentage of its fee
+    pub collateral_percent: u32,
+    pub max_collateral_inputs: u32,
+    /// Lovelace per unit of script memory
+    pub price_mem: UnitInterval,
+    /// Lovelace per script CPU step
+    pub price_step: UnitInterval,
+    pub max_tx_ex_units: ExUnits,
+    pub cost_models: Costmdls,
 }
 
 #[derive(sqlx::FromRow, Debug)]
 struct PgProtocolParams {
+    protocol_major: i32,
     min_fee_a: i32,
     min_fee_b: i32,
     max_tx_size: i32,
@@ -32,32 +83,54 @@ struct PgProtocolParams {
     min_utxo_value: BigDecimal,
     max_val_size: Option<BigDecimal>,
     coins_per_utxo_word: Option<BigDecimal>,
+    coins_per_utxo_size: Option<BigDecimal>,
+    collateral_percent: Option<i32>,
+    max_collateral_inputs: Option<i32>,
+    price_mem: Option<f64>,
+    price_step: Option<f64>,
+    max_tx_ex_mem: Option<BigDecimal>,
+    max_tx_ex_steps: Option<BigDecimal>,
+    cost_models: Option<serde_json::Value>,
 }
 
 pub async fn get_protocol_params(pool: &PgPool) -> Result<ProtocolParams, sqlx::Error> {
     let rec: PgProtocolParams = sqlx::query_as::<_, PgProtocolParams>(
         r#"
-    SELECT min_fee_a, min_fee_b, max_tx_size, key_deposit,
-            pool_deposit, max_val_size, coins_per_utxo_word, min_utxo_value
-    FROM epoch_param 
+    SELECT protocol_major, min_fee_a, min_fee_b, max_tx_size, key_deposit,
+            pool_deposit, max_val_size, min_utxo_value,
+            -- db-sync renamed the column for Babbage, read whichever exists
+            (to_jsonb(epoch_param) ->> 'coins_per_utxo_word')::NUMERIC AS coins_per_utxo_word,
+            (to_jsonb(epoch_param) ->> 'coins_per_utxo_size')::NUMERIC AS coins_per_utxo_size,
+            collateral_percent, max_collateral_inputs, price_mem, price_step,
+            max_tx_ex_mem, max_tx_ex_steps, cost_models
+    FROM epoch_param
     ORDER BY epoch_no DESC LIMIT 1
     "#,
     )
     .fetch_one(pool)
     .await?;
-    let min_utxo_value = match rec.min_utxo_value.to_u64() {
-        Some(0) => MIN_UTXO_VALUE,
-        Some(v) => v,
-        _ => MIN_UTXO_VALUE,
-    };
+    let era = Era::from_protocol_major(rec.protocol_major).ok_or_else(|| {
+        sqlx::Error::Decode(format!("Unknown protocol version {}", rec.protocol_major).into())
+    })?;
+    let coins_per_utxo_word = coins_per_utxo_word(
+        era,
+        rec.coins_per_utxo_word.and_then(|bd| bd.to_u64()),
+        rec.coins_per_utxo_size.and_then(|bd| bd.to_u64()),
+    );
 
-    let coins_per_utxo_word = match rec.coins_per_utxo_word.and_then(|bd| bd.to_u64()) {
-        Some(0) => COINS_PER_UTXO_WORD,
-        Some(v) => v,
-        _ => COINS_PER_UTXO_WORD,
+    let min_utxo_value = if era >= Era::Alonzo {
+        // Errs a little high for UTxOs with assets, never low
+        coins_per_utxo_word * ADA_ONLY_UTXO_WORDS
+    } else {
+        match rec.min_utxo_value.to_u64() {
+            Some(0) => MIN_UTXO_VALUE,
+            Some(v) => v,
+            _ => MIN_UTXO_VALUE,
+        }
     };
 
     Ok(ProtocolParams {
+        era,
         linear_fee: LinearFee::new(
             &to_bignum(rec.min_fee_a as u64),
             &to_bignum(rec.min_fee_b as u64),
@@ -71,12 +144,84 @@ pub async fn get_protocol_params(pool: &PgPool) -> Result<ProtocolParams, sqlx::
             .and_then(|bd| bd.to_u32())
             .unwrap_or(MAX_VAL_SIZE),
         coins_per_utxo_word: to_bignum(coins_per_utxo_word),
+        collateral_percent: rec
+            .collateral_percent
+            .map(|percent| percent as u32)
+            .unwrap_or(COLLATERAL_PERCENT),
+        max_collateral_inputs: rec
+            .max_collateral_inputs
+            .map(|inputs| inputs as u32)
+            .unwrap_or(MAX_COLLATERAL_INPUTS),
+        price_mem: price(rec.price_mem),
+        price_step: price(rec.price_step),
+        max_tx_ex_units: ExUnits::new(
+            &to_bignum(rec.max_tx_ex_mem.and_then(|bd| bd.to_u64()).unwrap_or(0)),
+            &to_bignum(rec.max_tx_ex_steps.and_then(|bd| bd.to_u64()).unwrap_or(0)),
+        ),
+        cost_models: match &rec.cost_models {
+            Some(json) => cost_models(json)?,
+            None => Costmdls::new(),
+        },
     })
 }
 
+/// The per-word price of UTxO storage. Babbage prices it per byte instead.
+fn coins_per_utxo_word(era: Era, per_word: Option<u64>, per_byte: Option<u64>) -> u64 {
+    let per_word = match era {
+        Era::Babbage | Era::Conway => per_byte.map(|per_byte| per_byte * BYTES_PER_WORD),
+        _ => per_word,
+    };
+    match per_word {
+        Some(0) | None => COINS_PER_UTXO_WORD,
+        Some(v) => v,
+    }
+}
+
+fn price(price: Option<f64>) -> UnitInterval {
+    let numerator = (price.unwrap_or(0.0) * PRICE_DENOMINATOR as f64).round() as u64;
+    UnitInterval::new(&to_bignum(numerator), &to_bignum(PRICE_DENOMINATOR))
+}
+
+/// db-sync keeps cost models as `{ "PlutusV1": { "<operation>": cost } }`.
+/// The ledger orders the costs of a model by operation name. A model that
+/// cannot be read is an error rather than left out, script transactions
+/// priced without it would be rejected by the node.
+fn cost_models(json: &serde_json::Value) -> Result<Costmdls, sqlx::Error> {
+    let mut cost_models = Costmdls::new();
+    let costs = match json.get("PlutusV1").and_then(|costs| costs.as_object()) {
+        Some(costs) => costs,
+        None => return Ok(cost_models),
+    };
+    let mut operations: Vec<(&String, &serde_json::Value)> = costs.iter().collect();
+    operations.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut cost_model = CostModel::new();
+    for (i, (operation, cost)) in operations.into_iter().enumerate() {
+        let set = cost
+            .as_i64()
+            .ok_or(())
+            .and_then(|cost| cost_model.set(i, &int(cost)).map_err(|_| ()));
+        if set.is_err() {
+            return Err(sqlx::Error::Decode(
+                format!("Cannot read the PlutusV1 cost of {}", operation).into(),
+            ));
+        }
+    }
+    cost_models.insert(&Language::new_plutus_v1(), &cost_model);
+    Ok(cost_models)
+}
+
+fn int(n: i64) -> Int {
+    if n < 0 {
+        Int::new_negative(&to_bignum(n.unsigned_abs()))
+    } else {
+        Int::new(&to_bignum(n as u64))
+    }
+}
+
 #[derive(sqlx::FromRow)]
 struct Slot {
-    slot_no: i32,
+    slot_no: i64,
 }
 
 pub async fn get_slot_number(pool: &PgPool) -> Result<u32, sqlx::Error> {
@@ -90,3 +235,49 @@ pub async fn get_slot_number(pool: &PgPool) -> Result<u32, sqlx::Error> {
 
     Ok(rec.slot_no as u32)
 }
+
+#[derive(sqlx::FromRow)]
+struct BlockHash {
+    hash: Vec<u8>,
+}
+
+pub async fn get_latest_block_hash(pool: &PgPool) -> Result<Vec<u8>, sqlx::Error> {
+    let rec = sqlx::query_as::<_, BlockHash>(
+        r#"
+        SELECT hash FROM block ORDER BY id DESC LIMIT 1
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(rec.hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_eras_from_the_protocol_version() {
+        assert_eq!(Era::from_protocol_major(6), Some(Era::Alonzo));
+        assert_eq!(Era::from_protocol_major(7), Some(Era::Babbage));
+        assert_eq!(Era::from_protocol_major(0), None);
+        assert_eq!(Era::from_protocol_major(11), None);
+    }
+
+    #[test]
+    fn prices_babbage_utxos_per_byte() {
+        assert_eq!(
+            coins_per_utxo_word(Era::Babbage, Some(34482), Some(4310)),
+            4310 * BYTES_PER_WORD
+        );
+        assert_eq!(
+            coins_per_utxo_word(Era::Alonzo, Some(34482), Some(4310)),
+            34482
+        );
+        assert_eq!(
+            coins_per_utxo_word(Era::Babbage, Some(34482), None),
+            COINS_PER_UTXO_WORD
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use bigdecimal::ToPrimitive;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::types::BigDecimal;
+use sqlx::PgPool;
+
+/// Addresses used to attribute income at the revenue addresses to a source.
+pub struct RevenueAddresses {
+    pub revenue: Vec<String>,
+    pub marketplace_holder: String,
+    pub projects_holder: String,
+    pub tax: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevenueSource {
+    pub source: String,
+    pub lovelace: u64,
+    pub transactions: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct PgRevenueSource {
+    source: String,
+    lovelace: BigDecimal,
+    transactions: i64,
+}
+
+/// Sums income at the revenue addresses between `from` and `to`. A payment is
+/// a marketplace sale or cancellation when the transaction spends the
+/// marketplace holder, told apart by whether the NFT goes back to the seller
+/// of the listing. Project sales spend the projects holder and mint tax comes
+/// with a mint.
+pub async fn query_revenue(
+    pool: &PgPool,
+    addresses: &RevenueAddresses,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> crate::Result<Vec<RevenueSource>> {
+    let rows = sqlx::query_as::<_, PgRevenueSource>(
+        r#"
+        SELECT source, SUM(value) AS lovelace, COUNT(DISTINCT tx_id) AS transactions
+        FROM (
+            SELECT
+                tx_out.value,
+                tx.id AS tx_id,
+                CASE
+                    WHEN tx_out.address = $4
+                        AND EXISTS (SELECT 1 FROM ma_tx_mint WHERE ma_tx_mint.tx_id = tx.id)
+                        THEN 'mint_tax'
+                    WHEN EXISTS (
+                        SELECT 1 FROM tx_in
+                        INNER JOIN tx_out AS spent
+                        ON spent.tx_id = tx_in.tx_out_id AND spent.index = tx_in.tx_out_index
+                        WHERE tx_in.tx_in_id = tx.id AND spent.address = $2
+                    ) THEN
+                        CASE WHEN EXISTS (
+                            SELECT 1 FROM tx_in
+                            INNER JOIN tx_out AS spent
+                            ON spent.tx_id = tx_in.tx_out_id AND spent.index = tx_in.tx_out_index
+                            INNER JOIN tx_metadata AS listing
+                            ON listing.tx_id = spent.tx_id AND listing.key = 888
+                            INNER JOIN tx_out AS returned ON returned.tx_id = tx.id
+                            INNER JOIN ma_tx_out ON ma_tx_out.tx_out_id = returned.id
+                            WHERE tx_in.tx_in_id = tx.id
+                            AND spent.address = $2
+                            -- Chunks of bech32, or raw bytes from version 3 on
+                            AND CASE jsonb_typeof(listing.json -> 'seller_address')
+                                WHEN 'array' THEN returned.address = array_to_string(ARRAY(
+                                    SELECT jsonb_array_elements_text(listing.json -> 'seller_address')
+                                ), '')
+                                ELSE listing.json ->> 'seller_address' IN (
+                                    returned.address,
+                                    '0x' || encode(returned.address_raw, 'hex')
+                                )
+                            END
+                        ) THEN 'cancellation' ELSE 'marketplace_sale' END
+                    WHEN EXISTS (
+                        SELECT 1 FROM tx_in
+                        INNER JOIN tx_out AS spent
+                        ON spent.tx_id = tx_in.tx_out_id AND spent.index = tx_in.tx_out_index
+                        WHERE tx_in.tx_in_id = tx.id AND spent.address = $3
+                    ) THEN 'project_sale'
+                    ELSE 'other'
+                END AS source
+            FROM tx_out
+            INNER JOIN tx ON tx.id = tx_out.tx_id
+            INNER JOIN block ON block.id = tx.block_id
+            WHERE tx_out.address = ANY($1)
+            AND block.time >= $5
+            AND block.time < $6
+        ) AS revenue
+        GROUP BY source
+        ORDER BY source
+        "#,
+    )
+    .bind(&addresses.revenue)
+    .bind(&addresses.marketplace_holder)
+    .bind(&addresses.projects_holder)
+    .bind(&addresses.tax)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| RevenueSource {
+            source: row.source,
+            lovelace: row.lovelace.to_u64().unwrap_or(0),
+            transactions: row.transactions,
+        })
+        .collect())
+}
@@ -0,0 +1,32 @@
+use cardano_serialization_lib::PolicyID;
+use serde_json::Value;
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
+
+/// CIP-27 royalty metadata of a policy. Only the first royalty token minted
+/// under a policy counts.
+pub async fn query_royalty_metadata(
+    pool: &PgPool,
+    policy_id: &PolicyID,
+) -> crate::Result<Option<Value>> {
+    let res: Option<Value> = sqlx::query(
+        r#"
+        SELECT tx_metadata.json
+        FROM ma_tx_mint
+        INNER JOIN tx_metadata
+        ON ma_tx_mint.tx_id = tx_metadata.tx_id
+        AND tx_metadata.key = 777
+        WHERE ma_tx_mint.policy = $1
+        AND ma_tx_mint.name = ''::bytea
+        AND ma_tx_mint.quantity > 0
+        ORDER BY ma_tx_mint.tx_id ASC
+        LIMIT 1
+        "#,
+    )
+    .bind(policy_id.to_bytes())
+    .map(|row: PgRow| row.get("json"))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(res)
+}
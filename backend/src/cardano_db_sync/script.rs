@@ -0,0 +1,161 @@
+use super::datum::datum_cbor;
+use super::utxo::{query_user_address_utxo, UtxoJson};
+use crate::error::Error;
+use cardano_serialization_lib::address::{
+    Address, BaseAddress, EnterpriseAddress, PointerAddress, StakeCredential,
+};
+use cardano_serialization_lib::crypto::ScriptHash;
+use cardano_serialization_lib::utils::TransactionUnspentOutput;
+use cardano_serialization_lib::TransactionInput;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// Datum hash of a script output, with the datum when a witness set has
+/// revealed it. db-sync 11 predates inline datums.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptDatum {
+    pub hash: String,
+    /// `None` when only the hash is known or the JSON does not re-encode to
+    /// the hashed bytes
+    pub cbor: Option<String>,
+    pub json: Option<Value>,
+}
+
+pub struct ScriptUtxo {
+    pub utxo: TransactionUnspentOutput,
+    pub datum: Option<ScriptDatum>,
+}
+
+#[derive(Serialize)]
+pub struct ScriptUtxoJson<'a> {
+    #[serde(flatten)]
+    utxo: UtxoJson<'a>,
+    datum: &'a Option<ScriptDatum>,
+}
+
+impl<'a> From<&'a ScriptUtxo> for ScriptUtxoJson<'a> {
+    fn from(script_utxo: &'a ScriptUtxo) -> Self {
+        Self {
+            utxo: UtxoJson(&script_utxo.utxo),
+            datum: &script_utxo.datum,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PgScriptDatum {
+    hash: Vec<u8>,
+    index: i16,
+    datum_hash: Vec<u8>,
+    value: Option<Value>,
+}
+
+fn payment_credential(addr: &Address) -> Option<StakeCredential> {
+    BaseAddress::from_address(addr)
+        .map(|a| a.payment_cred())
+        .or_else(|| EnterpriseAddress::from_address(addr).map(|a| a.payment_cred()))
+        .or_else(|| PointerAddress::from_address(addr).map(|a| a.payment_cred()))
+}
+
+pub fn is_script_address(addr: &Address) -> bool {
+    script_hash(addr).is_some()
+}
+
+/// Hash of the script locking outputs at `addr`
+pub fn script_hash(addr: &Address) -> Option<ScriptHash> {
+    payment_credential(addr).and_then(|cred| cred.to_scripthash())
+}
+
+/// Hash of the reference script carried by the output `input` refers to.
+/// db-sync only records reference scripts from Babbage on, the column is
+/// read whether it exists or not.
+pub async fn query_reference_script_hash(
+    pool: &PgPool,
+    input: &TransactionInput,
+) -> crate::Result<Option<ScriptHash>> {
+    let hash: Option<Vec<u8>> = sqlx::query_scalar(
+        r#"
+    SELECT script.hash
+    FROM tx_out
+    JOIN tx ON tx_out.tx_id = tx.id
+    JOIN script ON script.id = (to_jsonb(tx_out) ->> 'reference_script_id')::BIGINT
+    WHERE tx.hash = $1
+    AND tx_out.index = $2
+    "#,
+    )
+    .bind(input.transaction_id().to_bytes())
+    .bind(input.index() as i16)
+    .fetch_optional(pool)
+    .await?;
+    Ok(match hash {
+        Some(hash) => Some(ScriptHash::from_bytes(hash)?),
+        None => None,
+    })
+}
+
+/// UTxOs locked at a script address along with their datums.
+pub async fn query_script_address_utxo(
+    pool: &PgPool,
+    addr: &Address,
+) -> crate::Result<Vec<ScriptUtxo>> {
+    if !is_script_address(addr) {
+        return Err(Error::Message(
+            "Address is not a script address".to_string(),
+        ));
+    }
+
+    let utxos = query_user_address_utxo(pool, addr).await?;
+    let datums = sqlx::query_as::<_, PgScriptDatum>(
+        r#"
+    SELECT
+        tx.hash,
+        tx_out.index,
+        tx_out.data_hash AS datum_hash,
+        datum.value
+    FROM tx_out
+    JOIN tx ON tx_out.tx_id = tx.id
+    LEFT JOIN tx_in ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
+    LEFT JOIN datum ON datum.hash = tx_out.data_hash
+    WHERE tx_out.address = $1
+    AND tx_in.id IS NULL
+    AND tx_out.data_hash IS NOT NULL
+    "#,
+    )
+    .bind(addr.to_bech32(None)?)
+    .fetch_all(pool)
+    .await?;
+
+    let mut datums_map: HashMap<(Vec<u8>, u32), ScriptDatum> = datums
+        .into_iter()
+        .map(|d| {
+            (
+                (d.hash, d.index as u32),
+                ScriptDatum {
+                    cbor: d
+                        .value
+                        .as_ref()
+                        .and_then(|value| datum_cbor(&d.datum_hash, value)),
+                    hash: hex::encode(d.datum_hash),
+                    json: d.value,
+                },
+            )
+        })
+        .collect();
+
+    Ok(utxos
+        .into_iter()
+        .map(|utxo| {
+            let key = (
+                utxo.input().transaction_id().to_bytes(),
+                utxo.input().index(),
+            );
+            ScriptUtxo {
+                datum: datums_map.remove(&key),
+                utxo,
+            }
+        })
+        .collect())
+}
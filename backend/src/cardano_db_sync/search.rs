@@ -0,0 +1,94 @@
+use serde::Serialize;
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
+
+/// Policy ADA Handles are minted under on mainnet
+const ADA_HANDLE_POLICY: &str = "f0ff48bbb7bbe9d59a40f1ce90e9e9d0ff5002ec48f232b49ca0fb9a";
+
+const SEARCH_LIMIT: i64 = 10;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetMatch {
+    pub policy_id: String,
+    pub asset_name: String,
+}
+
+/// Policies with assets minted whose hex starts with `prefix`
+pub async fn query_policies_by_prefix(pool: &PgPool, prefix: &str) -> crate::Result<Vec<String>> {
+    Ok(sqlx::query(
+        r#"
+        SELECT DISTINCT encode(policy, 'hex') AS policy_id
+        FROM ma_tx_mint
+        WHERE encode(policy, 'hex') LIKE $1
+        ORDER BY policy_id
+        LIMIT $2
+        "#,
+    )
+    .bind(format!("{}%", prefix.to_lowercase()))
+    .bind(SEARCH_LIMIT)
+    .map(|row: PgRow| row.get("policy_id"))
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Assets whose name contains `name`, ignoring case. Names that are not
+/// printable come back with escaped bytes.
+pub async fn query_assets_by_name(pool: &PgPool, name: &str) -> crate::Result<Vec<AssetMatch>> {
+    Ok(sqlx::query(
+        r#"
+        SELECT DISTINCT encode(policy, 'hex') AS policy_id, encode(name, 'escape') AS asset_name
+        FROM ma_tx_mint
+        WHERE quantity > 0
+        AND encode(name, 'escape') ILIKE $1
+        ORDER BY asset_name
+        LIMIT $2
+        "#,
+    )
+    .bind(format!("%{}%", name))
+    .bind(SEARCH_LIMIT)
+    .map(|row: PgRow| AssetMatch {
+        policy_id: row.get("policy_id"),
+        asset_name: row.get("asset_name"),
+    })
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Address currently holding the ADA Handle `handle`, without its leading `$`
+pub async fn query_handle_address(pool: &PgPool, handle: &str) -> crate::Result<Option<String>> {
+    query_asset_address(pool, ADA_HANDLE_POLICY, &handle.to_lowercase().into_bytes()).await
+}
+
+/// Address of an unspent output holding the asset, `policy_id` in hex
+pub async fn query_asset_address(
+    pool: &PgPool,
+    policy_id: &str,
+    asset_name: &[u8],
+) -> crate::Result<Option<String>> {
+    Ok(sqlx::query(
+        r#"
+        SELECT tx_out.address
+        FROM ma_tx_out
+        INNER JOIN tx_out ON tx_out.id = ma_tx_out.tx_out_id
+        LEFT JOIN tx_in ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
+        WHERE encode(ma_tx_out.policy, 'hex') = $1
+        AND ma_tx_out.name = $2
+        AND tx_in.id IS NULL
+        LIMIT 1
+        "#,
+    )
+    .bind(policy_id)
+    .bind(asset_name)
+    .map(|row: PgRow| row.get("address"))
+    .fetch_optional(pool)
+    .await?)
+}
+
+pub async fn query_transaction_exists(pool: &PgPool, tx_hash: &[u8]) -> crate::Result<bool> {
+    Ok(sqlx::query("SELECT 1 FROM tx WHERE hash = $1")
+        .bind(tx_hash)
+        .fetch_optional(pool)
+        .await?
+        .is_some())
+}
@@ -0,0 +1,59 @@
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::crypto::TransactionHash;
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
+
+/// Distinct addresses of the inputs spent by a transaction, in input order.
+/// Empty when db-sync has not seen the transaction.
+pub async fn query_transaction_senders(
+    pool: &PgPool,
+    tx_hash: &TransactionHash,
+) -> crate::Result<Vec<Address>> {
+    let addresses: Vec<String> = sqlx::query(
+        r#"
+        SELECT tx_out.address
+        FROM tx
+        INNER JOIN tx_in ON tx_in.tx_in_id = tx.id
+        INNER JOIN tx_out ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
+        WHERE tx.hash = $1
+        GROUP BY tx_out.address
+        ORDER BY MIN(tx_in.id)
+        "#,
+    )
+    .bind(tx_hash.to_bytes())
+    .map(|row: PgRow| row.get("address"))
+    .fetch_all(pool)
+    .await?;
+
+    addresses
+        .iter()
+        .map(|address| Ok(Address::from_bech32(address)?))
+        .collect()
+}
+
+/// Where a transaction landed on chain
+#[derive(sqlx::FromRow, Debug, PartialEq)]
+pub struct TransactionDepth {
+    pub block_no: i64,
+    /// Blocks on top of and including the one holding the transaction
+    pub confirmations: i64,
+}
+
+pub async fn query_transaction_depth(
+    pool: &PgPool,
+    tx_hash: &TransactionHash,
+) -> crate::Result<Option<TransactionDepth>> {
+    Ok(sqlx::query_as::<_, TransactionDepth>(
+        r#"
+        SELECT
+            block.block_no::BIGINT AS block_no,
+            ((SELECT max(block_no) FROM block) - block.block_no + 1)::BIGINT AS confirmations
+        FROM tx
+        INNER JOIN block ON block.id = tx.block_id
+        WHERE tx.hash = $1
+        "#,
+    )
+    .bind(tx_hash.to_bytes())
+    .fetch_optional(pool)
+    .await?)
+}
@@ -1,3 +1,5 @@
+use super::protocol::get_latest_block_hash;
+use crate::error::Error;
 use bigdecimal::ToPrimitive;
 use cardano_serialization_lib::address::Address;
 use cardano_serialization_lib::crypto::{DataHash, TransactionHash};
@@ -10,10 +12,17 @@ use serde::{Serialize, Serializer};
 use sqlx::types::BigDecimal;
 use sqlx::PgPool;
 use std::collections::HashMap;
+use std::sync::Mutex;
 use tokio_stream::StreamExt;
 
+/// Unspent outputs fetched per query when walking an address
+const UTXO_PAGE_SIZE: i64 = 1_000;
+/// Beyond this many unspent outputs an address has to be read page by page
+pub const MAX_ADDRESS_UTXOS: usize = 10_000;
+
 #[derive(Debug, sqlx::FromRow)]
 pub struct PgTxOut {
+    id: i64,
     hash: Vec<u8>,
     index: i16,
     value: BigDecimal,
@@ -23,50 +32,170 @@ pub struct PgTxOut {
     quantity: Option<BigDecimal>,
 }
 
+/// Unspent outputs of the addresses queried since the latest block. The UTxO
+/// set only changes with a new block, so users refreshing during checkout do
+/// not repeat the heavy joins.
+struct UtxoCache {
+    block_hash: Vec<u8>,
+    utxos: HashMap<Vec<u8>, Vec<TransactionUnspentOutput>>,
+}
+
+/// Addresses kept per block, beyond which the cache is not filled further
+const MAX_CACHED_ADDRESSES: usize = 1_000;
+
+lazy_static! {
+    static ref UTXO_CACHE: Mutex<UtxoCache> = Mutex::new(UtxoCache {
+        block_hash: vec![],
+        utxos: HashMap::new(),
+    });
+}
+
 pub async fn query_user_address_utxo(
     pool: &PgPool,
     addr: &Address,
 ) -> crate::Result<Vec<TransactionUnspentOutput>> {
+    let block_hash = get_latest_block_hash(pool).await?;
+    {
+        let mut cache = UTXO_CACHE.lock().unwrap();
+        if cache.block_hash != block_hash {
+            cache.block_hash = block_hash.clone();
+            cache.utxos.clear();
+        } else if let Some(utxos) = cache.utxos.get(&addr.to_bytes()) {
+            return Ok(utxos.clone());
+        }
+    }
+
+    let utxos = fetch_user_address_utxo(pool, addr).await?;
+
+    let mut cache = UTXO_CACHE.lock().unwrap();
+    // A block may have arrived while querying, the result belongs to the older one
+    if cache.block_hash == block_hash && cache.utxos.len() < MAX_CACHED_ADDRESSES {
+        cache.utxos.insert(addr.to_bytes(), utxos.clone());
+    }
+    Ok(utxos)
+}
+
+async fn fetch_user_address_utxo(
+    pool: &PgPool,
+    addr: &Address,
+) -> crate::Result<Vec<TransactionUnspentOutput>> {
+    let mut utxos = vec![];
+    let mut after = None;
+    loop {
+        let (page, next) = query_user_address_utxo_page(pool, addr, after, UTXO_PAGE_SIZE).await?;
+        utxos.extend(page);
+        if utxos.len() > MAX_ADDRESS_UTXOS {
+            return Err(Error::Message(format!(
+                "Address has more than {} unspent outputs, query them page by page instead",
+                MAX_ADDRESS_UTXOS
+            )));
+        }
+        match next {
+            Some(next) => after = Some(next),
+            None => return Ok(utxos),
+        }
+    }
+}
+
+/// Up to `limit` unspent outputs of an address, ordered by their db-sync id.
+/// Returns a cursor to pass as `after` for the next page, if there may be one.
+pub async fn query_user_address_utxo_page(
+    pool: &PgPool,
+    addr: &Address,
+    after: Option<i64>,
+    limit: i64,
+) -> crate::Result<(Vec<TransactionUnspentOutput>, Option<i64>)> {
     let mut rows = sqlx::query_as::<_, PgTxOut>(
         r#"
+    WITH outputs AS (
+        SELECT
+            tx_out.id,
+            tx.hash,
+            tx_out.index,
+            tx_out.value,
+            tx_out.data_hash
+        FROM tx_out
+        JOIN tx ON tx_out.tx_id = tx.id
+        LEFT JOIN tx_in ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
+        WHERE address = $1
+        AND tx_in.id IS NULL
+        AND tx_out.id > $2
+        ORDER BY tx_out.id
+        LIMIT $3
+    )
     SELECT
-        tx.hash,
-        tx_out.index,
-        tx_out.value,
-        tx_out.data_hash,
+        outputs.id,
+        outputs.hash,
+        outputs.index,
+        outputs.value,
+        outputs.data_hash,
         ma_tx_out.policy,
         ma_tx_out.name,
         ma_tx_out.quantity
-    FROM tx_out
-    JOIN tx ON tx_out.tx_id = tx.id
-    LEFT JOIN ma_tx_out ON tx_out.id = ma_tx_out.tx_out_id
-    LEFT JOIN tx_in ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
-	WHERE address = $1
-	AND tx_in.id IS NULL
+    FROM outputs
+    LEFT JOIN ma_tx_out ON outputs.id = ma_tx_out.tx_out_id
+    ORDER BY outputs.id
     "#,
     )
     .bind(addr.to_bech32(None)?)
+    .bind(after.unwrap_or(0))
+    .bind(limit)
     .fetch(pool);
 
-    let mut pgs = vec![];
+    // Rows of one output are adjacent, so each output is complete as soon as
+    // a row of the next one arrives
+    let mut utxos = vec![];
+    let mut current: Vec<PgTxOut> = vec![];
     while let Some(pg_tx_out) = rows.try_next().await? {
-        pgs.push(pg_tx_out);
+        if current
+            .first()
+            .map(|pg| pg.id != pg_tx_out.id)
+            .unwrap_or(false)
+        {
+            utxos.push(pgtxout_to_utxo(&current, addr)?);
+            current.clear();
+        }
+        current.push(pg_tx_out);
+    }
+    let last_id = current.first().map(|pg| pg.id);
+    if !current.is_empty() {
+        utxos.push(pgtxout_to_utxo(&current, addr)?);
     }
 
-    pgtxout_to_utxo(pgs, addr)
+    let next = if utxos.len() as i64 == limit {
+        last_id
+    } else {
+        None
+    };
+    Ok((utxos, next))
 }
 
-fn pgtxout_to_utxo(
-    pgs: Vec<PgTxOut>,
-    addr: &Address,
-) -> crate::Result<Vec<TransactionUnspentOutput>> {
-    let mut multiassets_map = HashMap::new();
+/// Whether the output referenced by `input` exists and has not been spent.
+pub async fn query_utxo_unspent(pool: &PgPool, input: &TransactionInput) -> crate::Result<bool> {
+    let res = sqlx::query(
+        r#"
+    SELECT 1
+    FROM tx_out
+    JOIN tx ON tx_out.tx_id = tx.id
+    LEFT JOIN tx_in ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
+    WHERE tx.hash = $1
+    AND tx_out.index = $2
+    AND tx_in.id IS NULL
+    "#,
+    )
+    .bind(input.transaction_id().to_bytes())
+    .bind(input.index() as i16)
+    .fetch_optional(pool)
+    .await?;
+    Ok(res.is_some())
+}
 
-    for pg in &pgs {
-        let multiasset = multiassets_map
-            .entry((&pg.hash, &pg.index, &pg.value, &pg.data_hash))
-            .or_insert_with(|| MultiAsset::new());
+/// Builds a single unspent output from its rows, one per native asset held
+fn pgtxout_to_utxo(pgs: &[PgTxOut], addr: &Address) -> crate::Result<TransactionUnspentOutput> {
+    let first = &pgs[0];
+    let mut multiasset = MultiAsset::new();
 
+    for pg in pgs {
         if let (Some(policy), Some(name), Some(bd_quantity)) = (&pg.policy, &pg.name, &pg.quantity)
         {
             if let Some(number) = bd_quantity.to_u64() {
@@ -82,27 +211,21 @@ fn pgtxout_to_utxo(
         }
     }
 
-    let mut utxos = Vec::with_capacity(multiassets_map.len());
-
-    for ((hash, index, lovelace, data_hash), multiasset) in multiassets_map {
-        let tx_hash = TransactionHash::from_bytes(hash.clone())?;
-        let tx_input = TransactionInput::new(&tx_hash, *index as u32);
-        let mut value = Value::new(&to_bignum(lovelace.to_u64().unwrap_or(0)));
+    let tx_hash = TransactionHash::from_bytes(first.hash.clone())?;
+    let tx_input = TransactionInput::new(&tx_hash, first.index as u32);
+    let mut value = Value::new(&to_bignum(first.value.to_u64().unwrap_or(0)));
 
-        if multiasset.len() > 0 {
-            value.set_multiasset(&multiasset);
-        }
-
-        let mut tx_output = TransactionOutput::new(addr, &value);
-        if let Some(data_hash) = data_hash {
-            let data_hash = DataHash::from_bytes(data_hash.clone())?;
-            tx_output.set_data_hash(&data_hash);
-        }
+    if multiasset.len() > 0 {
+        value.set_multiasset(&multiasset);
+    }
 
-        utxos.push(TransactionUnspentOutput::new(&tx_input, &tx_output));
+    let mut tx_output = TransactionOutput::new(addr, &value);
+    if let Some(data_hash) = &first.data_hash {
+        let data_hash = DataHash::from_bytes(data_hash.clone())?;
+        tx_output.set_data_hash(&data_hash);
     }
 
-    Ok(utxos)
+    Ok(TransactionUnspentOutput::new(&tx_input, &tx_output))
 }
 
 #[derive(Serialize)]
@@ -0,0 +1,219 @@
+// Chain data read by the transaction builders, behind a trait so they can be
+// exercised without db-sync
+
+use crate::cardano_db_sync::{
+    get_protocol_params, get_slot_number, query_user_address_utxo, query_utxo_unspent,
+    ProtocolParams,
+};
+use crate::marketplace::holder::{query_listing, SellMetadata};
+use crate::Result;
+use async_trait::async_trait;
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::utils::TransactionUnspentOutput;
+use cardano_serialization_lib::{AssetName, PolicyID, TransactionInput};
+use sqlx::PgPool;
+
+#[async_trait]
+pub trait ChainQuery: Send + Sync {
+    async fn address_utxos(&self, address: &Address) -> Result<Vec<TransactionUnspentOutput>>;
+
+    async fn slot_number(&self) -> Result<u32>;
+
+    async fn protocol_params(&self) -> Result<ProtocolParams>;
+
+    /// Whether the output referenced by `input` exists and has not been spent
+    async fn utxo_unspent(&self, input: &TransactionInput) -> Result<bool>;
+
+    /// Sale metadata of an NFT held for sale at `holder`
+    async fn listing(
+        &self,
+        holder: &Address,
+        policy_id: &PolicyID,
+        asset_name: &AssetName,
+    ) -> Result<Option<SellMetadata>>;
+}
+
+#[async_trait]
+impl ChainQuery for PgPool {
+    async fn address_utxos(&self, address: &Address) -> Result<Vec<TransactionUnspentOutput>> {
+        query_user_address_utxo(self, address).await
+    }
+
+    async fn slot_number(&self) -> Result<u32> {
+        Ok(get_slot_number(self).await?)
+    }
+
+    async fn protocol_params(&self) -> Result<ProtocolParams> {
+        Ok(get_protocol_params(self).await?)
+    }
+
+    async fn utxo_unspent(&self, input: &TransactionInput) -> Result<bool> {
+        query_utxo_unspent(self, input).await
+    }
+
+    async fn listing(
+        &self,
+        holder: &Address,
+        policy_id: &PolicyID,
+        asset_name: &AssetName,
+    ) -> Result<Option<SellMetadata>> {
+        query_listing(self, holder, policy_id, asset_name).await
+    }
+}
+
+/// Chain data for building transactions in tests without a database
+#[cfg(test)]
+pub mod memory {
+    use super::*;
+    use crate::cardano_db_sync::Era;
+    use cardano_serialization_lib::address::{EnterpriseAddress, StakeCredential};
+    use cardano_serialization_lib::crypto::{PrivateKey, TransactionHash};
+    use cardano_serialization_lib::fees::LinearFee;
+    use cardano_serialization_lib::plutus::{Costmdls, ExUnits};
+    use cardano_serialization_lib::utils::{to_bignum, Value};
+    use cardano_serialization_lib::{TransactionOutput, UnitInterval};
+    use sqlx::postgres::PgPoolOptions;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    /// Chain state kept in memory
+    pub struct MemoryChain {
+        pub slot: u32,
+        pub params: ProtocolParams,
+        utxos: HashMap<Vec<u8>, Vec<TransactionUnspentOutput>>,
+        listings: HashMap<(Vec<u8>, Vec<u8>, Vec<u8>), SellMetadata>,
+        tx_count: u8,
+    }
+
+    impl MemoryChain {
+        pub fn new(slot: u32, params: ProtocolParams) -> Self {
+            Self {
+                slot,
+                params,
+                utxos: HashMap::new(),
+                listings: HashMap::new(),
+                tx_count: 0,
+            }
+        }
+
+        /// At `slot` under the Mary parameters of mainnet
+        pub fn mary(slot: u32) -> Self {
+            Self::new(
+                slot,
+                ProtocolParams {
+                    era: Era::Mary,
+                    linear_fee: LinearFee::new(&to_bignum(44), &to_bignum(155_381)),
+                    minimum_utxo_value: to_bignum(1_000_000),
+                    pool_deposit: to_bignum(500_000_000),
+                    key_deposit: to_bignum(2_000_000),
+                    max_tx_size: 16384,
+                    max_value_size: 5000,
+                    coins_per_utxo_word: to_bignum(34482),
+                    collateral_percent: 150,
+                    max_collateral_inputs: 3,
+                    price_mem: UnitInterval::new(&to_bignum(0), &to_bignum(1)),
+                    price_step: UnitInterval::new(&to_bignum(0), &to_bignum(1)),
+                    max_tx_ex_units: ExUnits::new(&to_bignum(0), &to_bignum(0)),
+                    cost_models: Costmdls::new(),
+                },
+            )
+        }
+
+        /// Pays `value` to `address` in a transaction of its own
+        pub fn pay(&mut self, address: &Address, value: &Value) -> TransactionUnspentOutput {
+            self.tx_count += 1;
+            let utxo = TransactionUnspentOutput::new(
+                &TransactionInput::new(
+                    &TransactionHash::from_bytes(vec![self.tx_count; 32]).unwrap(),
+                    0,
+                ),
+                &TransactionOutput::new(address, value),
+            );
+            self.add_utxo(utxo.clone());
+            utxo
+        }
+
+        pub fn add_utxo(&mut self, utxo: TransactionUnspentOutput) {
+            self.utxos
+                .entry(utxo.output().address().to_bytes())
+                .or_default()
+                .push(utxo);
+        }
+
+        pub fn add_listing(
+            &mut self,
+            holder: &Address,
+            policy_id: &PolicyID,
+            asset_name: &AssetName,
+            sell_metadata: SellMetadata,
+        ) {
+            self.listings.insert(
+                (
+                    holder.to_bytes(),
+                    policy_id.to_bytes(),
+                    asset_name.to_bytes(),
+                ),
+                sell_metadata,
+            );
+        }
+    }
+
+    #[async_trait]
+    impl ChainQuery for MemoryChain {
+        async fn address_utxos(&self, address: &Address) -> Result<Vec<TransactionUnspentOutput>> {
+            Ok(self
+                .utxos
+                .get(&address.to_bytes())
+                .cloned()
+                .unwrap_or_default())
+        }
+
+        async fn slot_number(&self) -> Result<u32> {
+            Ok(self.slot)
+        }
+
+        async fn protocol_params(&self) -> Result<ProtocolParams> {
+            Ok(self.params.clone())
+        }
+
+        async fn utxo_unspent(&self, input: &TransactionInput) -> Result<bool> {
+            let input = input.to_bytes();
+            Ok(self
+                .utxos
+                .values()
+                .flatten()
+                .any(|utxo| utxo.input().to_bytes() == input))
+        }
+
+        async fn listing(
+            &self,
+            holder: &Address,
+            policy_id: &PolicyID,
+            asset_name: &AssetName,
+        ) -> Result<Option<SellMetadata>> {
+            Ok(self
+                .listings
+                .get(&(
+                    holder.to_bytes(),
+                    policy_id.to_bytes(),
+                    asset_name.to_bytes(),
+                ))
+                .cloned())
+        }
+    }
+
+    /// Never connects, for code that only audits into the database or
+    /// fails before reaching it
+    pub fn unreachable_pool() -> PgPool {
+        PgPoolOptions::new()
+            .connect_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://127.0.0.1:1/none")
+            .unwrap()
+    }
+
+    /// A fresh enterprise address on testnet
+    pub fn new_address() -> Address {
+        let key_hash = PrivateKey::generate_ed25519().unwrap().to_public().hash();
+        EnterpriseAddress::new(0, &StakeCredential::from_keyhash(&key_hash)).to_address()
+    }
+}
@@ -0,0 +1,79 @@
+// Updates of CIP-68 reference token datums
+//
+// The (100) reference token of an asset sits in an output with an inline
+// datum. Inline datums only exist from the Babbage era onwards, which the
+// serialization library used here cannot build yet. Updates are answered
+// with the parts of the transaction instead, for cardano-cli to build: the
+// reference UTxO to spend, its current datum, the new datum and where the
+// locking script is deployed.
+
+use crate::cardano_db_sync::{
+    query_asset_address, query_script_address_utxo, script_hash, ScriptUtxo,
+};
+use crate::reference_script::{get_reference_scripts, ReferenceScript};
+use crate::{Error, Result};
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::plutus::PlutusData;
+use cardano_serialization_lib::{AssetName, PolicyID};
+use sqlx::PgPool;
+
+/// Asset name prefix of reference tokens, label 100 in CIP-67 encoding
+const REFERENCE_TOKEN_PREFIX: [u8; 4] = [0x00, 0x06, 0x43, 0xb0];
+
+pub struct DatumUpdate {
+    pub reference_utxo: ScriptUtxo,
+    pub datum: PlutusData,
+    /// Registered deployment of the script locking the reference UTxO
+    pub reference_script: Option<ReferenceScript>,
+}
+
+/// Looks up the reference UTxO of the asset named `name`, without its label,
+/// to carry `datum` from now on
+pub async fn plan_datum_update(
+    pool: &PgPool,
+    policy_id: &PolicyID,
+    name: &str,
+    datum: PlutusData,
+) -> Result<DatumUpdate> {
+    let mut reference_name = REFERENCE_TOKEN_PREFIX.to_vec();
+    reference_name.extend(name.as_bytes());
+    let reference_name = AssetName::new(reference_name)?;
+    let hex_policy = hex::encode(policy_id.to_bytes());
+
+    let address = query_asset_address(pool, &hex_policy, &reference_name.name())
+        .await?
+        .ok_or_else(|| Error::NotFound("No reference token for this asset".to_string()))?;
+    let address = Address::from_bech32(&address)?;
+    let script_hash = script_hash(&address).ok_or_else(|| {
+        Error::Message(
+            "The reference token is not locked by a script, its holder can update it directly"
+                .to_string(),
+        )
+    })?;
+
+    let reference_utxo = query_script_address_utxo(pool, &address)
+        .await?
+        .into_iter()
+        .find(|script_utxo| {
+            script_utxo
+                .utxo
+                .output()
+                .amount()
+                .multiasset()
+                .and_then(|ma| ma.get(policy_id))
+                .and_then(|assets| assets.get(&reference_name))
+                .is_some()
+        })
+        .ok_or_else(|| Error::NotFound("No reference token for this asset".to_string()))?;
+
+    let reference_script = get_reference_scripts(pool)
+        .await?
+        .into_iter()
+        .find(|script| script.script_hash == script_hash.to_bytes());
+
+    Ok(DatumUpdate {
+        reference_utxo,
+        datum,
+        reference_script,
+    })
+}
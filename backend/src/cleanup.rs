@@ -0,0 +1,55 @@
+// Periodically prunes server-side state that has gone stale
+
+use crate::audit::prune_audit_entries;
+use crate::cardano_db_sync::get_slot_number;
+use crate::drops::release_expired_reservations;
+use crate::jobs::prune_jobs;
+use crate::marketplace::pending::prune_pending_sales;
+use crate::{Error, Result};
+use chrono::Utc;
+use cron::Schedule;
+use sqlx::PgPool;
+use std::str::FromStr;
+
+pub fn parse_schedule(expression: &str) -> Result<Schedule> {
+    Schedule::from_str(expression)
+        .map_err(|e| Error::Message(format!("Invalid cleanup schedule: {}", e)))
+}
+
+pub fn spawn(
+    pool: PgPool,
+    schedule: Schedule,
+    job_retention_days: u32,
+    audit_retention_days: Option<u32>,
+) {
+    actix_web::rt::spawn(async move {
+        for next in schedule.upcoming(Utc) {
+            if let Ok(wait) = (next - Utc::now()).to_std() {
+                tokio::time::sleep(wait).await;
+            }
+            if let Err(e) = cleanup(&pool, job_retention_days, audit_retention_days).await {
+                println!("Cleanup failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn cleanup(
+    pool: &PgPool,
+    job_retention_days: u32,
+    audit_retention_days: Option<u32>,
+) -> Result<()> {
+    let slot = get_slot_number(pool).await?;
+    let reservations = release_expired_reservations(pool, slot).await?;
+    let pending_sales = prune_pending_sales(pool, slot).await?;
+    let jobs = prune_jobs(pool, job_retention_days).await?;
+    let audit_entries = match audit_retention_days {
+        Some(days) => prune_audit_entries(pool, days).await?,
+        None => 0,
+    };
+    println!(
+        "Cleanup released {} expired reservations, pruned {} pending sales, {} jobs and {} audit entries",
+        reservations, pending_sales, jobs, audit_entries
+    );
+    Ok(())
+}
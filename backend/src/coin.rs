@@ -1,42 +1,72 @@
 use cardano_serialization_lib::{
+    address::Address,
     error::JsError,
     utils::{BigNum, Coin},
-    Mint, NativeScripts, Transaction, TransactionBody, TransactionOutput, TransactionWitnessSet,
+    Mint, NativeScripts, Transaction, TransactionBody, TransactionInputs, TransactionOutput,
+    TransactionWitnessSet, UnitInterval,
 };
 
-use crate::cardano_db_sync::ProtocolParams;
+use crate::cardano_db_sync::{Era, ProtocolParams};
+use crate::config::Config;
 use crate::Result;
 use cardano_serialization_lib::crypto::{
-    BootstrapWitnesses, PrivateKey, TransactionHash, Vkeywitnesses,
+    BootstrapWitnesses, PrivateKey, TransactionHash, Vkeywitness, Vkeywitnesses,
 };
 use cardano_serialization_lib::fees::min_fee;
 use cardano_serialization_lib::metadata::AuxiliaryData;
 use cardano_serialization_lib::plutus::{PlutusList, PlutusScripts, Redeemers};
 use cardano_serialization_lib::tx_builder::TransactionBuilder;
 use cardano_serialization_lib::utils::{
-    hash_transaction, make_vkey_witness, min_ada_required, TransactionUnspentOutput, Value,
+    from_bignum, hash_script_data, hash_transaction, make_vkey_witness, min_ada_required,
+    to_bignum, TransactionUnspentOutput, Value,
 };
+use rand::seq::SliceRandom;
 
 lazy_static! {
     static ref PRIVATE_KEY: PrivateKey = PrivateKey::generate_ed25519().unwrap();
 }
 
-const MAX_TRIES: usize = 10;
+/// Fee passes before giving up, each paying the fee the previous one needed
+const MAX_TRIES: usize = 5;
+
+/// Bytes a key-locked input adds to a transaction, its hash and index
+const INPUT_BYTES: u64 = 38;
 
 #[derive(Debug, thiserror::Error)]
 pub enum CoinSelectionFailure {
-    #[error("Total value of initial UTxO set is less than total value of requested output")]
-    BalanceInsufficient,
+    #[error(
+        "Total value of initial UTxO set is less than total value of requested output, short by {} lovelace",
+        shortfall
+    )]
+    BalanceInsufficient { shortfall: u64 },
 
     #[error("Number of entries in initial UTxO set is smaller than number of entries in requested output set")]
     NotFragmentedEnough,
 
+    /// Outputs and fee are covered, but what is left cannot form a change
+    /// output and there is nothing more to add to it
+    #[error(
+        "Change would be below the minimum UTxO value, {} more lovelace are needed",
+        shortfall
+    )]
+    ChangeBelowMinimum { shortfall: u64 },
+
     #[error("Number of entries are depleted before ideal selection can be made")]
     FullyDepleted,
 
     #[error("Maximum input count limit exceeded")]
     MaximumInputCountExceeded,
 
+    /// Not enough ADA-only UTxOs to put up as collateral for a script
+    #[error(
+        "Not enough ADA-only UTxOs for collateral, {} more lovelace are needed",
+        shortfall
+    )]
+    InsufficientCollateral { shortfall: u64 },
+
+    #[error("Transaction fee did not settle after {} attempts", MAX_TRIES)]
+    FeeEstimationDidNotConverge,
+
     #[error("{}", 0)]
     Other(String),
 }
@@ -53,6 +83,8 @@ pub struct TransactionWitnessSetParams<'a> {
     pub bootstraps: Option<&'a BootstrapWitnesses>,
     pub plutus_scripts: Option<&'a PlutusScripts>,
     pub plutus_data: Option<&'a PlutusList>,
+    /// Scripts are not evaluated here, redeemers come with their execution
+    /// units already set. Any redeemer makes the transaction put up collateral.
     pub redeemers: Option<&'a Redeemers>,
 }
 
@@ -108,8 +140,30 @@ fn create_dummy_tx_witness_set(
     tx_witness_set
 }
 
-pub fn calculate_maximum_fees(protocol_params: &ProtocolParams) -> Coin {
-    protocol_params.linear_fee.coefficient()
+/// How a deployment builds its transactions
+#[derive(Debug, Clone)]
+pub struct BuildSettings {
+    /// Lovelace paid on top of the minimum fee of every transaction, as a
+    /// margin against the node computing a slightly larger size
+    pub fee_padding: u64,
+    /// Most inputs coin selection may spend
+    pub max_input_count: usize,
+    /// Written under "Minted At" into the metadata of mints that do not
+    /// bring their own
+    pub mint_attribution: Option<String>,
+    /// Labels buys, cancels and mints with their operation, see `purpose`
+    pub tag_purpose: bool,
+}
+
+impl BuildSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            fee_padding: config.fee_padding_lovelace,
+            max_input_count: config.max_input_count,
+            mint_attribution: Some(config.mint_attribution.clone()).filter(|a| !a.is_empty()),
+            tag_purpose: config.tag_tx_purpose,
+        }
+    }
 }
 
 pub fn build_transaction_body(
@@ -122,17 +176,31 @@ pub fn build_transaction_body(
     mint: Option<Mint>,
     witness_params: &TransactionWitnessSetParams,
     auxiliary_data: Option<AuxiliaryData>,
+    selection: &dyn CoinSelection,
+    change_address: Option<&Address>,
+    settings: &BuildSettings,
 ) -> Result<TransactionBody> {
-    let mut fees = fees.unwrap_or_else(|| calculate_maximum_fees(protocol_params));
+    if witness_params.redeemers.is_some() && protocol_params.era < Era::Alonzo {
+        return Err(crate::Error::Message(
+            "Scripts cannot run before the Alonzo era".to_string(),
+        ));
+    }
+    let padding = to_bignum(settings.fee_padding);
+    // Without a starting fee, the first pass only measures the size of the
+    // transaction for the fee estimate
+    let mut fees = fees.unwrap_or_else(BigNum::zero);
 
     for _ in 0..MAX_TRIES {
-        let mut tx_builder = largest_first_coin_selection(
+        let mut tx_builder = select_coins(
+            selection,
             outputs.clone(),
             inputs.clone(),
             utxos.clone(),
             fees,
             protocol_params,
             ttl,
+            change_address,
+            settings,
         )?;
 
         if let Some(aux_data) = &auxiliary_data {
@@ -145,31 +213,247 @@ pub fn build_transaction_body(
             tx_body.set_mint(m);
         }
 
+        if let Some(redeemers) = witness_params.redeemers {
+            tx_body.set_collateral(&select_collateral(&utxos, &fees, protocol_params)?);
+            tx_body.set_script_data_hash(&hash_script_data(
+                redeemers,
+                &protocol_params.cost_models,
+                witness_params.plutus_data.cloned(),
+            ));
+        }
+
         let witness_set = create_dummy_tx_witness_set(witness_params, &hash_transaction(&tx_body));
         let tx = Transaction::new(&tx_body, &witness_set, auxiliary_data.clone());
 
-        let calculated_fees = min_fee(&tx, &protocol_params.linear_fee)?;
+        let mut required_fees = min_fee(&tx, &protocol_params.linear_fee)?.checked_add(&padding)?;
+        if let Some(redeemers) = witness_params.redeemers {
+            required_fees = required_fees.checked_add(&script_fee(redeemers, protocol_params)?)?;
+        }
 
-        if calculated_fees.eq(&fees) {
+        // Paying a little more than required is fine, chasing the exact fee
+        // can flip between two sizes forever
+        if required_fees.le(&fees) {
+            // The dummy witnesses make the size that of the signed transaction
+            let size = tx.to_bytes().len();
+            if size > protocol_params.max_tx_size as usize {
+                return Err(crate::Error::TransactionTooLarge {
+                    size,
+                    max_size: protocol_params.max_tx_size,
+                    inputs: tx_body.inputs().len(),
+                    outputs: tx_body.outputs().len(),
+                });
+            }
             return Ok(tx_body);
         }
 
-        fees = calculated_fees
+        fees = required_fees
+    }
+
+    Err(CoinSelectionFailure::FeeEstimationDidNotConverge.into())
+}
+
+/// Price of the execution units of `redeemers`, rounded up
+fn script_fee(redeemers: &Redeemers, params: &ProtocolParams) -> Result<Coin> {
+    let (mut mem, mut steps) = (0u64, 0u64);
+    for i in 0..redeemers.len() {
+        let ex_units = redeemers.get(i).ex_units();
+        mem = mem.saturating_add(from_bignum(&ex_units.mem()));
+        steps = steps.saturating_add(from_bignum(&ex_units.steps()));
+    }
+    if mem > from_bignum(&params.max_tx_ex_units.mem())
+        || steps > from_bignum(&params.max_tx_ex_units.steps())
+    {
+        return Err(crate::Error::Message(format!(
+            "Scripts need {} memory and {} steps, more than a transaction may use",
+            mem, steps
+        )));
+    }
+    Ok(to_bignum(
+        price_units(mem, &params.price_mem) + price_units(steps, &params.price_step),
+    ))
+}
+
+fn price_units(units: u64, price: &UnitInterval) -> u64 {
+    let numerator = units as u128 * from_bignum(&price.numerator()) as u128;
+    let denominator = from_bignum(&price.denominator()).max(1) as u128;
+    ((numerator + denominator - 1) / denominator) as u64
+}
+
+/// Collateral for a transaction running scripts and paying `fees`. The era
+/// of the serialization library in use has neither collateral return nor
+/// total collateral, so whatever is put up is forfeited should a script fail.
+/// The smallest single ADA-only UTxO that covers it is preferred, otherwise
+/// the largest ones up to the protocol's input limit.
+fn select_collateral(
+    utxos: &[TransactionUnspentOutput],
+    fees: &Coin,
+    params: &ProtocolParams,
+) -> Result<TransactionInputs> {
+    let required = from_bignum(fees).saturating_mul(params.collateral_percent as u64) / 100;
+    let mut candidates: Vec<&TransactionUnspentOutput> = utxos
+        .iter()
+        .filter(|utxo| utxo.output().amount().multiasset().is_none())
+        .collect();
+    candidates.sort_by_key(|utxo| from_bignum(&utxo.output().amount().coin()));
+
+    let mut collateral = TransactionInputs::new();
+    if let Some(utxo) = candidates
+        .iter()
+        .find(|utxo| from_bignum(&utxo.output().amount().coin()) >= required)
+    {
+        collateral.add(&utxo.input());
+        return Ok(collateral);
+    }
+
+    let mut covered = 0;
+    for utxo in candidates
+        .iter()
+        .rev()
+        .take(params.max_collateral_inputs as usize)
+    {
+        covered += from_bignum(&utxo.output().amount().coin());
+        collateral.add(&utxo.input());
+        if covered >= required {
+            return Ok(collateral);
+        }
+    }
+    Err(CoinSelectionFailure::InsufficientCollateral {
+        shortfall: required - covered,
+    }
+    .into())
+}
+
+/// Picks which UTxOs pay for a transaction
+pub trait CoinSelection {
+    /// Splits `utxos` into those to spend, in order, towards `target`
+    /// lovelace, and the rest
+    fn select(
+        &self,
+        utxos: Vec<TransactionUnspentOutput>,
+        target: u64,
+        params: &ProtocolParams,
+    ) -> (Vec<TransactionUnspentOutput>, Vec<TransactionUnspentOutput>);
+}
+
+/// Spends the largest UTxOs first, keeping the number of inputs low
+pub struct LargestFirst;
+
+impl CoinSelection for LargestFirst {
+    fn select(
+        &self,
+        mut utxos: Vec<TransactionUnspentOutput>,
+        target: u64,
+        params: &ProtocolParams,
+    ) -> (Vec<TransactionUnspentOutput>, Vec<TransactionUnspentOutput>) {
+        utxos.sort_by_key(|utxo| std::cmp::Reverse(spendable_lovelace(utxo, params)));
+        let mut selected_amount = 0;
+        let mut selected = vec![];
+        let mut remaining = utxos.into_iter();
+        while selected_amount < target {
+            match remaining.next() {
+                Some(utxo) => {
+                    selected_amount += spendable_lovelace(&utxo, params);
+                    selected.push(utxo);
+                }
+                None => break,
+            }
+        }
+        (selected, remaining.collect())
     }
+}
 
-    Err(CoinSelectionFailure::BalanceInsufficient.into())
+/// Random-Improve from CIP-2. Random UTxOs cover the target, then more are
+/// added while that brings the total closer to twice the target, so change
+/// outputs come out similar in size to payments and the wallet does not
+/// fragment into dust.
+pub struct RandomImprove;
+
+impl CoinSelection for RandomImprove {
+    fn select(
+        &self,
+        mut utxos: Vec<TransactionUnspentOutput>,
+        target: u64,
+        params: &ProtocolParams,
+    ) -> (Vec<TransactionUnspentOutput>, Vec<TransactionUnspentOutput>) {
+        utxos.shuffle(&mut rand::thread_rng());
+        let mut selected_amount = 0;
+        let mut selected = vec![];
+        let mut remaining = vec![];
+        let mut utxos = utxos.into_iter();
+
+        while selected_amount < target {
+            match utxos.next() {
+                Some(utxo) => {
+                    selected_amount += spendable_lovelace(&utxo, params);
+                    selected.push(utxo);
+                }
+                None => break,
+            }
+        }
+
+        let ideal = target.saturating_mul(2);
+        let maximum = target.saturating_mul(3);
+        for utxo in utxos {
+            let amount = spendable_lovelace(&utxo, params);
+            let improved = selected_amount + amount;
+            if selected_amount >= target
+                && improved <= maximum
+                && distance(ideal, improved) < distance(ideal, selected_amount)
+            {
+                selected_amount = improved;
+                selected.push(utxo);
+            } else {
+                remaining.push(utxo);
+            }
+        }
+        (selected, remaining)
+    }
 }
 
-fn largest_first_coin_selection(
+fn distance(a: u64, b: u64) -> u64 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+/// Lovelace a UTxO contributes, UTxOs with assets keep their minimum ADA
+fn spendable_lovelace(utxo: &TransactionUnspentOutput, params: &ProtocolParams) -> u64 {
+    let amount = utxo.output().amount();
+    let coin = from_bignum(&amount.coin());
+    if amount.multiasset().is_some() {
+        coin.saturating_sub(from_bignum(&min_ada_required(
+            &amount,
+            &params.minimum_utxo_value,
+        )))
+    } else {
+        coin
+    }
+}
+
+/// Whether spending `utxo` costs more in fees than it brings in. Spending a
+/// UTxO with assets also pays for the output returning them.
+fn is_dust(utxo: &TransactionUnspentOutput, params: &ProtocolParams) -> bool {
+    let mut bytes = INPUT_BYTES;
+    if utxo.output().amount().multiasset().is_some() {
+        bytes += utxo.output().to_bytes().len() as u64;
+    }
+    let marginal_fee = from_bignum(&params.linear_fee.coefficient()).saturating_mul(bytes);
+    spendable_lovelace(utxo, params) <= marginal_fee
+}
+
+fn select_coins(
+    selection: &dyn CoinSelection,
     outputs: Vec<TransactionOutput>,
     inputs: Vec<TransactionUnspentOutput>,
-    mut utxos: Vec<TransactionUnspentOutput>,
+    utxos: Vec<TransactionUnspentOutput>,
     fees: Coin,
     params: &ProtocolParams,
     ttl: u32,
+    change_address: Option<&Address>,
+    settings: &BuildSettings,
 ) -> Result<TransactionBuilder> {
-    utxos.sort_by_key(|utxo| utxo.output().amount().coin());
-
     let (outputs, total_output_amount) =
         calculate_output_amount(outputs, fees, &params.minimum_utxo_value)?;
 
@@ -191,15 +475,39 @@ fn largest_first_coin_selection(
         selected_amount = selected_amount.checked_add(&utxo.output().amount().coin())?
     }
 
-    while let Some(utxo) = utxos.pop() {
+    let change_amount = min_ada_required(
+        &Value::new(&params.minimum_utxo_value),
+        &params.minimum_utxo_value,
+    );
+    let utxos: Vec<_> = utxos
+        .into_iter()
+        .filter(|utxo| !is_dust(utxo, params))
+        .collect();
+    if utxos.is_empty() {
+        // Change is only settled against the wallet's UTxOs
+        return Err(
+            unsettled(selected_amount, total_output_amount, change_amount)
+                .unwrap_or(CoinSelectionFailure::NotFragmentedEnough)
+                .into(),
+        );
+    }
+    let input_count = inputs.len();
+    let target = from_bignum(&total_output_amount).saturating_sub(from_bignum(&selected_amount));
+    let (selected, mut remaining) = selection.select(utxos, target, params);
+    let selected_count = selected.len();
+    // Should the selection fall short of change, the largest of the rest top it up
+    remaining.sort_by_key(|utxo| std::cmp::Reverse(from_bignum(&utxo.output().amount().coin())));
+
+    for (i, utxo) in selected.into_iter().chain(remaining).enumerate() {
         let amt = utxo.output().amount();
         if amt.multiasset().is_some() {
             // Has asset so we leave a minimum amount inside to preserve the assets
             let min_amount = min_ada_required(&amt, &params.minimum_utxo_value);
-            let extracted_amount = amt
-                .coin()
-                .checked_sub(&min_amount)
-                .map_err(|_| CoinSelectionFailure::BalanceInsufficient)?;
+            let extracted_amount = amt.coin().checked_sub(&min_amount).map_err(|_| {
+                CoinSelectionFailure::BalanceInsufficient {
+                    shortfall: from_bignum(&min_amount) - from_bignum(&amt.coin()),
+                }
+            })?;
 
             tx_builder.add_output(&set_output_lovelace(&utxo.output(), &min_amount))?;
             selected_amount = selected_amount.checked_add(&extracted_amount)?;
@@ -212,12 +520,16 @@ fn largest_first_coin_selection(
             &utxo.input(),
             &utxo.output().amount(),
         );
+        if input_count + i + 1 > settings.max_input_count {
+            return Err(CoinSelectionFailure::MaximumInputCountExceeded.into());
+        }
+
+        // Everything selected is spent before settling the change
+        if i + 1 < selected_count {
+            continue;
+        }
 
         if selected_amount.ge(&total_output_amount) {
-            let change_amount = min_ada_required(
-                &Value::new(&params.minimum_utxo_value),
-                &params.minimum_utxo_value,
-            );
             if selected_amount
                 .checked_sub(&total_output_amount)?
                 .lt(&change_amount)
@@ -225,13 +537,42 @@ fn largest_first_coin_selection(
                 continue;
             }
             let change_value = Value::new(&selected_amount.checked_sub(&total_output_amount)?);
-            let change_output = TransactionOutput::new(&utxo.output().address(), &change_value);
+            // Without a change address, change goes where the last UTxO came from
+            let change_output = TransactionOutput::new(
+                change_address.unwrap_or(&utxo.output().address()),
+                &change_value,
+            );
             tx_builder.add_output(&change_output)?;
             return Ok(tx_builder);
         }
     }
 
-    Err(CoinSelectionFailure::BalanceInsufficient.into())
+    Err(
+        unsettled(selected_amount, total_output_amount, change_amount)
+            .unwrap_or(CoinSelectionFailure::FullyDepleted)
+            .into(),
+    )
+}
+
+/// What `selected` lovelace lack to pay for `total` and a change output of
+/// `change`, none when they are enough
+fn unsettled(selected: Coin, total: Coin, change: Coin) -> Option<CoinSelectionFailure> {
+    let (selected, total, change) = (
+        from_bignum(&selected),
+        from_bignum(&total),
+        from_bignum(&change),
+    );
+    if selected < total {
+        Some(CoinSelectionFailure::BalanceInsufficient {
+            shortfall: total - selected,
+        })
+    } else if selected - total < change {
+        Some(CoinSelectionFailure::ChangeBelowMinimum {
+            shortfall: change - (selected - total),
+        })
+    } else {
+        None
+    }
 }
 
 pub fn start_transaction(params: &ProtocolParams, ttl: u32) -> TransactionBuilder {
@@ -248,6 +589,37 @@ pub fn start_transaction(params: &ProtocolParams, ttl: u32) -> TransactionBuilde
     tx_builder
 }
 
+/// UTxOs without native assets, which can be spent without having to send
+/// assets anywhere
+pub fn is_ada_only(utxo: &TransactionUnspentOutput) -> bool {
+    utxo.output()
+        .amount()
+        .multiasset()
+        .map_or(true, |ma| ma.len() == 0)
+}
+
+/// Spends all of `utxos` into a single output to `to`, less the fee. None
+/// when they cannot cover the fee and the minimum UTxO value.
+pub fn build_sweep(
+    utxos: &[TransactionUnspentOutput],
+    to: &Address,
+    params: &ProtocolParams,
+    ttl: u32,
+) -> Result<Option<TransactionBody>> {
+    let mut tx_builder = start_transaction(params, ttl);
+    for utxo in utxos {
+        tx_builder.add_input(
+            &utxo.output().address(),
+            &utxo.input(),
+            &utxo.output().amount(),
+        );
+    }
+    if !tx_builder.add_change_if_needed(to)? {
+        return Ok(None);
+    }
+    Ok(Some(tx_builder.build()?))
+}
+
 fn calculate_output_amount(
     outputs: Vec<TransactionOutput>,
     fees: Coin,
@@ -284,6 +656,28 @@ fn set_output_lovelace(output: &TransactionOutput, new_lovelace: &Coin) -> Trans
     new_output
 }
 
+/// Wallets hand back signatures in different shapes, a witness set, a whole
+/// signed transaction or a single vkey witness. All come out as a witness set.
+pub fn decode_witnesses(bytes: Vec<u8>) -> Result<TransactionWitnessSet> {
+    if let Ok(witness_set) = TransactionWitnessSet::from_bytes(bytes.clone()) {
+        return Ok(witness_set);
+    }
+    if let Ok(tx) = Transaction::from_bytes(bytes.clone()) {
+        return Ok(tx.witness_set());
+    }
+    let vkey = Vkeywitness::from_bytes(bytes).map_err(|_| {
+        crate::Error::Message(
+            "Signature is neither a witness set, a signed transaction nor a vkey witness"
+                .to_string(),
+        )
+    })?;
+    let mut vkeys = Vkeywitnesses::new();
+    vkeys.add(&vkey);
+    let mut witness_set = TransactionWitnessSet::new();
+    witness_set.set_vkeys(&vkeys);
+    Ok(witness_set)
+}
+
 pub fn combine_witness_set(
     tx: Transaction,
     witness_set: TransactionWitnessSet,
@@ -8,6 +8,22 @@ pub struct Config {
     #[envconfig(from = "SUBMIT_API_BASE_URL")]
     pub submit_api_base_url: String,
 
+    /// One of submit-api, blockfrost or ogmios
+    #[envconfig(from = "TX_SUBMITTER", default = "submit-api")]
+    pub tx_submitter: String,
+
+    #[envconfig(
+        from = "BLOCKFROST_URL",
+        default = "https://cardano-mainnet.blockfrost.io/api/v0"
+    )]
+    pub blockfrost_url: String,
+
+    #[envconfig(from = "BLOCKFROST_PROJECT_ID")]
+    pub blockfrost_project_id: Option<String>,
+
+    #[envconfig(from = "OGMIOS_URL")]
+    pub ogmios_url: Option<String>,
+
     #[envconfig(from = "PORT")]
     pub port: u32,
 
@@ -28,4 +44,259 @@ pub struct Config {
 
     #[envconfig(from = "PROJECTS_REVENUE_ADDRESS")]
     pub projects_revenue_address: String,
+
+    /// Flat fee on project sales, for projects without a fee of their own
+    #[envconfig(from = "PROJECTS_FEE_LOVELACE", default = "1500000")]
+    pub projects_fee_lovelace: u64,
+
+    /// JSON file of further projects keyed by slug, each with a
+    /// privateKeyFile, revenueAddress and optional feeLovelace
+    #[envconfig(from = "PROJECTS_FILE")]
+    pub projects_file: Option<String>,
+
+    /// JSON file of whitelabel marketplaces keyed by tenant id, each with a
+    /// privateKeyFile, revenueAddress, optional feeBps and minFeeLovelace,
+    /// the hosts it is served under, its corsOrigins and its adminToken
+    #[envconfig(from = "TENANTS_FILE")]
+    pub tenants_file: Option<String>,
+
+    #[envconfig(from = "GRPC_PORT")]
+    pub grpc_port: Option<u32>,
+
+    #[envconfig(from = "ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
+
+    #[envconfig(from = "DROP_WATCHER_INTERVAL_SECONDS", default = "30")]
+    pub drop_watcher_interval_seconds: u64,
+
+    #[envconfig(from = "JOB_WORKER_INTERVAL_SECONDS", default = "5")]
+    pub job_worker_interval_seconds: u64,
+
+    /// ADA-only UTxOs at the marketplace holder that trigger merging them into
+    /// one. Unset leaves them alone.
+    #[envconfig(from = "HOLDER_CONSOLIDATION_MIN_UTXOS")]
+    pub holder_consolidation_min_utxos: Option<usize>,
+
+    #[envconfig(from = "HOLDER_CONSOLIDATION_INTERVAL_SECONDS", default = "3600")]
+    pub holder_consolidation_interval_seconds: u64,
+
+    /// How often offers past their expiry are refunded to the bidders
+    #[envconfig(from = "OFFER_REFUND_INTERVAL_SECONDS", default = "300")]
+    pub offer_refund_interval_seconds: u64,
+
+    /// Spendable ADA below which the marketplace holder is reported low, in
+    /// lovelace. Unset leaves it unwatched.
+    #[envconfig(from = "MARKETPLACE_HOLDER_MIN_BALANCE_LOVELACE")]
+    pub marketplace_holder_min_balance_lovelace: Option<u64>,
+
+    #[envconfig(from = "PROJECTS_HOLDER_MIN_BALANCE_LOVELACE")]
+    pub projects_holder_min_balance_lovelace: Option<u64>,
+
+    /// Receives a JSON POST when a holder balance drops below or recovers
+    /// above its threshold
+    #[envconfig(from = "BALANCE_ALERT_WEBHOOK_URL")]
+    pub balance_alert_webhook_url: Option<String>,
+
+    /// How often spends from the holders are checked against the audit log.
+    /// Unset does not check.
+    #[envconfig(from = "HOLDER_WATCH_INTERVAL_SECONDS")]
+    pub holder_watch_interval_seconds: Option<u64>,
+
+    /// Receives a JSON POST for every holder spend missing from the audit log
+    #[envconfig(from = "SECURITY_ALERT_WEBHOOK_URL")]
+    pub security_alert_webhook_url: Option<String>,
+
+    /// Turn maintenance mode on as soon as such a spend is seen
+    #[envconfig(from = "HOLDER_WATCH_AUTO_MAINTENANCE", default = "false")]
+    pub holder_watch_auto_maintenance: bool,
+
+    #[envconfig(from = "BALANCE_CHECK_INTERVAL_MINUTES", default = "5")]
+    pub balance_check_interval_minutes: u64,
+
+    /// Receives the ADA swept from the revenue addresses. Unset disables the
+    /// sweep.
+    #[envconfig(from = "COLD_STORAGE_ADDRESS")]
+    pub cold_storage_address: Option<String>,
+
+    /// Signing key of MARKETPLACE_REVENUE_ADDRESS, needed to sweep it
+    #[envconfig(from = "MARKETPLACE_REVENUE_KEY_FILE")]
+    pub marketplace_revenue_key_file: Option<String>,
+
+    /// Signing key of PROJECTS_REVENUE_ADDRESS, needed to sweep it
+    #[envconfig(from = "PROJECTS_REVENUE_KEY_FILE")]
+    pub projects_revenue_key_file: Option<String>,
+
+    /// Revenue addresses holding less are not swept
+    #[envconfig(from = "REVENUE_SWEEP_THRESHOLD_LOVELACE", default = "100000000")]
+    pub revenue_sweep_threshold_lovelace: u64,
+
+    /// Cron expression, with a leading seconds field
+    #[envconfig(from = "REVENUE_SWEEP_SCHEDULE", default = "0 0 3 * * *")]
+    pub revenue_sweep_schedule: String,
+
+    /// Start read-only, as during a db-sync resync. Can be switched at runtime
+    /// through /admin/maintenance.
+    #[envconfig(from = "MAINTENANCE_MODE", default = "false")]
+    pub maintenance_mode: bool,
+
+    /// Comma separated features off until switched on through
+    /// /admin/features: auctions, offers, projects, minting. Auctions are
+    /// off by default, set it empty to turn everything on.
+    #[envconfig(from = "DISABLED_FEATURES", default = "auctions")]
+    pub disabled_features: String,
+
+    /// Transactions built at the same time by the buy, sell and mint routes,
+    /// requests past it are answered with 429
+    #[envconfig(from = "MAX_CONCURRENT_TX_BUILDS", default = "8")]
+    pub max_concurrent_tx_builds: usize,
+
+    /// Seconds clients turned away by MAX_CONCURRENT_TX_BUILDS are told to
+    /// wait
+    #[envconfig(from = "TX_BUILD_RETRY_AFTER_SECONDS", default = "2")]
+    pub tx_build_retry_after_seconds: u64,
+
+    /// Largest JSON request body accepted, in bytes. Big enough for signed
+    /// transactions, which are sent hex encoded.
+    #[envconfig(from = "MAX_JSON_PAYLOAD_BYTES", default = "65536")]
+    pub max_json_payload_bytes: usize,
+
+    /// Largest JSON request body accepted by the minting routes, in bytes
+    #[envconfig(from = "MAX_MINT_PAYLOAD_BYTES", default = "16384")]
+    pub max_mint_payload_bytes: usize,
+
+    /// Comma separated sources of the ADA rates listing prices are shown in
+    /// USD and EUR with, tried in order: coingecko, kraken. Unset shows
+    /// lovelace only.
+    #[envconfig(from = "PRICE_ORACLE_SOURCES")]
+    pub price_oracle_sources: Option<String>,
+
+    #[envconfig(from = "PRICE_ORACLE_INTERVAL_SECONDS", default = "300")]
+    pub price_oracle_interval_seconds: u64,
+
+    /// Rates older than this are flagged stale next to the prices
+    #[envconfig(from = "PRICE_ORACLE_MAX_AGE_SECONDS", default = "1800")]
+    pub price_oracle_max_age_seconds: u64,
+
+    /// Written under "Minted At" into the metadata of every mint, unless the
+    /// request brings its own. Empty leaves it out.
+    #[envconfig(from = "MINT_ATTRIBUTION", default = "© 2021 WottleNFT")]
+    pub mint_attribution: String,
+
+    /// Label buys, cancels and mints with their operation under metadata
+    /// label 889, so analytics need not guess from the outputs
+    #[envconfig(from = "TAG_TX_PURPOSE", default = "false")]
+    pub tag_tx_purpose: bool,
+
+    /// Password the policy keys of managed collections and drops, and the
+    /// payment keys of drops, are sealed with. Unset refuses to keep any.
+    /// Keys stored in plaintext before are sealed at startup.
+    #[envconfig(from = "POLICY_KEYSTORE_SECRET")]
+    pub policy_keystore_secret: Option<String>,
+
+    /// First pages of GET /marketplace kept in memory, for all listings and
+    /// for each popular policy. 0 queries db-sync on every request.
+    #[envconfig(from = "LISTING_CACHE_PAGES", default = "5")]
+    pub listing_cache_pages: u32,
+
+    /// Most requested policy filters whose first pages are kept too
+    #[envconfig(from = "LISTING_CACHE_POPULAR_POLICIES", default = "10")]
+    pub listing_cache_popular_policies: usize,
+
+    /// How often the kept pages are refreshed, new listings show up on them
+    /// this much later
+    #[envconfig(from = "LISTING_CACHE_INTERVAL_SECONDS", default = "5")]
+    pub listing_cache_interval_seconds: u64,
+
+    /// How often db-sync is polled for the collection WebSocket channels
+    #[envconfig(from = "TICKER_INTERVAL_SECONDS", default = "10")]
+    pub ticker_interval_seconds: u64,
+
+    /// Cron expression, with a leading seconds field
+    #[envconfig(from = "CLEANUP_SCHEDULE", default = "0 0 * * * *")]
+    pub cleanup_schedule: String,
+
+    #[envconfig(from = "JOB_RETENTION_DAYS", default = "7")]
+    pub job_retention_days: u32,
+
+    /// Audit log entries are kept forever unless set
+    #[envconfig(from = "AUDIT_RETENTION_DAYS")]
+    pub audit_retention_days: Option<u32>,
+
+    /// Marketplace commission in basis points of the sale price
+    #[envconfig(from = "MARKETPLACE_FEE_BPS", default = "200")]
+    pub marketplace_fee_bps: u64,
+
+    /// Lovelace added to the minimum network fee of built transactions
+    #[envconfig(from = "FEE_PADDING_LOVELACE", default = "0")]
+    pub fee_padding_lovelace: u64,
+
+    /// Most inputs a built transaction may spend
+    #[envconfig(from = "MAX_INPUT_COUNT", default = "200")]
+    pub max_input_count: usize,
+
+    /// Least fee charged on a sale, waived for buyers with a holder discount
+    #[envconfig(from = "MARKETPLACE_MIN_FEE_LOVELACE", default = "1000000")]
+    pub marketplace_min_fee_lovelace: u64,
+
+    /// Purchases at or above this price are only signed by the holder once
+    /// an admin approves them. Unset signs every purchase right away.
+    #[envconfig(from = "SETTLEMENT_APPROVAL_THRESHOLD_LOVELACE")]
+    pub settlement_approval_threshold_lovelace: Option<u64>,
+
+    /// Lovelace escrowed with a listed NFT, raised to the protocol minimum
+    /// when lower. Defaults to that minimum.
+    #[envconfig(from = "LISTING_DEPOSIT_LOVELACE")]
+    pub listing_deposit_lovelace: Option<u64>,
+
+    /// Attach the hash of the sale terms as a datum to escrowed listings
+    #[envconfig(from = "ESCROW_DATUM_HASH", default = "false")]
+    pub escrow_datum_hash: bool,
+
+    #[envconfig(from = "COUPON_FEE_FLOOR_BPS", default = "50")]
+    pub coupon_fee_floor_bps: u64,
+
+    #[envconfig(from = "CACHE_TTL_MARKETPLACE_SECONDS", default = "10")]
+    pub cache_ttl_marketplace_seconds: u64,
+
+    /// Comma separated hex ids of well known policies, whose asset names new
+    /// mints are checked against
+    #[envconfig(from = "WATCHED_POLICIES")]
+    pub watched_policies: Option<String>,
+
+    /// Refuse to mint duplicate asset names instead of only warning
+    #[envconfig(from = "BLOCK_DUPLICATE_ASSET_NAMES", default = "false")]
+    pub block_duplicate_asset_names: bool,
+
+    /// Longest a new policy may stay open for minting, in seconds
+    #[envconfig(from = "MAX_POLICY_LOCK_SECONDS", default = "31536000")]
+    pub max_policy_lock_seconds: u32,
+
+    /// Fetch the image of new NFTs and warn when it is missing, not an image
+    /// or too large
+    #[envconfig(from = "CHECK_NFT_IMAGES", default = "false")]
+    pub check_nft_images: bool,
+
+    /// Refuse to mint NFTs failing the image check instead of only warning
+    #[envconfig(from = "BLOCK_INVALID_NFT_IMAGES", default = "false")]
+    pub block_invalid_nft_images: bool,
+
+    #[envconfig(from = "IPFS_GATEWAY_URL", default = "https://ipfs.io")]
+    pub ipfs_gateway_url: String,
+
+    #[envconfig(from = "MAX_NFT_IMAGE_BYTES", default = "10485760")]
+    pub max_nft_image_bytes: u64,
+
+    #[envconfig(from = "CACHE_TTL_NFT_SECONDS", default = "300")]
+    pub cache_ttl_nft_seconds: u64,
+
+    #[envconfig(from = "CACHE_TTL_PROJECTS_SECONDS", default = "30")]
+    pub cache_ttl_projects_seconds: u64,
+
+    /// Routes with a slower 95th percentile are flagged on /admin/slo
+    #[envconfig(from = "SLO_P95_LATENCY_MS", default = "1000")]
+    pub slo_p95_latency_ms: u64,
+
+    /// Share of 5xx responses above which a route is flagged on /admin/slo
+    #[envconfig(from = "SLO_ERROR_RATE", default = "0.01")]
+    pub slo_error_rate: f64,
 }
@@ -0,0 +1,463 @@
+// Scheduled drops minted under a policy the backend keeps for the whole drop
+
+use crate::cardano_db_sync::{
+    get_protocol_params, get_slot_number, query_user_address_utxo, ProtocolParams,
+};
+use crate::coin::BuildSettings;
+use crate::drops::names::claim_asset_name;
+use crate::nft::{NftPolicy, NftTransactionBuilder, WottleNftMetadata};
+use crate::policies::Keystore;
+use crate::{Error, Result};
+use cardano_serialization_lib::address::{Address, EnterpriseAddress, StakeCredential};
+use cardano_serialization_lib::crypto::PrivateKey;
+use cardano_serialization_lib::utils::{to_bignum, TransactionUnspentOutput, Value};
+use cardano_serialization_lib::{Transaction, TransactionOutput};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+pub mod names;
+pub mod watcher;
+
+/// How long an item stays reserved for a minter, matches the TTL of the
+/// transaction handed out for it.
+const RESERVATION_SLOTS: u32 = 3600;
+
+/// An item counts against the supply while its reservation is live, or for
+/// good once db-sync has seen it minted. Expects the drop id as `$1` and the
+/// current slot as `$2`. Items never reserved have no `reserved_until`.
+const ITEM_TAKEN: &str = r#"(
+    COALESCE(drop_item.reserved_until, -1) >= $2
+    OR EXISTS (
+        SELECT 1 FROM ma_tx_mint
+        WHERE ma_tx_mint.policy = nft_drop.policy_id
+        AND ma_tx_mint.name = convert_to(drop_item.asset_name, 'UTF8')
+        AND ma_tx_mint.quantity > 0
+    )
+)"#;
+
+#[derive(Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct NftDrop {
+    pub id: i32,
+    pub name: String,
+    pub price: i64,
+    pub supply: i32,
+    pub start_slot: i64,
+    pub per_address_limit: Option<i32>,
+    pub payment_address: String,
+    #[serde(serialize_with = "hex::serialize")]
+    pub policy_id: Vec<u8>,
+    #[serde(skip)]
+    policy_skey: Vec<u8>,
+    #[serde(skip)]
+    policy_skey_sealed: bool,
+    pub policy_lock_slot: i64,
+    pub random_allocation: bool,
+    /// Set for drops paid by sending ADA to a backend controlled address
+    #[serde(skip)]
+    payment_skey: Option<Vec<u8>>,
+    #[serde(skip)]
+    payment_skey_sealed: bool,
+}
+
+impl NftDrop {
+    fn policy(&self, keystore: &Keystore) -> Result<NftPolicy> {
+        NftPolicy::from_key(
+            keystore.open(&self.policy_skey, self.policy_skey_sealed)?,
+            self.policy_lock_slot as u32,
+        )
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct DropItem {
+    pub id: i32,
+    pub asset_name: String,
+    pub metadata: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewDrop {
+    pub name: String,
+    pub price: u64,
+    /// Defaults to the number of items
+    pub supply: Option<u32>,
+    pub start_slot: u32,
+    pub policy_lock_slot: u32,
+    pub per_address_limit: Option<u32>,
+    /// Required unless `payment_watcher` is set
+    pub payment_address: Option<String>,
+    /// Generate a payment address watched by the backend, which mints to
+    /// whoever pays the drop price to it
+    #[serde(default)]
+    pub payment_watcher: bool,
+    /// Hand out a random remaining item instead of the items in order
+    #[serde(default)]
+    pub random_allocation: bool,
+    pub items: Vec<WottleNftMetadata>,
+}
+
+const DROP_COLUMNS: &str = r#"
+    id, name, price, supply, start_slot, per_address_limit, payment_address,
+    policy_id, policy_skey, policy_skey_sealed, policy_lock_slot, random_allocation,
+    payment_skey, payment_skey_sealed
+"#;
+
+/// The policy and payment keys of the drop are sealed in the keystore, which
+/// has to be configured
+pub async fn create_drop(
+    pool: &PgPool,
+    keystore: &Keystore,
+    new_drop: NewDrop,
+    payment_address: Option<&Address>,
+    network_id: u8,
+) -> Result<NftDrop> {
+    keystore.check()?;
+    if new_drop.items.is_empty() {
+        return Err(Error::Message("A drop needs at least one item".to_string()));
+    }
+    let supply = new_drop.supply.unwrap_or(new_drop.items.len() as u32);
+    if supply == 0 || supply as usize > new_drop.items.len() {
+        return Err(Error::Message(
+            "Supply must be between 1 and the number of items".to_string(),
+        ));
+    }
+    if new_drop.start_slot >= new_drop.policy_lock_slot {
+        return Err(Error::Message(
+            "The policy must lock after the drop starts".to_string(),
+        ));
+    }
+
+    let (payment_address, payment_skey) = match (payment_address, new_drop.payment_watcher) {
+        (None, true) => {
+            let payment_key = PrivateKey::generate_ed25519()?;
+            let address = EnterpriseAddress::new(
+                network_id,
+                &StakeCredential::from_keyhash(&payment_key.to_public().hash()),
+            )
+            .to_address();
+            (address, Some(keystore.seal(&payment_key)?))
+        }
+        (Some(address), false) => (address.clone(), None),
+        _ => {
+            return Err(Error::Message(
+                "Either a payment address or the payment watcher is required".to_string(),
+            ))
+        }
+    };
+
+    let skey = PrivateKey::generate_ed25519()?;
+    let policy_skey = keystore.seal(&skey)?;
+    let policy = NftPolicy::from_key(skey, new_drop.policy_lock_slot)?;
+
+    let mut tx = pool.begin().await?;
+    let drop = sqlx::query_as::<_, NftDrop>(&format!(
+        r#"
+        INSERT INTO marketplace.nft_drop
+            (name, price, supply, start_slot, per_address_limit, payment_address,
+             policy_id, policy_skey, policy_skey_sealed, policy_lock_slot, random_allocation,
+             payment_skey, payment_skey_sealed)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, true, $9, $10, $11, true)
+        RETURNING {}
+        "#,
+        DROP_COLUMNS
+    ))
+    .bind(&new_drop.name)
+    .bind(new_drop.price as i64)
+    .bind(supply as i32)
+    .bind(new_drop.start_slot as i64)
+    .bind(new_drop.per_address_limit.map(|l| l as i32))
+    .bind(payment_address.to_bech32(None)?)
+    .bind(policy.hash.to_bytes())
+    .bind(policy_skey)
+    .bind(new_drop.policy_lock_slot as i64)
+    .bind(new_drop.random_allocation)
+    .bind(payment_skey)
+    .fetch_one(&mut tx)
+    .await?;
+
+    for item in &new_drop.items {
+        sqlx::query(
+            r#"
+            INSERT INTO marketplace.drop_item (drop_id, asset_name, metadata)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(drop.id)
+        .bind(item.name())
+        .bind(serde_json::to_value(item)?)
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(drop)
+}
+
+pub async fn get_drop(pool: &PgPool, drop_id: i32) -> Result<NftDrop> {
+    sqlx::query_as::<_, NftDrop>(&format!(
+        "SELECT {} FROM marketplace.nft_drop WHERE id = $1",
+        DROP_COLUMNS
+    ))
+    .bind(drop_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::NotFound("No such drop".to_string()))
+}
+
+/// The policy of the drop minting under `policy_id`, if it is one of ours
+pub async fn get_drop_policy(
+    pool: &PgPool,
+    keystore: &Keystore,
+    policy_id: &[u8],
+) -> Result<Option<NftPolicy>> {
+    let drop = sqlx::query_as::<_, NftDrop>(&format!(
+        "SELECT {} FROM marketplace.nft_drop WHERE policy_id = $1",
+        DROP_COLUMNS
+    ))
+    .bind(policy_id)
+    .fetch_optional(pool)
+    .await?;
+    drop.map(|drop| drop.policy(keystore)).transpose()
+}
+
+pub async fn get_remaining_supply(pool: &PgPool, drop: &NftDrop, slot: u32) -> Result<i64> {
+    let taken: i64 = sqlx::query_scalar(&format!(
+        r#"
+        SELECT COUNT(*)
+        FROM marketplace.drop_item
+        INNER JOIN marketplace.nft_drop ON nft_drop.id = drop_item.drop_id
+        WHERE drop_item.drop_id = $1 AND {}
+        "#,
+        ITEM_TAKEN
+    ))
+    .bind(drop.id)
+    .bind(slot as i64)
+    .fetch_one(pool)
+    .await?;
+    Ok((drop.supply as i64 - taken).max(0))
+}
+
+/// Reserves the next free item of a drop for `address`, or a random one for
+/// drops with random allocation. The drop row is locked for the duration so
+/// concurrent mints see each other's reservations when checking supply and
+/// per-address limits, and can never be handed the same item.
+async fn reserve_item(
+    pool: &PgPool,
+    drop_id: i32,
+    address: &Address,
+    slot: u32,
+) -> Result<(NftDrop, DropItem)> {
+    let address = address.to_bech32(None)?;
+    let mut tx = pool.begin().await?;
+
+    let drop = sqlx::query_as::<_, NftDrop>(&format!(
+        "SELECT {} FROM marketplace.nft_drop WHERE id = $1 FOR UPDATE",
+        DROP_COLUMNS
+    ))
+    .bind(drop_id)
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or_else(|| Error::NotFound("No such drop".to_string()))?;
+
+    if (slot as i64) < drop.start_slot {
+        return Err(Error::Forbidden("The drop has not started yet".to_string()));
+    }
+    if slot as i64 >= drop.policy_lock_slot {
+        return Err(Error::Forbidden("The drop has ended".to_string()));
+    }
+
+    let taken: i64 = sqlx::query_scalar(&format!(
+        r#"
+        SELECT COUNT(*)
+        FROM marketplace.drop_item
+        INNER JOIN marketplace.nft_drop ON nft_drop.id = drop_item.drop_id
+        WHERE drop_item.drop_id = $1 AND {}
+        "#,
+        ITEM_TAKEN
+    ))
+    .bind(drop.id)
+    .bind(slot as i64)
+    .fetch_one(&mut tx)
+    .await?;
+    if taken >= drop.supply as i64 {
+        return Err(Error::Forbidden("The drop is sold out".to_string()));
+    }
+
+    if let Some(limit) = drop.per_address_limit {
+        let minted: i64 = sqlx::query_scalar(&format!(
+            r#"
+            SELECT COUNT(*)
+            FROM marketplace.drop_item
+            INNER JOIN marketplace.nft_drop ON nft_drop.id = drop_item.drop_id
+            WHERE drop_item.drop_id = $1 AND drop_item.reserved_by = $3 AND {}
+            "#,
+            ITEM_TAKEN
+        ))
+        .bind(drop.id)
+        .bind(slot as i64)
+        .bind(&address)
+        .fetch_one(&mut tx)
+        .await?;
+        if minted >= limit as i64 {
+            return Err(Error::Forbidden(
+                "Address has reached the mint limit of this drop".to_string(),
+            ));
+        }
+    }
+
+    let item = sqlx::query_as::<_, DropItem>(&format!(
+        r#"
+        SELECT drop_item.id, drop_item.asset_name, drop_item.metadata
+        FROM marketplace.drop_item
+        INNER JOIN marketplace.nft_drop ON nft_drop.id = drop_item.drop_id
+        WHERE drop_item.drop_id = $1 AND NOT {}
+        ORDER BY {}
+        LIMIT 1
+        "#,
+        ITEM_TAKEN,
+        if drop.random_allocation {
+            "random()"
+        } else {
+            "drop_item.id"
+        }
+    ))
+    .bind(drop.id)
+    .bind(slot as i64)
+    .fetch_optional(&mut tx)
+    .await?
+    .ok_or_else(|| Error::Forbidden("The drop is sold out".to_string()))?;
+
+    let reserved_until = (slot + RESERVATION_SLOTS).min(drop.policy_lock_slot as u32);
+    sqlx::query(
+        r#"
+        UPDATE marketplace.drop_item
+        SET reserved_by = $2, reserved_until = $3
+        WHERE id = $1
+        "#,
+    )
+    .bind(item.id)
+    .bind(&address)
+    .bind(reserved_until as i64)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok((drop, item))
+}
+
+async fn release_item(pool: &PgPool, item_id: i32) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE marketplace.drop_item
+        SET reserved_by = NULL, reserved_until = NULL
+        WHERE id = $1
+        "#,
+    )
+    .bind(item_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Frees items whose reservation ran out without them being minted.
+pub async fn release_expired_reservations(pool: &PgPool, slot: u32) -> Result<u64> {
+    let res = sqlx::query(
+        r#"
+        UPDATE marketplace.drop_item
+        SET reserved_by = NULL, reserved_until = NULL
+        FROM marketplace.nft_drop
+        WHERE nft_drop.id = drop_item.drop_id
+        AND drop_item.reserved_until < $1
+        AND NOT EXISTS (
+            SELECT 1 FROM ma_tx_mint
+            WHERE ma_tx_mint.policy = nft_drop.policy_id
+            AND ma_tx_mint.name = convert_to(drop_item.asset_name, 'UTF8')
+            AND ma_tx_mint.quantity > 0
+        )
+        "#,
+    )
+    .bind(slot as i64)
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+fn mint_builder(
+    drop: &NftDrop,
+    keystore: &Keystore,
+    item: &DropItem,
+    slot: u32,
+    params: ProtocolParams,
+    build: &BuildSettings,
+) -> Result<NftTransactionBuilder> {
+    let policy = drop.policy(keystore)?;
+    let nft: WottleNftMetadata = serde_json::from_value(item.metadata.clone())?;
+    NftTransactionBuilder::with_policy(nft, policy, slot, params, build)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_mint_transaction(
+    drop: &NftDrop,
+    keystore: &Keystore,
+    item: &DropItem,
+    address: &Address,
+    tax_address: &Address,
+    utxos: Vec<TransactionUnspentOutput>,
+    slot: u32,
+    params: ProtocolParams,
+    build: &BuildSettings,
+) -> Result<Transaction> {
+    let mut builder = mint_builder(drop, keystore, item, slot, params, build)?;
+    if drop.price > 0 {
+        let payment_address = Address::from_bech32(&drop.payment_address)?;
+        builder.add_output(TransactionOutput::new(
+            &payment_address,
+            &Value::new(&to_bignum(drop.price as u64)),
+        ));
+    }
+    builder.create_transaction(address, tax_address, utxos, None)
+}
+
+/// Builds the mint transaction of the next free item of a drop, paying the
+/// drop price to its payment address.
+pub async fn mint(
+    pool: &PgPool,
+    keystore: &Keystore,
+    drop_id: i32,
+    address: &Address,
+    tax_address: &Address,
+    build: &BuildSettings,
+) -> Result<(Transaction, NftDrop, DropItem)> {
+    let utxos = query_user_address_utxo(pool, address).await?;
+    let slot = get_slot_number(pool).await?;
+    let params = get_protocol_params(pool).await?;
+
+    let (drop, item) = reserve_item(pool, drop_id, address, slot).await?;
+
+    let tx = match build_mint_transaction(
+        &drop,
+        keystore,
+        &item,
+        address,
+        tax_address,
+        utxos,
+        slot,
+        params,
+        build,
+    ) {
+        Ok(tx) => claim_asset_name(pool, &drop.policy_id, &item.asset_name, &tx, slot)
+            .await
+            .map(|_| tx),
+        Err(e) => Err(e),
+    };
+
+    match tx {
+        Ok(tx) => Ok((tx, drop, item)),
+        Err(e) => {
+            release_item(pool, item.id).await?;
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,95 @@
+// Uniqueness of asset names under the managed policy of a drop
+
+use crate::{Error, Result};
+use cardano_serialization_lib::utils::hash_transaction;
+use cardano_serialization_lib::Transaction;
+use sqlx::PgPool;
+
+/// Claims `asset_name` under `policy_id` for the mint transaction `tx`. Fails
+/// with the hash of the conflicting mint when the name was minted before, or
+/// is claimed by a transaction that can still make it on chain.
+pub async fn claim_asset_name(
+    pool: &PgPool,
+    policy_id: &[u8],
+    asset_name: &str,
+    tx: &Transaction,
+    slot: u32,
+) -> Result<()> {
+    let tx_hash = hex::encode(hash_transaction(&tx.body()).to_bytes());
+
+    let minted: Option<String> = sqlx::query_scalar(
+        r#"
+        SELECT encode(tx.hash, 'hex')
+        FROM ma_tx_mint
+        INNER JOIN tx ON tx.id = ma_tx_mint.tx_id
+        WHERE ma_tx_mint.policy = $1
+        AND ma_tx_mint.name = convert_to($2, 'UTF8')
+        AND ma_tx_mint.quantity > 0
+        ORDER BY ma_tx_mint.id
+        LIMIT 1
+        "#,
+    )
+    .bind(policy_id)
+    .bind(asset_name)
+    .fetch_optional(pool)
+    .await?;
+    if let Some(minted) = minted {
+        return Err(Error::AssetNameTaken(minted));
+    }
+
+    // Nothing is minted yet, so a claim past its validity never made it
+    let claimed: Option<String> = sqlx::query_scalar(
+        r#"
+        INSERT INTO marketplace.minted_name (policy_id, asset_name, tx_hash, valid_until)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (policy_id, asset_name) DO UPDATE
+        SET tx_hash = EXCLUDED.tx_hash, valid_until = EXCLUDED.valid_until, created_at = now()
+        WHERE minted_name.valid_until < $5
+        RETURNING tx_hash
+        "#,
+    )
+    .bind(policy_id)
+    .bind(asset_name)
+    .bind(&tx_hash)
+    .bind(tx.body().ttl().unwrap_or(slot) as i64)
+    .bind(slot as i64)
+    .fetch_optional(pool)
+    .await?;
+    if claimed.is_some() {
+        return Ok(());
+    }
+
+    let existing: String = sqlx::query_scalar(
+        "SELECT tx_hash FROM marketplace.minted_name WHERE policy_id = $1 AND asset_name = $2",
+    )
+    .bind(policy_id)
+    .bind(asset_name)
+    .fetch_one(pool)
+    .await?;
+    if existing == tx_hash {
+        return Ok(());
+    }
+    Err(Error::AssetNameTaken(existing))
+}
+
+/// Gives up the claim of `tx_hash` on `asset_name`, for mints that never made
+/// it to the chain.
+pub async fn release_asset_name(
+    pool: &PgPool,
+    policy_id: &[u8],
+    asset_name: &str,
+    tx_hash: &str,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        DELETE FROM marketplace.minted_name
+        WHERE policy_id = $1 AND asset_name = $2 AND tx_hash = $3
+        "#,
+    )
+    .bind(policy_id)
+    .bind(asset_name)
+    .bind(tx_hash)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
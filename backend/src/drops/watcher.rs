@@ -0,0 +1,511 @@
+// Mints to whoever pays the drop price to a watched payment address
+
+use super::names::{claim_asset_name, release_asset_name};
+use super::{mint_builder, release_item, reserve_item, DropItem, NftDrop, DROP_COLUMNS};
+use crate::audit::AuditEntry;
+use crate::cardano_db_sync::{
+    get_protocol_params, get_slot_number, is_script_address, query_transaction_senders,
+    query_user_address_utxo, ProtocolParams,
+};
+use crate::coin::{combine_witness_set, start_transaction, BuildSettings};
+use crate::features::{Feature, Features};
+use crate::policies::Keystore;
+use crate::transaction::TxSubmitter;
+use crate::{Error, Result};
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::crypto::{PrivateKey, Vkeywitnesses};
+use cardano_serialization_lib::utils::{
+    from_bignum, hash_transaction, make_vkey_witness, min_ada_required, to_bignum,
+    TransactionUnspentOutput, Value,
+};
+use cardano_serialization_lib::{Transaction, TransactionOutput, TransactionWitnessSet};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+const ONE_HOUR: u32 = 3600;
+
+/// Payments wait while minting is switched off, and are served once it is
+/// back on. Only payments spending from a single key address are served, as
+/// that address is where the NFT or refund goes. Payments from several
+/// addresses or from a script, such as exchange withdrawals, are held for
+/// the operator to refund by hand.
+pub fn spawn(
+    pool: PgPool,
+    submitter: Arc<dyn TxSubmitter>,
+    tax_address: Address,
+    keystore: Keystore,
+    build: BuildSettings,
+    features: Features,
+    interval_seconds: u64,
+) {
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+        loop {
+            interval.tick().await;
+            if features.check(&pool, Feature::Minting).await.is_err() {
+                continue;
+            }
+            if let Err(e) =
+                process_payments(&pool, submitter.as_ref(), &tax_address, &keystore, &build).await
+            {
+                println!("Drop payment watcher failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn process_payments(
+    pool: &PgPool,
+    submitter: &dyn TxSubmitter,
+    tax_address: &Address,
+    keystore: &Keystore,
+    build: &BuildSettings,
+) -> Result<()> {
+    let drops = sqlx::query_as::<_, NftDrop>(&format!(
+        "SELECT {} FROM marketplace.nft_drop WHERE payment_skey IS NOT NULL",
+        DROP_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    for drop in drops {
+        let payment_address = Address::from_bech32(&drop.payment_address)?;
+        for utxo in query_user_address_utxo(pool, &payment_address).await? {
+            if is_processed(pool, &utxo).await? {
+                continue;
+            }
+            let senders = query_transaction_senders(pool, &utxo.input().transaction_id()).await?;
+            let payer = match senders.as_slice() {
+                [] => continue,
+                // Change of the transactions built here comes back to the payment address
+                [payer] if payer.to_bytes() == payment_address.to_bytes() => continue,
+                [payer] if !is_script_address(payer) => payer.clone(),
+                _ => {
+                    hold_payment(pool, &drop, &utxo, &senders).await?;
+                    continue;
+                }
+            };
+            if let Err(e) = process_payment(
+                pool,
+                submitter,
+                tax_address,
+                keystore,
+                &drop,
+                &utxo,
+                &payer,
+                build,
+            )
+            .await
+            {
+                println!(
+                    "Failed to process payment {}#{} for drop {}: {}",
+                    hex::encode(utxo.input().transaction_id().to_bytes()),
+                    utxo.input().index(),
+                    drop.id,
+                    e
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn is_processed(pool: &PgPool, utxo: &TransactionUnspentOutput) -> Result<bool> {
+    let res = sqlx::query(
+        r#"
+        SELECT 1 FROM marketplace.drop_payment
+        WHERE tx_hash = $1 AND tx_index = $2
+        "#,
+    )
+    .bind(utxo.input().transaction_id().to_bytes())
+    .bind(utxo.input().index() as i16)
+    .fetch_optional(pool)
+    .await?;
+    Ok(res.is_some())
+}
+
+/// Mints for exact and overpayments, refunding any excess. Underpayments,
+/// payments carrying native assets and payments that cannot be served (not
+/// started, sold out, over the address limit) are refunded in full.
+#[allow(clippy::too_many_arguments)]
+async fn process_payment(
+    pool: &PgPool,
+    submitter: &dyn TxSubmitter,
+    tax_address: &Address,
+    keystore: &Keystore,
+    drop: &NftDrop,
+    utxo: &TransactionUnspentOutput,
+    payer: &Address,
+    build: &BuildSettings,
+) -> Result<()> {
+    let payment_skey = drop
+        .payment_skey
+        .as_ref()
+        .ok_or_else(|| Error::Message("Drop has no payment key".to_string()))?;
+    let payment_key = keystore.open(payment_skey, drop.payment_skey_sealed)?;
+    let amount = from_bignum(&utxo.output().amount().coin());
+    let has_assets = utxo
+        .output()
+        .amount()
+        .multiasset()
+        .map(|ma| ma.len() > 0)
+        .unwrap_or(false);
+    let slot = get_slot_number(pool).await?;
+    let params = get_protocol_params(pool).await?;
+
+    let mut outcome = "refunded";
+    let mut tx = None;
+    let mut reserved = None;
+    if !has_assets && amount >= drop.price as u64 {
+        match reserve_item(pool, drop.id, payer, slot).await {
+            Ok((drop, item)) => {
+                match build_vending_transaction(
+                    &drop,
+                    keystore,
+                    &item,
+                    utxo,
+                    payer,
+                    tax_address,
+                    amount,
+                    slot,
+                    params.clone(),
+                    build,
+                ) {
+                    Ok(mint_tx) => {
+                        match claim_asset_name(
+                            pool,
+                            &drop.policy_id,
+                            &item.asset_name,
+                            &mint_tx,
+                            slot,
+                        )
+                        .await
+                        {
+                            Ok(()) => {
+                                outcome = "minted";
+                                tx = Some(mint_tx);
+                                reserved = Some(item);
+                            }
+                            Err(e @ Error::AssetNameTaken(_)) => {
+                                release_item(pool, item.id).await?;
+                                println!("Refunding payment to drop {}: {}", drop.id, e);
+                            }
+                            Err(e) => {
+                                release_item(pool, item.id).await?;
+                                return Err(e);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        release_item(pool, item.id).await?;
+                        println!("Refunding payment to drop {}: {}", drop.id, e);
+                    }
+                }
+            }
+            Err(Error::Forbidden(reason)) => {
+                println!("Refunding payment to drop {}: {}", drop.id, reason);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let tx = match tx {
+        Some(tx) => Some(tx),
+        None => match build_refund_transaction(utxo, payer, slot, &params) {
+            Ok(tx) => Some(tx),
+            Err(e) => {
+                // Dust that cannot pay for its own refund, kept so it is not retried
+                println!("Not refunding payment to drop {}: {}", drop.id, e);
+                outcome = "ignored";
+                None
+            }
+        },
+    };
+
+    let tx = tx.map(|tx| sign_with(tx, &payment_key)).transpose();
+    let tx_hash = match &tx {
+        Ok(Some(tx)) => Some(hex::encode(hash_transaction(&tx.body()).to_bytes())),
+        _ => None,
+    };
+    let res = match tx {
+        Ok(tx) => submit_payment(pool, submitter, drop, utxo, payer, amount, outcome, tx).await,
+        Err(e) => Err(e),
+    };
+    if res.is_err() {
+        if let Some(item) = reserved {
+            release_item(pool, item.id).await?;
+            if let Some(tx_hash) = &tx_hash {
+                release_asset_name(pool, &drop.policy_id, &item.asset_name, tx_hash).await?;
+            }
+        }
+    }
+    res
+}
+
+/// Records the payment as pending before its transaction is submitted, so a
+/// crash in between never serves it twice. Submissions that fail take the
+/// record back out, for the payment to be retried, as does
+/// `reconcile_pending_payments` after a crash.
+#[allow(clippy::too_many_arguments)]
+async fn submit_payment(
+    pool: &PgPool,
+    submitter: &dyn TxSubmitter,
+    drop: &NftDrop,
+    utxo: &TransactionUnspentOutput,
+    payer: &Address,
+    amount: u64,
+    outcome: &str,
+    tx: Option<Transaction>,
+) -> Result<()> {
+    let tx_hash = tx
+        .as_ref()
+        .map(|tx| hex::encode(hash_transaction(&tx.body()).to_bytes()));
+    let status = if tx.is_some() { "pending" } else { "done" };
+
+    let mut db_tx = pool.begin().await?;
+    let recorded = sqlx::query(
+        r#"
+        INSERT INTO marketplace.drop_payment
+            (tx_hash, tx_index, drop_id, payer, amount, outcome, result_tx_hash, status)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (tx_hash, tx_index) DO NOTHING
+        "#,
+    )
+    .bind(utxo.input().transaction_id().to_bytes())
+    .bind(utxo.input().index() as i16)
+    .bind(drop.id)
+    .bind(payer.to_bech32(None)?)
+    .bind(amount as i64)
+    .bind(outcome)
+    .bind(&tx_hash)
+    .bind(status)
+    .execute(&mut db_tx)
+    .await?
+    .rows_affected();
+    db_tx.commit().await?;
+    if recorded == 0 {
+        return Err(Error::Message(
+            "The payment is already being processed".to_string(),
+        ));
+    }
+
+    let tx = match tx {
+        Some(tx) => tx,
+        None => return Ok(()),
+    };
+    match submitter.submit_tx(&tx).await {
+        Ok(tx_id) => {
+            AuditEntry::new("drop_payment")
+                .address(payer)
+                .submitted(pool, &tx, &tx_id)
+                .await;
+            sqlx::query(
+                r#"
+                UPDATE marketplace.drop_payment
+                SET status = 'done'
+                WHERE tx_hash = $1 AND tx_index = $2
+                "#,
+            )
+            .bind(utxo.input().transaction_id().to_bytes())
+            .bind(utxo.input().index() as i16)
+            .execute(pool)
+            .await?;
+            Ok(())
+        }
+        Err(e) => {
+            sqlx::query(
+                r#"
+                DELETE FROM marketplace.drop_payment
+                WHERE tx_hash = $1 AND tx_index = $2 AND status = 'pending'
+                "#,
+            )
+            .bind(utxo.input().transaction_id().to_bytes())
+            .bind(utxo.input().index() as i16)
+            .execute(pool)
+            .await?;
+            Err(e)
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PgPendingPayment {
+    tx_hash: Vec<u8>,
+    tx_index: i16,
+    drop_id: i32,
+    policy_id: Vec<u8>,
+    result_tx_hash: Option<String>,
+    /// Claimed by the transaction serving the payment, if it mints
+    asset_name: Option<String>,
+}
+
+/// Settles the payments a crash left pending between recording them and
+/// submitting their transaction, run before the watcher starts. Those whose
+/// transaction is on chain are done. The others are taken back out along
+/// with the item and asset name they held, and served again. A transaction
+/// still in flight spends the same payment as its retry, so only one of them
+/// can land. Returns the payments settled and the ones to retry.
+pub async fn reconcile_pending_payments(pool: &PgPool) -> Result<(u64, u64)> {
+    let settled = sqlx::query(
+        r#"
+        UPDATE marketplace.drop_payment
+        SET status = 'done'
+        WHERE status = 'pending'
+        AND EXISTS (SELECT 1 FROM tx WHERE tx.hash = decode(drop_payment.result_tx_hash, 'hex'))
+        "#,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    let abandoned = sqlx::query_as::<_, PgPendingPayment>(
+        r#"
+        SELECT
+            drop_payment.tx_hash,
+            drop_payment.tx_index,
+            drop_payment.drop_id,
+            nft_drop.policy_id,
+            drop_payment.result_tx_hash,
+            minted_name.asset_name
+        FROM marketplace.drop_payment
+        INNER JOIN marketplace.nft_drop ON nft_drop.id = drop_payment.drop_id
+        LEFT JOIN marketplace.minted_name
+        ON minted_name.policy_id = nft_drop.policy_id
+        AND minted_name.tx_hash = drop_payment.result_tx_hash
+        WHERE drop_payment.status = 'pending'
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    for payment in &abandoned {
+        if let (Some(result_tx_hash), Some(asset_name)) =
+            (&payment.result_tx_hash, &payment.asset_name)
+        {
+            release_asset_name(pool, &payment.policy_id, asset_name, result_tx_hash).await?;
+            sqlx::query(
+                r#"
+                UPDATE marketplace.drop_item
+                SET reserved_by = NULL, reserved_until = NULL
+                WHERE drop_id = $1 AND asset_name = $2
+                "#,
+            )
+            .bind(payment.drop_id)
+            .bind(asset_name)
+            .execute(pool)
+            .await?;
+        }
+        sqlx::query(
+            r#"
+            DELETE FROM marketplace.drop_payment
+            WHERE tx_hash = $1 AND tx_index = $2 AND status = 'pending'
+            "#,
+        )
+        .bind(&payment.tx_hash)
+        .bind(payment.tx_index)
+        .execute(pool)
+        .await?;
+    }
+    Ok((settled, abandoned.len() as u64))
+}
+
+/// Keeps a payment whose sender cannot be told apart out of the watcher, all
+/// input addresses are recorded as the payer for the manual refund.
+async fn hold_payment(
+    pool: &PgPool,
+    drop: &NftDrop,
+    utxo: &TransactionUnspentOutput,
+    senders: &[Address],
+) -> Result<()> {
+    let payers = senders
+        .iter()
+        .map(|sender| Ok(sender.to_bech32(None)?))
+        .collect::<Result<Vec<_>>>()?;
+    println!(
+        "Holding payment {}#{} to drop {} from {}",
+        hex::encode(utxo.input().transaction_id().to_bytes()),
+        utxo.input().index(),
+        drop.id,
+        payers.join(", ")
+    );
+    sqlx::query(
+        r#"
+        INSERT INTO marketplace.drop_payment
+            (tx_hash, tx_index, drop_id, payer, amount, outcome)
+        VALUES ($1, $2, $3, $4, $5, 'held')
+        ON CONFLICT (tx_hash, tx_index) DO NOTHING
+        "#,
+    )
+    .bind(utxo.input().transaction_id().to_bytes())
+    .bind(utxo.input().index() as i16)
+    .bind(drop.id)
+    .bind(payers.join(","))
+    .bind(from_bignum(&utxo.output().amount().coin()) as i64)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// The payment funds the whole mint, what is left of the price after fees
+/// stays at the payment address as change.
+#[allow(clippy::too_many_arguments)]
+fn build_vending_transaction(
+    drop: &NftDrop,
+    keystore: &Keystore,
+    item: &DropItem,
+    utxo: &TransactionUnspentOutput,
+    payer: &Address,
+    tax_address: &Address,
+    amount: u64,
+    slot: u32,
+    params: ProtocolParams,
+    build: &BuildSettings,
+) -> Result<Transaction> {
+    let min_utxo_value = params.minimum_utxo_value.clone();
+    let mut builder = mint_builder(drop, keystore, item, slot, params, build)?;
+
+    let excess = amount - drop.price as u64;
+    let min_ada = from_bignum(&min_ada_required(
+        &Value::new(&min_utxo_value),
+        &min_utxo_value,
+    ));
+    if excess >= min_ada {
+        builder.add_output(TransactionOutput::new(
+            payer,
+            &Value::new(&to_bignum(excess)),
+        ));
+    }
+    builder.create_transaction(payer, tax_address, vec![utxo.clone()], None)
+}
+
+fn build_refund_transaction(
+    utxo: &TransactionUnspentOutput,
+    payer: &Address,
+    slot: u32,
+    params: &ProtocolParams,
+) -> Result<Transaction> {
+    let mut tx_builder = start_transaction(params, slot + ONE_HOUR);
+    tx_builder.add_input(
+        &utxo.output().address(),
+        &utxo.input(),
+        &utxo.output().amount(),
+    );
+    if !tx_builder.add_change_if_needed(payer)? {
+        return Err(Error::Message(
+            "Payment is too small to be refunded".to_string(),
+        ));
+    }
+    let tx_body = tx_builder.build()?;
+    Ok(Transaction::new(
+        &tx_body,
+        &TransactionWitnessSet::new(),
+        None,
+    ))
+}
+
+fn sign_with(tx: Transaction, key: &PrivateKey) -> Result<Transaction> {
+    let mut witness_set = TransactionWitnessSet::new();
+    let mut vkeys = Vkeywitnesses::new();
+    vkeys.add(&make_vkey_witness(&hash_transaction(&tx.body()), key));
+    witness_set.set_vkeys(&vkeys);
+    combine_witness_set(tx, witness_set)
+}
@@ -2,8 +2,9 @@ use cardano_serialization_lib::error::{DeserializeError, JsError};
 use hex::FromHexError;
 
 use crate::coin::CoinSelectionFailure;
+use crate::transaction::TxRejection;
 
-use actix_web::http::header;
+use actix_web::http::{header, StatusCode};
 use actix_web::{HttpResponse, HttpResponseBuilder};
 use serde_json::json;
 
@@ -39,6 +40,61 @@ pub enum Error {
     #[error("sqlx: {}", .0)]
     Sqlx(#[from] sqlx::Error),
 
+    #[error("Migration failed: {}", .0)]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    #[error("{}", .0)]
+    Forbidden(String),
+
+    #[error("{}", .0)]
+    NotFound(String),
+
+    #[error("{}", .0)]
+    TxRejected(#[from] TxRejection),
+
+    /// The listed NFT left the holder while a purchase was being built
+    #[error("This listing is no longer available")]
+    ListingUnavailable,
+
+    /// Holds the hash of the transaction that minted or claimed the name
+    #[error("Asset name is already taken by transaction {}", .0)]
+    AssetNameTaken(String),
+
+    /// The built transaction, witnesses included, exceeds the protocol limit
+    #[error(
+        "Transaction would be {} bytes with {} inputs and {} outputs, over the limit of {} bytes. \
+        Try fewer assets at once, or consolidate the wallet's UTxOs by sending its funds to itself.",
+        size,
+        inputs,
+        outputs,
+        max_size
+    )]
+    TransactionTooLarge {
+        size: usize,
+        max_size: u32,
+        inputs: usize,
+        outputs: usize,
+    },
+
+    /// Holds the limit in bytes of the request body
+    #[error("Request body is over the limit of {} bytes", .0)]
+    PayloadTooLarge(usize),
+
+    /// Holds the seconds the client should wait before retrying
+    #[error("Too many transactions are being built, retry in {} seconds", .0)]
+    TooManyRequests(u64),
+
+    /// Holds the message shown while the service is read-only
+    #[error("{}", .0)]
+    Maintenance(String),
+
+    /// Holds the name of a feature switched off by the operator
+    #[error("The {} feature is currently disabled", .0)]
+    FeatureDisabled(&'static str),
+
     #[error("Unknown error occured")]
     Unknown,
 }
@@ -56,14 +112,73 @@ impl From<DeserializeError> for Error {
 }
 
 impl actix_web::error::ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::TxRejected(_) | Self::TransactionTooLarge { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            Self::ListingUnavailable | Self::AssetNameTaken(_) => StatusCode::CONFLICT,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Maintenance(_) | Self::FeatureDisabled(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
     fn error_response(&self) -> HttpResponse {
-        let response_body = json!({
-            "error": self.to_string()
-        })
+        let response_body = match self {
+            Self::TxRejected(rejection) => json!({
+                "error": self.to_string(),
+                "code": rejection.code(),
+            }),
+            Self::ListingUnavailable => json!({
+                "error": self.to_string(),
+                "code": "LISTING_NO_LONGER_AVAILABLE",
+            }),
+            Self::TransactionTooLarge { size, max_size, .. } => json!({
+                "error": self.to_string(),
+                "code": "TX_TOO_LARGE",
+                "size": size,
+                "maxSize": max_size,
+            }),
+            Self::AssetNameTaken(tx_hash) => json!({
+                "error": self.to_string(),
+                "code": "ASSET_NAME_TAKEN",
+                "txHash": tx_hash,
+            }),
+            Self::PayloadTooLarge(limit) => json!({
+                "error": self.to_string(),
+                "code": "PAYLOAD_TOO_LARGE",
+                "limit": limit,
+            }),
+            Self::TooManyRequests(retry_after) => json!({
+                "error": self.to_string(),
+                "code": "TOO_MANY_REQUESTS",
+                "retryAfter": retry_after,
+            }),
+            Self::Maintenance(_) => json!({
+                "error": self.to_string(),
+                "code": "MAINTENANCE",
+            }),
+            Self::FeatureDisabled(feature) => json!({
+                "error": self.to_string(),
+                "code": "FEATURE_DISABLED",
+                "feature": feature,
+            }),
+            _ => json!({
+                "error": self.to_string()
+            }),
+        }
         .to_string();
-        HttpResponseBuilder::new(self.status_code())
-            .insert_header((header::CONTENT_TYPE, "application/json"))
-            .body(response_body)
+        let mut response = HttpResponseBuilder::new(self.status_code());
+        response.insert_header((header::CONTENT_TYPE, "application/json"));
+        if let Self::TooManyRequests(retry_after) = self {
+            response.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+        }
+        response.body(response_body)
     }
 }
 
@@ -0,0 +1,144 @@
+// Subsystems operators can switch on and off without redeploying
+
+use crate::{Error, Result};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Auctions,
+    Offers,
+    Projects,
+    Minting,
+}
+
+const ALL_FEATURES: [Feature; 4] = [
+    Feature::Auctions,
+    Feature::Offers,
+    Feature::Projects,
+    Feature::Minting,
+];
+
+impl Feature {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::Auctions => "auctions",
+            Feature::Offers => "offers",
+            Feature::Projects => "projects",
+            Feature::Minting => "minting",
+        }
+    }
+}
+
+impl FromStr for Feature {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        ALL_FEATURES
+            .iter()
+            .find(|feature| feature.name() == name)
+            .copied()
+            .ok_or_else(|| Error::NotFound(format!("No feature named {}", name)))
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureState {
+    pub name: &'static str,
+    pub enabled: bool,
+    /// Set at runtime rather than taken from the configuration
+    pub overridden: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct PgFeatureFlag {
+    name: String,
+    enabled: bool,
+}
+
+/// The configured defaults, overridden by the flags stored in the database.
+/// The database is read on every check so all instances agree.
+#[derive(Clone)]
+pub struct Features {
+    disabled_by_default: Vec<Feature>,
+}
+
+impl Features {
+    /// `disabled` is a comma separated list of feature names
+    pub fn from_config(disabled: &str) -> Result<Self> {
+        let disabled_by_default = disabled
+            .split(',')
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .map(|name| name.parse())
+            .collect::<Result<Vec<Feature>>>()?;
+        Ok(Self {
+            disabled_by_default,
+        })
+    }
+
+    /// Fails with 503 when `feature` is switched off
+    pub async fn check(&self, pool: &PgPool, feature: Feature) -> Result<()> {
+        let flag = sqlx::query_as::<_, PgFeatureFlag>(
+            "SELECT name, enabled FROM marketplace.feature_flag WHERE name = $1",
+        )
+        .bind(feature.name())
+        .fetch_optional(pool)
+        .await?;
+        let enabled = flag
+            .map(|flag| flag.enabled)
+            .unwrap_or_else(|| !self.disabled_by_default.contains(&feature));
+        if enabled {
+            Ok(())
+        } else {
+            Err(Error::FeatureDisabled(feature.name()))
+        }
+    }
+
+    pub async fn states(&self, pool: &PgPool) -> Result<Vec<FeatureState>> {
+        let flags = sqlx::query_as::<_, PgFeatureFlag>(
+            "SELECT name, enabled FROM marketplace.feature_flag",
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(ALL_FEATURES
+            .iter()
+            .map(|feature| {
+                let flag = flags.iter().find(|flag| flag.name == feature.name());
+                FeatureState {
+                    name: feature.name(),
+                    enabled: flag
+                        .map(|flag| flag.enabled)
+                        .unwrap_or_else(|| !self.disabled_by_default.contains(feature)),
+                    overridden: flag.is_some(),
+                }
+            })
+            .collect())
+    }
+}
+
+pub async fn set_feature(pool: &PgPool, feature: Feature, enabled: bool) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO marketplace.feature_flag (name, enabled)
+        VALUES ($1, $2)
+        ON CONFLICT (name) DO UPDATE SET enabled = EXCLUDED.enabled, updated_at = now()
+        "#,
+    )
+    .bind(feature.name())
+    .bind(enabled)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Falls back to the configured default again
+pub async fn reset_feature(pool: &PgPool, feature: Feature) -> Result<()> {
+    sqlx::query("DELETE FROM marketplace.feature_flag WHERE name = $1")
+        .bind(feature.name())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,347 @@
+// Typed RPC mirror of the core REST operations, for backend integrators
+
+use crate::audit::AuditEntry;
+use crate::cardano_db_sync::query_user_address_utxo;
+use crate::coin::BuildSettings;
+use crate::features::{Feature, Features};
+use crate::maintenance::Maintenance;
+use crate::marketplace::approval::request_approval;
+use crate::marketplace::holder::Filters;
+use crate::marketplace::{Marketplace, MIN_SALE_PRICE};
+use crate::nft::{mint_nft, WottleNftMetadata, DEFAULT_POLICY_LOCK_SECONDS};
+use crate::rest::parse_address;
+use crate::Error;
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::utils::from_bignum;
+use cardano_serialization_lib::{AssetName, PolicyID};
+use proto::marketplace_server::MarketplaceServer;
+use proto::*;
+use sqlx::PgPool;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("marketplace.v1");
+}
+
+pub struct MarketplaceService {
+    pool: PgPool,
+    marketplace: Marketplace,
+    tax_address: Address,
+    maintenance: Arc<Maintenance>,
+    features: Features,
+    build_settings: BuildSettings,
+}
+
+impl From<Error> for Status {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Unauthorized => Status::unauthenticated(e.to_string()),
+            Error::Forbidden(_) => Status::permission_denied(e.to_string()),
+            Error::NotFound(_) => Status::not_found(e.to_string()),
+            Error::Message(_) => Status::invalid_argument(e.to_string()),
+            Error::TxRejected(_) => Status::failed_precondition(e.to_string()),
+            Error::ListingUnavailable => Status::failed_precondition(e.to_string()),
+            Error::TransactionTooLarge { .. } => Status::failed_precondition(e.to_string()),
+            Error::AssetNameTaken(_) => Status::already_exists(e.to_string()),
+            Error::Maintenance(_) | Error::FeatureDisabled(_) => Status::unavailable(e.to_string()),
+            _ => Status::internal(e.to_string()),
+        }
+    }
+}
+
+fn parse_asset(policy_id: &str, asset_name: &str) -> crate::Result<(PolicyID, AssetName)> {
+    Ok((
+        PolicyID::from_bytes(hex::decode(policy_id)?)?,
+        AssetName::new(asset_name.as_bytes().to_vec())?,
+    ))
+}
+
+fn non_empty(s: String) -> Option<String> {
+    if s.is_empty() {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+#[tonic::async_trait]
+impl proto::marketplace_server::Marketplace for MarketplaceService {
+    async fn list_sales(
+        &self,
+        request: Request<ListSalesRequest>,
+    ) -> Result<Response<ListSalesResponse>, Status> {
+        let request = request.into_inner();
+        let policy = match non_empty(request.policy_id) {
+            Some(policy) => Some(
+                PolicyID::from_bytes(hex::decode(policy).map_err(Error::from)?)
+                    .map_err(Error::from)?,
+            ),
+            None => None,
+        };
+        let filters = Filters {
+            page: request.page.max(1),
+            policy,
+            asset_name: non_empty(request.asset_name),
+        };
+        let sales = self
+            .marketplace
+            .holder
+            .get_nfts_for_sale(&self.pool, filters)
+            .await?
+            .into_iter()
+            .map(|sale| {
+                Ok(Sale {
+                    transaction_hash: sale.hash,
+                    policy_id: hex::encode(sale.policy_id.to_bytes()),
+                    asset_name: String::from_utf8(sale.asset_name.name())
+                        .unwrap_or_else(|_| hex::encode(sale.asset_name.name())),
+                    seller_address: sale.sale_metadata.seller_address.to_bech32(None)?,
+                    price: sale.sale_metadata.price,
+                    asset_metadata: sale.asset_metadata.to_string(),
+                    status: if sale.pending { "pending" } else { "listed" }.to_string(),
+                })
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        Ok(Response::new(ListSalesResponse { sales }))
+    }
+
+    async fn sell(
+        &self,
+        request: Request<SellRequest>,
+    ) -> Result<Response<TransactionResponse>, Status> {
+        self.maintenance.check()?;
+        let request = request.into_inner();
+        if request.price < MIN_SALE_PRICE {
+            return Err(Status::invalid_argument("Price cannot be less than 5 ADA"));
+        }
+        let seller_address = parse_address(&request.seller_address)?;
+        let allowed_buyer = non_empty(request.allowed_buyer)
+            .as_deref()
+            .map(parse_address)
+            .transpose()?;
+        let payout_address = non_empty(request.payout_address)
+            .as_deref()
+            .map(parse_address)
+            .transpose()?;
+        let (policy_id, asset_name) = parse_asset(&request.policy_id, &request.asset_name)?;
+        let (tx, _, _) = self
+            .marketplace
+            .sell(
+                seller_address.clone(),
+                policy_id.clone(),
+                asset_name.clone(),
+                request.price,
+                Some(request.live_at).filter(|slot| *slot > 0),
+                None,
+                allowed_buyer,
+                payout_address,
+                &self.pool,
+            )
+            .await?;
+        AuditEntry::new("sell")
+            .address(&seller_address)
+            .asset(&policy_id, &asset_name)
+            .price(request.price)
+            .built(&self.pool, &tx)
+            .await?;
+        Ok(Response::new(TransactionResponse {
+            transaction: hex::encode(tx.to_bytes()),
+        }))
+    }
+
+    async fn buy(
+        &self,
+        request: Request<BuyRequest>,
+    ) -> Result<Response<TransactionResponse>, Status> {
+        self.maintenance.check()?;
+        let request = request.into_inner();
+        let buyer_address = parse_address(&request.buyer_address)?;
+        let (policy_id, asset_name) = parse_asset(&request.policy_id, &request.asset_name)?;
+        let coupon = non_empty(request.coupon);
+        let recipient = non_empty(request.recipient_address)
+            .as_deref()
+            .map(parse_address)
+            .transpose()?;
+        let change_address = non_empty(request.change_address)
+            .as_deref()
+            .map(parse_address)
+            .transpose()?;
+        let (tx, fee, approval_price) = self
+            .marketplace
+            .buy(
+                buyer_address.clone(),
+                policy_id.clone(),
+                asset_name.clone(),
+                coupon.as_deref(),
+                recipient,
+                change_address,
+                request.allow_self_trade,
+                &self.pool,
+                &self.pool,
+            )
+            .await?;
+        AuditEntry::new("buy")
+            .address(&buyer_address)
+            .asset(&policy_id, &asset_name)
+            .fee_bps(fee.fee_bps)
+            .built(&self.pool, &tx)
+            .await?;
+        if let Some(price) = approval_price {
+            let approval = request_approval(
+                &self.pool,
+                &self.marketplace.holder,
+                &buyer_address,
+                price,
+                &tx,
+            )
+            .await?;
+            return Err(Status::failed_precondition(format!(
+                "The purchase waits for settlement approval {}, fetch it from \
+                /marketplace/approvals/{} once approved",
+                approval.id, approval.id
+            )));
+        }
+        Ok(Response::new(TransactionResponse {
+            transaction: hex::encode(tx.to_bytes()),
+        }))
+    }
+
+    async fn cancel(
+        &self,
+        request: Request<CancelRequest>,
+    ) -> Result<Response<TransactionResponse>, Status> {
+        self.maintenance.check()?;
+        let request = request.into_inner();
+        let seller_address = parse_address(&request.seller_address)?;
+        let (policy_id, asset_name) = parse_asset(&request.policy_id, &request.asset_name)?;
+        let tx = self
+            .marketplace
+            .cancel(
+                seller_address.clone(),
+                policy_id.clone(),
+                asset_name.clone(),
+                &self.pool,
+            )
+            .await?;
+        AuditEntry::new("cancel")
+            .address(&seller_address)
+            .asset(&policy_id, &asset_name)
+            .built(&self.pool, &tx)
+            .await?;
+        Ok(Response::new(TransactionResponse {
+            transaction: hex::encode(tx.to_bytes()),
+        }))
+    }
+
+    async fn mint(&self, request: Request<MintRequest>) -> Result<Response<MintResponse>, Status> {
+        self.maintenance.check()?;
+        self.features.check(&self.pool, Feature::Minting).await?;
+        let request = request.into_inner();
+        let address = parse_address(&request.address)?;
+        let change_address = non_empty(request.change_address)
+            .as_deref()
+            .map(parse_address)
+            .transpose()?;
+        let mut nft = WottleNftMetadata::new(request.name, request.description, request.image);
+        nft.rest = request
+            .properties
+            .into_iter()
+            .map(|(k, v)| (k, serde_json::Value::String(v)))
+            .collect();
+        let (builder, tx) = mint_nft(
+            &self.pool,
+            &self.pool,
+            nft,
+            None,
+            DEFAULT_POLICY_LOCK_SECONDS,
+            &address,
+            change_address.as_ref(),
+            &self.tax_address,
+            &self.build_settings,
+        )
+        .await?;
+        AuditEntry::new("mint")
+            .address(&address)
+            .built(&self.pool, &tx)
+            .await?;
+        Ok(Response::new(MintResponse {
+            transaction: hex::encode(tx.to_bytes()),
+            policy_id: builder.policy_id(),
+            policy: builder.policy_json().to_string(),
+        }))
+    }
+
+    async fn get_utxos(
+        &self,
+        request: Request<GetUtxosRequest>,
+    ) -> Result<Response<GetUtxosResponse>, Status> {
+        let address = parse_address(&request.into_inner().address)?;
+        let utxos = query_user_address_utxo(&self.pool, &address)
+            .await?
+            .into_iter()
+            .map(|utxo| {
+                let amount = utxo.output().amount();
+                let mut assets = vec![];
+                if let Some(multiasset) = amount.multiasset() {
+                    let policies = multiasset.keys();
+                    for i in 0..policies.len() {
+                        let policy_id = policies.get(i);
+                        if let Some(policy_assets) = multiasset.get(&policy_id) {
+                            let asset_names = policy_assets.keys();
+                            for j in 0..asset_names.len() {
+                                let asset_name = asset_names.get(j);
+                                if let Some(qty) = policy_assets.get(&asset_name) {
+                                    assets.push(Asset {
+                                        policy_id: hex::encode(policy_id.to_bytes()),
+                                        asset_name: String::from_utf8(asset_name.name())
+                                            .unwrap_or_else(|_| hex::encode(asset_name.name())),
+                                        qty: from_bignum(&qty),
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+                Utxo {
+                    tx_hash: hex::encode(utxo.input().transaction_id().to_bytes()),
+                    tx_idx: utxo.input().index(),
+                    lovelace: from_bignum(&amount.coin()),
+                    assets,
+                }
+            })
+            .collect();
+        Ok(Response::new(GetUtxosResponse { utxos }))
+    }
+}
+
+pub fn spawn(
+    port: u32,
+    pool: PgPool,
+    marketplace: Marketplace,
+    tax_address: Address,
+    maintenance: Arc<Maintenance>,
+    features: Features,
+    build_settings: BuildSettings,
+) {
+    let service = MarketplaceService {
+        pool,
+        marketplace,
+        tax_address,
+        maintenance,
+        features,
+        build_settings,
+    };
+    actix_web::rt::spawn(async move {
+        let address: SocketAddr = format!("0.0.0.0:{}", port).parse().unwrap();
+        println!("Starting gRPC server on {}", &address);
+        if let Err(e) = tonic::transport::Server::builder()
+            .add_service(MarketplaceServer::new(service))
+            .serve(address)
+            .await
+        {
+            println!("gRPC server failed: {}", e);
+        }
+    });
+}
@@ -0,0 +1,126 @@
+// Alerts on holder spends the service never built, a sign of a leaked key
+
+use crate::jobs::webhook::enqueue_webhook;
+use crate::maintenance::Maintenance;
+use crate::marketplace::ticker::latest_tx_id;
+use crate::Result;
+use cardano_serialization_lib::address::Address;
+use serde_json::json;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A holder whose spends are checked against the audit log
+pub struct WatchedHolder {
+    /// Name used in alerts
+    pub name: String,
+    pub address: Address,
+}
+
+#[derive(sqlx::FromRow)]
+struct PgSpend {
+    hash: String,
+}
+
+/// Polls db-sync every `interval_seconds` for transactions spending from the
+/// holders. Each one missing from the audit log is reported on the console
+/// and queued for `webhook_url`, and turns maintenance on with
+/// `auto_maintenance`. Only what is indexed after startup is checked.
+pub fn spawn(
+    pool: PgPool,
+    holders: Vec<WatchedHolder>,
+    maintenance: Arc<Maintenance>,
+    webhook_url: Option<String>,
+    auto_maintenance: bool,
+    interval_seconds: u64,
+) {
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+        let mut last_tx_id = None;
+        loop {
+            interval.tick().await;
+            let latest = match latest_tx_id(&pool).await {
+                Ok(latest) => latest,
+                Err(e) => {
+                    println!("Holder watch failed: {}", e);
+                    continue;
+                }
+            };
+            let since = match last_tx_id {
+                Some(since) if since < latest => since,
+                Some(_) => continue,
+                None => {
+                    last_tx_id = Some(latest);
+                    continue;
+                }
+            };
+            let mut checked = true;
+            for holder in &holders {
+                let spends = match unaudited_spends(&pool, &holder.address, since, latest).await {
+                    Ok(spends) => spends,
+                    Err(e) => {
+                        println!("Holder watch failed for the {} holder: {}", holder.name, e);
+                        checked = false;
+                        continue;
+                    }
+                };
+                for tx_hash in spends {
+                    println!(
+                        "ALERT: the {} holder was spent by {}, which the service did not build",
+                        holder.name, tx_hash
+                    );
+                    if auto_maintenance {
+                        maintenance.enable(None);
+                    }
+                    if let Some(webhook_url) = &webhook_url {
+                        let alert = json!({
+                            "holder": holder.name,
+                            "address": holder.address.to_bech32(None).ok(),
+                            "txHash": tx_hash,
+                            "maintenance": auto_maintenance,
+                        });
+                        if let Err(e) = enqueue_webhook(&pool, webhook_url, alert).await {
+                            println!("Failed to queue the {} holder alert: {}", holder.name, e);
+                        }
+                    }
+                }
+            }
+            // Retried on the next tick, so that no spend goes unchecked
+            if checked {
+                last_tx_id = Some(latest);
+            }
+        }
+    });
+}
+
+/// Hashes of the transactions with ids in `since + 1..=until` spending from
+/// `address` that are not in the audit log
+async fn unaudited_spends(
+    pool: &PgPool,
+    address: &Address,
+    since: i64,
+    until: i64,
+) -> Result<Vec<String>> {
+    Ok(sqlx::query_as::<_, PgSpend>(
+        r#"
+        SELECT DISTINCT encode(tx.hash, 'hex') AS hash
+        FROM tx_in
+        INNER JOIN tx_out AS spent
+        ON spent.tx_id = tx_in.tx_out_id AND spent.index = tx_in.tx_out_index
+        INNER JOIN tx ON tx.id = tx_in.tx_in_id
+        WHERE spent.address = $1 AND tx_in.tx_in_id > $2 AND tx_in.tx_in_id <= $3
+        AND NOT EXISTS (
+            SELECT 1 FROM marketplace.tx_audit
+            WHERE tx_audit.tx_hash = encode(tx.hash, 'hex')
+        )
+        "#,
+    )
+    .bind(address.to_bech32(None)?)
+    .bind(since)
+    .bind(until)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|spend| spend.hash)
+    .collect())
+}
@@ -0,0 +1,104 @@
+// Checks that the image of an NFT can be served before it is minted
+
+use crate::{Error, Result};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, Url};
+use std::time::Duration;
+
+const FETCH_TIMEOUT_SECONDS: u64 = 10;
+
+#[derive(Clone)]
+pub struct ImageCheck {
+    client: Client,
+    ipfs_gateway: String,
+    max_bytes: u64,
+    /// Refuse to mint when the image is unfit instead of only warning
+    pub block: bool,
+}
+
+impl ImageCheck {
+    /// `ipfs_gateway` serves `ipfs://<cid>` at `<ipfs_gateway>/ipfs/<cid>`
+    pub fn new(ipfs_gateway: &str, max_bytes: u64, block: bool) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(FETCH_TIMEOUT_SECONDS))
+            .build()?;
+        Ok(Self {
+            client,
+            ipfs_gateway: ipfs_gateway.trim_end_matches('/').to_string(),
+            max_bytes,
+            block,
+        })
+    }
+
+    /// Why the image at `uri` is unfit for minting, if it is. The image has to
+    /// exist, be served with an image MIME type and fit within the size limit.
+    pub async fn problem(&self, uri: &str) -> Option<String> {
+        match self.check(uri).await {
+            Ok(()) => None,
+            Err(Error::NetworkRequest(e)) => Some(format!("Image could not be fetched: {}", e)),
+            Err(e) => Some(e.to_string()),
+        }
+    }
+
+    async fn check(&self, uri: &str) -> Result<()> {
+        let mut res = self
+            .client
+            .get(self.resolve(uri)?)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let content_type = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_lowercase();
+        if !content_type.starts_with("image/") {
+            return Err(Error::Message(format!(
+                "Image is served as {} rather than an image",
+                if content_type.is_empty() {
+                    "an unknown type"
+                } else {
+                    &content_type
+                }
+            )));
+        }
+
+        let too_large = || {
+            Error::Message(format!(
+                "Image is larger than the limit of {} bytes",
+                self.max_bytes
+            ))
+        };
+        if res.content_length().unwrap_or(0) > self.max_bytes {
+            return Err(too_large());
+        }
+        // The length is not always announced, count what is actually sent
+        let mut size = 0;
+        while let Some(chunk) = res.chunk().await? {
+            size += chunk.len() as u64;
+            if size > self.max_bytes {
+                return Err(too_large());
+            }
+        }
+        if size == 0 {
+            return Err(Error::Message("Image is empty".to_string()));
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, uri: &str) -> Result<Url> {
+        let url = if let Some(path) = uri.strip_prefix("ipfs://") {
+            let path = path.strip_prefix("ipfs/").unwrap_or(path);
+            format!("{}/ipfs/{}", self.ipfs_gateway, path)
+        } else if uri.starts_with("https://") {
+            uri.to_string()
+        } else {
+            return Err(Error::Message(
+                "Image must be an ipfs:// or https:// URI".to_string(),
+            ));
+        };
+        Url::parse(&url).map_err(|e| Error::Message(format!("Invalid image URI: {}", e)))
+    }
+}
@@ -0,0 +1,173 @@
+// Postgres backed queue of background work, retried until it succeeds or is
+// dead-lettered
+
+use crate::{Error, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+
+pub mod webhook;
+pub mod worker;
+
+/// Retries back off exponentially from this delay, up to an hour
+const RETRY_BASE_SECONDS: i64 = 30;
+const MAX_RETRY_SECONDS: i64 = 3600;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub id: i64,
+    pub kind: String,
+    pub payload: Value,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const JOB_COLUMNS: &str = r#"
+    id, kind, payload, status, attempts, max_attempts, run_at, last_error,
+    created_at, updated_at
+"#;
+
+/// Queues a job of `kind`, run as soon as possible unless `run_at` is given.
+pub async fn enqueue(
+    pool: &PgPool,
+    kind: &str,
+    payload: Value,
+    run_at: Option<DateTime<Utc>>,
+) -> Result<Job> {
+    Ok(sqlx::query_as::<_, Job>(&format!(
+        r#"
+        INSERT INTO marketplace.job (kind, payload, run_at)
+        VALUES ($1, $2, COALESCE($3, now()))
+        RETURNING {}
+        "#,
+        JOB_COLUMNS
+    ))
+    .bind(kind)
+    .bind(payload)
+    .bind(run_at)
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn get_jobs(pool: &PgPool, status: Option<&str>) -> Result<Vec<Job>> {
+    Ok(sqlx::query_as::<_, Job>(&format!(
+        r#"
+        SELECT {} FROM marketplace.job
+        WHERE $1::text IS NULL OR status = $1
+        ORDER BY id DESC
+        LIMIT 100
+        "#,
+        JOB_COLUMNS
+    ))
+    .bind(status)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Gives a dead-lettered job a fresh set of attempts.
+pub async fn retry_job(pool: &PgPool, id: i64) -> Result<Job> {
+    sqlx::query_as::<_, Job>(&format!(
+        r#"
+        UPDATE marketplace.job
+        SET status = 'pending', attempts = 0, run_at = now(), updated_at = now()
+        WHERE id = $1 AND status = 'dead'
+        RETURNING {}
+        "#,
+        JOB_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::NotFound("No dead-lettered job with this id".to_string()))
+}
+
+/// Deletes finished jobs older than `retention_days`. Dead-lettered ones are
+/// kept until an admin retries them.
+pub async fn prune_jobs(pool: &PgPool, retention_days: u32) -> Result<u64> {
+    let res = sqlx::query(
+        r#"
+        DELETE FROM marketplace.job
+        WHERE status = 'done' AND updated_at < now() - make_interval(days => $1)
+        "#,
+    )
+    .bind(retention_days as i32)
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+/// Takes the next due job, skipping those other workers are holding.
+async fn claim_job(pool: &PgPool) -> Result<Option<Job>> {
+    Ok(sqlx::query_as::<_, Job>(&format!(
+        r#"
+        UPDATE marketplace.job
+        SET status = 'running', attempts = attempts + 1, updated_at = now()
+        WHERE id = (
+            SELECT id FROM marketplace.job
+            WHERE status = 'pending' AND run_at <= now()
+            ORDER BY run_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING {}
+        "#,
+        JOB_COLUMNS
+    ))
+    .fetch_optional(pool)
+    .await?)
+}
+
+async fn complete_job(pool: &PgPool, job: &Job) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE marketplace.job
+        SET status = 'done', last_error = NULL, updated_at = now()
+        WHERE id = $1
+        "#,
+    )
+    .bind(job.id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn fail_job(pool: &PgPool, job: &Job, error: &str) -> Result<()> {
+    let delay = (RETRY_BASE_SECONDS << (job.attempts - 1).clamp(0, 16)).min(MAX_RETRY_SECONDS);
+    sqlx::query(
+        r#"
+        UPDATE marketplace.job
+        SET status = CASE WHEN attempts >= max_attempts THEN 'dead' ELSE 'pending' END,
+            run_at = now() + make_interval(secs => $2),
+            last_error = $3,
+            updated_at = now()
+        WHERE id = $1
+        "#,
+    )
+    .bind(job.id)
+    .bind(delay as f64)
+    .bind(error)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Jobs left running by a worker that went away are handed out again.
+async fn requeue_stale_jobs(pool: &PgPool) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE marketplace.job
+        SET status = 'pending', updated_at = now()
+        WHERE status = 'running' AND updated_at < now() - interval '10 minutes'
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
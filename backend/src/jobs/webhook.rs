@@ -0,0 +1,41 @@
+// Alerts posted to webhooks through the queue, so that a receiver being down
+// does not lose them
+
+use super::{enqueue, Job};
+use crate::Result;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+
+pub const KIND: &str = "webhook";
+
+#[derive(Deserialize, Serialize)]
+struct Delivery {
+    url: String,
+    body: Value,
+}
+
+/// Queues `body` to be posted to `url` as JSON
+pub async fn enqueue_webhook(pool: &PgPool, url: &str, body: Value) -> Result<Job> {
+    let delivery = Delivery {
+        url: url.to_string(),
+        body,
+    };
+    enqueue(pool, KIND, serde_json::to_value(delivery)?, None).await
+}
+
+/// Fails unless the receiver answers with a success status, so the job is
+/// retried
+pub(super) async fn deliver(client: &Client, payload: &Value) -> Result<()> {
+    let delivery: Delivery = serde_json::from_value(payload.clone())?;
+    client
+        .post(&delivery.url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(delivery.body.to_string())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,42 @@
+// Runs due jobs off the queue
+
+use super::{claim_job, complete_job, fail_job, requeue_stale_jobs, webhook, Job};
+use crate::{Error, Result};
+use reqwest::Client;
+use sqlx::PgPool;
+use std::time::Duration;
+
+pub fn spawn(pool: PgPool, interval_seconds: u64) {
+    let client = Client::new();
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_due_jobs(&pool, &client).await {
+                println!("Job worker failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn run_due_jobs(pool: &PgPool, client: &Client) -> Result<()> {
+    requeue_stale_jobs(pool).await?;
+    while let Some(job) = claim_job(pool).await? {
+        match run_job(client, &job).await {
+            Ok(()) => complete_job(pool, &job).await?,
+            Err(e) => {
+                println!("Job {} ({}) failed: {}", job.id, job.kind, e);
+                fail_job(pool, &job, &e.to_string()).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Dispatches a job to the worker owning its kind
+async fn run_job(client: &Client, job: &Job) -> Result<()> {
+    match job.kind.as_str() {
+        webhook::KIND => webhook::deliver(client, &job.payload).await,
+        kind => Err(Error::Message(format!("Unknown job kind {}", kind))),
+    }
+}
@@ -1,15 +1,39 @@
 #[macro_use]
 extern crate lazy_static;
 
+mod audit;
+mod balance_monitor;
+mod build_limit;
+mod burn;
 mod cardano_db_sync;
+mod chain;
+mod cip68;
+mod cleanup;
 mod coin;
 mod config;
+mod drops;
 mod error;
+mod features;
+mod grpc;
+mod holder_watch;
+mod image_check;
+mod jobs;
+mod maintenance;
 mod marketplace;
 mod nft;
+mod phase;
+mod policies;
+mod price_oracle;
 mod project;
+mod purpose;
+mod reference_script;
+mod remint;
 mod rest;
+mod revenue_sweep;
+mod tenant;
 mod transaction;
+mod transfer;
+mod tx_session;
 
 use std::fs::File;
 
@@ -18,7 +42,9 @@ use envconfig::Envconfig;
 use error::Result;
 
 use crate::error::Error;
-use cardano_serialization_lib::address::{Address, BaseAddress, NetworkInfo};
+use cardano_serialization_lib::address::{
+    Address, BaseAddress, EnterpriseAddress, NetworkInfo, PointerAddress, RewardAddress,
+};
 
 #[actix_web::main]
 async fn main() -> Result<()> {
@@ -54,7 +80,12 @@ fn decode_public_key(key_path: &str) -> Result<PublicKey> {
 
 fn decode_private_key(key_path: &str) -> Result<PrivateKey> {
     let text_envelope = read_key(key_path)?;
-    let hex_decode = hex::decode(text_envelope.cbor_hex.as_bytes())?;
+    decode_private_key_cbor(&text_envelope.cbor_hex)
+}
+
+/// Decodes the cborHex field of a signing key file
+fn decode_private_key_cbor(cbor_hex: &str) -> Result<PrivateKey> {
+    let hex_decode = hex::decode(cbor_hex.as_bytes())?;
     use cbor_event::de::*;
     use std::io::Cursor;
     let mut raw = Deserializer::from(Cursor::new(hex_decode));
@@ -63,12 +94,43 @@ fn decode_private_key(key_path: &str) -> Result<PrivateKey> {
     Ok(PrivateKey::from_normal_bytes(&bytes)?)
 }
 
-fn convert_to_testnet(address: Address) -> Address {
-    let base_addr = BaseAddress::from_address(&address).unwrap();
-    return BaseAddress::new(
-        NetworkInfo::testnet().network_id(),
-        &base_addr.payment_cred(),
-        &base_addr.stake_cred(),
-    )
-    .to_address();
+/// Rewrites `address` for `network_id`, keeping its payment and stake
+/// credentials. Byron addresses carry their network in their attributes and
+/// cannot be rewritten.
+fn convert_to_network(address: Address, network_id: u8) -> Result<Address> {
+    if address.network_id()? == network_id {
+        return Ok(address);
+    }
+    if let Some(base) = BaseAddress::from_address(&address) {
+        return Ok(
+            BaseAddress::new(network_id, &base.payment_cred(), &base.stake_cred()).to_address(),
+        );
+    }
+    if let Some(enterprise) = EnterpriseAddress::from_address(&address) {
+        return Ok(EnterpriseAddress::new(network_id, &enterprise.payment_cred()).to_address());
+    }
+    if let Some(pointer) = PointerAddress::from_address(&address) {
+        return Ok(PointerAddress::new(
+            network_id,
+            &pointer.payment_cred(),
+            &pointer.stake_pointer(),
+        )
+        .to_address());
+    }
+    if let Some(reward) = RewardAddress::from_address(&address) {
+        return Ok(RewardAddress::new(network_id, &reward.payment_cred()).to_address());
+    }
+    Err(Error::Message(
+        "Byron addresses cannot be moved to another network".to_string(),
+    ))
+}
+
+/// A bech32 address from the configuration, moved to the configured network
+fn configured_address(bech32: &str, is_testnet: bool) -> Result<Address> {
+    let network_id = if is_testnet {
+        NetworkInfo::testnet().network_id()
+    } else {
+        NetworkInfo::mainnet().network_id()
+    };
+    convert_to_network(Address::from_bech32(bech32)?, network_id)
 }
@@ -0,0 +1,57 @@
+// Read-only mode for db-sync resyncs and key rotations
+
+use crate::{Error, Result};
+use serde::Serialize;
+use std::sync::RwLock;
+
+const DEFAULT_MESSAGE: &str = "The marketplace is under maintenance, please try again later";
+
+/// While on, browsing keeps working but nothing can be built or submitted
+pub struct Maintenance {
+    message: RwLock<Option<String>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    pub message: Option<String>,
+}
+
+impl Maintenance {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            message: RwLock::new(if enabled {
+                Some(DEFAULT_MESSAGE.to_string())
+            } else {
+                None
+            }),
+        }
+    }
+
+    /// Turns maintenance on with `message`, or the default one
+    pub fn enable(&self, message: Option<String>) {
+        *self.message.write().unwrap() =
+            Some(message.unwrap_or_else(|| DEFAULT_MESSAGE.to_string()));
+    }
+
+    pub fn disable(&self) {
+        *self.message.write().unwrap() = None;
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        let message = self.message.read().unwrap().clone();
+        MaintenanceStatus {
+            enabled: message.is_some(),
+            message,
+        }
+    }
+
+    /// Called before building or submitting anything
+    pub fn check(&self) -> Result<()> {
+        match &*self.message.read().unwrap() {
+            Some(message) => Err(Error::Maintenance(message.clone())),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+// Second approval of high-value purchases before the holder signs them
+
+use super::holder::MarketplaceHolder;
+use crate::{Error, Result};
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::crypto::Vkeywitnesses;
+use cardano_serialization_lib::utils::hash_transaction;
+use cardano_serialization_lib::Transaction;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+const APPROVAL_COLUMNS: &str = r#"
+    id, tx_hash, holder_address, buyer_address, price, transaction, status,
+    decided_at, created_at
+"#;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementApproval {
+    pub id: i64,
+    pub tx_hash: String,
+    pub holder_address: String,
+    pub buyer_address: String,
+    pub price: i64,
+    /// Signed by the holder once approved
+    #[serde(with = "hex")]
+    pub transaction: Vec<u8>,
+    pub status: String,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Holds a purchase built without the holder signature until an admin
+/// approves it. The transaction expires an hour after it was built, so it
+/// has to be approved within that time.
+pub async fn request_approval(
+    pool: &PgPool,
+    holder: &MarketplaceHolder,
+    buyer_address: &Address,
+    price: u64,
+    tx: &Transaction,
+) -> Result<SettlementApproval> {
+    Ok(sqlx::query_as::<_, SettlementApproval>(&format!(
+        r#"
+        INSERT INTO marketplace.settlement_approval
+            (tx_hash, holder_address, buyer_address, price, transaction)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING {}
+        "#,
+        APPROVAL_COLUMNS
+    ))
+    .bind(hex::encode(hash_transaction(&tx.body()).to_bytes()))
+    .bind(holder.address.to_bech32(None)?)
+    .bind(buyer_address.to_bech32(None)?)
+    .bind(price as i64)
+    .bind(tx.to_bytes())
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn get_approval(pool: &PgPool, id: i64) -> Result<SettlementApproval> {
+    sqlx::query_as::<_, SettlementApproval>(&format!(
+        "SELECT {} FROM marketplace.settlement_approval WHERE id = $1",
+        APPROVAL_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("No settlement approval {}", id)))
+}
+
+/// Purchases signed by `holder` still waiting, oldest first
+pub async fn get_pending_approvals(
+    pool: &PgPool,
+    holder: &MarketplaceHolder,
+) -> Result<Vec<SettlementApproval>> {
+    Ok(sqlx::query_as::<_, SettlementApproval>(&format!(
+        r#"
+        SELECT {} FROM marketplace.settlement_approval
+        WHERE holder_address = $1 AND status = 'pending'
+        ORDER BY id ASC
+        "#,
+        APPROVAL_COLUMNS
+    ))
+    .bind(holder.address.to_bech32(None)?)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Signs the purchase with `holder` when `approved`, for the buyer to
+/// fetch. Only pending purchases of that holder can be decided.
+pub async fn decide_approval(
+    pool: &PgPool,
+    holder: &MarketplaceHolder,
+    id: i64,
+    approved: bool,
+) -> Result<SettlementApproval> {
+    let approval = get_approval(pool, id).await?;
+    if approval.holder_address != holder.address.to_bech32(None)? {
+        return Err(Error::NotFound(format!("No settlement approval {}", id)));
+    }
+    if approval.status != "pending" {
+        return Err(Error::Message(format!(
+            "Settlement approval {} was already {}",
+            id, approval.status
+        )));
+    }
+
+    let (status, transaction) = if approved {
+        let tx = Transaction::from_bytes(approval.transaction)?;
+        let mut witness_set = tx.witness_set();
+        let mut vkeys = witness_set.vkeys().unwrap_or_else(Vkeywitnesses::new);
+        vkeys.add(&holder.sign_transaction_hash(&hash_transaction(&tx.body())));
+        witness_set.set_vkeys(&vkeys);
+        let signed = Transaction::new(&tx.body(), &witness_set, tx.auxiliary_data());
+        ("approved", signed.to_bytes())
+    } else {
+        ("rejected", approval.transaction)
+    };
+    // Conditional, so that two admins deciding at once cannot both win
+    sqlx::query_as::<_, SettlementApproval>(&format!(
+        r#"
+        UPDATE marketplace.settlement_approval
+        SET status = $2, transaction = $3, decided_at = now()
+        WHERE id = $1 AND status = 'pending'
+        RETURNING {}
+        "#,
+        APPROVAL_COLUMNS
+    ))
+    .bind(id)
+    .bind(status)
+    .bind(transaction)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::Message(format!("Settlement approval {} was already decided", id)))
+}
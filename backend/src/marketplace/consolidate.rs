@@ -0,0 +1,89 @@
+// Merges the ADA-only UTxOs piling up at the holder address
+
+use super::holder::MarketplaceHolder;
+use super::offers::get_escrow_outputs;
+use crate::audit::AuditEntry;
+use crate::cardano_db_sync::{get_protocol_params, get_slot_number, query_user_address_utxo};
+use crate::coin::{build_sweep, is_ada_only};
+use crate::transaction::TxSubmitter;
+use crate::Result;
+use cardano_serialization_lib::crypto::Vkeywitnesses;
+use cardano_serialization_lib::utils::{from_bignum, hash_transaction};
+use cardano_serialization_lib::{Transaction, TransactionWitnessSet};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+const ONE_HOUR: u32 = 3600;
+/// Inputs merged per transaction, well within the transaction size limit
+const MAX_CONSOLIDATION_INPUTS: usize = 100;
+
+/// Consolidates once `min_utxos` ADA-only UTxOs have gathered at the holder.
+/// UTxOs holding any asset, escrowed NFTs above all, are never spent, nor
+/// are the ones escrowing offers.
+pub fn spawn(
+    pool: PgPool,
+    submitter: Arc<dyn TxSubmitter>,
+    holder: MarketplaceHolder,
+    min_utxos: usize,
+    interval_seconds: u64,
+) {
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+        loop {
+            interval.tick().await;
+            match consolidate(&pool, submitter.as_ref(), &holder, min_utxos).await {
+                Ok(Some(tx_hash)) => println!("Consolidated holder UTxOs in {}", tx_hash),
+                Ok(None) => {}
+                Err(e) => println!("Holder UTxO consolidation failed: {}", e),
+            }
+        }
+    });
+}
+
+async fn consolidate(
+    pool: &PgPool,
+    submitter: &dyn TxSubmitter,
+    holder: &MarketplaceHolder,
+    min_utxos: usize,
+) -> Result<Option<String>> {
+    let escrow_outputs = get_escrow_outputs(pool, &holder.address).await?;
+    let mut utxos: Vec<_> = query_user_address_utxo(pool, &holder.address)
+        .await?
+        .into_iter()
+        .filter(is_ada_only)
+        .filter(|utxo| {
+            let input = utxo.input();
+            !escrow_outputs.contains(&(
+                hex::encode(input.transaction_id().to_bytes()),
+                input.index(),
+            ))
+        })
+        .collect();
+    if utxos.len() < min_utxos.max(2) {
+        return Ok(None);
+    }
+    // The smallest are the ones cluttering coin selection the most
+    utxos.sort_by_key(|utxo| from_bignum(&utxo.output().amount().coin()));
+    utxos.truncate(MAX_CONSOLIDATION_INPUTS);
+
+    let slot = get_slot_number(pool).await?;
+    let params = get_protocol_params(pool).await?;
+    let tx_body = match build_sweep(&utxos, &holder.address, &params, slot + ONE_HOUR)? {
+        Some(tx_body) => tx_body,
+        // Not even enough to cover the fee of merging them
+        None => return Ok(None),
+    };
+
+    let mut witness_set = TransactionWitnessSet::new();
+    let mut vkeys = Vkeywitnesses::new();
+    vkeys.add(&holder.sign_transaction_hash(&hash_transaction(&tx_body)));
+    witness_set.set_vkeys(&vkeys);
+    let tx = Transaction::new(&tx_body, &witness_set, None);
+
+    let tx_hash = submitter.submit_tx(&tx).await?;
+    AuditEntry::new("consolidate")
+        .submitted(pool, &tx, &tx_hash)
+        .await;
+    Ok(Some(tx_hash))
+}
@@ -0,0 +1,164 @@
+// Promo codes lowering the marketplace fee of a buy
+
+use crate::{Error, Result};
+use cardano_serialization_lib::utils::hash_transaction;
+use cardano_serialization_lib::Transaction;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Coupon {
+    pub code: String,
+    pub discount_bps: i32,
+    pub single_use: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub redemptions: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewCoupon {
+    pub code: String,
+    pub discount_bps: u32,
+    #[serde(default = "default_single_use")]
+    pub single_use: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn default_single_use() -> bool {
+    true
+}
+
+/// Redeemable means not expired, and neither used nor held for a buy when
+/// single use. A buy submitted by the wallet itself is never redeemed here,
+/// its reservation counts as used once the transaction is on chain.
+const REDEEMABLE: &str = r#"
+    (expires_at IS NULL OR expires_at > now())
+    AND (NOT single_use OR (redemptions = 0 AND NOT EXISTS (
+        SELECT 1 FROM marketplace.coupon_reservation
+        LEFT JOIN tx ON tx.hash = decode(coupon_reservation.tx_hash, 'hex')
+        WHERE coupon_reservation.code = coupon.code
+        AND (tx.id IS NOT NULL OR coupon_reservation.expires_at > now())
+    )))
+"#;
+
+pub async fn create_coupon(pool: &PgPool, coupon: NewCoupon) -> Result<Coupon> {
+    if coupon.discount_bps == 0 || coupon.discount_bps > 10_000 {
+        return Err(Error::Message(
+            "Discount must be between 1 and 10000 basis points".to_string(),
+        ));
+    }
+    Ok(sqlx::query_as::<_, Coupon>(
+        r#"
+        INSERT INTO marketplace.coupon (code, discount_bps, single_use, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING code, discount_bps, single_use, expires_at, redemptions
+        "#,
+    )
+    .bind(coupon.code)
+    .bind(coupon.discount_bps as i32)
+    .bind(coupon.single_use)
+    .bind(coupon.expires_at)
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn get_coupons(pool: &PgPool) -> Result<Vec<Coupon>> {
+    Ok(sqlx::query_as::<_, Coupon>(
+        r#"
+        SELECT code, discount_bps, single_use, expires_at, redemptions
+        FROM marketplace.coupon
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn get_redeemable_coupon(pool: &PgPool, code: &str) -> Result<Coupon> {
+    sqlx::query_as::<_, Coupon>(&format!(
+        r#"
+        SELECT code, discount_bps, single_use, expires_at, redemptions
+        FROM marketplace.coupon
+        WHERE code = $1 AND {}
+        "#,
+        REDEEMABLE
+    ))
+    .bind(code)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::Message("Coupon is invalid, expired or already used".to_string()))
+}
+
+/// Holds a coupon for the buy transaction `tx_hash` for `valid_seconds`,
+/// the validity of the transaction. The coupon row stays locked while it is
+/// checked so a single use coupon cannot be held by two concurrent buys.
+pub async fn reserve_coupon(
+    pool: &PgPool,
+    code: &str,
+    tx_hash: &str,
+    valid_seconds: u32,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT 1 FROM marketplace.coupon WHERE code = $1 FOR UPDATE")
+        .bind(code)
+        .execute(&mut tx)
+        .await?;
+    let redeemable = sqlx::query(&format!(
+        "SELECT 1 FROM marketplace.coupon WHERE code = $1 AND {}",
+        REDEEMABLE
+    ))
+    .bind(code)
+    .fetch_optional(&mut tx)
+    .await?;
+    if redeemable.is_none() {
+        return Err(Error::Message(
+            "Coupon is invalid, expired or already used".to_string(),
+        ));
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO marketplace.coupon_reservation (tx_hash, code, expires_at)
+        VALUES ($1, $2, now() + $3 * interval '1 second')
+        ON CONFLICT (tx_hash) DO NOTHING
+        "#,
+    )
+    .bind(tx_hash)
+    .bind(code)
+    .bind(valid_seconds as i64)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Counts the coupon held for the submitted transaction as redeemed, if
+/// there is one.
+pub async fn redeem_coupon(pool: &PgPool, submitted: &Transaction) -> Result<()> {
+    let tx_hash = hex::encode(hash_transaction(&submitted.body()).to_bytes());
+    let mut tx = pool.begin().await?;
+    let code: Option<String> = sqlx::query_scalar(
+        "DELETE FROM marketplace.coupon_reservation WHERE tx_hash = $1 RETURNING code",
+    )
+    .bind(tx_hash)
+    .fetch_optional(&mut tx)
+    .await?;
+    if let Some(code) = code {
+        sqlx::query("UPDATE marketplace.coupon SET redemptions = redemptions + 1 WHERE code = $1")
+            .bind(code)
+            .execute(&mut tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Applies a coupon discount to a fee without going below `floor_bps`, or
+/// raising a fee that is already below it.
+pub fn apply_discount(fee_bps: u64, discount_bps: u64, floor_bps: u64) -> u64 {
+    fee_bps
+        .saturating_sub(discount_bps)
+        .max(floor_bps.min(fee_bps))
+}
@@ -0,0 +1,135 @@
+// Price estimates for assets that are not listed, from sale history and rarity
+
+use super::sales::{PgSale, Sale, SALES_QUERY};
+use super::ticker::get_floor;
+use crate::cardano_db_sync::query_collection_metadata;
+use crate::Result;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// Recent sales of the collection the estimate is drawn from
+const COMPARABLE_SALES: i64 = 100;
+/// CIP-25 fields describing the file rather than the asset
+const NON_TRAIT_FIELDS: &[&str] = &["name", "image", "description", "mediaType", "files"];
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceEstimate {
+    pub last_sale: Option<u64>,
+    pub floor: Option<u64>,
+    /// Statistical rarity, the sum of the inverse frequencies of its traits
+    /// within the collection. None when the asset has no traits.
+    pub rarity_score: Option<f64>,
+    /// Recent sales of the collection scaled by how rare the asset is next to
+    /// each sold asset, the median of those. None without comparable sales.
+    pub estimate: Option<u64>,
+}
+
+pub async fn estimate_price(
+    pool: &PgPool,
+    holder_address: &str,
+    revenue_address: &str,
+    policy: &[u8],
+    name: &[u8],
+) -> Result<PriceEstimate> {
+    let policy_hex = hex::encode(policy);
+    let floor = get_floor(pool, holder_address, &policy_hex).await?;
+
+    let sales: Vec<Sale> = sqlx::query_as::<_, PgSale>(&format!(
+        "{} AND ma_tx_out.policy = $3 ORDER BY sale_tx.id DESC LIMIT $4",
+        SALES_QUERY
+    ))
+    .bind(holder_address)
+    .bind(revenue_address)
+    .bind(policy)
+    .bind(COMPARABLE_SALES)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .filter_map(PgSale::into_sale)
+    .collect();
+    let asset_name = String::from_utf8(name.to_vec()).unwrap_or_else(|_| hex::encode(name));
+    let last_sale = sales
+        .iter()
+        .find(|sale| sale.asset_name == asset_name)
+        .map(|sale| sale.price);
+
+    let scores = rarity_scores(pool, policy).await?;
+    let rarity_score = scores.get(&asset_name).copied();
+    let estimate = rarity_score.and_then(|score| {
+        let mut scaled: Vec<f64> = sales
+            .iter()
+            .filter_map(|sale| {
+                let sold_score = scores.get(&sale.asset_name)?;
+                Some(sale.price as f64 * score / sold_score)
+            })
+            .collect();
+        if scaled.is_empty() {
+            return None;
+        }
+        scaled.sort_by(|a, b| a.total_cmp(b));
+        Some(scaled[scaled.len() / 2] as u64)
+    });
+
+    Ok(PriceEstimate {
+        last_sale,
+        floor,
+        rarity_score,
+        estimate,
+    })
+}
+
+/// Rarity scores of the assets of a collection with any traits, by asset name
+async fn rarity_scores(pool: &PgPool, policy: &[u8]) -> Result<HashMap<String, f64>> {
+    let policy_hex = hex::encode(policy);
+    let assets: Vec<(String, Vec<(String, String)>)> = query_collection_metadata(pool, policy)
+        .await?
+        .into_iter()
+        .filter_map(|asset| {
+            let name = String::from_utf8(asset.name).ok()?;
+            let traits = traits(asset.json.get(&policy_hex)?.get(&name)?);
+            Some((name, traits))
+        })
+        .filter(|(_, traits)| !traits.is_empty())
+        .collect();
+
+    let mut counts: HashMap<&(String, String), usize> = HashMap::new();
+    for (_, traits) in &assets {
+        for t in traits {
+            *counts.entry(t).or_default() += 1;
+        }
+    }
+    let total = assets.len() as f64;
+    Ok(assets
+        .iter()
+        .map(|(name, traits)| {
+            let score = traits.iter().map(|t| total / counts[t] as f64).sum::<f64>();
+            (name.clone(), score)
+        })
+        .collect())
+}
+
+/// Trait names and values of an asset. Collections keep them under
+/// `attributes` or `traits`, or otherwise next to the standard fields.
+fn traits(metadata: &Value) -> Vec<(String, String)> {
+    let fields = match metadata
+        .get("attributes")
+        .or_else(|| metadata.get("traits"))
+        .unwrap_or(metadata)
+    {
+        Value::Object(fields) => fields,
+        _ => return vec![],
+    };
+    fields
+        .iter()
+        .filter(|(key, _)| !NON_TRAIT_FIELDS.contains(&key.as_str()))
+        .filter_map(|(key, value)| match value {
+            Value::String(s) => Some((key.clone(), s.clone())),
+            Value::Number(n) => Some((key.clone(), n.to_string())),
+            Value::Bool(b) => Some((key.clone(), b.to_string())),
+            _ => None,
+        })
+        .collect()
+}
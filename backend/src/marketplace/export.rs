@@ -0,0 +1,106 @@
+// Every active listing at once, for aggregators and analytics partners
+
+use crate::marketplace::hidden::not_hidden;
+use crate::marketplace::holder::SellMetadata;
+use crate::marketplace::sales::csv_field;
+use crate::Result;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Listing {
+    pub policy_id: String,
+    pub asset_name: String,
+    pub price: u64,
+    pub seller: String,
+    pub tx_hash: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct PgListing {
+    tx_hash: String,
+    policy: Vec<u8>,
+    name: Vec<u8>,
+    sale_json: Value,
+}
+
+impl PgListing {
+    fn into_listing(self) -> Option<Listing> {
+        let sale_metadata = SellMetadata::for_asset(self.sale_json, &self.policy, &self.name)?;
+        Some(Listing {
+            policy_id: hex::encode(self.policy),
+            asset_name: String::from_utf8(self.name.clone())
+                .unwrap_or_else(|_| hex::encode(&self.name)),
+            price: sale_metadata.price,
+            seller: sale_metadata.seller_address.to_bech32(None).ok()?,
+            tx_hash: self.tx_hash,
+        })
+    }
+}
+
+/// Streams every indexed listing of the holder that is not hidden, oldest
+/// first, without loading them all into memory.
+pub fn stream_listings(pool: PgPool, holder_address: String) -> ReceiverStream<Result<Listing>> {
+    let (sender, receiver) = mpsc::channel(64);
+    actix_web::rt::spawn(async move {
+        let mut rows = sqlx::query_as::<_, PgListing>(&format!(
+            r#"
+            SELECT
+                encode(tx.hash, 'hex') AS tx_hash,
+                ma_tx_out.policy,
+                ma_tx_out.name,
+                sale_metadata.json AS sale_json
+            FROM tx_out
+            INNER JOIN tx ON tx.id = tx_out.tx_id
+            LEFT JOIN tx_in ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
+            INNER JOIN tx_metadata AS sale_metadata
+            ON tx_out.tx_id = sale_metadata.tx_id AND sale_metadata.key = 888
+            INNER JOIN ma_tx_out ON tx_out.id = ma_tx_out.tx_out_id
+            WHERE tx_out.address = $1
+            AND tx_in.id IS NULL
+            AND {}
+            ORDER BY tx.id ASC
+            "#,
+            not_hidden("encode(tx.hash, 'hex')")
+        ))
+        .bind(holder_address)
+        .fetch(&pool);
+        loop {
+            match rows.try_next().await {
+                Ok(Some(row)) => {
+                    if let Some(listing) = row.into_listing() {
+                        if sender.send(Ok(listing)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = sender.send(Err(e.into())).await;
+                    break;
+                }
+            }
+        }
+    });
+    ReceiverStream::new(receiver)
+}
+
+impl Listing {
+    pub const CSV_HEADER: &'static str = "policy_id,asset_name,price,seller,tx_hash\n";
+
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{}\n",
+            self.policy_id,
+            csv_field(&self.asset_name),
+            self.price,
+            self.seller,
+            self.tx_hash
+        )
+    }
+}
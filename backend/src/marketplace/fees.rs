@@ -0,0 +1,144 @@
+// Marketplace fee discounts for holders of partner collections
+
+use crate::{Error, Result};
+use cardano_serialization_lib::utils::TransactionUnspentOutput;
+use cardano_serialization_lib::PolicyID;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeDiscount {
+    #[serde(with = "hex")]
+    pub policy_id: Vec<u8>,
+    pub fee_bps: i32,
+}
+
+pub async fn get_fee_discounts(pool: &PgPool) -> Result<Vec<FeeDiscount>> {
+    Ok(sqlx::query_as::<_, FeeDiscount>(
+        r#"
+        SELECT policy_id, fee_bps
+        FROM marketplace.fee_discount
+        ORDER BY fee_bps
+        "#,
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn set_fee_discount(pool: &PgPool, policy_id: &PolicyID, fee_bps: u32) -> Result<()> {
+    if fee_bps > 10_000 {
+        return Err(Error::Message(
+            "Fee cannot exceed the sale price".to_string(),
+        ));
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO marketplace.fee_discount (policy_id, fee_bps)
+        VALUES ($1, $2)
+        ON CONFLICT (policy_id) DO UPDATE SET fee_bps = EXCLUDED.fee_bps
+        "#,
+    )
+    .bind(policy_id.to_bytes())
+    .bind(fee_bps as i32)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_fee_discount(pool: &PgPool, policy_id: &PolicyID) -> Result<()> {
+    sqlx::query("DELETE FROM marketplace.fee_discount WHERE policy_id = $1")
+        .bind(policy_id.to_bytes())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Fee overrides granted to the policies of launch partners
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyFee {
+    #[serde(with = "hex")]
+    pub policy_id: Vec<u8>,
+    pub fee_bps: i32,
+}
+
+pub async fn get_policy_fees(pool: &PgPool) -> Result<Vec<PolicyFee>> {
+    Ok(sqlx::query_as::<_, PolicyFee>(
+        r#"
+        SELECT policy_id, fee_bps
+        FROM marketplace.policy_fee
+        ORDER BY policy_id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// The fee set for sales of assets under `policy_id`, if any
+pub async fn get_policy_fee_bps(pool: &PgPool, policy_id: &PolicyID) -> Result<Option<u64>> {
+    let fee: Option<(i32,)> =
+        sqlx::query_as("SELECT fee_bps FROM marketplace.policy_fee WHERE policy_id = $1")
+            .bind(policy_id.to_bytes())
+            .fetch_optional(pool)
+            .await?;
+    Ok(fee.map(|(fee,)| fee as u64))
+}
+
+pub async fn set_policy_fee(pool: &PgPool, policy_id: &PolicyID, fee_bps: u32) -> Result<()> {
+    if fee_bps > 10_000 {
+        return Err(Error::Message(
+            "Fee cannot exceed the sale price".to_string(),
+        ));
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO marketplace.policy_fee (policy_id, fee_bps)
+        VALUES ($1, $2)
+        ON CONFLICT (policy_id) DO UPDATE SET fee_bps = EXCLUDED.fee_bps
+        "#,
+    )
+    .bind(policy_id.to_bytes())
+    .bind(fee_bps as i32)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_policy_fee(pool: &PgPool, policy_id: &PolicyID) -> Result<()> {
+    sqlx::query("DELETE FROM marketplace.policy_fee WHERE policy_id = $1")
+        .bind(policy_id.to_bytes())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The lowest discounted fee the buyer qualifies for by holding an asset of a
+/// partner policy, `None` when no discount applies.
+pub async fn get_discounted_fee_bps(
+    pool: &PgPool,
+    buyer_utxos: &[TransactionUnspentOutput],
+) -> Result<Option<u64>> {
+    let discounts = get_fee_discounts(pool).await?;
+    if discounts.is_empty() {
+        return Ok(None);
+    }
+
+    let mut best: Option<u64> = None;
+    for discount in discounts {
+        let policy_id = PolicyID::from_bytes(discount.policy_id)?;
+        let holds = buyer_utxos.iter().any(|utxo| {
+            utxo.output()
+                .amount()
+                .multiasset()
+                .and_then(|ma| ma.get(&policy_id))
+                .map(|assets| assets.len() > 0)
+                .unwrap_or(false)
+        });
+        if holds {
+            let fee_bps = discount.fee_bps as u64;
+            best = Some(best.map_or(fee_bps, |b| b.min(fee_bps)));
+        }
+    }
+    Ok(best)
+}
@@ -0,0 +1,61 @@
+// Listings hidden by an admin without touching their escrow
+
+use crate::{Error, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+/// Condition excluding the hidden listings, for queries selecting
+/// `tx_hash_column` as the hex sell transaction hash
+pub fn not_hidden(tx_hash_column: &str) -> String {
+    format!(
+        "{} NOT IN (SELECT tx_hash FROM marketplace.hidden_listing)",
+        tx_hash_column
+    )
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct HiddenListing {
+    pub tx_hash: String,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub async fn get_hidden_listings(pool: &PgPool) -> Result<Vec<HiddenListing>> {
+    Ok(sqlx::query_as::<_, HiddenListing>(
+        r#"
+        SELECT tx_hash, reason, created_at
+        FROM marketplace.hidden_listing
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn hide_listing(pool: &PgPool, tx_hash: &str, reason: Option<&str>) -> Result<()> {
+    if hex::decode(tx_hash)?.len() != 32 {
+        return Err(Error::Message("Invalid transaction hash".to_string()));
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO marketplace.hidden_listing (tx_hash, reason)
+        VALUES ($1, $2)
+        ON CONFLICT (tx_hash) DO UPDATE SET reason = EXCLUDED.reason
+        "#,
+    )
+    .bind(tx_hash.to_lowercase())
+    .bind(reason)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn unhide_listing(pool: &PgPool, tx_hash: &str) -> Result<()> {
+    sqlx::query("DELETE FROM marketplace.hidden_listing WHERE tx_hash = $1")
+        .bind(tx_hash.to_lowercase())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
@@ -1,14 +1,18 @@
 // Wallet that holds NFTs for sale
 
+use super::hidden::not_hidden;
+use super::pending::{get_pending_sale, get_pending_sales, get_pending_sales_from_user};
+use crate::chain::ChainQuery;
 use crate::{decode_private_key, Error, Result};
 use cardano_serialization_lib::address::{
-    Address, EnterpriseAddress, NetworkInfo, StakeCredential,
+    Address, BaseAddress, EnterpriseAddress, NetworkInfo, RewardAddress, StakeCredential,
 };
 use cardano_serialization_lib::crypto::{PrivateKey, TransactionHash, Vkeywitness};
 use cardano_serialization_lib::metadata::{
-    AuxiliaryData, GeneralTransactionMetadata, MetadataList, MetadataMap, TransactionMetadatum,
+    AuxiliaryData, GeneralTransactionMetadata, MetadataMap, TransactionMetadatum,
 };
-use cardano_serialization_lib::utils::{make_vkey_witness, to_bignum, Int};
+use cardano_serialization_lib::plutus::{ConstrPlutusData, PlutusData, PlutusList};
+use cardano_serialization_lib::utils::{make_vkey_witness, to_bignum, BigInt, Int};
 use cardano_serialization_lib::{AssetName, PolicyID};
 use serde::ser::SerializeStruct;
 use serde::{Serialize, Serializer};
@@ -16,7 +20,13 @@ use serde_json::Value;
 use sqlx::PgPool;
 use tokio_stream::StreamExt;
 
-const MARKETPLACE_METADATA_LABEL_KEY: u64 = 888;
+pub const MARKETPLACE_METADATA_LABEL_KEY: u64 = 888;
+/// Schema of the sale metadata written here, under `version`. Listings from
+/// before versioning have no `version` and are read as version 1.
+///
+/// 2. Adds `expires_at`, and `currency` which must be `lovelace` if present
+/// 3. Writes addresses as raw bytes rather than chunks of bech32
+pub const SALE_METADATA_VERSION: u64 = 3;
 
 pub struct MarketplaceHolder {
     pub address: Address,
@@ -30,42 +40,165 @@ pub struct SellData {
     pub asset_name: AssetName,
     pub sale_metadata: SellMetadata,
     pub asset_metadata: Value,
+    /// Submitted but not yet indexed by db-sync
+    pub pending: bool,
 }
 
+#[derive(Clone)]
 pub struct SellMetadata {
     pub seller_address: Address,
     pub price: u64,
+    /// Slot before which the listing cannot be bought
+    pub live_at: Option<u64>,
+    /// The only address allowed to buy, for private sales
+    pub allowed_buyer: Option<Address>,
+    /// Receives the proceeds instead of the seller. Cancelling still needs
+    /// the seller.
+    pub payout_address: Option<Address>,
+    /// Slot from which the listing can no longer be bought, only cancelled
+    pub expires_at: Option<u64>,
+}
+
+/// Addresses are either raw bytes, which db-sync renders as `0x` and hex, or
+/// bech32 split into chunks as metadata strings are limited to 64 bytes.
+/// Short bech32 addresses may come as a single string.
+fn address_from_metadata(value: Option<&Value>) -> Result<Address> {
+    let text = match value {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(arr)) => arr
+            .iter()
+            .map(|v| v.as_str())
+            .collect::<Option<Vec<&str>>>()
+            .ok_or(Error::Unknown)?
+            .join(""),
+        _ => return Err(Error::Unknown),
+    };
+    match text.strip_prefix("0x") {
+        Some(hex) => Ok(Address::from_bytes(hex::decode(hex)?)?),
+        None => Address::from_bech32(&text).map_err(Error::Js),
+    }
+}
+
+/// Raw bytes fit a single metadatum, at most 57 bytes for base addresses
+fn address_to_metadata(address: &Address) -> Result<TransactionMetadatum> {
+    Ok(TransactionMetadatum::new_bytes(address.to_bytes())?)
 }
 
 impl SellMetadata {
-    pub fn try_from_value(value: Value) -> Option<SellMetadata> {
-        let seller_address = value
-            .get("seller_address")
-            .and_then(|v| v.as_array())
-            .and_then(|arr| {
-                arr.into_iter()
-                    .map(|v| v.as_str().map(|s| s.to_string()))
-                    .collect::<Option<Vec<String>>>()
-            })
-            .map(|v| v.join(""))
-            .ok_or(Error::Unknown)
-            .and_then(|s| Address::from_bech32(&s).map_err(|e| Error::Js(e)));
+    /// Parses sale metadata strictly, saying what is wrong with payloads that
+    /// cannot be listed. Third-party tools escrowing at the holder write them
+    /// too.
+    pub fn validate(value: &Value) -> std::result::Result<SellMetadata, String> {
+        SellMetadata::parse(value, metadata_version(value)?)
+    }
 
-        let price = value.get("price").and_then(|v| v.as_u64());
+    /// Listings of a schema newer than this build understands are left out,
+    /// their terms could be misread
+    fn parse(value: &Value, version: u64) -> std::result::Result<SellMetadata, String> {
+        match version {
+            1 => SellMetadata::parse_v1(value),
+            // Addresses are read in either encoding whatever the version
+            2 | 3 => SellMetadata::parse_v2(value),
+            version => Err(format!("Unknown version {}", version)),
+        }
+    }
 
-        if let (Ok(seller_address), Some(price)) = (seller_address, price) {
-            Some(SellMetadata {
-                seller_address,
-                price,
-            })
-        } else {
-            None
+    fn parse_v2(value: &Value) -> std::result::Result<SellMetadata, String> {
+        match value.get("currency") {
+            None => {}
+            Some(currency) if currency.as_str() == Some("lovelace") => {}
+            // Only sales in ADA can be bought here
+            Some(currency) => return Err(format!("Unsupported currency {}", currency)),
         }
+        Ok(SellMetadata {
+            expires_at: optional_slot(value, "expires_at")?,
+            ..SellMetadata::parse_v1(value)?
+        })
+    }
+
+    fn parse_v1(value: &Value) -> std::result::Result<SellMetadata, String> {
+        if !value.is_object() {
+            return Err("Not a map".to_string());
+        }
+        let seller_address = address_from_metadata(value.get("seller_address"))
+            .map_err(|_| "Missing or unreadable seller_address".to_string())?;
+        let price = match value.get("price").and_then(|v| v.as_u64()) {
+            Some(price) if price > 0 => price,
+            _ => return Err("Missing or invalid price".to_string()),
+        };
+        // A private listing with an unreadable buyer is not listed at all
+        let allowed_buyer = optional_address(value, "allowed_buyer")?;
+        // Nor is one whose proceeds would go astray
+        let payout_address = optional_address(value, "payout_address")?;
+
+        Ok(SellMetadata {
+            seller_address,
+            price,
+            live_at: optional_slot(value, "live_at")?,
+            allowed_buyer,
+            payout_address,
+            expires_at: None,
+        })
+    }
+
+    /// Sale metadata of one NFT. Batch listings keep an entry per NFT under
+    /// `batch`, by policy id in hex and then asset name, and the version at
+    /// the top.
+    pub fn for_asset(value: Value, policy: &[u8], name: &[u8]) -> Option<SellMetadata> {
+        SellMetadata::validate_for_asset(&value, policy, name).ok()
+    }
+
+    pub fn validate_for_asset(
+        value: &Value,
+        policy: &[u8],
+        name: &[u8],
+    ) -> std::result::Result<SellMetadata, String> {
+        match value.get("batch") {
+            Some(batch) => {
+                let name = std::str::from_utf8(name)
+                    .map_err(|_| "Batch entries need UTF-8 asset names".to_string())?;
+                let entry = batch
+                    .get(hex::encode(policy))
+                    .and_then(|assets| assets.get(name))
+                    .ok_or_else(|| format!("No batch entry for {}", name))?;
+                SellMetadata::parse(entry, metadata_version(value)?)
+            }
+            None => SellMetadata::validate(value),
+        }
+    }
+}
+
+fn metadata_version(value: &Value) -> std::result::Result<u64, String> {
+    match value.get("version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| format!("Invalid version {}", version)),
+        None => Ok(1),
+    }
+}
+
+/// Fields that can be left out, but not be given unreadable
+fn optional_slot(value: &Value, field: &str) -> std::result::Result<Option<u64>, String> {
+    match value.get(field) {
+        Some(slot) => slot
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| format!("Invalid {}", field)),
+        None => Ok(None),
+    }
+}
+
+fn optional_address(value: &Value, field: &str) -> std::result::Result<Option<Address>, String> {
+    match value.get(field) {
+        Some(address) => address_from_metadata(Some(address))
+            .map(Some)
+            .map_err(|_| format!("Unreadable {}", field)),
+        None => Ok(None),
     }
 }
 
 #[derive(sqlx::FromRow)]
-struct PgSellData {
+pub(super) struct PgSellData {
     hash: String,
     policy: Vec<u8>,
     name: Vec<u8>,
@@ -79,12 +212,19 @@ struct PgSellMetadata {
 }
 
 impl PgSellData {
-    fn to_sell_data(self) -> Option<SellData> {
+    pub(super) fn to_sell_data(self) -> Option<SellData> {
+        let sale_metadata =
+            match SellMetadata::validate_for_asset(&self.sale_json, &self.policy, &self.name) {
+                Ok(sale_metadata) => Some(sale_metadata),
+                Err(reason) => {
+                    println!("Skipping listing {}: {}", self.hash, reason);
+                    None
+                }
+            };
         let policy_id = PolicyID::from_bytes(self.policy);
         let asset_name = String::from_utf8(self.name)
             .map_err(|_| Error::Message("Failed to convert asset name to string".to_string()))
             .and_then(|s| AssetName::new(s.into_bytes()).map_err(|e| Error::Js(e)));
-        let sale_metadata = SellMetadata::try_from_value(self.sale_json);
 
         if let (Ok(policy_id), Ok(asset_name), Some(sale_metadata)) =
             (policy_id, asset_name, sale_metadata)
@@ -95,6 +235,7 @@ impl PgSellData {
                 asset_name,
                 sale_metadata,
                 asset_metadata: self.asset_json,
+                pending: false,
             })
         } else {
             None
@@ -102,6 +243,53 @@ impl PgSellData {
     }
 }
 
+/// Sale metadata of an NFT held for sale at `holder`
+pub async fn query_listing(
+    pool: &PgPool,
+    holder: &Address,
+    policy_id: &PolicyID,
+    asset_name: &AssetName,
+) -> Result<Option<SellMetadata>> {
+    let hex_policy = hex::encode(policy_id.to_bytes());
+    let asset_name_str = String::from_utf8(asset_name.name())
+        .map_err(|_| Error::Message("Cannot convert asset name to string".to_string()))?;
+    let pg_sell_metadata: Option<PgSellMetadata> = sqlx::query_as::<_, PgSellMetadata>(
+        r#"
+            SELECT
+                sale_metadata.json AS sale_json
+            FROM tx_out 
+            LEFT JOIN tx_in ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
+            INNER JOIN tx_metadata AS sale_metadata
+            ON tx_out.tx_id = sale_metadata.tx_id AND sale_metadata.key = 888
+            INNER JOIN ma_tx_out
+            ON tx_out.id = ma_tx_out.tx_out_id
+            AND tx_in.id IS NULL
+            WHERE address = $1
+            AND encode(policy, 'hex') = $2
+            AND convert_from(name, 'utf-8') = $3
+        "#,
+    )
+    .bind(holder.to_bech32(None)?)
+    .bind(&hex_policy)
+    .bind(&asset_name_str)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(pg_sell_metadata.and_then(|sell_metadata| {
+        SellMetadata::for_asset(
+            sell_metadata.sale_json,
+            &policy_id.to_bytes(),
+            &asset_name.name(),
+        )
+    }))
+}
+
+impl MarketplaceHolder {
+    pub(crate) fn private_key_bytes(&self) -> Vec<u8> {
+        self.private_key.as_bytes()
+    }
+}
+
 impl Clone for MarketplaceHolder {
     fn clone(&self) -> Self {
         let bytes = self.private_key.as_bytes();
@@ -132,12 +320,16 @@ impl Default for Filters {
 impl MarketplaceHolder {
     pub fn from_key_file(key_file_path: &str, is_testnet: bool) -> Result<Self> {
         let private_key = decode_private_key(key_file_path)?;
-        let pub_key_hash = private_key.to_public().hash();
         let network = if is_testnet {
             NetworkInfo::testnet().network_id()
         } else {
             NetworkInfo::mainnet().network_id()
         };
+        Self::from_key(private_key, network)
+    }
+
+    pub fn from_key(private_key: PrivateKey, network: u8) -> Result<Self> {
+        let pub_key_hash = private_key.to_public().hash();
         let address =
             EnterpriseAddress::new(network, &StakeCredential::from_keyhash(&pub_key_hash))
                 .to_address();
@@ -149,39 +341,13 @@ impl MarketplaceHolder {
         })
     }
 
-    pub async fn get_nft_details(
+    pub async fn get_nft_details<C: ChainQuery>(
         &self,
-        pool: &PgPool,
+        chain: &C,
         policy_id: &PolicyID,
         asset_name: &AssetName,
     ) -> Result<Option<SellMetadata>> {
-        let hex_policy = hex::encode(policy_id.to_bytes());
-        let asset_name_str = String::from_utf8(asset_name.name())
-            .map_err(|_| Error::Message("Cannot convert asset name to string".to_string()))?;
-        let pg_sell_metadata: Option<PgSellMetadata> = sqlx::query_as::<_, PgSellMetadata>(
-            r#"
-                SELECT
-                    sale_metadata.json AS sale_json
-                FROM tx_out 
-                LEFT JOIN tx_in ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
-                INNER JOIN tx_metadata AS sale_metadata
-                ON tx_out.tx_id = sale_metadata.tx_id AND sale_metadata.key = 888
-                INNER JOIN ma_tx_out
-                ON tx_out.id = ma_tx_out.tx_out_id
-                AND tx_in.id IS NULL
-                WHERE address = $1
-                AND encode(policy, 'hex') = $2
-                AND convert_from(name, 'utf-8') = $3
-            "#,
-        )
-        .bind(&self.address_bech32)
-        .bind(&hex_policy)
-        .bind(&asset_name_str)
-        .fetch_optional(pool)
-        .await?;
-
-        Ok(pg_sell_metadata
-            .and_then(|sell_metadata| SellMetadata::try_from_value(sell_metadata.sale_json)))
+        chain.listing(&self.address, policy_id, asset_name).await
     }
 
     pub async fn get_nfts_for_sale(
@@ -203,7 +369,9 @@ impl MarketplaceHolder {
             "Page: {}, Policy: {}, Asset: {}",
             offset, policy_filter, asset_name_filter
         );
-        let mut rows = sqlx::query_as::<_, PgSellData>(r#"
+        let pending_asset_name_filter = asset_name_filter.clone();
+        let pending_policy_filter = policy_filter.clone();
+        let mut rows = sqlx::query_as::<_, PgSellData>(&format!(r#"
                 SELECT
 				 	encode(tx.hash, 'hex') as hash,
                     ma_tx_out.policy,
@@ -226,17 +394,29 @@ impl MarketplaceHolder {
                 WHERE address = $1
                 AND lower(convert_from(ma_tx_out.name, 'utf-8')) LIKE $2
                 AND lower(encode(ma_tx_out.policy, 'hex')) LIKE $3
+                AND {}
 				ORDER BY tx.id DESC
 				LIMIT 16
 				OFFSET $4
-                "#)
+                "#, not_hidden("encode(tx.hash, 'hex')")))
             .bind(&self.address_bech32)
             .bind(asset_name_filter)
             .bind(policy_filter)
             .bind(offset)
             .fetch(pool);
 
-        let mut sell_datas = vec![];
+        // Pending sales are the newest, so they lead the first page
+        let mut sell_datas = if filters.page <= 1 {
+            get_pending_sales(
+                pool,
+                &self.address_bech32,
+                &pending_asset_name_filter,
+                &pending_policy_filter,
+            )
+            .await?
+        } else {
+            vec![]
+        };
 
         while let Some(pg_data) = rows.try_next::<PgSellData, _>().await? {
             let pg_data: PgSellData = pg_data;
@@ -283,15 +463,28 @@ impl MarketplaceHolder {
             .fetch_optional(pool)
             .await?;
 
-        Ok(op_pg_sell_data.and_then(|sell_data| sell_data.to_sell_data()))
+        match op_pg_sell_data.and_then(|sell_data| sell_data.to_sell_data()) {
+            Some(sell_data) => Ok(Some(sell_data)),
+            None => get_pending_sale(pool, &self.address_bech32, hash).await,
+        }
     }
 
+    /// Listings whose sell transaction spent from `address`. A stake address
+    /// matches every payment address delegating with it, as wallets rotate
+    /// payment addresses.
     pub async fn get_listings_from_user(
         &self,
         pool: &PgPool,
         address: &Address,
     ) -> Result<Vec<SellData>> {
-        let mut rows = sqlx::query_as::<_, PgSellData>(
+        let stake_credential =
+            RewardAddress::from_address(address).map(|reward| reward.payment_cred());
+        let spent_from = if stake_credential.is_some() {
+            "tx_out.stake_address_id IN (SELECT id FROM stake_address WHERE view = $2)"
+        } else {
+            "tx_out.address = $2"
+        };
+        let mut rows = sqlx::query_as::<_, PgSellData>(&format!(
             r#"
                 SELECT 
                     encode(tx.hash, 'hex') as hash,
@@ -317,15 +510,31 @@ impl MarketplaceHolder {
                     INNER JOIN tx_in ON tx_out.tx_id = tx_in.tx_out_id
                     INNER JOIN tx AS tx_inner ON tx_inner.id = tx_in.tx_in_id AND tx_in.tx_out_index = tx_out.index
                     where tx.hash = tx_inner.hash
-                    AND tx_out.address = $2)
+                    AND {})
                 ORDER BY tx.id DESC
                 "#,
-        )
+            spent_from
+        ))
             .bind(&self.address_bech32)
             .bind(address.to_bech32(None)?)
             .fetch(pool);
 
-        let mut sell_datas = vec![];
+        let mut sell_datas = match &stake_credential {
+            Some(stake_credential) => get_pending_sales(pool, &self.address_bech32, "%%", "%%")
+                .await?
+                .into_iter()
+                .filter(|sell_data| {
+                    BaseAddress::from_address(&sell_data.sale_metadata.seller_address)
+                        .map_or(false, |base| {
+                            base.stake_cred().to_bytes() == stake_credential.to_bytes()
+                        })
+                })
+                .collect(),
+            None => {
+                get_pending_sales_from_user(pool, &self.address_bech32, &address.to_bech32(None)?)
+                    .await?
+            }
+        };
 
         while let Some(pg_data) = rows.try_next::<PgSellData, _>().await? {
             let pg_data: PgSellData = pg_data;
@@ -346,7 +555,7 @@ impl Serialize for SellData {
     where
         S: Serializer,
     {
-        let mut serialize_struct = serializer.serialize_struct("SellData", 5)?;
+        let mut serialize_struct = serializer.serialize_struct("SellData", 6)?;
 
         serialize_struct.serialize_field("transactionHash", &self.hash)?;
         serialize_struct.serialize_field("policyId", &hex::encode(self.policy_id.to_bytes()))?;
@@ -357,6 +566,8 @@ impl Serialize for SellData {
         )?;
         serialize_struct.serialize_field("saleMetadata", &self.sale_metadata)?;
         serialize_struct.serialize_field("assetMetadata", &self.asset_metadata)?;
+        serialize_struct
+            .serialize_field("status", if self.pending { "pending" } else { "listed" })?;
         serialize_struct.end()
     }
 }
@@ -366,7 +577,7 @@ impl Serialize for SellMetadata {
     where
         S: Serializer,
     {
-        let mut serialize_struct = serializer.serialize_struct("SellMetadata", 3)?;
+        let mut serialize_struct = serializer.serialize_struct("SellMetadata", 7)?;
         serialize_struct.serialize_field(
             "sellerAddress",
             &self
@@ -378,49 +589,175 @@ impl Serialize for SellMetadata {
 
         serialize_struct
             .serialize_field("namiAddress", &hex::encode(&self.seller_address.to_bytes()))?;
+        serialize_struct.serialize_field("liveAt", &self.live_at)?;
+        serialize_struct.serialize_field("expiresAt", &self.expires_at)?;
+        serialize_struct.serialize_field(
+            "allowedBuyer",
+            &self
+                .allowed_buyer
+                .as_ref()
+                .map(|buyer| buyer.to_bech32(None))
+                .transpose()
+                .map_err(|_| serde::ser::Error::custom("Failed to serialize allowed buyer"))?,
+        )?;
+        serialize_struct.serialize_field(
+            "payoutAddress",
+            &self
+                .payout_address
+                .as_ref()
+                .map(|payout| payout.to_bech32(None))
+                .transpose()
+                .map_err(|_| serde::ser::Error::custom("Failed to serialize payout address"))?,
+        )?;
         serialize_struct.end()
     }
 }
 
 impl SellMetadata {
     pub fn create_sell_nft_metadata(&self) -> Result<AuxiliaryData> {
+        let mut auxiliary_data = AuxiliaryData::new();
+        let mut general_tx_data = GeneralTransactionMetadata::new();
+        let mut map = self.to_metadata_map()?;
+        map.insert_str("version", &version_metadatum())?;
+        general_tx_data.insert(
+            &to_bignum(MARKETPLACE_METADATA_LABEL_KEY),
+            &TransactionMetadatum::new_map(&map),
+        );
+        auxiliary_data.set_metadata(&general_tx_data);
+        Ok(auxiliary_data)
+    }
+
+    /// Sale metadata of NFTs listed together, all by `seller_address`. The
+    /// seller is repeated at the top for queries reading it from any listing.
+    pub fn create_batch_sell_metadata(
+        seller_address: &Address,
+        listings: &[(PolicyID, AssetName, SellMetadata)],
+    ) -> Result<AuxiliaryData> {
+        let mut policies: Vec<(String, MetadataMap)> = vec![];
+        for (policy_id, asset_name, sell_metadata) in listings {
+            let policy_hex = hex::encode(policy_id.to_bytes());
+            let index = match policies.iter().position(|(hex, _)| *hex == policy_hex) {
+                Some(index) => index,
+                None => {
+                    policies.push((policy_hex, MetadataMap::new()));
+                    policies.len() - 1
+                }
+            };
+            let name = String::from_utf8(asset_name.name())
+                .map_err(|_| Error::Message("Asset names must be UTF-8 text".to_string()))?;
+            policies[index].1.insert_str(
+                &name,
+                &TransactionMetadatum::new_map(&sell_metadata.to_metadata_map()?),
+            )?;
+        }
+
+        let mut batch = MetadataMap::new();
+        for (policy_hex, assets) in policies {
+            batch.insert_str(&policy_hex, &TransactionMetadatum::new_map(&assets))?;
+        }
+        let mut map = MetadataMap::new();
+        map.insert_str("version", &version_metadatum())?;
+        map.insert_str("seller_address", &address_to_metadata(seller_address)?)?;
+        map.insert_str("batch", &TransactionMetadatum::new_map(&batch))?;
+
+        let mut auxiliary_data = AuxiliaryData::new();
+        let mut general_tx_data = GeneralTransactionMetadata::new();
+        general_tx_data.insert(
+            &to_bignum(MARKETPLACE_METADATA_LABEL_KEY),
+            &TransactionMetadatum::new_map(&map),
+        );
+        auxiliary_data.set_metadata(&general_tx_data);
+        Ok(auxiliary_data)
+    }
+
+    /// The terms of one listing, the version is written next to them or at
+    /// the top of a batch
+    fn to_metadata_map(&self) -> Result<MetadataMap> {
         let SellMetadata {
             seller_address,
             price,
+            live_at,
+            allowed_buyer,
+            payout_address,
+            expires_at,
         } = self;
 
-        let mut auxiliary_data = AuxiliaryData::new();
-        let mut general_tx_data = GeneralTransactionMetadata::new();
-
-        let tx_metadata = TransactionMetadatum::new_map(&{
+        Ok({
             let mut map = MetadataMap::new();
             map.insert_str(
                 "price",
                 &TransactionMetadatum::new_int(&Int::new(&to_bignum(*price))),
             )?;
 
-            let addr_string = seller_address.to_bech32(None)?;
-            let addr_string_list: Vec<String> = addr_string
-                .chars()
-                .collect::<Vec<char>>()
-                .chunks(64)
-                .map(|c| c.iter().collect::<String>())
-                .collect();
-            let mut addr_list = MetadataList::new();
-
-            for s in addr_string_list {
-                addr_list.add(&TransactionMetadatum::new_text(s)?);
+            map.insert_str("seller_address", &address_to_metadata(seller_address)?)?;
+            if let Some(live_at) = live_at {
+                map.insert_str(
+                    "live_at",
+                    &TransactionMetadatum::new_int(&Int::new(&to_bignum(*live_at))),
+                )?;
+            }
+            if let Some(allowed_buyer) = allowed_buyer {
+                map.insert_str("allowed_buyer", &address_to_metadata(allowed_buyer)?)?;
+            }
+            if let Some(payout_address) = payout_address {
+                map.insert_str("payout_address", &address_to_metadata(payout_address)?)?;
+            }
+            if let Some(expires_at) = expires_at {
+                map.insert_str(
+                    "expires_at",
+                    &TransactionMetadatum::new_int(&Int::new(&to_bignum(*expires_at))),
+                )?;
             }
-
-            map.insert_str(
-                "seller_address",
-                &TransactionMetadatum::new_list(&addr_list),
-            )?;
             map
-        });
+        })
+    }
 
-        general_tx_data.insert(&to_bignum(MARKETPLACE_METADATA_LABEL_KEY), &tx_metadata);
-        auxiliary_data.set_metadata(&general_tx_data);
-        Ok(auxiliary_data)
+    /// The sale terms as a datum, for a validator to check once listings move
+    /// to a script address:
+    /// `Constr 0 [seller, price, policy id, asset name, live_at, allowed_buyer, payout, expires_at]`
+    /// with addresses as raw bytes and the optional fields as `Maybe`,
+    /// `Constr 0 [x]` or `Constr 1 []`.
+    pub fn to_datum(&self, policy_id: &PolicyID, asset_name: &AssetName) -> PlutusData {
+        let mut fields = PlutusList::new();
+        fields.add(&PlutusData::new_bytes(self.seller_address.to_bytes()));
+        fields.add(&integer_datum(self.price));
+        fields.add(&PlutusData::new_bytes(policy_id.to_bytes()));
+        fields.add(&PlutusData::new_bytes(asset_name.name()));
+        fields.add(&maybe_datum(self.live_at.map(integer_datum)));
+        fields.add(&maybe_datum(
+            self.allowed_buyer
+                .as_ref()
+                .map(|buyer| PlutusData::new_bytes(buyer.to_bytes())),
+        ));
+        fields.add(&maybe_datum(
+            self.payout_address
+                .as_ref()
+                .map(|payout| PlutusData::new_bytes(payout.to_bytes())),
+        ));
+        fields.add(&maybe_datum(self.expires_at.map(integer_datum)));
+        constr_datum(0, &fields)
+    }
+}
+
+fn version_metadatum() -> TransactionMetadatum {
+    TransactionMetadatum::new_int(&Int::new(&to_bignum(SALE_METADATA_VERSION)))
+}
+
+fn integer_datum(n: u64) -> PlutusData {
+    PlutusData::new_integer(&BigInt::from_str(&n.to_string()).unwrap())
+}
+
+fn maybe_datum(value: Option<PlutusData>) -> PlutusData {
+    let mut fields = PlutusList::new();
+    match value {
+        Some(value) => {
+            fields.add(&value);
+            constr_datum(0, &fields)
+        }
+        None => constr_datum(1, &fields),
     }
 }
+
+fn constr_datum(alternative: u64, fields: &PlutusList) -> PlutusData {
+    PlutusData::new_constr_plutus_data(&ConstrPlutusData::new(&to_bignum(alternative), fields))
+}
@@ -0,0 +1,128 @@
+// The first pages of the listings, refreshed in the background so the
+// busiest read path is served from memory
+
+use super::holder::{Filters, MarketplaceHolder, SellData};
+use crate::Result;
+use cardano_serialization_lib::PolicyID;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Listings per page, as `get_nfts_for_sale` pages them
+const PAGE_SIZE: usize = 16;
+/// Policy filters counted towards popularity, so that requests for random
+/// policies cannot grow the counts without bound
+const MAX_TRACKED_POLICIES: usize = 1000;
+
+type PageKey = (Option<String>, u32);
+
+#[derive(Clone, Default)]
+pub struct ListingCache {
+    pages: Arc<RwLock<HashMap<PageKey, Arc<Vec<SellData>>>>>,
+    policy_hits: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl ListingCache {
+    /// The page as of the last refresh, if it is one of those kept. Requests
+    /// filtering by a policy count towards it being kept.
+    pub fn get(&self, policy_id: Option<&str>, page: u32) -> Option<Arc<Vec<SellData>>> {
+        if let Some(policy_id) = policy_id {
+            let mut hits = self.policy_hits.lock().unwrap();
+            if hits.len() < MAX_TRACKED_POLICIES || hits.contains_key(policy_id) {
+                *hits.entry(policy_id.to_string()).or_default() += 1;
+            }
+        }
+        self.pages
+            .read()
+            .unwrap()
+            .get(&(policy_id.map(str::to_string), page))
+            .cloned()
+    }
+
+    /// The most requested policies, with their counts halved so that
+    /// popularity follows recent traffic
+    fn popular_policies(&self, count: usize) -> Vec<String> {
+        let mut hits = self.policy_hits.lock().unwrap();
+        let mut ranked: Vec<(String, u64)> = hits.iter().map(|(p, n)| (p.clone(), *n)).collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1));
+        hits.retain(|_, n| {
+            *n /= 2;
+            *n > 0
+        });
+        ranked.into_iter().take(count).map(|(p, _)| p).collect()
+    }
+}
+
+/// Keeps the first `pages` pages of all listings, and of the
+/// `popular_policies` most requested policies, refreshed every
+/// `interval_seconds`. No pages disables it, every request then queries
+/// db-sync.
+pub fn spawn(
+    pool: PgPool,
+    holder: MarketplaceHolder,
+    pages: u32,
+    popular_policies: usize,
+    interval_seconds: u64,
+) -> ListingCache {
+    let cache = ListingCache::default();
+    if pages == 0 {
+        return cache;
+    }
+    let refreshed = cache.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+        loop {
+            interval.tick().await;
+            let mut policies = vec![None];
+            policies.extend(
+                refreshed
+                    .popular_policies(popular_policies)
+                    .into_iter()
+                    .map(Some),
+            );
+            match precompute(&pool, &holder, pages, policies).await {
+                Ok(precomputed) => *refreshed.pages.write().unwrap() = precomputed,
+                Err(e) => println!("Listing cache refresh failed: {}", e),
+            }
+        }
+    });
+    cache
+}
+
+async fn precompute(
+    pool: &PgPool,
+    holder: &MarketplaceHolder,
+    pages: u32,
+    policies: Vec<Option<String>>,
+) -> Result<HashMap<PageKey, Arc<Vec<SellData>>>> {
+    let mut precomputed = HashMap::new();
+    for policy_id in policies {
+        let policy = match &policy_id {
+            Some(policy_id) => Some(PolicyID::from_bytes(hex::decode(policy_id)?)?),
+            None => None,
+        };
+        for page in 1..=pages {
+            let listings = holder
+                .get_nfts_for_sale(
+                    pool,
+                    Filters {
+                        page,
+                        policy: policy.clone(),
+                        asset_name: None,
+                    },
+                )
+                .await?;
+            let last = listings.len() < PAGE_SIZE;
+            precomputed.insert((policy_id.clone(), page), Arc::new(listings));
+            // The pages past it are empty, and kept as such
+            if last {
+                for empty in page + 1..=pages {
+                    precomputed.insert((policy_id.clone(), empty), Arc::new(vec![]));
+                }
+                break;
+            }
+        }
+    }
+    Ok(precomputed)
+}
@@ -1,29 +1,69 @@
+use crate::cardano_db_sync::{Era, ProtocolParams};
 use crate::coin::TransactionWitnessSetParams;
 use crate::config::Config;
+use crate::marketplace::coupon::{apply_discount, get_redeemable_coupon, reserve_coupon};
+use crate::marketplace::fees::{get_discounted_fee_bps, get_policy_fee_bps};
 use crate::marketplace::holder::{MarketplaceHolder, SellMetadata};
+use crate::marketplace::royalty::get_royalties;
+use crate::purpose::{self, Purpose};
 use crate::{
-    cardano_db_sync::{get_protocol_params, get_slot_number, query_user_address_utxo},
-    coin::build_transaction_body,
-    convert_to_testnet, Error, Result,
+    chain::ChainQuery,
+    coin::{build_transaction_body, BuildSettings, LargestFirst},
+    configured_address, Error, Result,
 };
-use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::address::{Address, BaseAddress};
 use cardano_serialization_lib::crypto::Vkeywitnesses;
+use cardano_serialization_lib::plutus::PlutusData;
 use cardano_serialization_lib::utils::{
-    hash_transaction, to_bignum, TransactionUnspentOutput, Value,
+    from_bignum, hash_plutus_data, hash_transaction, min_ada_required, to_bignum,
+    TransactionUnspentOutput, Value,
 };
 use cardano_serialization_lib::{
     AssetName, Assets, MultiAsset, PolicyID, Transaction, TransactionOutput, TransactionWitnessSet,
 };
+use serde::Serialize;
 use sqlx::PgPool;
 
+pub mod approval;
+pub mod consolidate;
+pub mod coupon;
+pub mod estimate;
+pub mod export;
+pub mod fees;
+pub mod hidden;
 pub mod holder;
+pub mod listing_cache;
+pub mod offers;
+pub mod pending;
+pub mod rejected;
+pub mod royalty;
+pub mod sales;
+pub mod ticker;
 
 const ONE_HOUR: u32 = 3600;
+pub const MIN_SALE_PRICE: u64 = 5_000_000;
+/// Words a datum hash adds to an output under the Alonzo min-ADA rule
+const DATA_HASH_WORDS: u64 = 10;
 
 #[derive(Clone)]
 pub struct Marketplace {
     pub(crate) holder: MarketplaceHolder,
     pub(crate) revenue_address: Address,
+    fee_bps: u64,
+    min_fee: u64,
+    listing_deposit: Option<u64>,
+    coupon_fee_floor_bps: u64,
+    escrow_datum: bool,
+    approval_threshold: Option<u64>,
+    build: BuildSettings,
+}
+
+/// The commission taken on a sale
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedFee {
+    pub fee_bps: u64,
+    pub lovelace: u64,
 }
 
 impl Marketplace {
@@ -32,37 +72,108 @@ impl Marketplace {
             &config.marketplace_private_key_file,
             config.is_testnet,
         )?;
-        let mut revenue_address = Address::from_bech32(&config.marketplace_revenue_address)?;
+        let revenue_address =
+            configured_address(&config.marketplace_revenue_address, config.is_testnet)?;
+        if config.marketplace_fee_bps > 10_000 {
+            return Err(Error::Message(
+                "Marketplace fee cannot exceed 10000 basis points".to_string(),
+            ));
+        }
+        Ok(Self {
+            holder,
+            revenue_address,
+            fee_bps: config.marketplace_fee_bps,
+            min_fee: config.marketplace_min_fee_lovelace,
+            listing_deposit: config.listing_deposit_lovelace,
+            coupon_fee_floor_bps: config.coupon_fee_floor_bps,
+            escrow_datum: config.escrow_datum_hash,
+            approval_threshold: config.settlement_approval_threshold_lovelace,
+            build: BuildSettings::from_config(config),
+        })
+    }
 
-        if config.is_testnet {
-            revenue_address = convert_to_testnet(revenue_address);
+    /// The marketplace of a whitelabel tenant, with its own holder, revenue
+    /// address and fees. The fees default to those of `self`.
+    pub fn for_tenant(
+        &self,
+        holder: MarketplaceHolder,
+        revenue_address: Address,
+        fee_bps: Option<u64>,
+        min_fee: Option<u64>,
+    ) -> Result<Marketplace> {
+        let fee_bps = fee_bps.unwrap_or(self.fee_bps);
+        if fee_bps > 10_000 {
+            return Err(Error::Message(
+                "Marketplace fee cannot exceed 10000 basis points".to_string(),
+            ));
         }
         Ok(Self {
             holder,
             revenue_address,
+            fee_bps,
+            min_fee: min_fee.unwrap_or(self.min_fee),
+            ..self.clone()
         })
     }
 
-    pub async fn sell(
+    /// Returns the lovelace deposited alongside the NFT with the transaction,
+    /// it is paid back to the seller on sale. A listing with `live_at` cannot
+    /// be bought before that slot nor from `expires_at` on, one with
+    /// `allowed_buyer` only by that address. The proceeds go to
+    /// `payout_address` when given.
+    ///
+    /// When configured, the escrow output also carries the hash of the sale
+    /// terms as a datum, which is returned too. The NFT stays in the custody
+    /// of the holder key either way. Inline datums need a newer
+    /// serialization library than the one in use.
+    pub async fn sell<C: ChainQuery>(
         &self,
         seller_address: Address,
         policy_id: PolicyID,
         asset_name: AssetName,
         price: u64,
-        pool: &PgPool,
-    ) -> Result<Transaction> {
-        let seller_utxos = query_user_address_utxo(pool, &seller_address).await?;
+        live_at: Option<u64>,
+        expires_at: Option<u64>,
+        allowed_buyer: Option<Address>,
+        payout_address: Option<Address>,
+        chain: &C,
+    ) -> Result<(Transaction, u64, Option<PlutusData>)> {
+        if let (Some(live_at), Some(expires_at)) = (live_at, expires_at) {
+            if expires_at <= live_at {
+                return Err(Error::Message(
+                    "Listing cannot expire before it goes live".to_string(),
+                ));
+            }
+        }
+        let seller_utxos = chain.address_utxos(&seller_address).await?;
         let (nft_utxo, seller_utxos) = find_nft(seller_utxos, &policy_id, &asset_name)?;
 
-        let slot = get_slot_number(pool).await?;
-        let protocol_params = get_protocol_params(pool).await?;
+        let slot = chain.slot_number().await?;
+        let protocol_params = chain.protocol_params().await?;
         let tx_witness_params = TransactionWitnessSetParams {
             vkey_count: 1,
             ..Default::default()
         };
         let mut nft_value = create_value_with_single_nft(&policy_id, &asset_name);
-        nft_value.set_coin(&to_bignum(2_000_000));
-        let mut outputs = vec![TransactionOutput::new(&self.holder.address, &nft_value)];
+        let deposit = self.deposit(&nft_value, &protocol_params);
+        nft_value.set_coin(&to_bignum(deposit));
+        let mut escrow_output = TransactionOutput::new(&self.holder.address, &nft_value);
+        let seller_metadata = SellMetadata {
+            seller_address: seller_address.clone(),
+            price,
+            live_at,
+            allowed_buyer,
+            payout_address,
+            expires_at,
+        };
+        let datum = if self.escrow_datum {
+            let datum = seller_metadata.to_datum(&policy_id, &asset_name);
+            escrow_output.set_data_hash(&hash_plutus_data(&datum));
+            Some(datum)
+        } else {
+            None
+        };
+        let mut outputs = vec![escrow_output];
         if nft_utxo.output().amount().multiasset().unwrap().len() > 1 {
             // More assets attached to the NFT UTxO, need to create an output to return these assets
             let mut value = nft_utxo.output().amount();
@@ -73,10 +184,6 @@ impl Marketplace {
             value.set_multiasset(&ma);
             outputs.push(TransactionOutput::new(&seller_address, &value));
         }
-        let seller_metadata = SellMetadata {
-            seller_address: seller_address.clone(),
-            price,
-        };
         let auxiliary_data = Some(seller_metadata.create_sell_nft_metadata()?);
         let tx_body = build_transaction_body(
             seller_utxos,
@@ -88,49 +195,276 @@ impl Marketplace {
             None,
             &tx_witness_params,
             auxiliary_data.clone(),
+            &LargestFirst,
+            None,
+            &self.build,
+        )?;
+
+        Ok((
+            Transaction::new(&tx_body, &TransactionWitnessSet::new(), auxiliary_data),
+            deposit,
+            datum,
+        ))
+    }
+
+    /// Lists several NFTs of `seller_address` in one transaction, each at its
+    /// own price. Every NFT gets an escrow output of its own and an entry in
+    /// the sale metadata, so it can be bought or cancelled on its own later.
+    /// Returns the lovelace deposited across all the listings.
+    pub async fn sell_batch<C: ChainQuery>(
+        &self,
+        seller_address: Address,
+        items: Vec<(PolicyID, AssetName, u64)>,
+        chain: &C,
+    ) -> Result<(Transaction, u64)> {
+        if items.is_empty() {
+            return Err(Error::Message("No NFTs to list".to_string()));
+        }
+        for (i, (policy_id, asset_name, _)) in items.iter().enumerate() {
+            if items[..i].iter().any(|(other_policy, other_name, _)| {
+                other_policy.to_bytes() == policy_id.to_bytes()
+                    && other_name.name() == asset_name.name()
+            }) {
+                return Err(Error::Message(
+                    "The same NFT is listed more than once".to_string(),
+                ));
+            }
+        }
+
+        let mut seller_utxos = chain.address_utxos(&seller_address).await?;
+        let mut nft_utxos: Vec<TransactionUnspentOutput> = vec![];
+        for (policy_id, asset_name, _) in &items {
+            if nft_utxos
+                .iter()
+                .any(|utxo| utxo_holds(utxo, policy_id, asset_name))
+            {
+                continue;
+            }
+            let (nft_utxo, rest) = find_nft(seller_utxos, policy_id, asset_name)?;
+            nft_utxos.push(nft_utxo);
+            seller_utxos = rest;
+        }
+
+        let slot = chain.slot_number().await?;
+        let protocol_params = chain.protocol_params().await?;
+        let mut outputs = vec![];
+        let mut listings = vec![];
+        let mut listed = Value::new(&to_bignum(0));
+        let mut total_deposit = 0;
+        for (policy_id, asset_name, price) in items {
+            let mut nft_value = create_value_with_single_nft(&policy_id, &asset_name);
+            listed = listed.checked_add(&nft_value)?;
+            let deposit = self.deposit(&nft_value, &protocol_params);
+            nft_value.set_coin(&to_bignum(deposit));
+            total_deposit += deposit;
+
+            let mut escrow_output = TransactionOutput::new(&self.holder.address, &nft_value);
+            let sell_metadata = SellMetadata {
+                seller_address: seller_address.clone(),
+                price,
+                live_at: None,
+                allowed_buyer: None,
+                payout_address: None,
+                expires_at: None,
+            };
+            if self.escrow_datum {
+                let datum = sell_metadata.to_datum(&policy_id, &asset_name);
+                escrow_output.set_data_hash(&hash_plutus_data(&datum));
+            }
+            outputs.push(escrow_output);
+            listings.push((policy_id, asset_name, sell_metadata));
+        }
+
+        // Assets sharing a UTxO with a listed NFT go back to the seller
+        for nft_utxo in &nft_utxos {
+            let mut value = nft_utxo.output().amount();
+            let remaining = value
+                .multiasset()
+                .unwrap()
+                .sub(&listed.multiasset().unwrap());
+            if remaining.len() > 0 {
+                value.set_multiasset(&remaining);
+                outputs.push(TransactionOutput::new(&seller_address, &value));
+            }
+        }
+
+        let tx_witness_params = TransactionWitnessSetParams {
+            vkey_count: 1,
+            ..Default::default()
+        };
+        let auxiliary_data = Some(SellMetadata::create_batch_sell_metadata(
+            &seller_address,
+            &listings,
+        )?);
+        let tx_body = build_transaction_body(
+            seller_utxos,
+            nft_utxos,
+            outputs,
+            slot + ONE_HOUR,
+            &protocol_params,
+            None,
+            None,
+            &tx_witness_params,
+            auxiliary_data.clone(),
+            &LargestFirst,
+            None,
+            &self.build,
         )?;
 
-        Ok(Transaction::new(
-            &tx_body,
-            &TransactionWitnessSet::new(),
-            auxiliary_data,
+        Ok((
+            Transaction::new(&tx_body, &TransactionWitnessSet::new(), auxiliary_data),
+            total_deposit,
         ))
     }
 
-    pub async fn buy(
+    /// Chain data is read from `chain`, fee discounts, coupons and royalty
+    /// splits from the marketplace tables in `pool`. Returns the fee that was
+    /// applied alongside the transaction. The NFT goes to `recipient` instead
+    /// of the buyer when given, for gifts, change to `change_address`.
+    ///
+    /// From the configured threshold on, the holder signature is left off
+    /// and the price is returned last. An admin has to approve the purchase
+    /// before it is signed, see `approval`.
+    ///
+    /// Buyers sharing a stake credential with the seller are refused, as
+    /// such trades only inflate the stats, unless `allow_self_trade` says
+    /// the NFT is genuinely moving between one's own wallets.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn buy<C: ChainQuery>(
         &self,
         buyer_address: Address,
         policy_id: PolicyID,
         asset_name: AssetName,
+        coupon: Option<&str>,
+        recipient: Option<Address>,
+        change_address: Option<Address>,
+        allow_self_trade: bool,
+        chain: &C,
         pool: &PgPool,
-    ) -> Result<Transaction> {
-        let buyer_utxos = query_user_address_utxo(pool, &buyer_address).await?;
-        let sell_metadata = self.get_sell_details(pool, &policy_id, &asset_name).await?;
+    ) -> Result<(Transaction, AppliedFee, Option<u64>)> {
+        if let Some(recipient) = &recipient {
+            if recipient.network_id()? != self.holder.address.network_id()? {
+                return Err(Error::Message(
+                    "Recipient address is on the wrong network".to_string(),
+                ));
+            }
+        }
+        if let Some(change_address) = &change_address {
+            if change_address.network_id()? != self.holder.address.network_id()? {
+                return Err(Error::Message(
+                    "Change address is on the wrong network".to_string(),
+                ));
+            }
+        }
+        let buyer_utxos = chain.address_utxos(&buyer_address).await?;
+        let sell_metadata = self
+            .get_sell_details(chain, &policy_id, &asset_name)
+            .await?;
+        let slot = chain.slot_number().await?;
+        if let Some(live_at) = sell_metadata.live_at {
+            if (slot as u64) < live_at {
+                return Err(Error::Forbidden(format!(
+                    "Listing goes live at slot {}",
+                    live_at
+                )));
+            }
+        }
+        if let Some(expires_at) = sell_metadata.expires_at {
+            if slot as u64 >= expires_at {
+                return Err(Error::Forbidden(format!(
+                    "Listing expired at slot {}",
+                    expires_at
+                )));
+            }
+        }
+        if let Some(allowed_buyer) = &sell_metadata.allowed_buyer {
+            if allowed_buyer.to_bytes() != buyer_address.to_bytes() {
+                return Err(Error::Forbidden(
+                    "This listing is reserved for another buyer".to_string(),
+                ));
+            }
+        }
+        if !allow_self_trade && same_owner(&buyer_address, &sell_metadata.seller_address) {
+            return Err(Error::Forbidden(
+                "Buyer and seller share a stake credential".to_string(),
+            ));
+        }
 
-        let holder_utxos = query_user_address_utxo(pool, &self.holder.address).await?;
+        let holder_utxos = chain.address_utxos(&self.holder.address).await?;
         let (nft_utxo, _) = find_nft(holder_utxos, &policy_id, &asset_name)?;
 
-        let (revenue_cut, seller_cut) = calculate_cuts(sell_metadata.price);
+        // A policy override replaces the standard fee and its minimum, buyer
+        // discounts and coupons can still lower it
+        let policy_fee_bps = get_policy_fee_bps(pool, &policy_id).await?;
+        let min_fee = match policy_fee_bps {
+            Some(_) => 0,
+            None => self.min_fee,
+        };
+        let base_fee_bps = policy_fee_bps.unwrap_or(self.fee_bps);
+        let (mut fee_bps, min_fee) = discounted_fee(
+            base_fee_bps,
+            min_fee,
+            get_discounted_fee_bps(pool, &buyer_utxos).await?,
+        );
+        if let Some(code) = coupon {
+            let coupon = get_redeemable_coupon(pool, code).await?;
+            fee_bps = apply_discount(
+                fee_bps,
+                coupon.discount_bps as u64,
+                self.coupon_fee_floor_bps,
+            );
+        }
+        let deposit = from_bignum(&nft_utxo.output().amount().coin());
+        let (revenue_cut, mut seller_cut) = waive_small_fee(
+            calculate_cuts(sell_metadata.price, deposit, fee_bps, min_fee)?,
+            min_fee,
+        );
+
+        let royalty_outputs =
+            royalty_outputs(pool, &policy_id, sell_metadata.price, &mut seller_cut).await?;
 
-        let revenue_output =
-            TransactionOutput::new(&self.revenue_address, &Value::new(&to_bignum(revenue_cut)));
+        let revenue_output = if revenue_cut > 0 {
+            Some(TransactionOutput::new(
+                &self.revenue_address,
+                &Value::new(&to_bignum(revenue_cut)),
+            ))
+        } else {
+            None
+        };
 
         let seller_output = TransactionOutput::new(
-            &sell_metadata.seller_address,
+            sell_metadata
+                .payout_address
+                .as_ref()
+                .unwrap_or(&sell_metadata.seller_address),
             &Value::new(&to_bignum(seller_cut)),
         );
 
-        let nft_output = TransactionOutput::new(&buyer_address, &nft_utxo.output().amount());
+        let nft_output = TransactionOutput::new(
+            recipient.as_ref().unwrap_or(&buyer_address),
+            &nft_utxo.output().amount(),
+        );
+
+        let protocol_params = chain.protocol_params().await?;
+        check_min_ada(
+            royalty_outputs
+                .iter()
+                .chain(revenue_output.iter())
+                .chain(vec![&seller_output, &nft_output]),
+            &protocol_params,
+        )?;
 
-        let outputs = vec![revenue_output, seller_output, nft_output];
+        let mut outputs: Vec<_> = revenue_output.into_iter().collect();
+        outputs.extend(vec![seller_output, nft_output]);
+        outputs.extend(royalty_outputs);
+        let nft_input = nft_utxo.input();
         let inputs = vec![nft_utxo];
 
         let tx_witness_params = TransactionWitnessSetParams {
             vkey_count: 2,
             ..Default::default()
         };
-        let slot = get_slot_number(pool).await?;
-        let protocol_params = get_protocol_params(pool).await?;
+        let auxiliary_data = purpose::auxiliary_data(Purpose::Buy, &self.build)?;
 
         let tx_body = build_transaction_body(
             buyer_utxos,
@@ -141,28 +475,56 @@ impl Marketplace {
             None,
             None,
             &tx_witness_params,
-            None,
+            auxiliary_data.clone(),
+            &LargestFirst,
+            change_address.as_ref(),
+            &self.build,
         )?;
 
-        let tx_hash = hash_transaction(&tx_body);
-        let vkey = self.holder.sign_transaction_hash(&tx_hash);
+        let approval_price = Some(sell_metadata.price).filter(|price| {
+            self.approval_threshold
+                .map_or(false, |threshold| *price >= threshold)
+        });
         let mut tx_witness_set = TransactionWitnessSet::new();
-        let mut vkeys = Vkeywitnesses::new();
-        vkeys.add(&vkey);
-        tx_witness_set.set_vkeys(&vkeys);
+        if approval_price.is_none() {
+            let tx_hash = hash_transaction(&tx_body);
+            let mut vkeys = Vkeywitnesses::new();
+            vkeys.add(&self.holder.sign_transaction_hash(&tx_hash));
+            tx_witness_set.set_vkeys(&vkeys);
+        }
 
-        let tx = Transaction::new(&tx_body, &tx_witness_set, None);
-        Ok(tx)
+        // A cancel or another sale may have landed while this was being built
+        if !chain.utxo_unspent(&nft_input).await? {
+            return Err(Error::ListingUnavailable);
+        }
+
+        // Redeemed once the transaction is submitted
+        if let Some(code) = coupon {
+            let tx_hash = hex::encode(hash_transaction(&tx_body).to_bytes());
+            reserve_coupon(pool, code, &tx_hash, ONE_HOUR).await?;
+        }
+
+        let tx = Transaction::new(&tx_body, &tx_witness_set, auxiliary_data);
+        Ok((
+            tx,
+            AppliedFee {
+                fee_bps,
+                lovelace: revenue_cut,
+            },
+            approval_price,
+        ))
     }
 
-    pub async fn cancel(
+    pub async fn cancel<C: ChainQuery>(
         &self,
         seller_address: Address,
         policy_id: PolicyID,
         asset_name: AssetName,
-        pool: &PgPool,
+        chain: &C,
     ) -> Result<Transaction> {
-        let sell_metadata = self.get_sell_details(pool, &policy_id, &asset_name).await?;
+        let sell_metadata = self
+            .get_sell_details(chain, &policy_id, &asset_name)
+            .await?;
         if sell_metadata
             .seller_address
             .to_bytes()
@@ -173,8 +535,8 @@ impl Marketplace {
             ));
         }
 
-        let seller_utxos = query_user_address_utxo(pool, &seller_address).await?;
-        let holder_utxos = query_user_address_utxo(pool, &self.holder.address).await?;
+        let seller_utxos = chain.address_utxos(&seller_address).await?;
+        let holder_utxos = chain.address_utxos(&self.holder.address).await?;
         let (nft_utxo, _) = find_nft(holder_utxos, &policy_id, &asset_name)?;
 
         let nft_output =
@@ -190,8 +552,9 @@ impl Marketplace {
             vkey_count: 2,
             ..Default::default()
         };
-        let slot = get_slot_number(pool).await?;
-        let protocol_params = get_protocol_params(pool).await?;
+        let slot = chain.slot_number().await?;
+        let protocol_params = chain.protocol_params().await?;
+        let auxiliary_data = purpose::auxiliary_data(Purpose::Cancel, &self.build)?;
 
         let tx_body = build_transaction_body(
             seller_utxos,
@@ -202,7 +565,10 @@ impl Marketplace {
             None,
             None,
             &tx_witness_params,
+            auxiliary_data.clone(),
+            &LargestFirst,
             None,
+            &self.build,
         )?;
 
         let tx_hash = hash_transaction(&tx_body);
@@ -212,18 +578,32 @@ impl Marketplace {
         vkeys.add(&vkey);
         tx_witness_set.set_vkeys(&vkeys);
 
-        let tx = Transaction::new(&tx_body, &tx_witness_set, None);
+        let tx = Transaction::new(&tx_body, &tx_witness_set, auxiliary_data);
         Ok(tx)
     }
 
-    async fn get_sell_details(
+    /// The configured deposit, raised to what the escrow output of `nft_value`
+    /// needs at the least. The serialization library leaves out the datum hash
+    /// escrow outputs carry when configured, it is added here.
+    fn deposit(&self, nft_value: &Value, protocol_params: &ProtocolParams) -> u64 {
+        let mut min_deposit = from_bignum(&min_ada_required(
+            nft_value,
+            &protocol_params.minimum_utxo_value,
+        ));
+        if self.escrow_datum && protocol_params.era >= Era::Alonzo {
+            min_deposit += DATA_HASH_WORDS * from_bignum(&protocol_params.coins_per_utxo_word);
+        }
+        self.listing_deposit.unwrap_or(0).max(min_deposit)
+    }
+
+    async fn get_sell_details<C: ChainQuery>(
         &self,
-        pool: &PgPool,
+        chain: &C,
         policy_id: &PolicyID,
         asset_name: &AssetName,
     ) -> Result<SellMetadata> {
         self.holder
-            .get_nft_details(pool, &policy_id, &asset_name)
+            .get_nft_details(chain, &policy_id, &asset_name)
             .await?
             .ok_or_else(|| Error::Message("No such NFT is for sale".to_string()))
     }
@@ -231,15 +611,91 @@ impl Marketplace {
 
 const ONE_ADA: u64 = 1_000_000;
 
-fn calculate_cuts(price: u64) -> (u64, u64) {
-    let one_percent = price / 100;
-    let revenue_cut = (one_percent * 2).max(ONE_ADA);
-    // The seller put in 2 ADA as deposit
-    let seller_cut = price - revenue_cut + (ONE_ADA * 2);
-    (revenue_cut, seller_cut)
+/// Outputs paying the royalties of `policy_id` on a sale at `price`, taken
+/// out of `seller_cut`
+async fn royalty_outputs(
+    pool: &PgPool,
+    policy_id: &PolicyID,
+    price: u64,
+    seller_cut: &mut u64,
+) -> Result<Vec<TransactionOutput>> {
+    let mut outputs = vec![];
+    for recipient in get_royalties(pool, policy_id).await? {
+        let amount = recipient.amount(price);
+        // Royalties too small for an output of their own are waived
+        if amount < ONE_ADA {
+            continue;
+        }
+        *seller_cut = seller_cut.checked_sub(amount).ok_or_else(|| {
+            Error::Message("Royalties exceed the seller's share of the sale".to_string())
+        })?;
+        outputs.push(TransactionOutput::new(
+            &Address::from_bech32(&recipient.address)?,
+            &Value::new(&to_bignum(amount)),
+        ));
+    }
+    Ok(outputs)
+}
+
+/// The coin selection would silently top these up, changing the cuts
+fn check_min_ada<'a>(
+    outputs: impl Iterator<Item = &'a TransactionOutput>,
+    protocol_params: &ProtocolParams,
+) -> Result<()> {
+    for output in outputs {
+        let min_ada = min_ada_required(&output.amount(), &protocol_params.minimum_utxo_value);
+        if output.amount().coin().lt(&min_ada) {
+            return Err(Error::Message(format!(
+                "Sale would pay {} lovelace to {}, below the minimum of {}",
+                from_bignum(&output.amount().coin()),
+                output.address().to_bech32(None)?,
+                from_bignum(&min_ada)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Base addresses with the same stake credential belong to one wallet, as
+/// wallets rotate payment addresses. Others only match themselves.
+fn same_owner(a: &Address, b: &Address) -> bool {
+    match (BaseAddress::from_address(a), BaseAddress::from_address(b)) {
+        (Some(a), Some(b)) => a.stake_cred().to_bytes() == b.stake_cred().to_bytes(),
+        _ => a.to_bytes() == b.to_bytes(),
+    }
+}
+
+/// The fee and minimum fee for a buyer holding a partner asset discounted to
+/// `discount_bps`. A discount below the base fee waives the minimum, which
+/// would otherwise undo discounts on cheap sales.
+fn discounted_fee(base_fee_bps: u64, min_fee: u64, discount_bps: Option<u64>) -> (u64, u64) {
+    match discount_bps {
+        Some(discount_bps) if discount_bps < base_fee_bps => (discount_bps, 0),
+        _ => (base_fee_bps, min_fee),
+    }
+}
+
+/// Without a minimum fee, fees too small for an output of their own go to
+/// the seller, as royalties that small are waived
+fn waive_small_fee((revenue_cut, seller_cut): (u64, u64), min_fee: u64) -> (u64, u64) {
+    if revenue_cut < ONE_ADA && min_fee == 0 {
+        (0, seller_cut + revenue_cut)
+    } else {
+        (revenue_cut, seller_cut)
+    }
 }
 
-fn create_value_with_single_nft(policy_id: &PolicyID, asset_name: &AssetName) -> Value {
+fn calculate_cuts(price: u64, deposit: u64, fee_bps: u64, min_fee: u64) -> Result<(u64, u64)> {
+    let revenue_cut = (price / 10_000 * fee_bps + price % 10_000 * fee_bps / 10_000).max(min_fee);
+    // The seller gets back the deposit put in with the NFT
+    let seller_cut = price
+        .checked_sub(revenue_cut)
+        .ok_or_else(|| Error::Message("Marketplace fee exceeds the sale price".to_string()))?
+        + deposit;
+    Ok((revenue_cut, seller_cut))
+}
+
+pub(crate) fn create_value_with_single_nft(policy_id: &PolicyID, asset_name: &AssetName) -> Value {
     let mut value = Value::new(&to_bignum(0));
     value.set_multiasset(&{
         let mut ma = MultiAsset::new();
@@ -262,14 +718,7 @@ pub fn find_nft(
     let mut nft_utxo = None;
 
     for utxo in utxos {
-        if utxo
-            .output()
-            .amount()
-            .multiasset()
-            .and_then(|ma| ma.get(policy_id))
-            .and_then(|assets| assets.get(asset_name))
-            .is_some()
-        {
+        if utxo_holds(&utxo, policy_id, asset_name) {
             nft_utxo = Some(utxo);
         } else {
             remaining_utxos.push(utxo);
@@ -280,3 +729,222 @@ pub fn find_nft(
         .ok_or_else(|| Error::Message("No such NFT is for sale".to_string()))
         .map(|nft| (nft, remaining_utxos))
 }
+
+fn utxo_holds(
+    utxo: &TransactionUnspentOutput,
+    policy_id: &PolicyID,
+    asset_name: &AssetName,
+) -> bool {
+    utxo.output()
+        .amount()
+        .multiasset()
+        .and_then(|ma| ma.get(policy_id))
+        .and_then(|assets| assets.get(asset_name))
+        .is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::memory::{new_address, unreachable_pool, MemoryChain};
+    use cardano_serialization_lib::crypto::PrivateKey;
+
+    const SLOT: u32 = 50_000_000;
+
+    fn marketplace() -> Marketplace {
+        Marketplace {
+            holder: MarketplaceHolder::from_key(PrivateKey::generate_ed25519().unwrap(), 0)
+                .unwrap(),
+            revenue_address: new_address(),
+            fee_bps: 250,
+            min_fee: 1_000_000,
+            listing_deposit: None,
+            coupon_fee_floor_bps: 0,
+            escrow_datum: false,
+            approval_threshold: None,
+            build: BuildSettings {
+                fee_padding: 0,
+                max_input_count: 20,
+                mint_attribution: None,
+                tag_purpose: false,
+            },
+        }
+    }
+
+    fn nft() -> (PolicyID, AssetName) {
+        (
+            PolicyID::from_bytes(vec![7; 28]).unwrap(),
+            AssetName::new(b"Rare".to_vec()).unwrap(),
+        )
+    }
+
+    fn listing(seller_address: &Address) -> SellMetadata {
+        SellMetadata {
+            seller_address: seller_address.clone(),
+            price: 10_000_000,
+            live_at: None,
+            allowed_buyer: None,
+            payout_address: None,
+            expires_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn escrows_the_nft_at_the_holder() {
+        let marketplace = marketplace();
+        let (policy_id, asset_name) = nft();
+        let seller = new_address();
+        let mut chain = MemoryChain::mary(SLOT);
+        let mut nft_value = create_value_with_single_nft(&policy_id, &asset_name);
+        nft_value.set_coin(&to_bignum(2_000_000));
+        let nft_utxo = chain.pay(&seller, &nft_value);
+        chain.pay(&seller, &Value::new(&to_bignum(10_000_000)));
+
+        let (tx, deposit, datum) = marketplace
+            .sell(
+                seller,
+                policy_id.clone(),
+                asset_name.clone(),
+                10_000_000,
+                None,
+                None,
+                None,
+                None,
+                &chain,
+            )
+            .await
+            .unwrap();
+        assert!(datum.is_none());
+        let body = tx.body();
+        let inputs: Vec<_> = (0..body.inputs().len())
+            .map(|i| body.inputs().get(i).to_bytes())
+            .collect();
+        assert!(inputs.contains(&nft_utxo.input().to_bytes()));
+        let escrow = body.outputs().get(0);
+        assert_eq!(
+            escrow.address().to_bytes(),
+            marketplace.holder.address.to_bytes()
+        );
+        assert_eq!(from_bignum(&escrow.amount().coin()), deposit);
+        let escrowed = escrow.amount().multiasset().unwrap();
+        assert_eq!(escrowed.len(), 1);
+        assert_eq!(
+            escrowed
+                .get(&policy_id)
+                .and_then(|assets| assets.get(&asset_name))
+                .map(|quantity| from_bignum(&quantity)),
+            Some(1)
+        );
+        assert_eq!(body.ttl(), Some(SLOT + ONE_HOUR));
+    }
+
+    #[tokio::test]
+    async fn refuses_listings_not_open_to_the_buyer() {
+        let marketplace = marketplace();
+        let (policy_id, asset_name) = nft();
+        let seller = new_address();
+        let buyer = new_address();
+        let pool = unreachable_pool();
+        let mut chain = MemoryChain::mary(SLOT);
+        chain.pay(&buyer, &Value::new(&to_bignum(20_000_000)));
+
+        let not_live = SellMetadata {
+            live_at: Some(SLOT as u64 + 1),
+            ..listing(&seller)
+        };
+        let expired = SellMetadata {
+            expires_at: Some(SLOT as u64),
+            ..listing(&seller)
+        };
+        let private = SellMetadata {
+            allowed_buyer: Some(new_address()),
+            ..listing(&seller)
+        };
+        for sell_metadata in &[not_live, expired, private] {
+            chain.add_listing(
+                &marketplace.holder.address,
+                &policy_id,
+                &asset_name,
+                sell_metadata.clone(),
+            );
+            let bought = marketplace
+                .buy(
+                    buyer.clone(),
+                    policy_id.clone(),
+                    asset_name.clone(),
+                    None,
+                    None,
+                    None,
+                    false,
+                    &chain,
+                    &pool,
+                )
+                .await;
+            assert!(matches!(bought, Err(Error::Forbidden(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_self_trades() {
+        let marketplace = marketplace();
+        let (policy_id, asset_name) = nft();
+        let seller = new_address();
+        let mut chain = MemoryChain::mary(SLOT);
+        chain.pay(&seller, &Value::new(&to_bignum(20_000_000)));
+        chain.add_listing(
+            &marketplace.holder.address,
+            &policy_id,
+            &asset_name,
+            listing(&seller),
+        );
+
+        let bought = marketplace
+            .buy(
+                seller,
+                policy_id,
+                asset_name,
+                None,
+                None,
+                None,
+                false,
+                &chain,
+                &unreachable_pool(),
+            )
+            .await;
+        assert!(matches!(bought, Err(Error::Forbidden(_))));
+    }
+
+    #[test]
+    fn waives_the_minimum_fee_for_discounts() {
+        assert_eq!(discounted_fee(250, 1_000_000, None), (250, 1_000_000));
+        assert_eq!(discounted_fee(250, 1_000_000, Some(300)), (250, 1_000_000));
+        assert_eq!(discounted_fee(250, 1_000_000, Some(100)), (100, 0));
+
+        let (fee_bps, min_fee) = discounted_fee(250, 1_000_000, Some(0));
+        assert_eq!(
+            calculate_cuts(10_000_000, 2_000_000, fee_bps, min_fee).unwrap(),
+            (0, 12_000_000)
+        );
+    }
+
+    #[test]
+    fn folds_fees_below_one_ada_into_the_seller_cut() {
+        let (fee_bps, min_fee) = discounted_fee(250, 1_000_000, Some(100));
+        let cuts = calculate_cuts(10_000_000, 2_000_000, fee_bps, min_fee).unwrap();
+        assert_eq!(cuts, (100_000, 11_900_000));
+        assert_eq!(waive_small_fee(cuts, min_fee), (0, 12_000_000));
+
+        let cuts = calculate_cuts(100_000_000, 2_000_000, fee_bps, min_fee).unwrap();
+        assert_eq!(waive_small_fee(cuts, min_fee), (1_000_000, 101_000_000));
+        let cuts = calculate_cuts(10_000_000, 2_000_000, 250, 1_000_000).unwrap();
+        assert_eq!(waive_small_fee(cuts, 1_000_000), (1_000_000, 11_000_000));
+    }
+
+    #[test]
+    fn charges_the_minimum_fee_without_discount() {
+        assert_eq!(
+            calculate_cuts(10_000_000, 2_000_000, 250, 1_000_000).unwrap(),
+            (1_000_000, 11_000_000)
+        );
+    }
+}
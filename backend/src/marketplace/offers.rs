@@ -0,0 +1,643 @@
+// Bids on NFTs, the bid ADA escrowed at the holder until the owner accepts
+// it, the bidder withdraws it or it expires and is refunded
+
+use super::fees::get_policy_fee_bps;
+use super::holder::MarketplaceHolder;
+use super::{
+    calculate_cuts, check_min_ada, create_value_with_single_nft, find_nft, royalty_outputs,
+    same_owner, waive_small_fee, AppliedFee, Marketplace, ONE_HOUR,
+};
+use crate::audit::AuditEntry;
+use crate::cardano_db_sync::ProtocolParams;
+use crate::chain::ChainQuery;
+use crate::coin::{build_sweep, build_transaction_body, LargestFirst, TransactionWitnessSetParams};
+use crate::purpose::{self, Purpose};
+use crate::transaction::TxSubmitter;
+use crate::{Error, Result};
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::crypto::Vkeywitnesses;
+use cardano_serialization_lib::metadata::AuxiliaryData;
+use cardano_serialization_lib::utils::{
+    hash_transaction, min_ada_required, to_bignum, TransactionUnspentOutput, Value,
+};
+use cardano_serialization_lib::{
+    AssetName, PolicyID, Transaction, TransactionBody, TransactionOutput, TransactionWitnessSet,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Expired offers refunded per holder and run
+const MAX_REFUNDS: i64 = 50;
+
+/// Offers at `$1` whose escrow output is on chain and unspent
+const ESCROWED_OFFERS_QUERY: &str = r#"
+    SELECT
+        offer.tx_hash,
+        offer.output_index,
+        offer.bidder,
+        offer.policy_id,
+        offer.asset_name,
+        offer.price,
+        offer.expires_at
+    FROM marketplace.offer
+    INNER JOIN tx ON tx.hash = decode(offer.tx_hash, 'hex')
+    WHERE offer.holder = $1
+    AND NOT EXISTS (
+        SELECT 1 FROM tx_in
+        WHERE tx_in.tx_out_id = tx.id AND tx_in.tx_out_index = offer.output_index
+    )
+"#;
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Offer {
+    /// The transaction escrowing the bid, which identifies the offer
+    pub tx_hash: String,
+    pub output_index: u32,
+    pub bidder_address: String,
+    pub policy_id: String,
+    /// Hex, as asset names need not be UTF-8
+    #[serde(serialize_with = "hex::serialize")]
+    pub asset_name: Vec<u8>,
+    pub price: u64,
+    pub expires_at: u64,
+}
+
+#[derive(sqlx::FromRow)]
+struct PgOffer {
+    tx_hash: String,
+    output_index: i32,
+    bidder: String,
+    policy_id: Vec<u8>,
+    asset_name: Vec<u8>,
+    price: i64,
+    expires_at: i64,
+}
+
+impl From<PgOffer> for Offer {
+    fn from(row: PgOffer) -> Self {
+        Self {
+            tx_hash: row.tx_hash,
+            output_index: row.output_index as u32,
+            bidder_address: row.bidder,
+            policy_id: hex::encode(row.policy_id),
+            asset_name: row.asset_name,
+            price: row.price as u64,
+            expires_at: row.expires_at as u64,
+        }
+    }
+}
+
+impl Offer {
+    /// The escrow output among the unspent outputs of the holder
+    fn find_escrow(
+        &self,
+        holder_utxos: &[TransactionUnspentOutput],
+    ) -> Result<TransactionUnspentOutput> {
+        holder_utxos
+            .iter()
+            .find(|utxo| {
+                hex::encode(utxo.input().transaction_id().to_bytes()) == self.tx_hash
+                    && utxo.input().index() == self.output_index
+            })
+            .cloned()
+            .ok_or_else(|| Error::Message("The offer is not escrowed, or no longer".to_string()))
+    }
+}
+
+impl Marketplace {
+    /// Builds the transaction paying the bid of `bidder_address` to the
+    /// holder, to be signed by the bidder. The offer is recorded right away
+    /// but only counts once that transaction is on chain.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn offer<C: ChainQuery>(
+        &self,
+        bidder_address: Address,
+        policy_id: PolicyID,
+        asset_name: AssetName,
+        price: u64,
+        expires_at: u64,
+        chain: &C,
+        pool: &PgPool,
+    ) -> Result<(Transaction, Offer)> {
+        if bidder_address.network_id()? != self.holder.address.network_id()? {
+            return Err(Error::Message(
+                "Bidder address is on the wrong network".to_string(),
+            ));
+        }
+        if bidder_address.to_bytes() == self.holder.address.to_bytes() {
+            return Err(Error::Message("The holder cannot make offers".to_string()));
+        }
+        let slot = chain.slot_number().await?;
+        if expires_at <= slot as u64 {
+            return Err(Error::Message(
+                "Offer must expire after the current slot".to_string(),
+            ));
+        }
+
+        let protocol_params = chain.protocol_params().await?;
+        let escrow_output =
+            TransactionOutput::new(&self.holder.address, &Value::new(&to_bignum(price)));
+        check_min_ada(std::iter::once(&escrow_output), &protocol_params)?;
+        // Refused now rather than when the owner tries to accept. The seller
+        // is not known yet, ADA-only outputs need the same minimum anywhere.
+        self.offer_payouts(
+            pool,
+            &policy_id,
+            price,
+            &self.holder.address,
+            &protocol_params,
+        )
+        .await?;
+
+        let bidder_utxos = chain.address_utxos(&bidder_address).await?;
+        let tx_witness_params = TransactionWitnessSetParams {
+            vkey_count: 1,
+            ..Default::default()
+        };
+        let auxiliary_data = purpose::auxiliary_data(Purpose::Offer, &self.build)?;
+        let tx_body = build_transaction_body(
+            bidder_utxos,
+            vec![],
+            vec![escrow_output],
+            slot + ONE_HOUR,
+            &protocol_params,
+            None,
+            None,
+            &tx_witness_params,
+            auxiliary_data.clone(),
+            &LargestFirst,
+            None,
+            &self.build,
+        )?;
+
+        let offer = Offer {
+            tx_hash: hex::encode(hash_transaction(&tx_body).to_bytes()),
+            output_index: escrow_index(&tx_body, &self.holder.address)?,
+            bidder_address: bidder_address.to_bech32(None)?,
+            policy_id: hex::encode(policy_id.to_bytes()),
+            asset_name: asset_name.name(),
+            price,
+            expires_at,
+        };
+        record_offer(pool, &self.holder.address, &offer).await?;
+        Ok((
+            Transaction::new(&tx_body, &TransactionWitnessSet::new(), auxiliary_data),
+            offer,
+        ))
+    }
+
+    /// The owner of the NFT takes the offer: the NFT goes to the bidder and
+    /// the escrowed bid to the owner, less the marketplace fee and
+    /// royalties, in one transaction. The owner pays the transaction fee
+    /// and the ADA sent along with the NFT.
+    pub async fn accept_offer<C: ChainQuery>(
+        &self,
+        seller_address: Address,
+        offer_hash: &str,
+        chain: &C,
+        pool: &PgPool,
+    ) -> Result<(Transaction, AppliedFee)> {
+        let offer = get_offer(pool, &self.holder.address, offer_hash).await?;
+        let slot = chain.slot_number().await?;
+        if slot as u64 >= offer.expires_at {
+            return Err(Error::Forbidden(format!(
+                "Offer expired at slot {}",
+                offer.expires_at
+            )));
+        }
+        let bidder_address = Address::from_bech32(&offer.bidder_address)?;
+        if same_owner(&seller_address, &bidder_address) {
+            return Err(Error::Forbidden(
+                "Bidder and seller share a stake credential".to_string(),
+            ));
+        }
+        let policy_id = PolicyID::from_bytes(hex::decode(&offer.policy_id)?)?;
+        let asset_name = AssetName::new(offer.asset_name.clone())?;
+
+        let holder_utxos = chain.address_utxos(&self.holder.address).await?;
+        let escrow_utxo = offer.find_escrow(&holder_utxos)?;
+        let seller_utxos = chain.address_utxos(&seller_address).await?;
+        let (nft_utxo, seller_utxos) = find_nft(seller_utxos, &policy_id, &asset_name)
+            .map_err(|_| Error::Message("The seller does not hold the NFT".to_string()))?;
+
+        let protocol_params = chain.protocol_params().await?;
+        let (mut outputs, fee) = self
+            .offer_payouts(
+                pool,
+                &policy_id,
+                offer.price,
+                &seller_address,
+                &protocol_params,
+            )
+            .await?;
+
+        let mut nft_value = create_value_with_single_nft(&policy_id, &asset_name);
+        nft_value.set_coin(&min_ada_required(
+            &nft_value,
+            &protocol_params.minimum_utxo_value,
+        ));
+        outputs.push(TransactionOutput::new(&bidder_address, &nft_value));
+        if nft_utxo.output().amount().multiasset().unwrap().len() > 1 {
+            // Assets sharing a UTxO with the NFT go back to the seller
+            let mut value = nft_utxo.output().amount();
+            let ma = value
+                .multiasset()
+                .unwrap()
+                .sub(&nft_value.multiasset().unwrap());
+            value.set_multiasset(&ma);
+            outputs.push(TransactionOutput::new(&seller_address, &value));
+        }
+        let escrow_input = escrow_utxo.input();
+        let inputs = vec![nft_utxo, escrow_utxo];
+
+        let tx_witness_params = TransactionWitnessSetParams {
+            vkey_count: 2,
+            ..Default::default()
+        };
+        let auxiliary_data = purpose::auxiliary_data(Purpose::AcceptOffer, &self.build)?;
+        // Cannot land once the offer has expired
+        let ttl = (slot as u64 + ONE_HOUR as u64).min(offer.expires_at) as u32;
+        let tx_body = build_transaction_body(
+            seller_utxos,
+            inputs,
+            outputs,
+            ttl,
+            &protocol_params,
+            None,
+            None,
+            &tx_witness_params,
+            auxiliary_data.clone(),
+            &LargestFirst,
+            None,
+            &self.build,
+        )?;
+
+        // The bid may have been withdrawn while this was being built
+        if !chain.utxo_unspent(&escrow_input).await? {
+            return Err(Error::Message(
+                "The offer is not escrowed, or no longer".to_string(),
+            ));
+        }
+
+        Ok((self.sign(tx_body, auxiliary_data), fee))
+    }
+
+    /// The outputs paying out a bid of `price` on an NFT of `policy_id`: the
+    /// marketplace fee, the rest to `seller_address` less royalties. Policy
+    /// overrides apply as on sales, buyer discounts and coupons do not, the
+    /// bid already says what the bidder pays.
+    async fn offer_payouts(
+        &self,
+        pool: &PgPool,
+        policy_id: &PolicyID,
+        price: u64,
+        seller_address: &Address,
+        protocol_params: &ProtocolParams,
+    ) -> Result<(Vec<TransactionOutput>, AppliedFee)> {
+        let (fee_bps, min_fee) = match get_policy_fee_bps(pool, policy_id).await? {
+            Some(fee_bps) => (fee_bps, 0),
+            None => (self.fee_bps, self.min_fee),
+        };
+        let (revenue_cut, mut seller_cut) =
+            waive_small_fee(calculate_cuts(price, 0, fee_bps, min_fee)?, min_fee);
+        let royalty_outputs = royalty_outputs(pool, policy_id, price, &mut seller_cut).await?;
+
+        let mut outputs = vec![];
+        if revenue_cut > 0 {
+            outputs.push(TransactionOutput::new(
+                &self.revenue_address,
+                &Value::new(&to_bignum(revenue_cut)),
+            ));
+        }
+        outputs.push(TransactionOutput::new(
+            seller_address,
+            &Value::new(&to_bignum(seller_cut)),
+        ));
+        outputs.extend(royalty_outputs);
+        check_min_ada(outputs.iter(), protocol_params)?;
+        Ok((
+            outputs,
+            AppliedFee {
+                fee_bps,
+                lovelace: revenue_cut,
+            },
+        ))
+    }
+
+    /// Returns the escrowed bid to the bidder, who signs for and pays the
+    /// transaction fee. Expired offers are refunded by `spawn` as well.
+    pub async fn withdraw_offer<C: ChainQuery>(
+        &self,
+        bidder_address: Address,
+        offer_hash: &str,
+        chain: &C,
+        pool: &PgPool,
+    ) -> Result<Transaction> {
+        let offer = get_offer(pool, &self.holder.address, offer_hash).await?;
+        if offer.bidder_address != bidder_address.to_bech32(None)? {
+            return Err(Error::Message(
+                "Only the bidder can withdraw the offer".to_string(),
+            ));
+        }
+
+        let holder_utxos = chain.address_utxos(&self.holder.address).await?;
+        let escrow_utxo = offer.find_escrow(&holder_utxos)?;
+        let bidder_utxos = chain.address_utxos(&bidder_address).await?;
+        let refund_output = TransactionOutput::new(&bidder_address, &escrow_utxo.output().amount());
+
+        let tx_witness_params = TransactionWitnessSetParams {
+            vkey_count: 2,
+            ..Default::default()
+        };
+        let slot = chain.slot_number().await?;
+        let protocol_params = chain.protocol_params().await?;
+        let auxiliary_data = purpose::auxiliary_data(Purpose::WithdrawOffer, &self.build)?;
+        let tx_body = build_transaction_body(
+            bidder_utxos,
+            vec![escrow_utxo],
+            vec![refund_output],
+            slot + ONE_HOUR,
+            &protocol_params,
+            None,
+            None,
+            &tx_witness_params,
+            auxiliary_data.clone(),
+            &LargestFirst,
+            None,
+            &self.build,
+        )?;
+
+        Ok(self.sign(tx_body, auxiliary_data))
+    }
+
+    fn sign(&self, tx_body: TransactionBody, auxiliary_data: Option<AuxiliaryData>) -> Transaction {
+        let mut tx_witness_set = TransactionWitnessSet::new();
+        let mut vkeys = Vkeywitnesses::new();
+        vkeys.add(
+            &self
+                .holder
+                .sign_transaction_hash(&hash_transaction(&tx_body)),
+        );
+        tx_witness_set.set_vkeys(&vkeys);
+        Transaction::new(&tx_body, &tx_witness_set, auxiliary_data)
+    }
+}
+
+/// The output paying the bid to the holder, the change goes to the bidder
+fn escrow_index(tx_body: &TransactionBody, holder: &Address) -> Result<u32> {
+    let outputs = tx_body.outputs();
+    (0..outputs.len())
+        .find(|i| outputs.get(*i).address().to_bytes() == holder.to_bytes())
+        .map(|i| i as u32)
+        .ok_or_else(|| Error::Message("Offer transaction has no escrow output".to_string()))
+}
+
+async fn record_offer(pool: &PgPool, holder: &Address, offer: &Offer) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO marketplace.offer (
+            tx_hash, output_index, holder, bidder, policy_id, asset_name, price, expires_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+    )
+    .bind(&offer.tx_hash)
+    .bind(offer.output_index as i32)
+    .bind(holder.to_bech32(None)?)
+    .bind(&offer.bidder_address)
+    .bind(hex::decode(&offer.policy_id)?)
+    .bind(&offer.asset_name)
+    .bind(offer.price as i64)
+    .bind(offer.expires_at as i64)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// An offer at `holder`, escrowed or not
+async fn get_offer(pool: &PgPool, holder: &Address, tx_hash: &str) -> Result<Offer> {
+    sqlx::query_as::<_, PgOffer>(
+        r#"
+        SELECT tx_hash, output_index, bidder, policy_id, asset_name, price, expires_at
+        FROM marketplace.offer
+        WHERE holder = $1 AND tx_hash = $2
+        "#,
+    )
+    .bind(holder.to_bech32(None)?)
+    .bind(tx_hash)
+    .fetch_optional(pool)
+    .await?
+    .map(Offer::from)
+    .ok_or_else(|| Error::NotFound("No such offer".to_string()))
+}
+
+/// Escrowed offers on an NFT that can still be accepted, highest first
+pub async fn get_open_offers(
+    pool: &PgPool,
+    holder: &Address,
+    policy_id: &PolicyID,
+    asset_name: &AssetName,
+) -> Result<Vec<Offer>> {
+    let rows = sqlx::query_as::<_, PgOffer>(&format!(
+        r#"{}
+        AND offer.policy_id = $2
+        AND offer.asset_name = $3
+        AND offer.expires_at > (SELECT max(slot_no) FROM block)
+        ORDER BY offer.price DESC
+        "#,
+        ESCROWED_OFFERS_QUERY
+    ))
+    .bind(holder.to_bech32(None)?)
+    .bind(policy_id.to_bytes())
+    .bind(asset_name.name())
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(Offer::from).collect())
+}
+
+/// Escrowed offers of a bidder, expired ones waiting for their refund too
+pub async fn get_bidder_offers(
+    pool: &PgPool,
+    holder: &Address,
+    bidder: &Address,
+) -> Result<Vec<Offer>> {
+    let rows = sqlx::query_as::<_, PgOffer>(&format!(
+        "{} AND offer.bidder = $2 ORDER BY offer.created_at DESC",
+        ESCROWED_OFFERS_QUERY
+    ))
+    .bind(holder.to_bech32(None)?)
+    .bind(bidder.to_bech32(None)?)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(Offer::from).collect())
+}
+
+/// The outputs at `holder` escrowing bids, as transaction hash and index.
+/// They are paid out or refunded one by one, never merged.
+pub async fn get_escrow_outputs(pool: &PgPool, holder: &Address) -> Result<HashSet<(String, u32)>> {
+    let rows = sqlx::query_as::<_, PgOffer>(ESCROWED_OFFERS_QUERY)
+        .bind(holder.to_bech32(None)?)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.tx_hash, row.output_index as u32))
+        .collect())
+}
+
+async fn get_expired_offers(pool: &PgPool, holder: &Address) -> Result<Vec<Offer>> {
+    let rows = sqlx::query_as::<_, PgOffer>(&format!(
+        r#"{}
+        AND offer.expires_at <= (SELECT max(slot_no) FROM block)
+        ORDER BY offer.expires_at
+        LIMIT $2
+        "#,
+        ESCROWED_OFFERS_QUERY
+    ))
+    .bind(holder.to_bech32(None)?)
+    .bind(MAX_REFUNDS)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(Offer::from).collect())
+}
+
+/// Refunds the expired offers escrowed at `holders` every
+/// `interval_seconds`. The transaction fee comes out of the bid, so only
+/// the holder signs.
+pub fn spawn(
+    pool: PgPool,
+    submitter: Arc<dyn TxSubmitter>,
+    holders: Vec<MarketplaceHolder>,
+    interval_seconds: u64,
+) {
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+        loop {
+            interval.tick().await;
+            for holder in &holders {
+                if let Err(e) = refund_expired(&pool, &pool, submitter.as_ref(), holder).await {
+                    println!("Refunding expired offers failed: {}", e);
+                }
+            }
+        }
+    });
+}
+
+async fn refund_expired<C: ChainQuery>(
+    chain: &C,
+    pool: &PgPool,
+    submitter: &dyn TxSubmitter,
+    holder: &MarketplaceHolder,
+) -> Result<()> {
+    let offers = get_expired_offers(pool, &holder.address).await?;
+    if offers.is_empty() {
+        return Ok(());
+    }
+    refund_offers(chain, pool, submitter, holder, offers).await
+}
+
+/// Pays the bids of `offers` back to their bidders, one transaction each.
+/// Chain data is read from `chain`, the refunds are audited in `pool`.
+async fn refund_offers<C: ChainQuery>(
+    chain: &C,
+    pool: &PgPool,
+    submitter: &dyn TxSubmitter,
+    holder: &MarketplaceHolder,
+    offers: Vec<Offer>,
+) -> Result<()> {
+    let holder_utxos = chain.address_utxos(&holder.address).await?;
+    let slot = chain.slot_number().await?;
+    let params = chain.protocol_params().await?;
+    for offer in offers {
+        // Spent since the offers were read
+        let escrow_utxo = match offer.find_escrow(&holder_utxos) {
+            Ok(escrow_utxo) => escrow_utxo,
+            Err(_) => continue,
+        };
+        let bidder_address = Address::from_bech32(&offer.bidder_address)?;
+        let tx_body = match build_sweep(&[escrow_utxo], &bidder_address, &params, slot + ONE_HOUR)?
+        {
+            Some(tx_body) => tx_body,
+            None => {
+                println!("Offer {} cannot cover its refund fee", offer.tx_hash);
+                continue;
+            }
+        };
+        let mut witness_set = TransactionWitnessSet::new();
+        let mut vkeys = Vkeywitnesses::new();
+        vkeys.add(&holder.sign_transaction_hash(&hash_transaction(&tx_body)));
+        witness_set.set_vkeys(&vkeys);
+        let tx = Transaction::new(&tx_body, &witness_set, None);
+
+        match submitter.submit_tx(&tx).await {
+            Ok(tx_hash) => {
+                println!("Refunded expired offer {} in {}", offer.tx_hash, tx_hash);
+                AuditEntry::new("offer_refund")
+                    .address(&bidder_address)
+                    .price(offer.price)
+                    .submitted(pool, &tx, &tx_hash)
+                    .await;
+            }
+            Err(e) => println!("Refunding offer {} failed: {}", offer.tx_hash, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::memory::{new_address, unreachable_pool, MemoryChain};
+    use crate::transaction::MockSubmitter;
+    use cardano_serialization_lib::crypto::PrivateKey;
+    use cardano_serialization_lib::utils::from_bignum;
+
+    #[tokio::test]
+    async fn refunds_escrowed_bids_to_their_bidders() {
+        let holder =
+            MarketplaceHolder::from_key(PrivateKey::generate_ed25519().unwrap(), 0).unwrap();
+        let bidder = new_address();
+        let mut chain = MemoryChain::mary(50_000_000);
+        let escrow = chain.pay(&holder.address, &Value::new(&to_bignum(15_000_000)));
+        let offer = |tx_hash: String| Offer {
+            tx_hash,
+            output_index: 0,
+            bidder_address: bidder.to_bech32(None).unwrap(),
+            policy_id: hex::encode([7; 28]),
+            asset_name: b"Rare".to_vec(),
+            price: 15_000_000,
+            expires_at: 49_000_000,
+        };
+        let escrowed = offer(hex::encode(escrow.input().transaction_id().to_bytes()));
+        // Withdrawn since the offers were read
+        let withdrawn = offer(hex::encode([0xee; 32]));
+        let submitter = MockSubmitter::default();
+
+        refund_offers(
+            &chain,
+            &unreachable_pool(),
+            &submitter,
+            &holder,
+            vec![escrowed, withdrawn],
+        )
+        .await
+        .unwrap();
+        let submitted = submitter.submitted.lock().unwrap();
+        assert_eq!(submitted.len(), 1);
+        let body = submitted[0].body();
+        assert_eq!(body.inputs().len(), 1);
+        assert_eq!(body.inputs().get(0).to_bytes(), escrow.input().to_bytes());
+        assert_eq!(body.outputs().len(), 1);
+        let refund = body.outputs().get(0);
+        assert_eq!(refund.address().to_bytes(), bidder.to_bytes());
+        assert_eq!(
+            from_bignum(&refund.amount().coin()) + from_bignum(&body.fee()),
+            15_000_000
+        );
+        assert_eq!(submitted[0].witness_set().vkeys().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,181 @@
+// Sells submitted to the node that db-sync has not indexed yet
+
+use super::hidden::not_hidden;
+use super::holder::{PgSellData, SellData, SellMetadata, MARKETPLACE_METADATA_LABEL_KEY};
+use crate::Result;
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::metadata::{decode_metadatum_to_json_str, MetadataJsonSchema};
+use cardano_serialization_lib::utils::to_bignum;
+use cardano_serialization_lib::Transaction;
+use sqlx::PgPool;
+
+/// Rows of pending sales that are neither indexed nor expired, shaped like
+/// the db-sync listing queries
+const PENDING_SALES_QUERY: &str = r#"
+    SELECT
+        pending_sale.tx_hash AS hash,
+        pending_sale.policy_id AS policy,
+        pending_sale.asset_name AS name,
+        pending_sale.sale_json,
+        COALESCE((
+            SELECT asset_metadata.json FROM ma_tx_mint
+            INNER JOIN tx_metadata AS asset_metadata
+            ON ma_tx_mint.tx_id = asset_metadata.tx_id AND asset_metadata.key = 721
+            WHERE ma_tx_mint.policy = pending_sale.policy_id
+            AND ma_tx_mint.name = pending_sale.asset_name
+            ORDER BY ma_tx_mint.id DESC
+            LIMIT 1
+        ), 'null'::jsonb) AS asset_json
+    FROM marketplace.pending_sale
+    WHERE holder = $1
+    AND NOT EXISTS (SELECT 1 FROM tx WHERE tx.hash = decode(pending_sale.tx_hash, 'hex'))
+    AND (ttl IS NULL OR ttl >= (SELECT max(slot_no) FROM block))
+"#;
+
+/// Remembers a submitted transaction when it lists NFTs at `holder`, so they
+/// can be shown before db-sync indexes it. Other transactions are ignored.
+pub async fn track_pending_sale(
+    pool: &PgPool,
+    holder: &Address,
+    tx: &Transaction,
+    tx_hash: &str,
+) -> Result<()> {
+    let metadatum = match tx
+        .auxiliary_data()
+        .and_then(|aux| aux.metadata())
+        .and_then(|metadata| metadata.get(&to_bignum(MARKETPLACE_METADATA_LABEL_KEY)))
+    {
+        Some(metadatum) => metadatum,
+        None => return Ok(()),
+    };
+    let sale_json: serde_json::Value = serde_json::from_str(&decode_metadatum_to_json_str(
+        &metadatum,
+        MetadataJsonSchema::BasicConversions,
+    )?)?;
+
+    let holder_bytes = holder.to_bytes();
+    let outputs = tx.body().outputs();
+    let mut nfts = vec![];
+    for output in (0..outputs.len()).map(|i| outputs.get(i)) {
+        let multiasset = match output.amount().multiasset() {
+            Some(multiasset) if output.address().to_bytes() == holder_bytes => multiasset,
+            _ => continue,
+        };
+        let policies = multiasset.keys();
+        for policy_id in (0..policies.len()).map(|i| policies.get(i)) {
+            let asset_names = match multiasset.get(&policy_id) {
+                Some(assets) => assets.keys(),
+                None => continue,
+            };
+            for i in 0..asset_names.len() {
+                nfts.push((policy_id.to_bytes(), asset_names.get(i).name()));
+            }
+        }
+    }
+
+    for (policy, name) in nfts {
+        let seller = match SellMetadata::for_asset(sale_json.clone(), &policy, &name) {
+            Some(sale) => sale.seller_address,
+            None => continue,
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO marketplace.pending_sale
+                (tx_hash, holder, seller, policy_id, asset_name, sale_json, ttl)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (tx_hash, policy_id, asset_name) DO NOTHING
+            "#,
+        )
+        .bind(tx_hash)
+        .bind(holder.to_bech32(None)?)
+        .bind(seller.to_bech32(None)?)
+        .bind(policy)
+        .bind(name)
+        .bind(&sale_json)
+        .bind(tx.body().ttl().map(|ttl| ttl as i64))
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Pending sales at `holder`, filtered with the same `LIKE` patterns as the
+/// indexed listings
+pub async fn get_pending_sales(
+    pool: &PgPool,
+    holder: &str,
+    asset_name_filter: &str,
+    policy_filter: &str,
+) -> Result<Vec<SellData>> {
+    let rows = sqlx::query_as::<_, PgSellData>(&format!(
+        r#"
+        {}
+        AND lower(convert_from(pending_sale.asset_name, 'utf-8')) LIKE $2
+        AND lower(encode(pending_sale.policy_id, 'hex')) LIKE $3
+        AND {}
+        ORDER BY pending_sale.created_at DESC
+        "#,
+        PENDING_SALES_QUERY,
+        not_hidden("pending_sale.tx_hash")
+    ))
+    .bind(holder)
+    .bind(asset_name_filter)
+    .bind(policy_filter)
+    .fetch_all(pool)
+    .await?;
+    Ok(to_pending_sell_data(rows))
+}
+
+pub async fn get_pending_sale(pool: &PgPool, holder: &str, hash: &str) -> Result<Option<SellData>> {
+    let row = sqlx::query_as::<_, PgSellData>(&format!(
+        "{} AND pending_sale.tx_hash = $2",
+        PENDING_SALES_QUERY
+    ))
+    .bind(holder)
+    .bind(hash)
+    .fetch_optional(pool)
+    .await?;
+    Ok(to_pending_sell_data(row.into_iter().collect()).pop())
+}
+
+pub async fn get_pending_sales_from_user(
+    pool: &PgPool,
+    holder: &str,
+    seller: &str,
+) -> Result<Vec<SellData>> {
+    let rows = sqlx::query_as::<_, PgSellData>(&format!(
+        "{} AND pending_sale.seller = $2 ORDER BY pending_sale.created_at DESC",
+        PENDING_SALES_QUERY
+    ))
+    .bind(holder)
+    .bind(seller)
+    .fetch_all(pool)
+    .await?;
+    Ok(to_pending_sell_data(rows))
+}
+
+fn to_pending_sell_data(rows: Vec<PgSellData>) -> Vec<SellData> {
+    rows.into_iter()
+        .filter_map(|row| row.to_sell_data())
+        .map(|sell_data| SellData {
+            pending: true,
+            ..sell_data
+        })
+        .collect()
+}
+
+/// Forgets pending sales that db-sync has indexed or that can no longer be
+/// included in a block
+pub async fn prune_pending_sales(pool: &PgPool, slot: u32) -> Result<u64> {
+    let res = sqlx::query(
+        r#"
+        DELETE FROM marketplace.pending_sale
+        WHERE ttl < $1
+        OR EXISTS (SELECT 1 FROM tx WHERE tx.hash = decode(pending_sale.tx_hash, 'hex'))
+        "#,
+    )
+    .bind(slot as i64)
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected())
+}
@@ -0,0 +1,70 @@
+// Escrowed NFTs whose sale metadata cannot be listed, e.g. from third-party
+// tools writing under the same label
+
+use super::holder::SellMetadata;
+use crate::Result;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedListing {
+    pub tx_hash: String,
+    pub policy_id: String,
+    pub asset_name: String,
+    pub reason: String,
+    pub metadata: Value,
+}
+
+#[derive(sqlx::FromRow)]
+struct PgListing {
+    tx_hash: String,
+    policy: Vec<u8>,
+    name: Vec<u8>,
+    sale_json: Value,
+}
+
+/// Unspent outputs at the holder carrying sale metadata that fails
+/// validation, newest first
+pub async fn get_rejected_listings(
+    pool: &PgPool,
+    holder_address: &str,
+) -> Result<Vec<RejectedListing>> {
+    let rows = sqlx::query_as::<_, PgListing>(
+        r#"
+        SELECT
+            encode(tx.hash, 'hex') AS tx_hash,
+            ma_tx_out.policy,
+            ma_tx_out.name,
+            sale_metadata.json AS sale_json
+        FROM tx_out
+        INNER JOIN tx ON tx.id = tx_out.tx_id
+        LEFT JOIN tx_in ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
+        INNER JOIN tx_metadata AS sale_metadata
+        ON tx_out.tx_id = sale_metadata.tx_id AND sale_metadata.key = 888
+        INNER JOIN ma_tx_out ON tx_out.id = ma_tx_out.tx_out_id
+        WHERE tx_out.address = $1
+        AND tx_in.id IS NULL
+        ORDER BY tx.id DESC
+        "#,
+    )
+    .bind(holder_address)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let reason =
+                SellMetadata::validate_for_asset(&row.sale_json, &row.policy, &row.name).err()?;
+            Some(RejectedListing {
+                tx_hash: row.tx_hash,
+                policy_id: hex::encode(&row.policy),
+                asset_name: String::from_utf8(row.name.clone())
+                    .unwrap_or_else(|_| hex::encode(&row.name)),
+                reason,
+                metadata: row.sale_json,
+            })
+        })
+        .collect())
+}
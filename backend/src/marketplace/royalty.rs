@@ -0,0 +1,173 @@
+// Royalties paid to creators on secondary sales
+
+use crate::cardano_db_sync::query_royalty_metadata;
+use crate::{Error, Result};
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::PolicyID;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+
+const BASIS_POINTS: u64 = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoyaltyRecipient {
+    pub address: String,
+    /// Share of the sale price in basis points
+    pub rate_bps: u32,
+}
+
+impl RoyaltyRecipient {
+    pub fn amount(&self, price: u64) -> u64 {
+        price / BASIS_POINTS * self.rate_bps as u64
+            + price % BASIS_POINTS * self.rate_bps as u64 / BASIS_POINTS
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PgRoyaltySplit {
+    address: String,
+    rate_bps: i32,
+}
+
+/// Royalty recipients of a policy. An off-chain split configured by an admin
+/// wins over the CIP-27 metadata minted with the policy.
+pub async fn get_royalties(pool: &PgPool, policy_id: &PolicyID) -> Result<Vec<RoyaltyRecipient>> {
+    let splits = get_royalty_splits(pool, policy_id).await?;
+    if !splits.is_empty() {
+        return Ok(splits);
+    }
+
+    Ok(query_royalty_metadata(pool, policy_id)
+        .await?
+        .map(|json| parse_cip27(&json))
+        .unwrap_or_default())
+}
+
+pub async fn get_royalty_splits(
+    pool: &PgPool,
+    policy_id: &PolicyID,
+) -> Result<Vec<RoyaltyRecipient>> {
+    Ok(sqlx::query_as::<_, PgRoyaltySplit>(
+        r#"
+        SELECT address, rate_bps
+        FROM marketplace.royalty_split
+        WHERE policy_id = $1
+        ORDER BY address
+        "#,
+    )
+    .bind(policy_id.to_bytes())
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|split| RoyaltyRecipient {
+        address: split.address,
+        rate_bps: split.rate_bps as u32,
+    })
+    .collect())
+}
+
+pub async fn set_royalty_splits(
+    pool: &PgPool,
+    policy_id: &PolicyID,
+    recipients: &[RoyaltyRecipient],
+) -> Result<()> {
+    if recipients.iter().any(|r| r.rate_bps as u64 > BASIS_POINTS) {
+        return Err(Error::Message(
+            "A royalty cannot exceed the sale price".to_string(),
+        ));
+    }
+    let total: u64 = recipients.iter().map(|r| r.rate_bps as u64).sum();
+    if total > BASIS_POINTS {
+        return Err(Error::Message(
+            "Royalties cannot exceed the sale price".to_string(),
+        ));
+    }
+    for recipient in recipients {
+        Address::from_bech32(&recipient.address)?;
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM marketplace.royalty_split WHERE policy_id = $1")
+        .bind(policy_id.to_bytes())
+        .execute(&mut tx)
+        .await?;
+    for recipient in recipients {
+        sqlx::query(
+            r#"
+            INSERT INTO marketplace.royalty_split (policy_id, address, rate_bps)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(policy_id.to_bytes())
+        .bind(&recipient.address)
+        .bind(recipient.rate_bps as i32)
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Parses CIP-27 metadata, `{ "rate": "0.05", "addr": "addr1..." }`, where
+/// `addr` may be chunked into a list of strings. Multiple recipients are
+/// declared with `"recipients": [{ "addr": ..., "share": 0.6 }, ...]`, the
+/// shares splitting the rate between them.
+fn parse_cip27(json: &Value) -> Vec<RoyaltyRecipient> {
+    let rate = match json.get("rate").and_then(parse_fraction) {
+        Some(rate) if rate > 0.0 && rate <= 1.0 => rate,
+        _ => return vec![],
+    };
+
+    let shares: Vec<(String, f64)> = match json.get("recipients").and_then(|r| r.as_array()) {
+        Some(recipients) => recipients
+            .iter()
+            .filter_map(|r| {
+                let addr = r.get("addr").and_then(parse_address_value)?;
+                let share = r.get("share").and_then(parse_fraction)?;
+                Some((addr, share))
+            })
+            .collect(),
+        None => json
+            .get("addr")
+            .and_then(parse_address_value)
+            .map(|addr| vec![(addr, 1.0)])
+            .unwrap_or_default(),
+    };
+
+    let total_share: f64 = shares.iter().map(|(_, share)| share).sum();
+    if total_share <= 0.0 {
+        return vec![];
+    }
+
+    shares
+        .into_iter()
+        .filter(|(addr, _)| Address::from_bech32(addr).is_ok())
+        .map(|(address, share)| RoyaltyRecipient {
+            address,
+            rate_bps: (rate * share / total_share * BASIS_POINTS as f64).round() as u32,
+        })
+        .filter(|r| r.rate_bps > 0)
+        .collect()
+}
+
+fn parse_fraction(value: &Value) -> Option<f64> {
+    match value {
+        Value::String(s) => s.parse().ok(),
+        Value::Number(n) => n.as_f64(),
+        _ => None,
+    }
+}
+
+fn parse_address_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Array(parts) => parts
+            .iter()
+            .map(|v| v.as_str())
+            .collect::<Option<Vec<&str>>>()
+            .map(|parts| parts.join("")),
+        _ => None,
+    }
+}
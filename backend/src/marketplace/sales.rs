@@ -0,0 +1,170 @@
+// Completed sales, escrow UTxOs of the holder spent to someone other than the seller
+
+use crate::marketplace::holder::SellMetadata;
+use crate::Result;
+use bigdecimal::ToPrimitive;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::types::BigDecimal;
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sale {
+    pub time: NaiveDateTime,
+    pub tx_hash: String,
+    pub policy_id: String,
+    pub asset_name: String,
+    pub price: u64,
+    pub fee: u64,
+    pub seller: String,
+    pub buyer: String,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct PgSale {
+    time: NaiveDateTime,
+    tx_hash: String,
+    policy: Vec<u8>,
+    name: Vec<u8>,
+    sale_json: Value,
+    buyer: String,
+    fee: BigDecimal,
+}
+
+impl PgSale {
+    /// Cancellations spend the escrow too, but send the NFT back to the seller.
+    pub(super) fn into_sale(self) -> Option<Sale> {
+        let sale_metadata = SellMetadata::for_asset(self.sale_json, &self.policy, &self.name)?;
+        let seller = sale_metadata.seller_address.to_bech32(None).ok()?;
+        if seller == self.buyer {
+            return None;
+        }
+        Some(Sale {
+            time: self.time,
+            tx_hash: self.tx_hash,
+            policy_id: hex::encode(self.policy),
+            asset_name: String::from_utf8(self.name.clone())
+                .unwrap_or_else(|_| hex::encode(&self.name)),
+            price: sale_metadata.price,
+            fee: self.fee.to_u64().unwrap_or(0),
+            seller,
+            buyer: self.buyer,
+        })
+    }
+}
+
+pub(super) const SALES_QUERY: &str = r#"
+    SELECT
+        block.time,
+        encode(sale_tx.hash, 'hex') AS tx_hash,
+        ma_tx_out.policy,
+        ma_tx_out.name,
+        listing.json AS sale_json,
+        buyer_out.address AS buyer,
+        (
+            SELECT COALESCE(SUM(fee_out.value), 0)
+            FROM tx_out AS fee_out
+            WHERE fee_out.tx_id = sale_tx.id AND fee_out.address = $2
+        ) AS fee
+    FROM tx_out AS escrow
+    INNER JOIN tx_metadata AS listing
+    ON listing.tx_id = escrow.tx_id AND listing.key = 888
+    INNER JOIN ma_tx_out ON ma_tx_out.tx_out_id = escrow.id
+    INNER JOIN tx_in ON tx_in.tx_out_id = escrow.tx_id AND tx_in.tx_out_index = escrow.index
+    INNER JOIN tx AS sale_tx ON sale_tx.id = tx_in.tx_in_id
+    INNER JOIN block ON block.id = sale_tx.block_id
+    INNER JOIN tx_out AS buyer_out ON buyer_out.tx_id = sale_tx.id
+    INNER JOIN ma_tx_out AS bought
+    ON bought.tx_out_id = buyer_out.id
+    AND bought.policy = ma_tx_out.policy
+    AND bought.name = ma_tx_out.name
+    WHERE escrow.address = $1
+"#;
+
+/// A page of 16 sales, newest first. Cancellations are dropped from the
+/// page, so it can come back shorter.
+pub async fn get_recent_sales(
+    pool: &PgPool,
+    holder_address: &str,
+    revenue_address: &str,
+    page: u32,
+) -> Result<Vec<Sale>> {
+    let offset = page.saturating_sub(1) * 16;
+    let rows = sqlx::query_as::<_, PgSale>(&format!(
+        "{} ORDER BY sale_tx.id DESC LIMIT 16 OFFSET $3",
+        SALES_QUERY
+    ))
+    .bind(holder_address)
+    .bind(revenue_address)
+    .bind(offset as i64)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().filter_map(PgSale::into_sale).collect())
+}
+
+/// Streams every sale of the holder, oldest first, without loading them all
+/// into memory.
+pub fn stream_sales(
+    pool: PgPool,
+    holder_address: String,
+    revenue_address: String,
+) -> ReceiverStream<Result<Sale>> {
+    let (sender, receiver) = mpsc::channel(64);
+    actix_web::rt::spawn(async move {
+        let query = format!("{} ORDER BY sale_tx.id ASC", SALES_QUERY);
+        let mut rows = sqlx::query_as::<_, PgSale>(&query)
+            .bind(holder_address)
+            .bind(revenue_address)
+            .fetch(&pool);
+        loop {
+            match rows.try_next().await {
+                Ok(Some(row)) => {
+                    if let Some(sale) = row.into_sale() {
+                        if sender.send(Ok(sale)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = sender.send(Err(e.into())).await;
+                    break;
+                }
+            }
+        }
+    });
+    ReceiverStream::new(receiver)
+}
+
+impl Sale {
+    pub const CSV_HEADER: &'static str =
+        "date,policy_id,asset_name,price,fee,seller,buyer,tx_hash\n";
+
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{}\n",
+            self.time.format("%Y-%m-%dT%H:%M:%SZ"),
+            self.policy_id,
+            csv_field(&self.asset_name),
+            self.price,
+            self.fee,
+            self.seller,
+            self.buyer,
+            self.tx_hash
+        )
+    }
+}
+
+/// Quotes a CSV field when needed, asset names can contain anything.
+pub fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
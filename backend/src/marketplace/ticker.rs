@@ -0,0 +1,275 @@
+// Listings, sales and floor prices of collections as db-sync indexes them
+
+use super::holder::SellMetadata;
+use super::sales::{PgSale, SALES_QUERY};
+use crate::Result;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Events a slow subscriber can fall behind by before missing some
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum CollectionEvent {
+    #[serde(rename_all = "camelCase")]
+    Listed {
+        policy_id: String,
+        asset_name: String,
+        price: u64,
+        tx_hash: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Sold {
+        policy_id: String,
+        asset_name: String,
+        price: u64,
+        tx_hash: String,
+    },
+    /// The cheapest listing of the collection, none once nothing is listed
+    #[serde(rename_all = "camelCase")]
+    FloorChanged {
+        policy_id: String,
+        floor: Option<u64>,
+    },
+}
+
+impl CollectionEvent {
+    pub fn policy_id(&self) -> &str {
+        match self {
+            CollectionEvent::Listed { policy_id, .. }
+            | CollectionEvent::Sold { policy_id, .. }
+            | CollectionEvent::FloorChanged { policy_id, .. } => policy_id,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Ticker {
+    sender: broadcast::Sender<CollectionEvent>,
+}
+
+impl Ticker {
+    pub fn subscribe(&self) -> broadcast::Receiver<CollectionEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Polls db-sync for what happened at `holder_address` since the last poll.
+/// Only what is indexed after startup is reported.
+pub fn spawn(
+    pool: PgPool,
+    holder_address: String,
+    revenue_address: String,
+    interval_seconds: u64,
+) -> Ticker {
+    let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+    let ticker = Ticker {
+        sender: sender.clone(),
+    };
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+        let mut last_tx_id = None;
+        let mut floors = HashMap::new();
+        loop {
+            interval.tick().await;
+            let latest = match latest_tx_id(&pool).await {
+                Ok(latest) => latest,
+                Err(e) => {
+                    println!("Collection ticker failed: {}", e);
+                    continue;
+                }
+            };
+            let since = match last_tx_id {
+                Some(since) if since < latest => since,
+                Some(_) => continue,
+                None => {
+                    last_tx_id = Some(latest);
+                    continue;
+                }
+            };
+            match poll(
+                &pool,
+                &holder_address,
+                &revenue_address,
+                since,
+                latest,
+                &mut floors,
+            )
+            .await
+            {
+                Ok(events) => {
+                    last_tx_id = Some(latest);
+                    for event in events {
+                        // Nobody listening is not an error
+                        let _ = sender.send(event);
+                    }
+                }
+                Err(e) => println!("Collection ticker failed: {}", e),
+            }
+        }
+    });
+    ticker
+}
+
+#[derive(sqlx::FromRow)]
+struct LatestTx {
+    id: Option<i64>,
+}
+
+pub async fn latest_tx_id(pool: &PgPool) -> Result<i64> {
+    let latest = sqlx::query_as::<_, LatestTx>("SELECT max(id) AS id FROM tx")
+        .fetch_one(pool)
+        .await?;
+    Ok(latest.id.unwrap_or(0))
+}
+
+#[derive(sqlx::FromRow)]
+struct PgListing {
+    hash: String,
+    policy: Vec<u8>,
+    name: Vec<u8>,
+    sale_json: Value,
+}
+
+#[derive(sqlx::FromRow)]
+struct PgPolicy {
+    policy: Vec<u8>,
+}
+
+/// Events of the transactions with ids in `since + 1..=until`. `floors` keeps
+/// the last reported floor of each collection.
+async fn poll(
+    pool: &PgPool,
+    holder_address: &str,
+    revenue_address: &str,
+    since: i64,
+    until: i64,
+    floors: &mut HashMap<String, Option<u64>>,
+) -> Result<Vec<CollectionEvent>> {
+    let mut events = vec![];
+    let mut touched = BTreeSet::new();
+
+    let listings = sqlx::query_as::<_, PgListing>(
+        r#"
+        SELECT
+            encode(tx.hash, 'hex') AS hash,
+            ma_tx_out.policy,
+            ma_tx_out.name,
+            sale_metadata.json AS sale_json
+        FROM tx_out
+        INNER JOIN tx ON tx.id = tx_out.tx_id
+        INNER JOIN tx_metadata AS sale_metadata
+        ON tx_out.tx_id = sale_metadata.tx_id AND sale_metadata.key = 888
+        INNER JOIN ma_tx_out ON tx_out.id = ma_tx_out.tx_out_id
+        WHERE tx_out.address = $1 AND tx.id > $2 AND tx.id <= $3
+        ORDER BY tx.id ASC
+        "#,
+    )
+    .bind(holder_address)
+    .bind(since)
+    .bind(until)
+    .fetch_all(pool)
+    .await?;
+    for listing in listings {
+        if let Some(sale) =
+            SellMetadata::for_asset(listing.sale_json, &listing.policy, &listing.name)
+        {
+            let policy_id = hex::encode(&listing.policy);
+            touched.insert(policy_id.clone());
+            events.push(CollectionEvent::Listed {
+                policy_id,
+                asset_name: String::from_utf8(listing.name.clone())
+                    .unwrap_or_else(|_| hex::encode(&listing.name)),
+                price: sale.price,
+                tx_hash: listing.hash,
+            });
+        }
+    }
+
+    let sales = sqlx::query_as::<_, PgSale>(&format!(
+        "{} AND sale_tx.id > $3 AND sale_tx.id <= $4 ORDER BY sale_tx.id ASC",
+        SALES_QUERY
+    ))
+    .bind(holder_address)
+    .bind(revenue_address)
+    .bind(since)
+    .bind(until)
+    .fetch_all(pool)
+    .await?;
+    for sale in sales.into_iter().filter_map(PgSale::into_sale) {
+        events.push(CollectionEvent::Sold {
+            policy_id: sale.policy_id,
+            asset_name: sale.asset_name,
+            price: sale.price,
+            tx_hash: sale.tx_hash,
+        });
+    }
+
+    // Sales and cancellations both spend escrow outputs
+    let spent = sqlx::query_as::<_, PgPolicy>(
+        r#"
+        SELECT DISTINCT ma_tx_out.policy
+        FROM tx_out AS escrow
+        INNER JOIN tx_in ON tx_in.tx_out_id = escrow.tx_id AND tx_in.tx_out_index = escrow.index
+        INNER JOIN ma_tx_out ON ma_tx_out.tx_out_id = escrow.id
+        WHERE escrow.address = $1 AND tx_in.tx_in_id > $2 AND tx_in.tx_in_id <= $3
+        "#,
+    )
+    .bind(holder_address)
+    .bind(since)
+    .bind(until)
+    .fetch_all(pool)
+    .await?;
+    touched.extend(spent.into_iter().map(|row| hex::encode(row.policy)));
+
+    for policy_id in touched {
+        let floor = get_floor(pool, holder_address, &policy_id).await?;
+        if floors.get(&policy_id) != Some(&floor) {
+            floors.insert(policy_id.clone(), floor);
+            events.push(CollectionEvent::FloorChanged { policy_id, floor });
+        }
+    }
+    Ok(events)
+}
+
+/// The lowest price among the indexed listings of a collection
+pub async fn get_floor(
+    pool: &PgPool,
+    holder_address: &str,
+    policy_id: &str,
+) -> Result<Option<u64>> {
+    let listings = sqlx::query_as::<_, PgListing>(
+        r#"
+        SELECT
+            encode(tx.hash, 'hex') AS hash,
+            ma_tx_out.policy,
+            ma_tx_out.name,
+            sale_metadata.json AS sale_json
+        FROM tx_out
+        INNER JOIN tx ON tx.id = tx_out.tx_id
+        LEFT JOIN tx_in ON tx_out.tx_id = tx_in.tx_out_id AND tx_out.index = tx_in.tx_out_index
+        INNER JOIN tx_metadata AS sale_metadata
+        ON tx_out.tx_id = sale_metadata.tx_id AND sale_metadata.key = 888
+        INNER JOIN ma_tx_out ON tx_out.id = ma_tx_out.tx_out_id
+        WHERE tx_out.address = $1
+        AND tx_in.id IS NULL
+        AND encode(ma_tx_out.policy, 'hex') = $2
+        "#,
+    )
+    .bind(holder_address)
+    .bind(policy_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(listings
+        .into_iter()
+        .filter_map(|listing| {
+            SellMetadata::for_asset(listing.sale_json, &listing.policy, &listing.name)
+        })
+        .map(|sale| sale.price)
+        .min())
+}
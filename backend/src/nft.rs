@@ -1,29 +1,47 @@
-use std::convert::TryFrom;
-
 use cardano_serialization_lib::{
     address::Address,
     crypto::{PrivateKey, PublicKey, ScriptHash, TransactionHash, Vkeywitnesses},
-    metadata::{AuxiliaryData, GeneralTransactionMetadata, MetadataMap, TransactionMetadatum},
+    metadata::{
+        AuxiliaryData, GeneralTransactionMetadata, MetadataList, MetadataMap, TransactionMetadatum,
+    },
     utils::{hash_transaction, make_vkey_witness, min_ada_required, to_bignum, Int, Value},
-    AssetName, Assets, Mint, MintAssets, MultiAsset, NativeScript, NativeScripts, ScriptAll,
-    ScriptHashNamespace, ScriptPubkey, TimelockExpiry, Transaction, TransactionOutput,
-    TransactionWitnessSet,
+    AssetName, Assets, Mint, MintAssets, MultiAsset, NativeScript, NativeScripts, PolicyID,
+    ScriptAll, ScriptHashNamespace, ScriptPubkey, TimelockExpiry, Transaction, TransactionBody,
+    TransactionOutput, TransactionWitnessSet,
 };
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 
-use crate::coin::TransactionWitnessSetParams;
+use crate::chain::ChainQuery;
+use crate::coin::{BuildSettings, TransactionWitnessSetParams};
+use crate::marketplace::royalty::{set_royalty_splits, RoyaltyRecipient};
+use crate::phase::{claim_mint, get_active_phase};
+use crate::purpose::{self, Purpose};
 use crate::{cardano_db_sync::ProtocolParams, error::Error, Result};
 use cardano_serialization_lib::utils::{Coin, TransactionUnspentOutput};
 use std::collections::HashMap;
 
 const EXPIRY_IN_SECONDS: u32 = 3600;
+/// How long new policies stay open for minting unless the request says
+/// otherwise
+pub const DEFAULT_POLICY_LOCK_SECONDS: u32 = 3600;
 const NFT_STANDARD_LABEL: u64 = 721;
+const ROYALTY_STANDARD_LABEL: u64 = 777;
+const MAX_METADATA_TEXT_BYTES: usize = 64;
+/// Bounds of the free-form metadata, well above what CIP-25 and CIP-60
+/// tokens use
+const MAX_EXTRA_METADATA_KEYS: usize = 32;
+const MAX_EXTRA_METADATA_VALUE_BYTES: usize = 4096;
+const MAX_EXTRA_METADATA_DEPTH: usize = 4;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WottleNftMetadata {
     name: String,
     description: String,
     image: String,
+    /// Replaces the configured "Minted At" attribution, empty to leave it out
+    #[serde(rename = "mintedAt", default, skip_serializing_if = "Option::is_none")]
+    minted_at: Option<String>,
     #[serde(flatten)]
     pub rest: HashMap<String, serde_json::Value>,
 }
@@ -34,74 +52,154 @@ impl WottleNftMetadata {
             name,
             description,
             image,
+            minted_at: None,
             rest: HashMap::new(),
         }
     }
-}
 
-impl std::convert::TryFrom<&WottleNftMetadata> for MetadataMap {
-    type Error = crate::Error;
-
-    fn try_from(value: &WottleNftMetadata) -> Result<Self> {
-        println!("{:#?}", &value);
-        let mut nft_metadata_map = MetadataMap::new();
-        use serde_json::Value::*;
-        for (k, v) in &value.rest {
-            let key = TransactionMetadatum::new_text(k.to_string())?;
-            let value = match v {
-                Bool(bool) => TransactionMetadatum::new_text(format!("{}", bool))?,
-                Number(n) => {
-                    if n.is_i64() {
-                        TransactionMetadatum::new_int(&Int::new_i32(
-                            n.as_i64().ok_or_else(|| {
-                                Error::Message("Failed to convert to i32".to_string())
-                            })? as i32,
-                        ))
-                    } else if n.is_u64() {
-                        TransactionMetadatum::new_int(&Int::new(&to_bignum(
-                            n.as_u64().ok_or_else(|| {
-                                Error::Message("Failed to convert to u64".to_string())
-                            })?,
-                        )))
-                    } else {
-                        TransactionMetadatum::new_text(
-                            n.as_f64()
-                                .ok_or_else(|| {
-                                    Error::Message("Failed to convert to u64".to_string())
-                                })?
-                                .to_string(),
-                        )?
-                    }
-                }
-                String(s) => TransactionMetadatum::new_text(s.to_string())?,
-                _ => continue,
-            };
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 
-            nft_metadata_map.insert(&key, &value);
+    pub fn image(&self) -> &str {
+        &self.image
+    }
+
+    /// Refuses free-form metadata too large or nested too deep to be worth
+    /// building a transaction for
+    pub fn validate(&self) -> Result<()> {
+        if self.rest.len() > MAX_EXTRA_METADATA_KEYS {
+            return Err(Error::Message(format!(
+                "Metadata can have at most {} extra fields",
+                MAX_EXTRA_METADATA_KEYS
+            )));
+        }
+        for (key, value) in &self.rest {
+            if key.len() > MAX_METADATA_TEXT_BYTES {
+                return Err(Error::Message(format!(
+                    "Metadata field names can be at most {} bytes",
+                    MAX_METADATA_TEXT_BYTES
+                )));
+            }
+            if value.to_string().len() > MAX_EXTRA_METADATA_VALUE_BYTES {
+                return Err(Error::Message(format!(
+                    "Metadata field {} is over {} bytes",
+                    key, MAX_EXTRA_METADATA_VALUE_BYTES
+                )));
+            }
+            if json_depth(value) > MAX_EXTRA_METADATA_DEPTH {
+                return Err(Error::Message(format!(
+                    "Metadata field {} is nested more than {} levels deep",
+                    key, MAX_EXTRA_METADATA_DEPTH
+                )));
+            }
         }
+        Ok(())
+    }
+}
 
-        nft_metadata_map.insert(
-            &TransactionMetadatum::new_text("name".to_string())?,
-            &TransactionMetadatum::new_text(value.name.clone())?,
-        );
+/// Levels of lists and maps in a JSON value, 0 for scalars
+fn json_depth(value: &serde_json::Value) -> usize {
+    match value {
+        serde_json::Value::Array(items) => 1 + items.iter().map(json_depth).max().unwrap_or(0),
+        serde_json::Value::Object(fields) => 1 + fields.values().map(json_depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
 
-        nft_metadata_map.insert(
-            &TransactionMetadatum::new_text("description".to_string())?,
-            &TransactionMetadatum::new_text(value.description.clone())?,
-        );
+/// The metadata of `value`, with `attribution` under "Minted At" unless it
+/// brings its own
+fn metadata_map(value: &WottleNftMetadata, attribution: Option<&str>) -> Result<MetadataMap> {
+    println!("{:#?}", &value);
+    let mut nft_metadata_map = MetadataMap::new();
+    for (k, v) in &value.rest {
+        let key = TransactionMetadatum::new_text(k.to_string())?;
+        let value = match json_to_metadatum(v)? {
+            Some(value) => value,
+            None => continue,
+        };
+        nft_metadata_map.insert(&key, &value);
+    }
 
-        nft_metadata_map.insert(
-            &TransactionMetadatum::new_text("image".to_string())?,
-            &TransactionMetadatum::new_text(value.image.clone())?,
-        );
+    nft_metadata_map.insert(
+        &TransactionMetadatum::new_text("name".to_string())?,
+        &TransactionMetadatum::new_text(value.name.clone())?,
+    );
+
+    nft_metadata_map.insert(
+        &TransactionMetadatum::new_text("description".to_string())?,
+        &text_metadatum(&value.description)?,
+    );
+
+    nft_metadata_map.insert(
+        &TransactionMetadatum::new_text("image".to_string())?,
+        &text_metadatum(&value.image)?,
+    );
 
+    let attribution = value.minted_at.as_deref().or(attribution);
+    if let Some(attribution) = attribution.filter(|a| !a.is_empty()) {
         nft_metadata_map.insert(
             &TransactionMetadatum::new_text("Minted At".to_string())?,
-            &TransactionMetadatum::new_text("© 2021 WottleNFT".to_string())?,
+            &text_metadatum(&attribution)?,
         );
-        println!("{:#?}", &nft_metadata_map);
-        Ok(nft_metadata_map)
     }
+    println!("{:#?}", &nft_metadata_map);
+    Ok(nft_metadata_map)
+}
+
+/// Metadata strings are limited to 64 bytes, longer ones are split into a
+/// list of chunks as CIP-25 and CIP-60 allow
+fn text_metadatum(s: &str) -> Result<TransactionMetadatum> {
+    if s.len() <= MAX_METADATA_TEXT_BYTES {
+        return Ok(TransactionMetadatum::new_text(s.to_string())?);
+    }
+    let mut chunks = MetadataList::new();
+    let mut chunk = String::new();
+    for c in s.chars() {
+        if chunk.len() + c.len_utf8() > MAX_METADATA_TEXT_BYTES {
+            chunks.add(&TransactionMetadatum::new_text(std::mem::take(&mut chunk))?);
+        }
+        chunk.push(c);
+    }
+    chunks.add(&TransactionMetadatum::new_text(chunk)?);
+    Ok(TransactionMetadatum::new_list(&chunks))
+}
+
+/// Converts JSON to metadata, recursing into lists and maps such as the
+/// artists, files and song details of CIP-60 music tokens. Nulls are left
+/// out, booleans and fractions become text.
+fn json_to_metadatum(value: &serde_json::Value) -> Result<Option<TransactionMetadatum>> {
+    use serde_json::Value::*;
+    Ok(Some(match value {
+        Null => return Ok(None),
+        Bool(bool) => TransactionMetadatum::new_text(format!("{}", bool))?,
+        Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(n), _) => TransactionMetadatum::new_int(&Int::new(&to_bignum(n))),
+            (None, Some(n)) => {
+                TransactionMetadatum::new_int(&Int::new_negative(&to_bignum(n.unsigned_abs())))
+            }
+            (None, None) => TransactionMetadatum::new_text(n.to_string())?,
+        },
+        String(s) => text_metadatum(s)?,
+        Array(items) => {
+            let mut list = MetadataList::new();
+            for item in items {
+                if let Some(item) = json_to_metadatum(item)? {
+                    list.add(&item);
+                }
+            }
+            TransactionMetadatum::new_list(&list)
+        }
+        Object(fields) => {
+            let mut map = MetadataMap::new();
+            for (k, v) in fields {
+                if let Some(v) = json_to_metadatum(v)? {
+                    map.insert(&TransactionMetadatum::new_text(k.to_string())?, &v);
+                }
+            }
+            TransactionMetadatum::new_map(&map)
+        }
+    }))
 }
 
 pub struct NftPolicy {
@@ -113,10 +211,17 @@ pub struct NftPolicy {
 }
 
 impl NftPolicy {
-    pub fn new(slot: u32) -> Result<Self> {
-        let skey = PrivateKey::generate_ed25519()?;
+    /// A fresh policy locking `lock_seconds` after `slot`
+    pub fn new(slot: u32, lock_seconds: u32) -> Result<Self> {
+        let expiry_slot = slot
+            .checked_add(lock_seconds)
+            .ok_or_else(|| Error::Message("Policy lock window is too long".to_string()))?;
+        Self::from_key(PrivateKey::generate_ed25519()?, expiry_slot)
+    }
+
+    /// Rebuilds a policy from a key kept around to mint more than one asset.
+    pub fn from_key(skey: PrivateKey, expiry_slot: u32) -> Result<Self> {
         let vkey = skey.to_public();
-        let expiry_slot = slot + EXPIRY_IN_SECONDS;
 
         let pub_key_script = NativeScript::new_script_pubkey(&ScriptPubkey::new(&vkey.hash()));
         let time_expiry_script =
@@ -156,32 +261,183 @@ impl NftPolicy {
     }
 }
 
+/// How the policy of an existing asset is witnessed, to burn or re-mint it
+pub enum PolicyWitness {
+    /// Script supplied by the holder, who also adds the policy signatures
+    Script(NativeScript),
+    /// Policy whose key the backend keeps, signed here
+    Managed(NftPolicy),
+}
+
+impl PolicyWitness {
+    fn script(&self) -> &NativeScript {
+        match self {
+            Self::Script(script) => script,
+            Self::Managed(policy) => &policy.script,
+        }
+    }
+
+    pub fn native_scripts(&self) -> NativeScripts {
+        let mut native_scripts = NativeScripts::new();
+        native_scripts.add(self.script());
+        native_scripts
+    }
+
+    /// Checks the script is the one of `policy_id` and can still be
+    /// satisfied, returning `ttl` capped to its time lock
+    pub fn ttl(&self, policy_id: &PolicyID, slot: u32, ttl: u32) -> Result<u32> {
+        let script = self.script();
+        let script_hash =
+            ScriptHash::from_bytes(script.hash(ScriptHashNamespace::NativeScript).to_bytes())?;
+        if script_hash.to_bytes() != policy_id.to_bytes() {
+            return Err(Error::Message(
+                "Policy script does not match the policy id".to_string(),
+            ));
+        }
+        match script_expiry(script) {
+            Some(expiry) if expiry <= slot => Err(Error::Forbidden(
+                "The policy is locked, its assets can no longer be minted or burned".to_string(),
+            )),
+            Some(expiry) => Ok(ttl.min(expiry)),
+            None => Ok(ttl),
+        }
+    }
+
+    /// The script, signed by the policy key when it is managed here
+    pub fn witness_set(&self, tx_body: &TransactionBody) -> TransactionWitnessSet {
+        let mut witnesses = TransactionWitnessSet::new();
+        witnesses.set_native_scripts(&self.native_scripts());
+        if let Self::Managed(policy) = self {
+            let mut vkeys = Vkeywitnesses::new();
+            vkeys.add(&make_vkey_witness(&hash_transaction(tx_body), &policy.skey));
+            witnesses.set_vkeys(&vkeys);
+        }
+        witnesses
+    }
+}
+
+/// Slot from which a script can no longer be satisfied, when it requires all
+/// of its parts including a time lock
+fn script_expiry(script: &NativeScript) -> Option<u32> {
+    if let Some(expiry) = script.as_timelock_expiry() {
+        return Some(expiry.slot());
+    }
+    let scripts = script.as_script_all()?.native_scripts();
+    (0..scripts.len())
+        .filter_map(|i| script_expiry(&scripts.get(i)))
+        .min()
+}
+
+/// CIP-25 metadata of `nft` minted under `policy_id`
+pub fn nft_metadata(
+    policy_id: &PolicyID,
+    nft: &WottleNftMetadata,
+    attribution: Option<&str>,
+) -> Result<GeneralTransactionMetadata> {
+    let nft_metadata_map = metadata_map(nft, attribution)?;
+
+    let mut nft_asset = MetadataMap::new();
+    nft_asset.insert(
+        &TransactionMetadatum::new_text(nft.name.clone())?,
+        &TransactionMetadatum::new_map(&nft_metadata_map),
+    );
+
+    let mut policy_metadata = MetadataMap::new();
+    policy_metadata.insert(
+        &TransactionMetadatum::new_text(hex::encode(policy_id.to_bytes()))?,
+        &TransactionMetadatum::new_map(&nft_asset),
+    );
+
+    Ok({
+        let mut general_metadata = GeneralTransactionMetadata::new();
+        general_metadata.insert(
+            &to_bignum(NFT_STANDARD_LABEL),
+            &TransactionMetadatum::new_map(&policy_metadata),
+        );
+        general_metadata
+    })
+}
+
 pub struct NftTransactionBuilder {
     policy: NftPolicy,
     asset_value: Value,
     asset_name: AssetName,
+    royalty: Option<RoyaltyRecipient>,
     metadata: GeneralTransactionMetadata,
+    extra_outputs: Vec<TransactionOutput>,
     slot: u32,
     params: ProtocolParams,
+    build: BuildSettings,
 }
 
 impl NftTransactionBuilder {
-    pub fn new(nft: WottleNftMetadata, slot: u32, params: ProtocolParams) -> Result<Self> {
-        let policy = NftPolicy::new(slot)?;
+    pub fn with_policy(
+        nft: WottleNftMetadata,
+        policy: NftPolicy,
+        slot: u32,
+        params: ProtocolParams,
+        build: &BuildSettings,
+    ) -> Result<Self> {
         let (asset_value, asset_name) =
             Self::generate_asset_and_value(&policy, &nft, &params.minimum_utxo_value)?;
-        let metadata = Self::build_metadata(&policy, &nft)?;
+        let mut metadata = nft_metadata(&policy.hash, &nft, build.mint_attribution.as_deref())?;
+        purpose::tag(&mut metadata, Purpose::Mint, build)?;
 
         Ok(Self {
             policy,
             asset_value,
             asset_name,
+            royalty: None,
             metadata,
+            extra_outputs: vec![],
             params,
             slot,
+            build: build.clone(),
         })
     }
 
+    /// Adds an output paid by the minter, e.g. the price of a drop.
+    pub fn add_output(&mut self, output: TransactionOutput) {
+        self.extra_outputs.push(output);
+    }
+
+    /// Also mints the CIP-27 royalty token of the policy, the nameless asset
+    /// whose metadata declares the rate and payout address of royalties.
+    /// The token goes to the receiver along with the NFT.
+    pub fn set_royalty(&mut self, royalty: RoyaltyRecipient) -> Result<()> {
+        if royalty.rate_bps == 0 || royalty.rate_bps > 10_000 {
+            return Err(Error::Message(
+                "Royalty rate must be between 1 and 10000 basis points".to_string(),
+            ));
+        }
+        Address::from_bech32(&royalty.address)?;
+
+        let mut royalty_metadata = MetadataMap::new();
+        royalty_metadata.insert(
+            &TransactionMetadatum::new_text("rate".to_string())?,
+            &TransactionMetadatum::new_text(format!("{}", royalty.rate_bps as f64 / 10_000.0))?,
+        );
+        royalty_metadata.insert(
+            &TransactionMetadatum::new_text("addr".to_string())?,
+            &text_metadatum(&royalty.address)?,
+        );
+        self.metadata.insert(
+            &to_bignum(ROYALTY_STANDARD_LABEL),
+            &TransactionMetadatum::new_map(&royalty_metadata),
+        );
+
+        let mut multi_asset = self.asset_value.multiasset().unwrap();
+        let mut assets = multi_asset.get(&self.policy.hash).unwrap();
+        assets.insert(&AssetName::new(vec![])?, &to_bignum(1));
+        multi_asset.insert(&self.policy.hash, &assets);
+        self.asset_value.set_multiasset(&multi_asset);
+        let min = min_ada_required(&self.asset_value, &self.params.minimum_utxo_value);
+        self.asset_value.set_coin(&min);
+
+        self.royalty = Some(royalty);
+        Ok(())
+    }
+
     fn generate_asset_and_value(
         policy: &NftPolicy,
         nft: &WottleNftMetadata,
@@ -201,39 +457,12 @@ impl NftTransactionBuilder {
         Ok((value, asset_name))
     }
 
-    fn build_metadata(
-        policy: &NftPolicy,
-        nft: &WottleNftMetadata,
-    ) -> Result<GeneralTransactionMetadata> {
-        let nft_metadata_map = MetadataMap::try_from(nft)?;
-
-        let mut nft_asset = MetadataMap::new();
-        nft_asset.insert(
-            &TransactionMetadatum::new_text(nft.name.clone())?,
-            &TransactionMetadatum::new_map(&nft_metadata_map),
-        );
-
-        let mut policy_metadata = MetadataMap::new();
-        policy_metadata.insert(
-            &TransactionMetadatum::new_text(hex::encode(policy.hash.to_bytes()))?,
-            &TransactionMetadatum::new_map(&nft_asset),
-        );
-
-        Ok({
-            let mut general_metadata = GeneralTransactionMetadata::new();
-            general_metadata.insert(
-                &to_bignum(NFT_STANDARD_LABEL),
-                &TransactionMetadatum::new_map(&policy_metadata),
-            );
-            general_metadata
-        })
-    }
-
     pub fn create_transaction(
         &self,
         receiver: &Address,
         tax_address: &Address,
         utxos: Vec<TransactionUnspentOutput>,
+        change_address: Option<&Address>,
     ) -> Result<Transaction> {
         let mut tx_outputs = vec![TransactionOutput::new(receiver, &self.asset_value)];
 
@@ -243,6 +472,7 @@ impl NftTransactionBuilder {
             tax_address,
             &Value::new(&tax_amount),
         ));
+        tx_outputs.extend(self.extra_outputs.iter().cloned());
 
         let native_scripts = &self.create_native_scripts();
         let witness_set_params: TransactionWitnessSetParams = TransactionWitnessSetParams {
@@ -255,12 +485,15 @@ impl NftTransactionBuilder {
             utxos,
             vec![],
             tx_outputs,
-            self.slot + EXPIRY_IN_SECONDS,
+            self.ttl(),
             &self.params,
             None,
             Some(self.create_mint()),
             &witness_set_params,
             Some(self.create_auxiliary_data()),
+            &crate::coin::RandomImprove,
+            change_address,
+            &self.build,
         )?;
 
         let tx_hash = hash_transaction(&tx_body);
@@ -271,6 +504,11 @@ impl NftTransactionBuilder {
         Ok(transaction)
     }
 
+    /// The transaction cannot outlive the policy's time lock.
+    pub fn ttl(&self) -> u32 {
+        (self.slot + EXPIRY_IN_SECONDS).min(self.policy.ttl)
+    }
+
     pub fn policy_json(&self) -> serde_json::Value {
         self.policy.to_json()
     }
@@ -279,10 +517,23 @@ impl NftTransactionBuilder {
         hex::encode(self.policy.hash.to_bytes())
     }
 
+    pub fn policy(&self) -> &NftPolicy {
+        &self.policy
+    }
+
+    /// The slot after which the policy mints nothing more
+    pub fn policy_ttl(&self) -> u32 {
+        self.policy.ttl
+    }
+
     fn create_mint(&self) -> Mint {
         let mut mint = Mint::new();
         let mut mint_assets = MintAssets::new();
         mint_assets.insert(&self.asset_name, Int::new_i32(1));
+        if self.royalty.is_some() {
+            // CSL 9 asset names may be empty, as CIP-27 requires
+            mint_assets.insert(&AssetName::new(vec![]).unwrap(), Int::new_i32(1));
+        }
         mint.insert(&self.policy.hash, &mint_assets);
         mint
     }
@@ -313,3 +564,175 @@ impl NftTransactionBuilder {
         vkey_witnesses
     }
 }
+
+/// Builds the transaction minting `nft` to `address`, counting it against the
+/// active mint phase of its policy, if any. With a `royalty`, the new policy's CIP-27 token
+/// is minted too and the royalty recorded for sales through the marketplace.
+/// Change goes to `change_address` when given.
+#[allow(clippy::too_many_arguments)]
+pub async fn mint_nft<C: ChainQuery>(
+    chain: &C,
+    pool: &PgPool,
+    nft: WottleNftMetadata,
+    royalty: Option<RoyaltyRecipient>,
+    policy_lock_seconds: u32,
+    address: &Address,
+    change_address: Option<&Address>,
+    tax_address: &Address,
+    build: &BuildSettings,
+) -> Result<(NftTransactionBuilder, Transaction)> {
+    let slot = chain.slot_number().await?;
+    let policy = NftPolicy::new(slot, policy_lock_seconds)?;
+    mint_nft_with_policy(
+        chain,
+        pool,
+        nft,
+        policy,
+        royalty,
+        address,
+        change_address,
+        tax_address,
+        build,
+    )
+    .await
+}
+
+/// Mints under a policy kept from an earlier mint
+#[allow(clippy::too_many_arguments)]
+pub async fn mint_nft_with_policy<C: ChainQuery>(
+    chain: &C,
+    pool: &PgPool,
+    nft: WottleNftMetadata,
+    policy: NftPolicy,
+    royalty: Option<RoyaltyRecipient>,
+    address: &Address,
+    change_address: Option<&Address>,
+    tax_address: &Address,
+    build: &BuildSettings,
+) -> Result<(NftTransactionBuilder, Transaction)> {
+    nft.validate()?;
+    let utxos = chain.address_utxos(address).await?;
+    let slot = chain.slot_number().await?;
+    let params = chain.protocol_params().await?;
+    if slot >= policy.ttl {
+        return Err(Error::Message(
+            "The policy is locked and mints nothing more".to_string(),
+        ));
+    }
+
+    let active_phase = get_active_phase(pool, &policy.hash, slot).await?;
+    let mut builder = NftTransactionBuilder::with_policy(nft, policy, slot, params, build)?;
+    if let Some(royalty) = royalty {
+        builder.set_royalty(royalty)?;
+    }
+    let tx = builder.create_transaction(address, tax_address, utxos, change_address)?;
+    if let Some(phase) = &active_phase {
+        claim_mint(pool, phase, address, &tx, slot).await?;
+    }
+    if let Some(royalty) = &builder.royalty {
+        set_royalty_splits(pool, &builder.policy.hash, &[royalty.clone()]).await?;
+    }
+    Ok((builder, tx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::memory::{new_address, MemoryChain};
+    use cardano_serialization_lib::utils::from_bignum;
+
+    const SLOT: u32 = 50_000_000;
+
+    fn build_settings() -> BuildSettings {
+        BuildSettings {
+            fee_padding: 0,
+            max_input_count: 20,
+            mint_attribution: None,
+            tag_purpose: false,
+        }
+    }
+
+    fn nft() -> WottleNftMetadata {
+        WottleNftMetadata::new(
+            "Rare".to_string(),
+            "A rare one".to_string(),
+            "ipfs://rare".to_string(),
+        )
+    }
+
+    fn minted(value: &Value, policy_id: &PolicyID, asset_name: &[u8]) -> Option<u64> {
+        value
+            .multiasset()?
+            .get(policy_id)?
+            .get(&AssetName::new(asset_name.to_vec()).unwrap())
+            .map(|quantity| from_bignum(&quantity))
+    }
+
+    #[tokio::test]
+    async fn mints_to_the_receiver_before_the_policy_locks() {
+        let minter = new_address();
+        let tax_address = new_address();
+        let mut chain = MemoryChain::mary(SLOT);
+        chain.pay(&minter, &Value::new(&to_bignum(10_000_000)));
+        let policy = NftPolicy::new(SLOT, 600).unwrap();
+        let builder = NftTransactionBuilder::with_policy(
+            nft(),
+            policy,
+            chain.slot_number().await.unwrap(),
+            chain.protocol_params().await.unwrap(),
+            &build_settings(),
+        )
+        .unwrap();
+
+        let utxos = chain.address_utxos(&minter).await.unwrap();
+        let tx = builder
+            .create_transaction(&minter, &tax_address, utxos, None)
+            .unwrap();
+        let body = tx.body();
+        let policy_id = builder.policy().hash.clone();
+        assert_eq!(body.ttl(), Some(SLOT + 600));
+        let nft_output = body.outputs().get(0);
+        assert_eq!(nft_output.address().to_bytes(), minter.to_bytes());
+        assert_eq!(minted(&nft_output.amount(), &policy_id, b"Rare"), Some(1));
+        let tax_output = body.outputs().get(1);
+        assert_eq!(tax_output.address().to_bytes(), tax_address.to_bytes());
+        assert_eq!(tx.witness_set().vkeys().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn mints_the_royalty_token_alongside() {
+        let minter = new_address();
+        let mut chain = MemoryChain::mary(SLOT);
+        chain.pay(&minter, &Value::new(&to_bignum(10_000_000)));
+        let mut builder = NftTransactionBuilder::with_policy(
+            nft(),
+            NftPolicy::new(SLOT, DEFAULT_POLICY_LOCK_SECONDS).unwrap(),
+            chain.slot,
+            chain.params.clone(),
+            &build_settings(),
+        )
+        .unwrap();
+        let royalty = |rate_bps| RoyaltyRecipient {
+            address: new_address().to_bech32(None).unwrap(),
+            rate_bps,
+        };
+        assert!(builder.set_royalty(royalty(0)).is_err());
+        builder.set_royalty(royalty(500)).unwrap();
+
+        let utxos = chain.address_utxos(&minter).await.unwrap();
+        let tx = builder
+            .create_transaction(&minter, &new_address(), utxos, None)
+            .unwrap();
+        let body = tx.body();
+        let policy_id = builder.policy().hash.clone();
+        assert_eq!(body.ttl(), Some(SLOT + EXPIRY_IN_SECONDS));
+        let nft_output = body.outputs().get(0);
+        assert_eq!(minted(&nft_output.amount(), &policy_id, b"Rare"), Some(1));
+        assert_eq!(minted(&nft_output.amount(), &policy_id, b""), Some(1));
+        assert!(tx
+            .auxiliary_data()
+            .and_then(|data| data.metadata())
+            .and_then(|metadata| metadata.get(&to_bignum(ROYALTY_STANDARD_LABEL)))
+            .is_some());
+    }
+}
@@ -0,0 +1,194 @@
+// Allowlist-gated minting windows
+
+use crate::{Error, Result};
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::utils::hash_transaction;
+use cardano_serialization_lib::{PolicyID, Transaction};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct MintPhase {
+    pub id: i32,
+    /// Hex, none for phases created before they were scoped to a policy
+    pub policy_id: Option<String>,
+    pub name: String,
+    pub start_slot: i64,
+    pub end_slot: i64,
+    pub mint_limit: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewMintPhase {
+    pub policy_id: String,
+    pub name: String,
+    pub start_slot: i64,
+    pub end_slot: i64,
+    pub mint_limit: i32,
+}
+
+const MINT_PHASE_COLUMNS: &str = r#"
+    id, encode(policy_id, 'hex') AS policy_id, name, start_slot, end_slot, mint_limit
+"#;
+
+pub async fn create_phase(pool: &PgPool, phase: NewMintPhase) -> Result<MintPhase> {
+    let policy_id = PolicyID::from_bytes(hex::decode(&phase.policy_id)?)?;
+    if phase.start_slot >= phase.end_slot {
+        return Err(Error::Message(
+            "Phase must start before it ends".to_string(),
+        ));
+    }
+    if phase.mint_limit <= 0 {
+        return Err(Error::Message(
+            "Mint limit must be greater than zero".to_string(),
+        ));
+    }
+
+    Ok(sqlx::query_as::<_, MintPhase>(&format!(
+        r#"
+        INSERT INTO marketplace.mint_phase (policy_id, name, start_slot, end_slot, mint_limit)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING {}
+        "#,
+        MINT_PHASE_COLUMNS
+    ))
+    .bind(policy_id.to_bytes())
+    .bind(phase.name)
+    .bind(phase.start_slot)
+    .bind(phase.end_slot)
+    .bind(phase.mint_limit)
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn get_phases(pool: &PgPool) -> Result<Vec<MintPhase>> {
+    Ok(sqlx::query_as::<_, MintPhase>(&format!(
+        "SELECT {} FROM marketplace.mint_phase ORDER BY start_slot",
+        MINT_PHASE_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Returns the phase of `policy_id` whose window contains `slot`. Phases are
+/// not expected to overlap, if they do the one that started last wins.
+pub async fn get_active_phase(
+    pool: &PgPool,
+    policy_id: &PolicyID,
+    slot: u32,
+) -> Result<Option<MintPhase>> {
+    Ok(sqlx::query_as::<_, MintPhase>(&format!(
+        r#"
+        SELECT {}
+        FROM marketplace.mint_phase
+        WHERE policy_id = $1 AND start_slot <= $2 AND end_slot > $2
+        ORDER BY start_slot DESC
+        LIMIT 1
+        "#,
+        MINT_PHASE_COLUMNS
+    ))
+    .bind(policy_id.to_bytes())
+    .bind(slot as i64)
+    .fetch_optional(pool)
+    .await?)
+}
+
+/// Adds addresses to the allowlist of a phase. `mint_limit` overrides the
+/// phase default for these addresses only.
+pub async fn add_to_allowlist(
+    pool: &PgPool,
+    phase_id: i32,
+    addresses: &[Address],
+    mint_limit: Option<i32>,
+) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    let mut added = 0;
+    for address in addresses {
+        let res = sqlx::query(
+            r#"
+            INSERT INTO marketplace.mint_allowlist (phase_id, address, mint_limit)
+            SELECT id, $2, COALESCE($3, mint_limit)
+            FROM marketplace.mint_phase
+            WHERE id = $1
+            ON CONFLICT (phase_id, address) DO UPDATE SET mint_limit = EXCLUDED.mint_limit
+            "#,
+        )
+        .bind(phase_id)
+        .bind(address.to_bech32(None)?)
+        .bind(mint_limit)
+        .execute(&mut tx)
+        .await?;
+        added += res.rows_affected();
+    }
+    tx.commit().await?;
+    Ok(added)
+}
+
+/// Counts the mint of `tx` for `address` in `phase`. It stays counted once
+/// the transaction is on chain, or while it is valid and still can be, so
+/// mints that are never submitted give their place back by themselves. The
+/// allowlist entry is locked while counting so concurrent mints cannot exceed
+/// the limit.
+pub async fn claim_mint(
+    pool: &PgPool,
+    phase: &MintPhase,
+    address: &Address,
+    tx: &Transaction,
+    slot: u32,
+) -> Result<()> {
+    let address = address.to_bech32(None)?;
+    let mut db_tx = pool.begin().await?;
+    let limit: Option<(i32, i32)> = sqlx::query_as(
+        r#"
+        SELECT mint_limit, minted
+        FROM marketplace.mint_allowlist
+        WHERE phase_id = $1 AND address = $2
+        FOR UPDATE
+        "#,
+    )
+    .bind(phase.id)
+    .bind(&address)
+    .fetch_optional(&mut db_tx)
+    .await?;
+    let claimed: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*)
+        FROM marketplace.mint_phase_claim
+        LEFT JOIN tx ON tx.hash = decode(mint_phase_claim.tx_hash, 'hex')
+        WHERE phase_id = $1 AND address = $2
+        AND (tx.id IS NOT NULL OR mint_phase_claim.valid_until >= $3)
+        "#,
+    )
+    .bind(phase.id)
+    .bind(&address)
+    .bind(slot as i64)
+    .fetch_one(&mut db_tx)
+    .await?;
+
+    match limit {
+        Some((mint_limit, minted)) if (minted as i64 + claimed) < mint_limit as i64 => {}
+        _ => {
+            return Err(Error::Forbidden(format!(
+                "Address is not allowed to mint during the {} phase or has reached its mint limit",
+                phase.name
+            )))
+        }
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO marketplace.mint_phase_claim (tx_hash, phase_id, address, valid_until)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(hex::encode(hash_transaction(&tx.body()).to_bytes()))
+    .bind(phase.id)
+    .bind(&address)
+    .bind(tx.body().ttl().unwrap_or(slot) as i64)
+    .execute(&mut db_tx)
+    .await?;
+    db_tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,308 @@
+// Signing keys of minting policies, kept here or handed back to their creators
+
+use crate::coin::decode_witnesses;
+use crate::config::Config;
+use crate::nft::NftPolicy;
+use crate::{Error, Result};
+use cardano_serialization_lib::address::{Address, BaseAddress, EnterpriseAddress};
+use cardano_serialization_lib::crypto::{PrivateKey, Vkeywitnesses};
+use cardano_serialization_lib::emip3::{decrypt_with_password, encrypt_with_password};
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::Serialize;
+use sqlx::PgPool;
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+const SALT_BYTES: usize = 32;
+const NONCE_BYTES: usize = 12;
+const MAX_COLLECTION_NAME_BYTES: usize = 64;
+
+/// Signing key columns, with the flag telling whether they are sealed
+const SEALED_KEY_COLUMNS: [(&str, &str, &str); 3] = [
+    ("marketplace.managed_policy", "policy_skey", "sealed"),
+    ("marketplace.nft_drop", "policy_skey", "policy_skey_sealed"),
+    (
+        "marketplace.nft_drop",
+        "payment_skey",
+        "payment_skey_sealed",
+    ),
+];
+
+const COLLECTION_COLUMNS: &str = r#"
+    encode(policy_id, 'hex') AS policy_id, name, owner_address, policy_lock_slot,
+    locked_at, created_at
+"#;
+
+/// Seals the signing keys kept here with the configured secret
+#[derive(Clone)]
+pub struct Keystore {
+    secret: Option<String>,
+}
+
+impl Keystore {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            secret: config.policy_keystore_secret.clone(),
+        }
+    }
+
+    /// Refuses early to keep a key there is no secret to seal with
+    pub fn check(&self) -> Result<()> {
+        self.secret().map(|_| ())
+    }
+
+    fn secret(&self) -> Result<&str> {
+        self.secret
+            .as_deref()
+            .ok_or_else(|| Error::Message("The policy keystore is not configured".to_string()))
+    }
+
+    pub fn seal(&self, skey: &PrivateKey) -> Result<Vec<u8>> {
+        Ok(hex::decode(encrypt(self.secret()?, &skey.as_bytes())?)?)
+    }
+
+    /// Keys kept before they were sealed are read as they are
+    pub fn open(&self, skey: &[u8], sealed: bool) -> Result<PrivateKey> {
+        if !sealed {
+            return Ok(PrivateKey::from_normal_bytes(skey)?);
+        }
+        let skey = decrypt_with_password(&hex::encode(self.secret()?), &hex::encode(skey))?;
+        Ok(PrivateKey::from_normal_bytes(&hex::decode(skey)?)?)
+    }
+}
+
+/// Seals the keys stored before the keystore existed, run at startup after
+/// the schema migrations as SQL has no EMIP-3. Without a secret they are left
+/// in plaintext, still readable by `Keystore::open`.
+pub async fn seal_stored_keys(pool: &PgPool, keystore: &Keystore) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    let mut sealed = 0;
+    for (table, column, flag) in SEALED_KEY_COLUMNS.iter() {
+        let skeys: Vec<Vec<u8>> = sqlx::query_scalar(&format!(
+            "SELECT {1} FROM {0} WHERE {1} IS NOT NULL AND NOT {2} FOR UPDATE",
+            table, column, flag
+        ))
+        .fetch_all(&mut tx)
+        .await?;
+        if skeys.is_empty() {
+            continue;
+        }
+        if keystore.check().is_err() {
+            println!(
+                "{} keys in {}.{} are not sealed, set POLICY_KEYSTORE_SECRET to seal them",
+                skeys.len(),
+                table,
+                column
+            );
+            continue;
+        }
+        for skey in skeys {
+            let sealed_skey = keystore.seal(&PrivateKey::from_normal_bytes(&skey)?)?;
+            sealed += sqlx::query(&format!(
+                "UPDATE {0} SET {1} = $1, {2} = true WHERE {1} = $2 AND NOT {2}",
+                table, column, flag
+            ))
+            .bind(sealed_skey)
+            .bind(skey)
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+        }
+    }
+    tx.commit().await?;
+    Ok(sealed)
+}
+
+#[derive(sqlx::FromRow)]
+struct PgManagedPolicy {
+    policy_skey: Option<Vec<u8>>,
+    policy_lock_slot: i64,
+    sealed: bool,
+}
+
+/// A policy kept here, without its key
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedCollection {
+    pub policy_id: String,
+    pub name: Option<String>,
+    pub owner_address: String,
+    pub policy_lock_slot: i64,
+    /// Set once the creator locked it, its key is gone from then on
+    pub locked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub fn check_password(password: &str) -> Result<()> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(Error::Message(format!(
+            "The policy key password must be at least {} characters",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+/// An EMIP-3 envelope: salt, nonce, tag and ciphertext, hex encoded, as the
+/// serialization lib's `decrypt_with_password` opens it
+fn encrypt(password: &str, data: &[u8]) -> Result<String> {
+    let mut salt = [0u8; SALT_BYTES];
+    let mut nonce = [0u8; NONCE_BYTES];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    Ok(encrypt_with_password(
+        &hex::encode(password),
+        &hex::encode(salt),
+        &hex::encode(nonce),
+        &hex::encode(data),
+    )?)
+}
+
+/// Encrypts the signing key of `policy` with `password` for its creator
+pub fn encrypt_policy_key(policy: &NftPolicy, password: &str) -> Result<String> {
+    check_password(password)?;
+    encrypt(password, &policy.skey.as_bytes())
+}
+
+/// Keeps the key of `policy`, sealed, so that more can be minted under it
+pub async fn keep_policy(
+    pool: &PgPool,
+    keystore: &Keystore,
+    policy: &NftPolicy,
+    owner: &Address,
+    name: Option<&str>,
+) -> Result<()> {
+    if name.map_or(false, |name| name.len() > MAX_COLLECTION_NAME_BYTES) {
+        return Err(Error::Message(format!(
+            "Collection names are limited to {} bytes",
+            MAX_COLLECTION_NAME_BYTES
+        )));
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO marketplace.managed_policy
+            (policy_id, policy_skey, policy_lock_slot, owner_address, name, sealed)
+        VALUES ($1, $2, $3, $4, $5, true)
+        "#,
+    )
+    .bind(policy.hash.to_bytes())
+    .bind(keystore.seal(&policy.skey)?)
+    .bind(policy.ttl as i64)
+    .bind(owner.to_bech32(None)?)
+    .bind(name)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// The policy kept for `policy_id`, if it is one of ours and not locked
+pub async fn get_managed_policy(
+    pool: &PgPool,
+    keystore: &Keystore,
+    policy_id: &[u8],
+) -> Result<Option<NftPolicy>> {
+    let policy = sqlx::query_as::<_, PgManagedPolicy>(
+        r#"
+        SELECT policy_skey, policy_lock_slot, sealed
+        FROM marketplace.managed_policy
+        WHERE policy_id = $1
+        "#,
+    )
+    .bind(policy_id)
+    .fetch_optional(pool)
+    .await?;
+    match policy {
+        Some(PgManagedPolicy {
+            policy_skey: Some(policy_skey),
+            policy_lock_slot,
+            sealed,
+        }) => Ok(Some(NftPolicy::from_key(
+            keystore.open(&policy_skey, sealed)?,
+            policy_lock_slot as u32,
+        )?)),
+        _ => Ok(None),
+    }
+}
+
+/// Starts a collection of `owner` under a new policy open until `lock_slot`
+pub async fn create_collection(
+    pool: &PgPool,
+    keystore: &Keystore,
+    owner: &Address,
+    name: Option<&str>,
+    lock_slot: u32,
+) -> Result<ManagedCollection> {
+    let policy = NftPolicy::from_key(PrivateKey::generate_ed25519()?, lock_slot)?;
+    keep_policy(pool, keystore, &policy, owner, name).await?;
+    get_collection(pool, &policy.hash.to_bytes()).await
+}
+
+pub async fn get_collection(pool: &PgPool, policy_id: &[u8]) -> Result<ManagedCollection> {
+    sqlx::query_as::<_, ManagedCollection>(&format!(
+        "SELECT {} FROM marketplace.managed_policy WHERE policy_id = $1",
+        COLLECTION_COLUMNS
+    ))
+    .bind(policy_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::NotFound("No such collection".to_string()))
+}
+
+pub async fn get_owner_collections(
+    pool: &PgPool,
+    owner: &Address,
+) -> Result<Vec<ManagedCollection>> {
+    Ok(sqlx::query_as::<_, ManagedCollection>(&format!(
+        "SELECT {} FROM marketplace.managed_policy WHERE owner_address = $1 ORDER BY created_at DESC",
+        COLLECTION_COLUMNS
+    ))
+    .bind(owner.to_bech32(None)?)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Drops the key of a collection for good, so nothing more can be minted
+/// into it whatever its time lock says. `signature` is a vkey witness, or a
+/// witness set holding one, of the owner's payment key over the policy id.
+pub async fn lock_collection(
+    pool: &PgPool,
+    policy_id: &[u8],
+    signature: Vec<u8>,
+) -> Result<ManagedCollection> {
+    let collection = get_collection(pool, policy_id).await?;
+    if collection.locked_at.is_some() {
+        return Ok(collection);
+    }
+    let owner = Address::from_bech32(&collection.owner_address)?;
+    let owner_key_hash = BaseAddress::from_address(&owner)
+        .map(|base| base.payment_cred())
+        .or_else(|| EnterpriseAddress::from_address(&owner).map(|ent| ent.payment_cred()))
+        .and_then(|cred| cred.to_keyhash())
+        .ok_or_else(|| Error::Message("The owner address has no payment key".to_string()))?;
+    let vkeys = decode_witnesses(signature)?
+        .vkeys()
+        .unwrap_or_else(Vkeywitnesses::new);
+    let signed = (0..vkeys.len()).map(|i| vkeys.get(i)).any(|vkey| {
+        let public_key = vkey.vkey().public_key();
+        public_key.hash().to_bytes() == owner_key_hash.to_bytes()
+            && public_key.verify(policy_id, &vkey.signature())
+    });
+    if !signed {
+        return Err(Error::Forbidden(
+            "Only the owner of a collection can lock it".to_string(),
+        ));
+    }
+
+    Ok(sqlx::query_as::<_, ManagedCollection>(&format!(
+        r#"
+        UPDATE marketplace.managed_policy
+        SET policy_skey = NULL, locked_at = now()
+        WHERE policy_id = $1
+        RETURNING {}
+        "#,
+        COLLECTION_COLUMNS
+    ))
+    .bind(policy_id)
+    .fetch_one(pool)
+    .await?)
+}
@@ -0,0 +1,173 @@
+// ADA exchange rates for showing listing prices in fiat
+
+use crate::marketplace::holder::SellData;
+use crate::{Error, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::Value;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+const LOVELACE_PER_ADA: f64 = 1_000_000.0;
+
+/// Where rates are fetched from, tried in the configured order
+#[derive(Debug, Clone, Copy)]
+pub enum PriceSource {
+    CoinGecko,
+    Kraken,
+}
+
+impl FromStr for PriceSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "coingecko" => Ok(PriceSource::CoinGecko),
+            "kraken" => Ok(PriceSource::Kraken),
+            other => Err(Error::Message(format!("Unknown price source {}", other))),
+        }
+    }
+}
+
+impl PriceSource {
+    async fn fetch(&self, client: &Client) -> Result<(f64, f64)> {
+        let (url, usd, eur): (_, &[&str], &[&str]) = match self {
+            PriceSource::CoinGecko => (
+                "https://api.coingecko.com/api/v3/simple/price?ids=cardano&vs_currencies=usd,eur",
+                &["cardano", "usd"],
+                &["cardano", "eur"],
+            ),
+            PriceSource::Kraken => (
+                "https://api.kraken.com/0/public/Ticker?pair=ADAUSD,ADAEUR",
+                &["result", "ADAUSD", "c", "0"],
+                &["result", "ADAEUR", "c", "0"],
+            ),
+        };
+        let res = client.get(url).send().await?.error_for_status()?;
+        let json: Value = serde_json::from_str(&res.text().await?)?;
+        Ok((rate_at(&json, usd)?, rate_at(&json, eur)?))
+    }
+}
+
+/// A number, or a decimal string as Kraken sends them, under `path`
+fn rate_at(json: &Value, path: &[&str]) -> Result<f64> {
+    let mut value = json;
+    for key in path {
+        value = match key.parse::<usize>() {
+            Ok(index) => value.get(index),
+            Err(_) => value.get(*key),
+        }
+        .ok_or_else(|| Error::Message(format!("Missing {} in price response", key)))?;
+    }
+    value
+        .as_f64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+        .filter(|rate| *rate > 0.0)
+        .ok_or_else(|| Error::Message("Invalid rate in price response".to_string()))
+}
+
+#[derive(Debug, Clone)]
+struct Rates {
+    usd: f64,
+    eur: f64,
+    fetched_at: DateTime<Utc>,
+    max_age: ChronoDuration,
+}
+
+/// A lovelace amount in fiat at the last fetched rates
+#[derive(Debug)]
+pub struct FiatPrice {
+    pub usd: f64,
+    pub eur: f64,
+    pub updated_at: DateTime<Utc>,
+    /// The rates have not been refreshed for longer than the configured age
+    pub stale: bool,
+}
+
+/// A listing with its price in fiat next to its fields
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PricedListing<'a> {
+    #[serde(flatten)]
+    listing: &'a SellData,
+    price_usd: Option<f64>,
+    price_eur: Option<f64>,
+    prices_updated_at: Option<DateTime<Utc>>,
+    prices_stale: Option<bool>,
+}
+
+/// The last fetched rates, shared between the refresh task and the handlers
+#[derive(Clone, Default)]
+pub struct PriceOracle {
+    rates: Arc<RwLock<Option<Rates>>>,
+}
+
+impl PriceOracle {
+    /// None until rates have been fetched once
+    pub fn fiat_price(&self, lovelace: u64) -> Option<FiatPrice> {
+        let rates = self.rates.read().ok()?.clone()?;
+        let ada = lovelace as f64 / LOVELACE_PER_ADA;
+        Some(FiatPrice {
+            usd: (ada * rates.usd * 100.0).round() / 100.0,
+            eur: (ada * rates.eur * 100.0).round() / 100.0,
+            updated_at: rates.fetched_at,
+            stale: Utc::now() - rates.fetched_at > rates.max_age,
+        })
+    }
+
+    /// Fiat prices for display only, sales are always in lovelace
+    pub fn price<'a>(&self, listing: &'a SellData) -> PricedListing<'a> {
+        let fiat = self.fiat_price(listing.sale_metadata.price);
+        PricedListing {
+            listing,
+            price_usd: fiat.as_ref().map(|fiat| fiat.usd),
+            price_eur: fiat.as_ref().map(|fiat| fiat.eur),
+            prices_updated_at: fiat.as_ref().map(|fiat| fiat.updated_at),
+            prices_stale: fiat.as_ref().map(|fiat| fiat.stale),
+        }
+    }
+
+    pub fn price_all<'a>(&self, listings: &'a [SellData]) -> Vec<PricedListing<'a>> {
+        listings.iter().map(|listing| self.price(listing)).collect()
+    }
+
+    /// Refreshes the rates every `interval_seconds` from the first source
+    /// that answers. Failed refreshes keep the last rates, flagged stale once
+    /// older than `max_age_seconds`.
+    pub fn spawn(&self, sources: Vec<PriceSource>, interval_seconds: u64, max_age_seconds: u64) {
+        let client = Client::new();
+        let oracle = self.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_seconds));
+            loop {
+                interval.tick().await;
+                for source in &sources {
+                    match source.fetch(&client).await {
+                        Ok((usd, eur)) => {
+                            if let Ok(mut rates) = oracle.rates.write() {
+                                *rates = Some(Rates {
+                                    usd,
+                                    eur,
+                                    fetched_at: Utc::now(),
+                                    max_age: ChronoDuration::seconds(max_age_seconds as i64),
+                                });
+                            }
+                            break;
+                        }
+                        Err(e) => println!("Failed to fetch ADA rates from {:?}: {}", source, e),
+                    }
+                }
+            }
+        });
+    }
+}
+
+pub fn parse_sources(sources: &str) -> Result<Vec<PriceSource>> {
+    sources
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(PriceSource::from_str)
+        .collect()
+}
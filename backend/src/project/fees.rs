@@ -0,0 +1,73 @@
+// Per project fees taken by the projects marketplace
+
+use crate::{Error, Result};
+use cardano_serialization_lib::PolicyID;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+/// Lowest fee allowed, the revenue output needs at least this much ADA
+pub const MIN_PROJECT_FEE: u64 = 1_000_000;
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectFee {
+    #[serde(with = "hex")]
+    pub policy_id: Vec<u8>,
+    pub fee_lovelace: i64,
+}
+
+pub async fn get_project_fees(pool: &PgPool) -> Result<Vec<ProjectFee>> {
+    Ok(sqlx::query_as::<_, ProjectFee>(
+        r#"
+        SELECT policy_id, fee_lovelace
+        FROM marketplace.project_fee
+        ORDER BY policy_id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// The fee set for the project minting under `policy_id`, if any
+pub async fn get_project_fee(pool: &PgPool, policy_id: &PolicyID) -> Result<Option<u64>> {
+    let fee: Option<(i64,)> =
+        sqlx::query_as("SELECT fee_lovelace FROM marketplace.project_fee WHERE policy_id = $1")
+            .bind(policy_id.to_bytes())
+            .fetch_optional(pool)
+            .await?;
+    Ok(fee.map(|(fee,)| fee as u64))
+}
+
+pub async fn set_project_fee(pool: &PgPool, policy_id: &PolicyID, fee_lovelace: u64) -> Result<()> {
+    validate_project_fee(fee_lovelace)?;
+    sqlx::query(
+        r#"
+        INSERT INTO marketplace.project_fee (policy_id, fee_lovelace)
+        VALUES ($1, $2)
+        ON CONFLICT (policy_id) DO UPDATE SET fee_lovelace = EXCLUDED.fee_lovelace
+        "#,
+    )
+    .bind(policy_id.to_bytes())
+    .bind(fee_lovelace as i64)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_project_fee(pool: &PgPool, policy_id: &PolicyID) -> Result<()> {
+    sqlx::query("DELETE FROM marketplace.project_fee WHERE policy_id = $1")
+        .bind(policy_id.to_bytes())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub fn validate_project_fee(fee_lovelace: u64) -> Result<()> {
+    if fee_lovelace < MIN_PROJECT_FEE || fee_lovelace > i64::MAX as u64 {
+        return Err(Error::Message(format!(
+            "Project fee must be at least {} lovelace",
+            MIN_PROJECT_FEE
+        )));
+    }
+    Ok(())
+}
@@ -1,60 +1,143 @@
 use crate::coin::TransactionWitnessSetParams;
 use crate::config::Config;
 use crate::marketplace::holder::{MarketplaceHolder, SellMetadata};
+use crate::project::fees::{get_project_fee, validate_project_fee};
+use crate::project::phase::{get_open_sale_phase, release_purchase, reserve_purchase};
+use crate::project::registry::get_projects;
 use crate::{
-    cardano_db_sync::{get_protocol_params, get_slot_number, query_user_address_utxo},
-    coin::build_transaction_body,
-    convert_to_testnet, Error, Result,
+    chain::ChainQuery,
+    coin::{build_transaction_body, BuildSettings, LargestFirst},
+    configured_address, Error, Result,
 };
-use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::address::{Address, NetworkInfo};
 use cardano_serialization_lib::crypto::Vkeywitnesses;
 use cardano_serialization_lib::utils::{
-    hash_transaction, to_bignum, TransactionUnspentOutput, Value,
+    from_bignum, hash_transaction, to_bignum, TransactionUnspentOutput, Value,
 };
 use cardano_serialization_lib::{
     AssetName, Assets, MultiAsset, PolicyID, Transaction, TransactionOutput, TransactionWitnessSet,
 };
+use serde::Deserialize;
 use sqlx::PgPool;
+use std::collections::HashMap;
+
+pub mod fees;
+pub mod phase;
+pub mod registry;
 
 const ONE_HOUR: u32 = 3600;
 
 #[derive(Clone)]
 pub struct Projects {
     pub(crate) holder: MarketplaceHolder,
-    revenue_address: Address,
+    pub(crate) revenue_address: Address,
+    pub(crate) default_fee: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectConfig {
+    private_key_file: String,
+    revenue_address: String,
+    fee_lovelace: Option<u64>,
 }
 
 impl Projects {
     pub fn from_config(config: &Config) -> Result<Projects> {
-        let holder =
-            MarketplaceHolder::from_key_file(&config.projects_private_key_file, config.is_testnet)?;
+        Self::new(
+            &config.projects_private_key_file,
+            &config.projects_revenue_address,
+            config.projects_fee_lovelace,
+            config.is_testnet,
+        )
+    }
 
-        let mut revenue_address = Address::from_bech32(&config.projects_revenue_address)?;
+    /// The projects listed in the projects file and those registered
+    /// through the admin API, by slug
+    pub async fn load_all(config: &Config, pool: &PgPool) -> Result<HashMap<String, Projects>> {
+        let project_configs: HashMap<String, ProjectConfig> = match &config.projects_file {
+            Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+            None => HashMap::new(),
+        };
+        let mut projects = project_configs
+            .into_iter()
+            .map(|(slug, project)| {
+                let projects = Self::new(
+                    &project.private_key_file,
+                    &project.revenue_address,
+                    project.fee_lovelace.unwrap_or(config.projects_fee_lovelace),
+                    config.is_testnet,
+                )
+                .map_err(|e| Error::Message(format!("Project {}: {}", slug, e)))?;
+                Ok((slug, projects))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
 
-        if config.is_testnet {
-            revenue_address = convert_to_testnet(revenue_address);
+        let network_id = if config.is_testnet {
+            NetworkInfo::testnet().network_id()
+        } else {
+            NetworkInfo::mainnet().network_id()
+        };
+        for record in get_projects(pool).await? {
+            if projects.contains_key(&record.slug) {
+                return Err(Error::Message(format!(
+                    "Project {} is both configured and registered",
+                    record.slug
+                )));
+            }
+            projects.insert(record.slug.clone(), record.to_projects(network_id)?);
         }
+        Ok(projects)
+    }
+
+    fn new(
+        key_file: &str,
+        revenue_address: &str,
+        default_fee: u64,
+        is_testnet: bool,
+    ) -> Result<Projects> {
+        let holder = MarketplaceHolder::from_key_file(key_file, is_testnet)?;
+
+        let revenue_address = configured_address(revenue_address, is_testnet)?;
+        validate_project_fee(default_fee)?;
 
         Ok(Self {
             holder,
             revenue_address,
+            default_fee,
         })
     }
 
-    pub async fn buy(
+    /// Chain data is read from `chain`, the project fee and sale phases from
+    /// `pool`. During a sale phase the phase price replaces the listed price.
+    pub async fn buy<C: ChainQuery>(
         &self,
         buyer_address: Address,
         policy_id: PolicyID,
         asset_name: AssetName,
+        chain: &C,
         pool: &PgPool,
+        build: &BuildSettings,
     ) -> Result<Transaction> {
-        let buyer_utxos = query_user_address_utxo(pool, &buyer_address).await?;
-        let sell_metadata = self.get_sell_details(pool, &policy_id, &asset_name).await?;
+        let buyer_utxos = chain.address_utxos(&buyer_address).await?;
+        let sell_metadata = self
+            .get_sell_details(chain, &policy_id, &asset_name)
+            .await?;
 
-        let holder_utxos = query_user_address_utxo(pool, &self.holder.address).await?;
+        let holder_utxos = chain.address_utxos(&self.holder.address).await?;
         let (nft_utxo, _) = find_nft(holder_utxos, &policy_id, &asset_name)?;
 
-        let (revenue_cut, seller_cut) = calculate_cuts(sell_metadata.price);
+        let slot = chain.slot_number().await?;
+        let sale_phase = get_open_sale_phase(pool, &policy_id, slot).await?;
+        let price = sale_phase
+            .as_ref()
+            .map(|phase| phase.price as u64)
+            .unwrap_or(sell_metadata.price);
+
+        let fee = get_project_fee(pool, &policy_id)
+            .await?
+            .unwrap_or(self.default_fee);
+        let (revenue_cut, seller_cut) = calculate_cuts(price, fee)?;
 
         let revenue_output =
             TransactionOutput::new(&self.revenue_address, &Value::new(&to_bignum(revenue_cut)));
@@ -100,8 +183,7 @@ impl Projects {
             vkey_count: 2,
             ..Default::default()
         };
-        let slot = get_slot_number(pool).await?;
-        let protocol_params = get_protocol_params(pool).await?;
+        let protocol_params = chain.protocol_params().await?;
 
         let aux_data = if return_asset.len() > 0 {
             Some(sell_metadata.create_sell_nft_metadata()?)
@@ -109,6 +191,9 @@ impl Projects {
             None
         };
 
+        if let Some(phase) = &sale_phase {
+            reserve_purchase(pool, phase, &buyer_address).await?;
+        }
         let tx_body = build_transaction_body(
             buyer_utxos,
             inputs,
@@ -119,7 +204,16 @@ impl Projects {
             None,
             &tx_witness_params,
             aux_data.clone(),
-        )?;
+            &LargestFirst,
+            None,
+            build,
+        );
+        if tx_body.is_err() {
+            if let Some(phase) = &sale_phase {
+                release_purchase(pool, phase, &buyer_address).await?;
+            }
+        }
+        let tx_body = tx_body?;
 
         let tx_hash = hash_transaction(&tx_body);
         let vkey = self.holder.sign_transaction_hash(&tx_hash);
@@ -132,14 +226,40 @@ impl Projects {
         Ok(tx)
     }
 
-    async fn get_sell_details(
+    /// Assets of `policy_id` still escrowed with the holder
+    pub async fn remaining_supply<C: ChainQuery>(
         &self,
-        pool: &PgPool,
+        chain: &C,
+        policy_id: &PolicyID,
+    ) -> Result<u64> {
+        let holder_utxos = chain.address_utxos(&self.holder.address).await?;
+        let mut remaining = 0;
+        for utxo in holder_utxos {
+            if let Some(assets) = utxo
+                .output()
+                .amount()
+                .multiasset()
+                .and_then(|ma| ma.get(policy_id))
+            {
+                let names = assets.keys();
+                for i in 0..names.len() {
+                    if let Some(qty) = assets.get(&names.get(i)) {
+                        remaining += from_bignum(&qty);
+                    }
+                }
+            }
+        }
+        Ok(remaining)
+    }
+
+    async fn get_sell_details<C: ChainQuery>(
+        &self,
+        chain: &C,
         policy_id: &PolicyID,
         asset_name: &AssetName,
     ) -> Result<SellMetadata> {
         self.holder
-            .get_nft_details(pool, &policy_id, &asset_name)
+            .get_nft_details(chain, &policy_id, &asset_name)
             .await?
             .ok_or_else(|| Error::Message("No such NFT is for sale".to_string()))
     }
@@ -147,11 +267,15 @@ impl Projects {
 
 const ONE_ADA: u64 = 1_000_000;
 
-fn calculate_cuts(price: u64) -> (u64, u64) {
-    let revenue_cut = 1_500_000;
-    // The seller put in 2 ADA as deposit
-    let seller_cut = price - revenue_cut;
-    (revenue_cut, seller_cut)
+fn calculate_cuts(price: u64, fee: u64) -> Result<(u64, u64)> {
+    // The seller needs enough left over for an output of their own
+    match price.checked_sub(fee) {
+        Some(seller_cut) if seller_cut >= ONE_ADA => Ok((fee, seller_cut)),
+        _ => Err(Error::Message(format!(
+            "Price of {} lovelace does not cover the project fee of {} lovelace",
+            price, fee
+        ))),
+    }
 }
 
 fn create_value_with_single_nft(policy_id: &PolicyID, asset_name: &AssetName) -> Value {
@@ -0,0 +1,150 @@
+// Phased primary sales of a project's assets, e.g. an early-bird price
+// followed by the public price
+
+use crate::{Error, Result};
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::PolicyID;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct SalePhase {
+    pub id: i32,
+    #[serde(with = "hex")]
+    pub policy_id: Vec<u8>,
+    pub name: String,
+    pub price: i64,
+    pub start_slot: i64,
+    pub end_slot: i64,
+    pub wallet_limit: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewSalePhase {
+    pub policy_id: String,
+    pub name: String,
+    pub price: u64,
+    pub start_slot: i64,
+    pub end_slot: i64,
+    pub wallet_limit: Option<i32>,
+}
+
+const SALE_PHASE_COLUMNS: &str = r#"
+    id, policy_id, name, price, start_slot, end_slot, wallet_limit
+"#;
+
+pub async fn create_sale_phase(pool: &PgPool, phase: NewSalePhase) -> Result<SalePhase> {
+    let policy_id = PolicyID::from_bytes(hex::decode(&phase.policy_id)?)?;
+    if phase.start_slot >= phase.end_slot {
+        return Err(Error::Message(
+            "Phase must start before it ends".to_string(),
+        ));
+    }
+    if phase.price == 0 || phase.price > i64::MAX as u64 {
+        return Err(Error::Message("Phase price is out of range".to_string()));
+    }
+    if matches!(phase.wallet_limit, Some(limit) if limit <= 0) {
+        return Err(Error::Message(
+            "Wallet limit must be greater than zero".to_string(),
+        ));
+    }
+
+    Ok(sqlx::query_as::<_, SalePhase>(&format!(
+        r#"
+        INSERT INTO marketplace.project_sale_phase
+            (policy_id, name, price, start_slot, end_slot, wallet_limit)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING {}
+        "#,
+        SALE_PHASE_COLUMNS
+    ))
+    .bind(policy_id.to_bytes())
+    .bind(phase.name)
+    .bind(phase.price as i64)
+    .bind(phase.start_slot)
+    .bind(phase.end_slot)
+    .bind(phase.wallet_limit)
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn get_sale_phases(pool: &PgPool, policy_id: &PolicyID) -> Result<Vec<SalePhase>> {
+    Ok(sqlx::query_as::<_, SalePhase>(&format!(
+        r#"
+        SELECT {}
+        FROM marketplace.project_sale_phase
+        WHERE policy_id = $1
+        ORDER BY start_slot
+        "#,
+        SALE_PHASE_COLUMNS
+    ))
+    .bind(policy_id.to_bytes())
+    .fetch_all(pool)
+    .await?)
+}
+
+/// The phase of `policy_id` open at `slot`. Policies without phases are sold
+/// at their listed prices at any time, so `None` means no phase applies and
+/// an error that the policy has phases but none is open.
+pub async fn get_open_sale_phase(
+    pool: &PgPool,
+    policy_id: &PolicyID,
+    slot: u32,
+) -> Result<Option<SalePhase>> {
+    let phases = get_sale_phases(pool, policy_id).await?;
+    if phases.is_empty() {
+        return Ok(None);
+    }
+    // If phases overlap the one that started last wins
+    phases
+        .into_iter()
+        .rev()
+        .find(|phase| phase.start_slot <= slot as i64 && phase.end_slot > slot as i64)
+        .map(Some)
+        .ok_or_else(|| Error::Forbidden("The sale of this project is not open".to_string()))
+}
+
+/// Claims one purchase for `address` in `phase`, in a single statement so
+/// concurrent requests cannot exceed the wallet limit.
+pub async fn reserve_purchase(pool: &PgPool, phase: &SalePhase, address: &Address) -> Result<()> {
+    let res = sqlx::query(
+        r#"
+        INSERT INTO marketplace.project_sale_purchase AS p (phase_id, address, purchased)
+        VALUES ($1, $2, 1)
+        ON CONFLICT (phase_id, address) DO UPDATE SET purchased = p.purchased + 1
+        WHERE $3::INTEGER IS NULL OR p.purchased < $3
+        "#,
+    )
+    .bind(phase.id)
+    .bind(address.to_bech32(None)?)
+    .bind(phase.wallet_limit)
+    .execute(pool)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        return Err(Error::Forbidden(format!(
+            "Address has reached its purchase limit for the {} phase",
+            phase.name
+        )));
+    }
+    Ok(())
+}
+
+/// Gives back a purchase claimed by `reserve_purchase` when the transaction
+/// could not be built.
+pub async fn release_purchase(pool: &PgPool, phase: &SalePhase, address: &Address) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE marketplace.project_sale_purchase
+        SET purchased = purchased - 1
+        WHERE phase_id = $1 AND address = $2 AND purchased > 0
+        "#,
+    )
+    .bind(phase.id)
+    .bind(address.to_bech32(None)?)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,122 @@
+// Launch projects onboarded at runtime, persisted so they survive restarts
+
+use crate::decode_private_key_cbor;
+use crate::marketplace::holder::MarketplaceHolder;
+use crate::project::fees::validate_project_fee;
+use crate::project::Projects;
+use crate::{Error, Result};
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::crypto::PrivateKey;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectRecord {
+    pub slug: String,
+    #[serde(skip)]
+    holder_skey: Vec<u8>,
+    pub holder_address: String,
+    pub revenue_address: String,
+    pub fee_lovelace: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewProject {
+    pub slug: String,
+    pub revenue_address: String,
+    /// Flat fee on sales, defaults to the configured projects fee
+    pub fee_lovelace: Option<u64>,
+    /// cborHex of an existing signing key, a new key is generated otherwise
+    pub private_key: Option<String>,
+}
+
+const PROJECT_COLUMNS: &str = r#"
+    slug, holder_skey, holder_address, revenue_address, fee_lovelace, created_at
+"#;
+
+impl ProjectRecord {
+    pub fn to_projects(&self, network_id: u8) -> Result<Projects> {
+        let private_key = PrivateKey::from_normal_bytes(&self.holder_skey)?;
+        Ok(Projects {
+            holder: MarketplaceHolder::from_key(private_key, network_id)?,
+            revenue_address: Address::from_bech32(&self.revenue_address)?,
+            default_fee: self.fee_lovelace as u64,
+        })
+    }
+}
+
+/// Slugs are used in URLs, so only lowercase letters, digits and dashes
+fn validate_slug(slug: &str) -> Result<()> {
+    let valid = !slug.is_empty()
+        && slug.len() <= 64
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !valid {
+        return Err(Error::Message(
+            "Project slugs may only contain lowercase letters, digits and dashes".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+pub async fn create_project(
+    pool: &PgPool,
+    new_project: NewProject,
+    revenue_address: &Address,
+    default_fee: u64,
+    network_id: u8,
+) -> Result<ProjectRecord> {
+    validate_slug(&new_project.slug)?;
+    let fee_lovelace = new_project.fee_lovelace.unwrap_or(default_fee);
+    validate_project_fee(fee_lovelace)?;
+    if revenue_address.network_id()? != network_id {
+        return Err(Error::Message(
+            "Revenue address is on the wrong network".to_string(),
+        ));
+    }
+
+    let private_key = match &new_project.private_key {
+        Some(cbor_hex) => decode_private_key_cbor(cbor_hex)?,
+        None => PrivateKey::generate_ed25519()?,
+    };
+    let holder = MarketplaceHolder::from_key(private_key, network_id)?;
+
+    let project = sqlx::query_as::<_, ProjectRecord>(&format!(
+        r#"
+        INSERT INTO marketplace.project
+            (slug, holder_skey, holder_address, revenue_address, fee_lovelace)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (slug) DO NOTHING
+        RETURNING {}
+        "#,
+        PROJECT_COLUMNS
+    ))
+    .bind(&new_project.slug)
+    .bind(holder.private_key_bytes())
+    .bind(holder.address.to_bech32(None)?)
+    .bind(revenue_address.to_bech32(None)?)
+    .bind(fee_lovelace as i64)
+    .fetch_optional(pool)
+    .await?;
+
+    project.ok_or_else(|| {
+        Error::Message(format!(
+            "A project named {} already exists",
+            new_project.slug
+        ))
+    })
+}
+
+pub async fn get_projects(pool: &PgPool) -> Result<Vec<ProjectRecord>> {
+    Ok(sqlx::query_as::<_, ProjectRecord>(&format!(
+        "SELECT {} FROM marketplace.project ORDER BY slug",
+        PROJECT_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?)
+}
@@ -0,0 +1,74 @@
+// Labels marketplace transactions with what they do, for on-chain analytics
+
+use crate::coin::BuildSettings;
+use crate::Result;
+use cardano_serialization_lib::metadata::{
+    AuxiliaryData, GeneralTransactionMetadata, MetadataMap, TransactionMetadatum,
+};
+use cardano_serialization_lib::utils::{to_bignum, Int};
+
+/// Next to the 888 sale metadata of listings
+pub const PURPOSE_METADATA_LABEL: u64 = 889;
+/// Schema of the purpose tag, bumped when its fields change
+const PURPOSE_VERSION: i32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Purpose {
+    Buy,
+    Cancel,
+    Mint,
+    Offer,
+    AcceptOffer,
+    WithdrawOffer,
+}
+
+impl Purpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Purpose::Buy => "buy",
+            Purpose::Cancel => "cancel",
+            Purpose::Mint => "mint",
+            Purpose::Offer => "offer",
+            Purpose::AcceptOffer => "accept_offer",
+            Purpose::WithdrawOffer => "withdraw_offer",
+        }
+    }
+}
+
+/// Adds `{"op": <purpose>, "v": <version>}` under the purpose label, unless
+/// tagging is turned off in `settings`
+pub fn tag(
+    metadata: &mut GeneralTransactionMetadata,
+    purpose: Purpose,
+    settings: &BuildSettings,
+) -> Result<()> {
+    if !settings.tag_purpose {
+        return Ok(());
+    }
+    let mut map = MetadataMap::new();
+    map.insert(
+        &TransactionMetadatum::new_text("op".to_string())?,
+        &TransactionMetadatum::new_text(purpose.as_str().to_string())?,
+    );
+    map.insert(
+        &TransactionMetadatum::new_text("v".to_string())?,
+        &TransactionMetadatum::new_int(&Int::new_i32(PURPOSE_VERSION)),
+    );
+    metadata.insert(
+        &to_bignum(PURPOSE_METADATA_LABEL),
+        &TransactionMetadatum::new_map(&map),
+    );
+    Ok(())
+}
+
+/// Auxiliary data carrying only the purpose tag, none when tagging is off
+pub fn auxiliary_data(purpose: Purpose, settings: &BuildSettings) -> Result<Option<AuxiliaryData>> {
+    let mut metadata = GeneralTransactionMetadata::new();
+    tag(&mut metadata, purpose, settings)?;
+    if metadata.len() == 0 {
+        return Ok(None);
+    }
+    let mut auxiliary_data = AuxiliaryData::new();
+    auxiliary_data.set_metadata(&metadata);
+    Ok(Some(auxiliary_data))
+}
@@ -0,0 +1,85 @@
+// Registry of reference scripts deployed on chain
+//
+// Outputs carrying reference scripts only exist from the Babbage era onwards,
+// which the serialization library used here cannot build yet. Deployments are
+// made with cardano-cli and recorded here once their UTxO is on chain, so
+// script transactions can look up where to reference them from.
+
+use crate::cardano_db_sync::{query_reference_script_hash, query_utxo_unspent};
+use crate::{Error, Result};
+use cardano_serialization_lib::crypto::{ScriptHash, TransactionHash};
+use cardano_serialization_lib::TransactionInput;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferenceScript {
+    pub name: String,
+    #[serde(serialize_with = "hex::serialize")]
+    pub script_hash: Vec<u8>,
+    #[serde(serialize_with = "hex::serialize")]
+    pub tx_hash: Vec<u8>,
+    pub tx_index: i16,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewReferenceScript {
+    pub name: String,
+    pub script_hash: String,
+    pub tx_hash: String,
+    pub tx_index: u32,
+}
+
+/// Records a deployment once its UTxO is unspent on chain and carries the
+/// script it is registered for
+pub async fn register_reference_script(
+    pool: &PgPool,
+    script: NewReferenceScript,
+) -> Result<ReferenceScript> {
+    let script_hash = ScriptHash::from_bytes(hex::decode(&script.script_hash)?)?;
+    let tx_hash = TransactionHash::from_bytes(hex::decode(&script.tx_hash)?)?;
+    let input = TransactionInput::new(&tx_hash, script.tx_index);
+    if !query_utxo_unspent(pool, &input).await? {
+        return Err(Error::Message(
+            "Reference script UTxO does not exist or has been spent".to_string(),
+        ));
+    }
+    let deployed = query_reference_script_hash(pool, &input).await?;
+    if deployed.map(|hash| hash.to_bytes()) != Some(script_hash.to_bytes()) {
+        return Err(Error::Message(
+            "Reference script UTxO does not carry this script".to_string(),
+        ));
+    }
+
+    Ok(sqlx::query_as::<_, ReferenceScript>(
+        r#"
+        INSERT INTO marketplace.reference_script (name, script_hash, tx_hash, tx_index)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (name) DO UPDATE
+        SET script_hash = EXCLUDED.script_hash,
+            tx_hash = EXCLUDED.tx_hash,
+            tx_index = EXCLUDED.tx_index
+        RETURNING name, script_hash, tx_hash, tx_index
+        "#,
+    )
+    .bind(script.name)
+    .bind(script_hash.to_bytes())
+    .bind(tx_hash.to_bytes())
+    .bind(script.tx_index as i16)
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn get_reference_scripts(pool: &PgPool) -> Result<Vec<ReferenceScript>> {
+    Ok(sqlx::query_as::<_, ReferenceScript>(
+        r#"
+        SELECT name, script_hash, tx_hash, tx_index
+        FROM marketplace.reference_script
+        ORDER BY name
+        "#,
+    )
+    .fetch_all(pool)
+    .await?)
+}
@@ -0,0 +1,81 @@
+// Re-minting of CIP-25 assets to replace their metadata
+
+use crate::chain::ChainQuery;
+use crate::coin::{
+    build_transaction_body, BuildSettings, LargestFirst, TransactionWitnessSetParams,
+};
+use crate::nft::{nft_metadata, PolicyWitness, WottleNftMetadata};
+use crate::Result;
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::metadata::AuxiliaryData;
+use cardano_serialization_lib::utils::Int;
+use cardano_serialization_lib::{AssetName, Mint, MintAssets, PolicyID, Transaction};
+
+const ONE_HOUR: u32 = 3600;
+
+/// Returned with every update transaction, as its effect depends on who
+/// reads the metadata
+pub const UPDATE_WARNINGS: &[&str] = &[
+    "Wallets, explorers and marketplaces show the metadata of the latest mint transaction, \
+     but many cache it and can keep showing the old metadata",
+    "The update mints a quantity of 0, which only ledger eras accepting zero quantities \
+     in the mint field will include",
+];
+
+/// Builds a transaction minting none of the asset named after `nft`, so its
+/// metadata becomes the latest for the asset. Paid by `creator`, who does not
+/// need to hold the asset.
+pub async fn update_nft_metadata<C: ChainQuery>(
+    chain: &C,
+    creator: &Address,
+    policy_id: &PolicyID,
+    nft: &WottleNftMetadata,
+    policy: PolicyWitness,
+    build: &BuildSettings,
+) -> Result<Transaction> {
+    let slot = chain.slot_number().await?;
+    let ttl = policy.ttl(policy_id, slot, slot + ONE_HOUR)?;
+
+    let asset_name = AssetName::new(nft.name().as_bytes().to_vec())?;
+    let mut mint_assets = MintAssets::new();
+    mint_assets.insert(&asset_name, Int::new_i32(0));
+    let mut mint = Mint::new();
+    mint.insert(policy_id, &mint_assets);
+
+    let mut auxiliary_data = AuxiliaryData::new();
+    auxiliary_data.set_metadata(&nft_metadata(
+        policy_id,
+        nft,
+        build.mint_attribution.as_deref(),
+    )?);
+
+    let utxos = chain.address_utxos(creator).await?;
+    let native_scripts = policy.native_scripts();
+    let protocol_params = chain.protocol_params().await?;
+    let tx_witness_params = TransactionWitnessSetParams {
+        vkey_count: 2,
+        native_scripts: Some(&native_scripts),
+        ..Default::default()
+    };
+    let tx_body = build_transaction_body(
+        utxos,
+        vec![],
+        vec![],
+        ttl,
+        &protocol_params,
+        None,
+        Some(mint),
+        &tx_witness_params,
+        Some(auxiliary_data.clone()),
+        &LargestFirst,
+        None,
+        build,
+    )?;
+
+    let tx_witness_set = policy.witness_set(&tx_body);
+    Ok(Transaction::new(
+        &tx_body,
+        &tx_witness_set,
+        Some(auxiliary_data),
+    ))
+}
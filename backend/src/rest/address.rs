@@ -1,9 +1,13 @@
 use crate::Result;
 use actix_web::{get, web, HttpResponse, Scope};
 use cardano_serialization_lib::utils::{from_bignum, BigNum};
+use serde::Deserialize;
 use serde_json::json;
 
-use crate::cardano_db_sync::{query_user_address_nfts, query_user_address_utxo, UtxoJson};
+use crate::cardano_db_sync::{
+    query_script_address_utxo, query_user_address_nfts, query_user_address_utxo,
+    query_user_address_utxo_page, ScriptUtxoJson, UtxoJson,
+};
 use crate::rest::AppState;
 
 #[get("/{address}/utxo")]
@@ -16,6 +20,43 @@ async fn get_all_utxos(path: web::Path<String>, data: web::Data<AppState>) -> Re
     Ok(HttpResponse::Ok().json(jsons))
 }
 
+#[derive(Deserialize)]
+struct UtxoPage {
+    after: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// Unspent outputs of an address in pages, for addresses too large to be
+/// returned at once. `next` is passed back as `after` to get the next page.
+#[get("/{address}/utxo/page")]
+async fn get_utxo_page(
+    path: web::Path<String>,
+    page: web::Query<UtxoPage>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let address = super::parse_address(&path.into_inner())?;
+    let limit = page.limit.unwrap_or(100).clamp(1, 1_000);
+    let (utxos, next) =
+        query_user_address_utxo_page(&data.pool, &address, page.after, limit).await?;
+
+    let jsons: Vec<UtxoJson> = utxos.iter().map(UtxoJson::from).collect();
+
+    Ok(HttpResponse::Ok().json(json!({ "utxos": jsons, "next": next })))
+}
+
+#[get("/{address}/script-utxo")]
+async fn get_script_utxos(
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let address = super::parse_address(&path.into_inner())?;
+    let utxos = query_script_address_utxo(&data.pool, &address).await?;
+
+    let jsons: Vec<ScriptUtxoJson> = utxos.iter().map(ScriptUtxoJson::from).collect();
+
+    Ok(HttpResponse::Ok().json(jsons))
+}
+
 #[get("/{address}/balance")]
 async fn get_address_balance(
     path: web::Path<String>,
@@ -52,12 +93,14 @@ async fn get_address_listings(
         .holder
         .get_listings_from_user(&data.pool, &address)
         .await?;
-    Ok(HttpResponse::Ok().json(listings))
+    Ok(HttpResponse::Ok().json(data.price_oracle.price_all(&listings)))
 }
 
 pub fn create_address_service() -> Scope {
     web::scope("/address")
         .service(get_all_utxos)
+        .service(get_utxo_page)
+        .service(get_script_utxos)
         .service(get_address_balance)
         .service(get_address_nfts)
         .service(get_address_listings)
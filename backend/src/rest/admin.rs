@@ -0,0 +1,707 @@
+use crate::audit::{get_audit_entries, AuditFilter};
+use crate::cardano_db_sync::{query_label_usage, query_revenue, RevenueAddresses};
+use crate::drops::{create_drop, NewDrop};
+use crate::features::{reset_feature, set_feature, Feature};
+use crate::jobs::{get_jobs, retry_job};
+use crate::marketplace::approval::{decide_approval, get_pending_approvals};
+use crate::marketplace::coupon::{create_coupon, get_coupons, NewCoupon};
+use crate::marketplace::fees::{
+    delete_fee_discount, delete_policy_fee, get_fee_discounts, get_policy_fees, set_fee_discount,
+    set_policy_fee,
+};
+use crate::marketplace::hidden::{get_hidden_listings, hide_listing, unhide_listing};
+use crate::marketplace::holder::MARKETPLACE_METADATA_LABEL_KEY;
+use crate::marketplace::rejected::get_rejected_listings;
+use crate::marketplace::royalty::{get_royalty_splits, set_royalty_splits, RoyaltyRecipient};
+use crate::marketplace::sales::{stream_sales, Sale};
+use crate::phase::{add_to_allowlist, create_phase, get_phases, NewMintPhase};
+use crate::project::fees::{delete_project_fee, get_project_fees, set_project_fee};
+use crate::project::phase::{create_sale_phase, get_sale_phases, NewSalePhase};
+use crate::project::registry::{create_project, get_projects, NewProject};
+use crate::reference_script::{
+    get_reference_scripts, register_reference_script, NewReferenceScript,
+};
+use crate::rest::{authorize_admin, authorize_tenant_admin, parse_address, AppState};
+use crate::{Error, Result};
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Scope};
+use cardano_serialization_lib::PolicyID;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use tokio_stream::StreamExt;
+
+#[get("/phases")]
+async fn list_phases(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let phases = get_phases(&data.pool).await?;
+    Ok(HttpResponse::Ok().json(phases))
+}
+
+#[post("/phases")]
+async fn add_phase(
+    req: HttpRequest,
+    phase: web::Json<NewMintPhase>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let phase = create_phase(&data.pool, phase.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(phase))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Allowlist {
+    addresses: Vec<String>,
+    mint_limit: Option<i32>,
+}
+
+#[post("/phases/{id}/allowlist")]
+async fn add_phase_allowlist(
+    req: HttpRequest,
+    path: web::Path<i32>,
+    allowlist: web::Json<Allowlist>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let allowlist = allowlist.into_inner();
+    let addresses = allowlist
+        .addresses
+        .iter()
+        .map(|a| parse_address(a))
+        .collect::<Result<Vec<_>>>()?;
+    let added = add_to_allowlist(
+        &data.pool,
+        path.into_inner(),
+        &addresses,
+        allowlist.mint_limit,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(json!({ "added": added })))
+}
+
+#[post("/drops")]
+async fn add_drop(
+    req: HttpRequest,
+    new_drop: web::Json<NewDrop>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let new_drop = new_drop.into_inner();
+    let payment_address = new_drop
+        .payment_address
+        .as_deref()
+        .map(parse_address)
+        .transpose()?;
+    let drop = create_drop(
+        &data.pool,
+        &data.keystore,
+        new_drop,
+        payment_address.as_ref(),
+        data.network_id,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(drop))
+}
+
+#[get("/royalties/{policy_id}")]
+async fn get_policy_royalties(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(path.into_inner())?)?;
+    let splits = get_royalty_splits(&data.pool, &policy_id).await?;
+    Ok(HttpResponse::Ok().json(splits))
+}
+
+#[put("/royalties/{policy_id}")]
+async fn set_policy_royalties(
+    req: HttpRequest,
+    path: web::Path<String>,
+    recipients: web::Json<Vec<RoyaltyRecipient>>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(path.into_inner())?)?;
+    if recipients.iter().any(|recipient| recipient.rate_bps == 0) {
+        return Err(Error::Message(
+            "A royalty recipient needs a rate above zero".to_string(),
+        ));
+    }
+    set_royalty_splits(&data.pool, &policy_id, &recipients).await?;
+    Ok(HttpResponse::Ok().json(recipients.into_inner()))
+}
+
+#[get("/fee-discounts")]
+async fn list_fee_discounts(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let discounts = get_fee_discounts(&data.pool).await?;
+    Ok(HttpResponse::Ok().json(discounts))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FeeDiscountRule {
+    fee_bps: u32,
+}
+
+#[put("/fee-discounts/{policy_id}")]
+async fn put_fee_discount(
+    req: HttpRequest,
+    path: web::Path<String>,
+    rule: web::Json<FeeDiscountRule>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(path.into_inner())?)?;
+    set_fee_discount(&data.pool, &policy_id, rule.fee_bps).await?;
+    Ok(HttpResponse::Ok().json(json!({ "feeBps": rule.fee_bps })))
+}
+
+#[delete("/fee-discounts/{policy_id}")]
+async fn remove_fee_discount(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(path.into_inner())?)?;
+    delete_fee_discount(&data.pool, &policy_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/policy-fees")]
+async fn list_policy_fees(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let fees = get_policy_fees(&data.pool).await?;
+    Ok(HttpResponse::Ok().json(fees))
+}
+
+/// Sets the fee of secondary sales under a policy, 0 exempts it
+#[put("/policy-fees/{policy_id}")]
+async fn put_policy_fee(
+    req: HttpRequest,
+    path: web::Path<String>,
+    rule: web::Json<FeeDiscountRule>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(path.into_inner())?)?;
+    set_policy_fee(&data.pool, &policy_id, rule.fee_bps).await?;
+    Ok(HttpResponse::Ok().json(json!({ "feeBps": rule.fee_bps })))
+}
+
+#[delete("/policy-fees/{policy_id}")]
+async fn remove_policy_fee(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(path.into_inner())?)?;
+    delete_policy_fee(&data.pool, &policy_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/hidden-listings")]
+async fn list_hidden_listings(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let listings = get_hidden_listings(&data.pool).await?;
+    Ok(HttpResponse::Ok().json(listings))
+}
+
+#[derive(Deserialize)]
+struct HideListing {
+    reason: Option<String>,
+}
+
+/// Takes a listing off the marketplace pages and search, its escrow stays
+/// on-chain and the seller can still cancel
+#[put("/hidden-listings/{tx_hash}")]
+async fn put_hidden_listing(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<HideListing>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    hide_listing(&data.pool, &path.into_inner(), body.reason.as_deref()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/hidden-listings/{tx_hash}")]
+async fn remove_hidden_listing(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    unhide_listing(&data.pool, &path.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Purchases over the approval threshold waiting for a second approval
+#[get("/approvals")]
+async fn list_pending_approvals(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let tenant = authorize_tenant_admin(&req, &data)?;
+    let approvals = get_pending_approvals(&data.pool, &tenant.marketplace.holder).await?;
+    Ok(HttpResponse::Ok().json(approvals))
+}
+
+/// Signs the purchase with the holder, the buyer then fetches it from
+/// /marketplace/approvals/{id}
+#[post("/approvals/{id}/approve")]
+async fn approve_purchase(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let tenant = authorize_tenant_admin(&req, &data)?;
+    let approval = decide_approval(
+        &data.pool,
+        &tenant.marketplace.holder,
+        path.into_inner(),
+        true,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(approval))
+}
+
+#[post("/approvals/{id}/reject")]
+async fn reject_purchase(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let tenant = authorize_tenant_admin(&req, &data)?;
+    let approval = decide_approval(
+        &data.pool,
+        &tenant.marketplace.holder,
+        path.into_inner(),
+        false,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(approval))
+}
+
+#[get("/projects")]
+async fn list_projects(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let projects = get_projects(&data.pool).await?;
+    Ok(HttpResponse::Ok().json(projects))
+}
+
+#[post("/projects")]
+async fn add_project(
+    req: HttpRequest,
+    new_project: web::Json<NewProject>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let new_project = new_project.into_inner();
+    if data
+        .projects
+        .read()
+        .unwrap()
+        .contains_key(&new_project.slug)
+    {
+        return Err(Error::Message(format!(
+            "A project named {} already exists",
+            new_project.slug
+        )));
+    }
+    let revenue_address = parse_address(&new_project.revenue_address)?;
+    let record = create_project(
+        &data.pool,
+        new_project,
+        &revenue_address,
+        data.projects_fee,
+        data.network_id,
+    )
+    .await?;
+    let project = record.to_projects(data.network_id)?;
+    data.projects
+        .write()
+        .unwrap()
+        .insert(record.slug.clone(), project);
+    Ok(HttpResponse::Ok().json(record))
+}
+
+#[get("/project-phases/{policy_id}")]
+async fn list_project_phases(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(path.into_inner())?)?;
+    let phases = get_sale_phases(&data.pool, &policy_id).await?;
+    Ok(HttpResponse::Ok().json(phases))
+}
+
+#[post("/project-phases")]
+async fn add_project_phase(
+    req: HttpRequest,
+    phase: web::Json<NewSalePhase>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let phase = create_sale_phase(&data.pool, phase.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(phase))
+}
+
+#[get("/project-fees")]
+async fn list_project_fees(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let fees = get_project_fees(&data.pool).await?;
+    Ok(HttpResponse::Ok().json(fees))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectFeeRule {
+    fee_lovelace: u64,
+}
+
+#[put("/project-fees/{policy_id}")]
+async fn put_project_fee(
+    req: HttpRequest,
+    path: web::Path<String>,
+    rule: web::Json<ProjectFeeRule>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(path.into_inner())?)?;
+    set_project_fee(&data.pool, &policy_id, rule.fee_lovelace).await?;
+    Ok(HttpResponse::Ok().json(json!({ "feeLovelace": rule.fee_lovelace })))
+}
+
+#[delete("/project-fees/{policy_id}")]
+async fn remove_project_fee(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(path.into_inner())?)?;
+    delete_project_fee(&data.pool, &policy_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/coupons")]
+async fn list_coupons(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let coupons = get_coupons(&data.pool).await?;
+    Ok(HttpResponse::Ok().json(coupons))
+}
+
+#[post("/coupons")]
+async fn add_coupon(
+    req: HttpRequest,
+    coupon: web::Json<NewCoupon>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let coupon = create_coupon(&data.pool, coupon.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(coupon))
+}
+
+#[derive(Deserialize)]
+struct RevenueRange {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+#[get("/revenue")]
+async fn get_revenue(
+    req: HttpRequest,
+    range: web::Query<RevenueRange>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let tenant = authorize_tenant_admin(&req, &data)?;
+    let from = range
+        .from
+        .map(|d| d.naive_utc())
+        .unwrap_or_else(|| NaiveDateTime::from_timestamp(0, 0));
+    let to = range.to.unwrap_or_else(Utc::now).naive_utc();
+
+    // Tenants only see what their own revenue address received
+    let revenue = if tenant.is_default() {
+        vec![
+            tenant.marketplace.revenue_address.to_bech32(None)?,
+            data.project.revenue_address.to_bech32(None)?,
+            data.tax_address.to_bech32(None)?,
+        ]
+    } else {
+        vec![tenant.marketplace.revenue_address.to_bech32(None)?]
+    };
+    let addresses = RevenueAddresses {
+        revenue,
+        marketplace_holder: tenant.marketplace.holder.address.to_bech32(None)?,
+        projects_holder: data.project.holder.address.to_bech32(None)?,
+        tax: data.tax_address.to_bech32(None)?,
+    };
+    let sources = query_revenue(&data.pool, &addresses, from, to).await?;
+    let total: u64 = sources.iter().map(|s| s.lovelace).sum();
+    Ok(HttpResponse::Ok().json(json!({
+        "from": from,
+        "to": to,
+        "sources": sources,
+        "total": total,
+    })))
+}
+
+/// How many listings carry the sale metadata label, per address receiving the
+/// assets and per policy. Outputs outside the holders are third parties
+/// using the same label.
+#[get("/metadata-usage")]
+async fn get_metadata_usage(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let tenant = authorize_tenant_admin(&req, &data)?;
+    let (by_address, by_policy) =
+        query_label_usage(&data.pool, MARKETPLACE_METADATA_LABEL_KEY).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "label": MARKETPLACE_METADATA_LABEL_KEY,
+        "marketplaceHolder": tenant.marketplace.holder.address.to_bech32(None)?,
+        "byAddress": by_address,
+        "byPolicy": by_policy,
+    })))
+}
+
+/// Escrowed NFTs left off the marketplace as their sale metadata fails
+/// validation, with the reason
+#[get("/rejected-listings")]
+async fn list_rejected_listings(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let tenant = authorize_tenant_admin(&req, &data)?;
+    let listings = get_rejected_listings(
+        &data.pool,
+        &tenant.marketplace.holder.address.to_bech32(None)?,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(listings))
+}
+
+#[get("/export/sales.csv")]
+async fn export_sales(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let tenant = authorize_tenant_admin(&req, &data)?;
+    let sales = stream_sales(
+        data.pool.clone(),
+        tenant.marketplace.holder.address.to_bech32(None)?,
+        tenant.marketplace.revenue_address.to_bech32(None)?,
+    );
+    let body = tokio_stream::once(Ok::<_, Error>(Bytes::from_static(
+        Sale::CSV_HEADER.as_bytes(),
+    )))
+    .chain(sales.map(|sale| sale.map(|sale| Bytes::from(sale.to_csv_row()))));
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"sales.csv\"",
+        ))
+        .streaming(body))
+}
+
+#[get("/reference-scripts")]
+async fn list_reference_scripts(
+    req: HttpRequest,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let scripts = get_reference_scripts(&data.pool).await?;
+    Ok(HttpResponse::Ok().json(scripts))
+}
+
+#[post("/reference-scripts")]
+async fn add_reference_script(
+    req: HttpRequest,
+    script: web::Json<NewReferenceScript>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let script = register_reference_script(&data.pool, script.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(script))
+}
+
+#[derive(Deserialize)]
+struct JobFilter {
+    status: Option<String>,
+}
+
+#[get("/jobs")]
+async fn list_jobs(
+    req: HttpRequest,
+    filter: web::Query<JobFilter>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let jobs = get_jobs(&data.pool, filter.status.as_deref()).await?;
+    Ok(HttpResponse::Ok().json(jobs))
+}
+
+#[post("/jobs/{id}/retry")]
+async fn retry_dead_job(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let job = retry_job(&data.pool, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(job))
+}
+
+#[get("/metrics")]
+async fn get_metrics(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let tenant = authorize_tenant_admin(&req, &data)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(tenant.metrics.render()))
+}
+
+#[get("/audit")]
+async fn list_audit_entries(
+    req: HttpRequest,
+    filter: web::Query<AuditFilter>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let entries = get_audit_entries(&data.pool, &filter).await?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+#[get("/slo")]
+async fn get_slo(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let tenant = authorize_tenant_admin(&req, &data)?;
+    let routes = tenant.metrics.slo();
+    let breached: Vec<&str> = routes
+        .iter()
+        .filter(|route| route.latency_breached || route.error_rate_breached)
+        .map(|route| route.route.as_str())
+        .collect();
+    Ok(HttpResponse::Ok().json(json!({
+        "breached": breached,
+        "routes": routes,
+    })))
+}
+
+#[get("/maintenance")]
+async fn get_maintenance(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    Ok(HttpResponse::Ok().json(data.maintenance.status()))
+}
+
+#[derive(Deserialize)]
+struct MaintenanceUpdate {
+    enabled: bool,
+    /// Shown to users instead of the default message
+    message: Option<String>,
+}
+
+/// Switches read-only mode on or off. Admin routes keep working either way.
+#[put("/maintenance")]
+async fn put_maintenance(
+    req: HttpRequest,
+    update: web::Json<MaintenanceUpdate>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let update = update.into_inner();
+    if update.enabled {
+        data.maintenance.enable(update.message);
+    } else {
+        data.maintenance.disable();
+    }
+    Ok(HttpResponse::Ok().json(data.maintenance.status()))
+}
+
+#[get("/features")]
+async fn list_features(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    Ok(HttpResponse::Ok().json(data.features.states(&data.pool).await?))
+}
+
+#[derive(Deserialize)]
+struct FeatureUpdate {
+    enabled: bool,
+}
+
+#[put("/features/{name}")]
+async fn put_feature(
+    req: HttpRequest,
+    path: web::Path<String>,
+    update: web::Json<FeatureUpdate>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let feature: Feature = path.parse()?;
+    set_feature(&data.pool, feature, update.enabled).await?;
+    Ok(HttpResponse::Ok().json(data.features.states(&data.pool).await?))
+}
+
+/// Drops the runtime override, back to the configured default
+#[delete("/features/{name}")]
+async fn remove_feature_override(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    authorize_admin(&req, &data)?;
+    let feature: Feature = path.parse()?;
+    reset_feature(&data.pool, feature).await?;
+    Ok(HttpResponse::Ok().json(data.features.states(&data.pool).await?))
+}
+
+pub fn create_admin_service() -> Scope {
+    web::scope("/admin")
+        .service(list_phases)
+        .service(add_phase)
+        .service(add_phase_allowlist)
+        .service(add_drop)
+        .service(get_policy_royalties)
+        .service(set_policy_royalties)
+        .service(list_fee_discounts)
+        .service(put_fee_discount)
+        .service(remove_fee_discount)
+        .service(list_policy_fees)
+        .service(put_policy_fee)
+        .service(remove_policy_fee)
+        .service(list_hidden_listings)
+        .service(put_hidden_listing)
+        .service(remove_hidden_listing)
+        .service(list_pending_approvals)
+        .service(approve_purchase)
+        .service(reject_purchase)
+        .service(list_projects)
+        .service(add_project)
+        .service(list_project_phases)
+        .service(add_project_phase)
+        .service(list_project_fees)
+        .service(put_project_fee)
+        .service(remove_project_fee)
+        .service(list_coupons)
+        .service(add_coupon)
+        .service(get_revenue)
+        .service(get_metadata_usage)
+        .service(list_rejected_listings)
+        .service(export_sales)
+        .service(list_reference_scripts)
+        .service(add_reference_script)
+        .service(list_jobs)
+        .service(retry_dead_job)
+        .service(get_metrics)
+        .service(get_slo)
+        .service(list_audit_entries)
+        .service(get_maintenance)
+        .service(put_maintenance)
+        .service(list_features)
+        .service(put_feature)
+        .service(remove_feature_override)
+}
@@ -0,0 +1,52 @@
+use crate::Result;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// How long clients and proxies may cache the read-only routes, in seconds
+#[derive(Clone, Copy)]
+pub struct CacheTtls {
+    pub marketplace: u64,
+    pub nft: u64,
+    pub projects: u64,
+}
+
+/// Responds with `value` as JSON, tagged with an ETag of its content. A
+/// request already holding that content gets an empty 304 instead.
+pub fn cached_json<T: Serialize>(
+    req: &HttpRequest,
+    value: &T,
+    max_age: u64,
+) -> Result<HttpResponse> {
+    let body = serde_json::to_vec(value)?;
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    // Weak, as the compression middleware may change the encoded bytes
+    let etag = format!("W/\"{:016x}\"", hasher.finish());
+    let cache_control = format!("public, max-age={}", max_age);
+
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim())
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == &etag[2..])
+        })
+        .unwrap_or(false);
+
+    let mut res = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    res.insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, cache_control));
+    if not_modified {
+        return Ok(res.finish());
+    }
+    Ok(res.content_type("application/json").body(body))
+}
@@ -0,0 +1,50 @@
+// The backend's view of the chain, for client-side transaction builders
+
+use crate::cardano_db_sync::{get_protocol_params, get_slot_number};
+use crate::rest::AppState;
+use crate::Result;
+use actix_web::{get, web, HttpResponse, Scope};
+use cardano_serialization_lib::utils::from_bignum;
+use cardano_serialization_lib::UnitInterval;
+use serde_json::json;
+
+/// The protocol parameters transactions are built with here, along with the
+/// latest slot db-sync has seen. `minimumUtxoValue` is the Mary-style
+/// equivalent for eras pricing UTxOs by size.
+#[get("/protocol-params")]
+async fn protocol_params(data: web::Data<AppState>) -> Result<HttpResponse> {
+    let params = get_protocol_params(&data.pool).await?;
+    let slot = get_slot_number(&data.pool).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "slot": slot,
+        "era": format!("{:?}", params.era),
+        "minFeeA": from_bignum(&params.linear_fee.coefficient()),
+        "minFeeB": from_bignum(&params.linear_fee.constant()),
+        "minimumUtxoValue": from_bignum(&params.minimum_utxo_value),
+        "poolDeposit": from_bignum(&params.pool_deposit),
+        "keyDeposit": from_bignum(&params.key_deposit),
+        "maxTxSize": params.max_tx_size,
+        "maxValueSize": params.max_value_size,
+        "coinsPerUtxoWord": from_bignum(&params.coins_per_utxo_word),
+        "collateralPercent": params.collateral_percent,
+        "maxCollateralInputs": params.max_collateral_inputs,
+        "priceMem": ratio(&params.price_mem),
+        "priceStep": ratio(&params.price_step),
+        "maxTxExUnits": {
+            "mem": from_bignum(&params.max_tx_ex_units.mem()),
+            "steps": from_bignum(&params.max_tx_ex_units.steps()),
+        },
+        "costModels": hex::encode(params.cost_models.to_bytes()),
+    })))
+}
+
+fn ratio(interval: &UnitInterval) -> serde_json::Value {
+    json!({
+        "numerator": from_bignum(&interval.numerator()),
+        "denominator": from_bignum(&interval.denominator()),
+    })
+}
+
+pub fn create_chain_service() -> Scope {
+    web::scope("/chain").service(protocol_params)
+}
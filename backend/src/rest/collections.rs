@@ -0,0 +1,188 @@
+use crate::audit::AuditEntry;
+use crate::cardano_db_sync::get_slot_number;
+use crate::error::Error;
+use crate::features::Feature;
+use crate::marketplace::royalty::{get_royalties, RoyaltyRecipient};
+use crate::nft::{mint_nft_with_policy, WottleNftMetadata, DEFAULT_POLICY_LOCK_SECONDS};
+use crate::policies::{
+    create_collection, get_collection, get_managed_policy, get_owner_collections, lock_collection,
+};
+use crate::rest::tx::summarize_transaction;
+use crate::rest::{parse_address, AppState};
+use crate::Result;
+use actix_web::{get, post, web, HttpResponse, Scope};
+use cardano_serialization_lib::PolicyID;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize)]
+struct OwnerQuery {
+    owner: String,
+}
+
+#[get("")]
+async fn list_collections(
+    query: web::Query<OwnerQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let owner = parse_address(&query.owner)?;
+    let collections = get_owner_collections(&data.pool, &owner).await?;
+    Ok(HttpResponse::Ok().json(collections))
+}
+
+#[derive(Deserialize)]
+struct CreateCollection {
+    address: String,
+    name: Option<String>,
+    /// Seconds the policy stays open for minting, one hour when not given
+    policy_lock_seconds: Option<u32>,
+}
+
+#[post("")]
+async fn create_managed_collection(
+    create: web::Json<CreateCollection>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    data.features.check(&data.pool, Feature::Minting).await?;
+    let create = create.into_inner();
+    let owner = parse_address(&create.address)?;
+    let policy_lock_seconds = create
+        .policy_lock_seconds
+        .unwrap_or(DEFAULT_POLICY_LOCK_SECONDS);
+    if policy_lock_seconds == 0 || policy_lock_seconds > data.max_policy_lock_seconds {
+        return Err(Error::Message(format!(
+            "The policy lock window must be between 1 and {} seconds",
+            data.max_policy_lock_seconds
+        )));
+    }
+    let slot = get_slot_number(&data.pool).await?;
+    let lock_slot = slot
+        .checked_add(policy_lock_seconds)
+        .ok_or_else(|| Error::Message("Policy lock window is too long".to_string()))?;
+    let collection = create_collection(
+        &data.pool,
+        &data.keystore,
+        &owner,
+        create.name.as_deref(),
+        lock_slot,
+    )
+    .await?;
+    let policy = get_managed_policy(
+        &data.pool,
+        &data.keystore,
+        &hex::decode(&collection.policy_id)?,
+    )
+    .await?
+    .ok_or_else(|| Error::NotFound("No such collection".to_string()))?;
+    Ok(HttpResponse::Ok().json(json!({
+        "collection": collection,
+        "policy": policy.to_json(),
+    })))
+}
+
+#[get("/{policy_id}")]
+async fn get_collection_details(
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let collection = get_collection(&data.pool, &hex::decode(path.into_inner())?).await?;
+    Ok(HttpResponse::Ok().json(collection))
+}
+
+#[derive(Deserialize)]
+struct MintIntoCollection {
+    address: String,
+    /// CIP-27 royalty of the collection, only until one is declared
+    royalty: Option<RoyaltyRecipient>,
+    change_address: Option<String>,
+    #[serde(flatten)]
+    nft: WottleNftMetadata,
+}
+
+/// Mints one more asset into a collection. Only its owner pays for and
+/// signs the mint.
+#[post("/{policy_id}/mint")]
+async fn mint_into_collection(
+    path: web::Path<String>,
+    mint: web::Json<MintIntoCollection>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    data.features.check(&data.pool, Feature::Minting).await?;
+    let _permit = data.build_limit.acquire()?;
+    let mint = mint.into_inner();
+    let policy_id = PolicyID::from_bytes(hex::decode(path.into_inner())?)?;
+    let address = parse_address(&mint.address)?;
+    let change_address = mint
+        .change_address
+        .as_deref()
+        .map(parse_address)
+        .transpose()?;
+    let collection = get_collection(&data.pool, &policy_id.to_bytes()).await?;
+    if collection.owner_address != address.to_bech32(None)? {
+        return Err(Error::Forbidden(
+            "Only the owner of a collection can mint into it".to_string(),
+        ));
+    }
+    let policy = get_managed_policy(&data.pool, &data.keystore, &policy_id.to_bytes())
+        .await?
+        .ok_or_else(|| Error::Message("The collection is locked".to_string()))?;
+    if mint.royalty.is_some() && !get_royalties(&data.pool, &policy_id).await?.is_empty() {
+        return Err(Error::Message(
+            "The collection already declares its royalty".to_string(),
+        ));
+    }
+    let (nft_tx_builder, tx) = mint_nft_with_policy(
+        &data.pool,
+        &data.pool,
+        mint.nft,
+        policy,
+        mint.royalty,
+        &address,
+        change_address.as_ref(),
+        &data.tax_address,
+        &data.build_settings,
+    )
+    .await?;
+    AuditEntry::new("collection_mint")
+        .address(&address)
+        .built(&data.pool, &tx)
+        .await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "transaction": hex::encode(tx.to_bytes()),
+        "summary": summarize_transaction(&tx)?,
+        "policy": {
+            "id": nft_tx_builder.policy_id(),
+            "json": nft_tx_builder.policy_json(),
+            "lockSlot": nft_tx_builder.policy_ttl(),
+        },
+    })))
+}
+
+#[derive(Deserialize)]
+struct LockCollection {
+    /// Hex vkey witness of the owner's payment key over the policy id
+    signature: String,
+}
+
+#[post("/{policy_id}/lock")]
+async fn lock_managed_collection(
+    path: web::Path<String>,
+    lock: web::Json<LockCollection>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let policy_id = hex::decode(path.into_inner())?;
+    let collection = lock_collection(&data.pool, &policy_id, hex::decode(&lock.signature)?).await?;
+    Ok(HttpResponse::Ok().json(collection))
+}
+
+pub fn create_collections_service() -> Scope {
+    web::scope("/collections")
+        .service(list_collections)
+        .service(create_managed_collection)
+        .service(get_collection_details)
+        .service(mint_into_collection)
+        .service(lock_managed_collection)
+}
@@ -0,0 +1,19 @@
+use crate::cardano_db_sync::query_datum;
+use crate::error::Error;
+use crate::rest::AppState;
+use crate::Result;
+use actix_web::{get, web, HttpResponse, Scope};
+use cardano_serialization_lib::crypto::DataHash;
+
+#[get("/{hash}")]
+async fn get_datum(path: web::Path<String>, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let hash = DataHash::from_bytes(hex::decode(path.into_inner())?)?;
+    let datum = query_datum(&data.pool, &hash)
+        .await?
+        .ok_or_else(|| Error::NotFound("No datum with this hash".to_string()))?;
+    Ok(HttpResponse::Ok().json(datum))
+}
+
+pub fn create_datum_service() -> Scope {
+    web::scope("/datum").service(get_datum)
+}
@@ -0,0 +1,63 @@
+use crate::audit::AuditEntry;
+use crate::cardano_db_sync::get_slot_number;
+use crate::drops::{get_drop, get_remaining_supply, mint};
+use crate::features::Feature;
+use crate::rest::tx::summarize_transaction;
+use crate::rest::{parse_address, AppState};
+use crate::Result;
+use actix_web::{get, post, web, HttpResponse, Scope};
+use serde::Deserialize;
+use serde_json::json;
+
+#[get("/{id}")]
+async fn get_drop_details(path: web::Path<i32>, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let drop = get_drop(&data.pool, path.into_inner()).await?;
+    let slot = get_slot_number(&data.pool).await?;
+    let remaining = get_remaining_supply(&data.pool, &drop, slot).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "drop": drop,
+        "remaining": remaining,
+        "currentSlot": slot,
+    })))
+}
+
+#[derive(Deserialize)]
+struct Mint {
+    address: String,
+}
+
+#[post("/{id}/mint")]
+async fn mint_from_drop(
+    path: web::Path<i32>,
+    mint_details: web::Json<Mint>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    data.features.check(&data.pool, Feature::Minting).await?;
+    let address = parse_address(&mint_details.address)?;
+    let (tx, drop, item) = mint(
+        &data.pool,
+        &data.keystore,
+        path.into_inner(),
+        &address,
+        &data.tax_address,
+        &data.build_settings,
+    )
+    .await?;
+    AuditEntry::new("drop_mint")
+        .address(&address)
+        .built(&data.pool, &tx)
+        .await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "transaction": hex::encode(tx.to_bytes()),
+        "summary": summarize_transaction(&tx)?,
+        "policyId": hex::encode(drop.policy_id),
+        "assetName": item.asset_name,
+    })))
+}
+
+pub fn create_drops_service() -> Scope {
+    web::scope("/drops")
+        .service(get_drop_details)
+        .service(mint_from_drop)
+}
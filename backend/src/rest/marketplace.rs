@@ -1,10 +1,22 @@
+use crate::audit::AuditEntry;
 use crate::error::Error;
+use crate::marketplace::approval::{get_approval, request_approval};
+use crate::marketplace::export::{stream_listings, Listing};
 use crate::marketplace::holder::Filters;
-use crate::rest::{parse_address, respond_with_transaction, AppState};
+use crate::marketplace::sales::get_recent_sales;
+use crate::marketplace::MIN_SALE_PRICE;
+use crate::rest::cache::cached_json;
+use crate::rest::tx::summarize_transaction;
+use crate::rest::{find_tenant, parse_address, respond_with_transaction, AppState};
 use crate::Result;
-use actix_web::{get, post, web, HttpResponse, Scope};
-use cardano_serialization_lib::{AssetName, PolicyID};
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Scope};
+use cardano_serialization_lib::utils::hash_plutus_data;
+use cardano_serialization_lib::{AssetName, PolicyID, Transaction};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_stream::StreamExt;
 
 #[derive(Deserialize)]
 pub struct WebFilter {
@@ -30,30 +42,74 @@ impl WebFilter {
 
 #[get("")]
 async fn get_all_sales(
+    req: HttpRequest,
     data: web::Data<AppState>,
     query: web::Query<WebFilter>,
 ) -> Result<HttpResponse> {
+    let tenant = find_tenant(&req, &data)?;
     let filters = query.into_inner().into_filters()?;
-    let sales = data
+    if filters.asset_name.is_none() {
+        let policy_id = filters.policy.as_ref().map(|p| hex::encode(p.to_bytes()));
+        if let Some(sales) = tenant.listings.get(policy_id.as_deref(), filters.page) {
+            return cached_json(
+                &req,
+                &data.price_oracle.price_all(&sales),
+                data.cache_ttls.marketplace,
+            );
+        }
+    }
+    let sales = tenant
         .marketplace
         .holder
         .get_nfts_for_sale(&data.pool, filters)
         .await?;
-    Ok(HttpResponse::Ok().json(sales))
+    cached_json(
+        &req,
+        &data.price_oracle.price_all(&sales),
+        data.cache_ttls.marketplace,
+    )
+}
+
+#[derive(Deserialize)]
+struct RecentSalesQuery {
+    page: Option<u32>,
+}
+
+/// Completed sales, newest first, for recent activity feeds
+#[get("/recent-sales")]
+async fn get_recent_sales_page(
+    req: HttpRequest,
+    query: web::Query<RecentSalesQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let tenant = find_tenant(&req, &data)?;
+    let sales = get_recent_sales(
+        &data.pool,
+        &tenant.marketplace.holder.address.to_bech32(None)?,
+        &tenant.marketplace.revenue_address.to_bech32(None)?,
+        query.page.unwrap_or(1),
+    )
+    .await?;
+    cached_json(&req, &sales, data.cache_ttls.marketplace)
 }
 
 #[get("/single/{transactionHash}")]
 async fn get_single_sale(
+    req: HttpRequest,
     path: web::Path<String>,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
+    let tenant = find_tenant(&req, &data)?;
     let hash = path.into_inner();
-    let sell_data = data
+    let sell_data = tenant
         .marketplace
         .holder
         .get_single_nft_for_sale(&data.pool, &hash)
         .await?;
-    Ok(HttpResponse::Ok().json(sell_data))
+    let sell_data = sell_data
+        .as_ref()
+        .map(|sell_data| data.price_oracle.price(sell_data));
+    cached_json(&req, &sell_data, data.cache_ttls.marketplace)
 }
 
 #[derive(Deserialize, Debug, Serialize)]
@@ -63,33 +119,124 @@ struct Sell {
     policy_id: String,
     asset_name: String,
     price: u64,
+    live_at: Option<u64>,
+    /// Slot from which the listing can no longer be bought
+    expires_at: Option<u64>,
+    allowed_buyer: Option<String>,
+    /// Receives the proceeds instead of the seller
+    payout_address: Option<String>,
 }
 
 #[post("/sell")]
 async fn sell_nft(
+    req: HttpRequest,
     sell_details: web::Json<Sell>,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let tenant = find_tenant(&req, &data)?;
+    let _permit = data.build_limit.acquire()?;
     let sell_details = sell_details.into_inner();
-    if sell_details.price < 5_000_000 {
+    if sell_details.price < MIN_SALE_PRICE {
         return Err(Error::Message(
             "Price cannot be less than 5 ADA".to_string(),
         ));
     }
     let seller_address = parse_address(&sell_details.seller_address)?;
+    let allowed_buyer = sell_details
+        .allowed_buyer
+        .as_deref()
+        .map(parse_address)
+        .transpose()?;
+    let payout_address = sell_details
+        .payout_address
+        .as_deref()
+        .map(parse_address)
+        .transpose()?;
     let policy_id = PolicyID::from_bytes(hex::decode(sell_details.policy_id)?)?;
     let asset_name = AssetName::new(sell_details.asset_name.into_bytes())?;
-    let tx = data
+    let (tx, deposit, datum) = tenant
         .marketplace
         .sell(
-            seller_address,
-            policy_id,
-            asset_name,
+            seller_address.clone(),
+            policy_id.clone(),
+            asset_name.clone(),
             sell_details.price,
+            sell_details.live_at,
+            sell_details.expires_at,
+            allowed_buyer,
+            payout_address,
             &data.pool,
         )
         .await?;
-    Ok(respond_with_transaction(&tx))
+    AuditEntry::new("sell")
+        .address(&seller_address)
+        .asset(&policy_id, &asset_name)
+        .price(sell_details.price)
+        .built(&data.pool, &tx)
+        .await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "transaction": hex::encode(tx.to_bytes()),
+        "summary": summarize_transaction(&tx)?,
+        "deposit": deposit,
+        "datum": datum.map(|datum| json!({
+            "hash": hex::encode(hash_plutus_data(&datum).to_bytes()),
+            "cbor": hex::encode(datum.to_bytes()),
+        })),
+    })))
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SellBatchItem {
+    policy_id: String,
+    asset_name: String,
+    price: u64,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SellBatch {
+    seller_address: String,
+    items: Vec<SellBatchItem>,
+}
+
+#[post("/sell-batch")]
+async fn sell_nft_batch(
+    req: HttpRequest,
+    sell_details: web::Json<SellBatch>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let tenant = find_tenant(&req, &data)?;
+    let _permit = data.build_limit.acquire()?;
+    let sell_details = sell_details.into_inner();
+    let seller_address = parse_address(&sell_details.seller_address)?;
+    let mut items = Vec::with_capacity(sell_details.items.len());
+    for item in sell_details.items {
+        if item.price < MIN_SALE_PRICE {
+            return Err(Error::Message(format!(
+                "Price of {} cannot be less than 5 ADA",
+                item.asset_name
+            )));
+        }
+        let policy_id = PolicyID::from_bytes(hex::decode(item.policy_id)?)?;
+        let asset_name = AssetName::new(item.asset_name.into_bytes())?;
+        items.push((policy_id, asset_name, item.price));
+    }
+    let (tx, deposit) = tenant
+        .marketplace
+        .sell_batch(seller_address.clone(), items, &data.pool)
+        .await?;
+    AuditEntry::new("sell_batch")
+        .address(&seller_address)
+        .built(&data.pool, &tx)
+        .await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "transaction": hex::encode(tx.to_bytes()),
+        "summary": summarize_transaction(&tx)?,
+        "deposit": deposit,
+    })))
 }
 
 #[derive(Deserialize, Debug, Serialize)]
@@ -98,21 +245,113 @@ struct Buy {
     buyer_address: String,
     policy_id: String,
     asset_name: String,
+    coupon: Option<String>,
+    /// Receives the NFT instead of the buyer, for gifts
+    recipient_address: Option<String>,
+    /// Receives the change, for wallets keeping it apart from payments
+    change_address: Option<String>,
+    /// Buys from a seller sharing the buyer's stake credential, for moving
+    /// an NFT between one's own wallets
+    #[serde(default)]
+    allow_self_trade: bool,
 }
 
 #[post("/buy")]
-async fn buy_nft(buy_details: web::Json<Buy>, data: web::Data<AppState>) -> Result<HttpResponse> {
+async fn buy_nft(
+    req: HttpRequest,
+    buy_details: web::Json<Buy>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let tenant = find_tenant(&req, &data)?;
+    let _permit = data.build_limit.acquire()?;
     let buy_details = buy_details.into_inner();
 
     let buyer_address = parse_address(&buy_details.buyer_address)?;
+    let recipient = buy_details
+        .recipient_address
+        .as_deref()
+        .map(parse_address)
+        .transpose()?;
+    let change_address = buy_details
+        .change_address
+        .as_deref()
+        .map(parse_address)
+        .transpose()?;
     let policy_id = PolicyID::from_bytes(hex::decode(buy_details.policy_id)?)?;
     let asset_name = AssetName::new(buy_details.asset_name.into_bytes())?;
 
-    let tx = data
+    let (tx, fee, approval_price) = tenant
         .marketplace
-        .buy(buyer_address, policy_id, asset_name, &data.pool)
+        .buy(
+            buyer_address.clone(),
+            policy_id.clone(),
+            asset_name.clone(),
+            buy_details.coupon.as_deref(),
+            recipient,
+            change_address,
+            buy_details.allow_self_trade,
+            &data.pool,
+            &data.pool,
+        )
         .await?;
-    Ok(respond_with_transaction(&tx))
+    AuditEntry::new("buy")
+        .address(&buyer_address)
+        .asset(&policy_id, &asset_name)
+        .fee_bps(fee.fee_bps)
+        .built(&data.pool, &tx)
+        .await?;
+    if let Some(price) = approval_price {
+        let approval = request_approval(
+            &data.pool,
+            &tenant.marketplace.holder,
+            &buyer_address,
+            price,
+            &tx,
+        )
+        .await?;
+        return Ok(HttpResponse::Accepted().json(json!({
+            "approvalId": approval.id,
+            "status": approval.status,
+            "fee": fee,
+        })));
+    }
+    Ok(HttpResponse::Ok().json(json!({
+        "transaction": hex::encode(tx.to_bytes()),
+        "summary": summarize_transaction(&tx)?,
+        "fee": fee,
+    })))
+}
+
+/// Where a purchase waiting for approval stands. The transaction, signed by
+/// the holder, comes along once approved.
+#[get("/approvals/{id}")]
+async fn get_purchase_approval(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let tenant = find_tenant(&req, &data)?;
+    let approval = get_approval(&data.pool, path.into_inner()).await?;
+    if approval.holder_address != tenant.marketplace.holder.address.to_bech32(None)? {
+        return Err(Error::NotFound("No such settlement approval".to_string()));
+    }
+    let transaction = match approval.status.as_str() {
+        "approved" => {
+            let tx = Transaction::from_bytes(approval.transaction)?;
+            Some(json!({
+                "transaction": hex::encode(tx.to_bytes()),
+                "summary": summarize_transaction(&tx)?,
+            }))
+        }
+        _ => None,
+    };
+    Ok(HttpResponse::Ok().json(json!({
+        "approvalId": approval.id,
+        "status": approval.status,
+        "txHash": approval.tx_hash,
+        "signed": transaction,
+    })))
 }
 
 #[derive(Deserialize, Debug, Serialize)]
@@ -125,27 +364,103 @@ struct Cancel {
 
 #[post("/cancel")]
 async fn cancel_nft(
+    req: HttpRequest,
     cancel_details: web::Json<Cancel>,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let tenant = find_tenant(&req, &data)?;
     let cancel_details = cancel_details.into_inner();
 
     let seller_address = parse_address(&cancel_details.seller_address)?;
     let policy_id = PolicyID::from_bytes(hex::decode(cancel_details.policy_id)?)?;
     let asset_name = AssetName::new(cancel_details.asset_name.into_bytes())?;
 
-    let tx = data
+    let tx = tenant
         .marketplace
-        .cancel(seller_address, policy_id, asset_name, &data.pool)
+        .cancel(
+            seller_address.clone(),
+            policy_id.clone(),
+            asset_name.clone(),
+            &data.pool,
+        )
+        .await?;
+    AuditEntry::new("cancel")
+        .address(&seller_address)
+        .asset(&policy_id, &asset_name)
+        .built(&data.pool, &tx)
         .await?;
-    Ok(respond_with_transaction(&tx))
+    respond_with_transaction(&tx)
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    /// `json` unless `csv`
+    format: Option<String>,
+}
+
+/// Every active listing in one response, not paginated. Pending listings are
+/// left out until db-sync indexes them.
+#[get("/export")]
+async fn export_listings(
+    req: HttpRequest,
+    query: web::Query<ExportQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let tenant = find_tenant(&req, &data)?;
+    let listings = stream_listings(
+        data.pool.clone(),
+        tenant.marketplace.holder.address.to_bech32(None)?,
+    );
+    match query.format.as_deref().unwrap_or("json") {
+        "csv" => {
+            let body = tokio_stream::once(Ok::<_, Error>(Bytes::from_static(
+                Listing::CSV_HEADER.as_bytes(),
+            )))
+            .chain(
+                listings.map(|listing| listing.map(|listing| Bytes::from(listing.to_csv_row()))),
+            );
+            Ok(HttpResponse::Ok()
+                .content_type("text/csv")
+                .insert_header((
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"listings.csv\"",
+                ))
+                .streaming(body))
+        }
+        "json" => {
+            let mut first = true;
+            let body = tokio_stream::once(Ok::<_, Error>(Bytes::from_static(b"[")))
+                .chain(listings.map(move |listing| {
+                    let separator = if first { "" } else { "," };
+                    first = false;
+                    Ok(Bytes::from(format!(
+                        "{}{}",
+                        separator,
+                        serde_json::to_string(&listing?)?
+                    )))
+                }))
+                .chain(tokio_stream::once(Ok(Bytes::from_static(b"]"))));
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .streaming(body))
+        }
+        format => Err(Error::Message(format!(
+            "Unknown export format {}, expected json or csv",
+            format
+        ))),
+    }
 }
 
 pub fn create_marketplace_service() -> Scope {
     web::scope("/marketplace")
         .service(sell_nft)
+        .service(sell_nft_batch)
         .service(buy_nft)
         .service(cancel_nft)
+        .service(get_purchase_approval)
         .service(get_all_sales)
         .service(get_single_sale)
+        .service(get_recent_sales_page)
+        .service(export_listings)
 }
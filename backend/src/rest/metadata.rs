@@ -0,0 +1,39 @@
+use crate::cardano_db_sync::query_metadata_by_label;
+use crate::rest::AppState;
+use crate::Result;
+use actix_web::{get, web, HttpResponse, Scope};
+use cardano_serialization_lib::PolicyID;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct MetadataFilter {
+    page: Option<u32>,
+    policy: Option<String>,
+    asset: Option<String>,
+}
+
+#[get("/{label}")]
+async fn get_metadata(
+    path: web::Path<u64>,
+    query: web::Query<MetadataFilter>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let policy = match query.policy {
+        Some(policy) => Some(PolicyID::from_bytes(hex::decode(policy)?)?.to_bytes()),
+        None => None,
+    };
+    let metadata = query_metadata_by_label(
+        &data.pool,
+        path.into_inner(),
+        policy,
+        query.asset.map(String::into_bytes),
+        query.page.unwrap_or(1),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
+pub fn create_metadata_service() -> Scope {
+    web::scope("/metadata").service(get_metadata)
+}
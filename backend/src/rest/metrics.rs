@@ -0,0 +1,190 @@
+// Request latency and error counts per route, kept in memory
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the latency histogram buckets, in milliseconds
+const BUCKETS_MS: [u64; 11] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+#[derive(Default)]
+struct RouteMetrics {
+    /// One count per bucket plus the overflow bucket
+    buckets: [u64; BUCKETS_MS.len() + 1],
+    count: u64,
+    sum_ms: f64,
+    /// Responses by status code, only for 4xx and 5xx
+    errors: BTreeMap<u16, u64>,
+}
+
+impl RouteMetrics {
+    /// Upper bound of the bucket the `q` quantile falls in
+    fn quantile_ms(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (q * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(BUCKETS_MS.get(i).copied().unwrap_or(u64::MAX));
+            }
+        }
+        None
+    }
+
+    fn server_errors(&self) -> u64 {
+        self.errors
+            .iter()
+            .filter(|(status, _)| **status >= 500)
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+/// Thresholds a route has to stay within, breaches are flagged on the SLO
+/// summary
+#[derive(Clone, Copy)]
+pub struct SloThresholds {
+    pub p95_latency_ms: u64,
+    pub error_rate: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteSlo {
+    pub route: String,
+    pub requests: u64,
+    pub mean_ms: f64,
+    pub p50_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+    /// Share of requests answered with a 5xx
+    pub error_rate: f64,
+    pub errors: BTreeMap<u16, u64>,
+    pub latency_breached: bool,
+    pub error_rate_breached: bool,
+}
+
+pub struct Metrics {
+    routes: Mutex<HashMap<String, RouteMetrics>>,
+    thresholds: SloThresholds,
+    /// Spendable lovelace of the holder wallets, by holder
+    balances: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    pub fn new(thresholds: SloThresholds) -> Self {
+        Self {
+            routes: Mutex::new(HashMap::new()),
+            thresholds,
+            balances: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record_balance(&self, holder: &'static str, lovelace: u64) {
+        self.balances.lock().unwrap().insert(holder, lovelace);
+    }
+
+    /// Records a response. `route` is the matched pattern, so requests for
+    /// different ids share one series.
+    pub fn record(&self, route: &str, method: &str, status: u16, elapsed: Duration) {
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|bound| elapsed_ms <= *bound as f64)
+            .unwrap_or(BUCKETS_MS.len());
+
+        let mut routes = self.routes.lock().unwrap();
+        let metrics = routes.entry(format!("{} {}", method, route)).or_default();
+        metrics.buckets[bucket] += 1;
+        metrics.count += 1;
+        metrics.sum_ms += elapsed_ms;
+        if status >= 400 {
+            *metrics.errors.entry(status).or_default() += 1;
+        }
+    }
+
+    /// Per route latency quantiles and error rates against the thresholds,
+    /// worst latency first
+    pub fn slo(&self) -> Vec<RouteSlo> {
+        let routes = self.routes.lock().unwrap();
+        let mut slos: Vec<RouteSlo> = routes
+            .iter()
+            .map(|(route, metrics)| {
+                let p95_ms = metrics.quantile_ms(0.95);
+                let error_rate = metrics.server_errors() as f64 / metrics.count.max(1) as f64;
+                RouteSlo {
+                    route: route.clone(),
+                    requests: metrics.count,
+                    mean_ms: metrics.sum_ms / metrics.count.max(1) as f64,
+                    p50_ms: metrics.quantile_ms(0.5),
+                    p95_ms,
+                    p99_ms: metrics.quantile_ms(0.99),
+                    error_rate,
+                    errors: metrics.errors.clone(),
+                    latency_breached: p95_ms
+                        .map(|p95| p95 > self.thresholds.p95_latency_ms)
+                        .unwrap_or(false),
+                    error_rate_breached: error_rate > self.thresholds.error_rate,
+                }
+            })
+            .collect();
+        slos.sort_by(|a, b| b.p95_ms.cmp(&a.p95_ms));
+        slos
+    }
+
+    /// The metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let routes = self.routes.lock().unwrap();
+        let mut out = String::new();
+        out.push_str("# TYPE http_request_duration_ms histogram\n");
+        for (route, metrics) in routes.iter() {
+            let mut cumulative = 0;
+            for (i, count) in metrics.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = BUCKETS_MS
+                    .get(i)
+                    .map(|bound| bound.to_string())
+                    .unwrap_or_else(|| "+Inf".to_string());
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_ms_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                    route, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "http_request_duration_ms_sum{{route=\"{}\"}} {}",
+                route, metrics.sum_ms
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_ms_count{{route=\"{}\"}} {}",
+                route, metrics.count
+            );
+        }
+        out.push_str("# TYPE http_request_errors_total counter\n");
+        for (route, metrics) in routes.iter() {
+            for (status, count) in &metrics.errors {
+                let _ = writeln!(
+                    out,
+                    "http_request_errors_total{{route=\"{}\",status=\"{}\"}} {}",
+                    route, status, count
+                );
+            }
+        }
+        out.push_str("# TYPE holder_balance_lovelace gauge\n");
+        for (holder, lovelace) in self.balances.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "holder_balance_lovelace{{holder=\"{}\"}} {}",
+                holder, lovelace
+            );
+        }
+        out
+    }
+}
@@ -1,26 +1,92 @@
 mod address;
+mod admin;
+mod cache;
+mod chain;
+mod collections;
+mod datum;
+mod drops;
 mod marketplace;
+mod metadata;
+pub(crate) mod metrics;
 mod nft;
+mod offers;
 mod project;
+mod search;
+mod tx;
+mod tx_session;
+mod ws;
 
-use crate::coin::combine_witness_set;
-use crate::marketplace::Marketplace;
+use crate::audit::AuditEntry;
+use crate::balance_monitor::{self, WatchedBalance};
+use crate::build_limit::BuildLimit;
+use crate::coin::{combine_witness_set, decode_witnesses, BuildSettings};
+use crate::features::Features;
+use crate::holder_watch::{self, WatchedHolder};
+use crate::image_check::ImageCheck;
+use crate::maintenance::Maintenance;
+use crate::marketplace::coupon::redeem_coupon;
+use crate::marketplace::pending::track_pending_sale;
+use crate::marketplace::{consolidate, Marketplace};
+use crate::policies::{seal_stored_keys, Keystore};
+use crate::price_oracle::PriceOracle;
 use crate::project::Projects;
-use crate::{config::Config, transaction::Submitter, Error, Result};
+use crate::rest::cache::CacheTtls;
+use crate::rest::metrics::{Metrics, SloThresholds};
+use crate::revenue_sweep::{self, RevenueWallet};
+use crate::tenant::{Tenant, Tenants};
+use crate::{cleanup, configured_address, jobs, price_oracle};
+use crate::{
+    config::Config,
+    transaction::{submitter_from_config, TxSubmitter},
+    Error, Result,
+};
 use actix_cors::Cors;
-use actix_web::{post, web, web::Data, App, HttpResponse, HttpServer};
-use cardano_serialization_lib::address::Address;
-use cardano_serialization_lib::{Transaction, TransactionWitnessSet};
+use actix_web::dev::{RequestHead, Service};
+use actix_web::error::JsonPayloadError;
+use actix_web::http::header;
+use actix_web::{middleware, post, web, web::Data, App, HttpRequest, HttpResponse, HttpServer};
+use cardano_serialization_lib::address::{Address, NetworkInfo};
+use cardano_serialization_lib::Transaction;
 use serde::Deserialize;
 use serde_json::json;
 use sqlx::postgres::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use subtle::ConstantTimeEq;
 
 struct AppState {
     pool: PgPool,
-    submitter: Submitter,
+    submitter: Arc<dyn TxSubmitter>,
     tax_address: Address,
-    marketplace: Marketplace,
+    tenants: Arc<Tenants>,
     project: Projects,
+    projects: Arc<RwLock<HashMap<String, Projects>>>,
+    projects_fee: u64,
+    admin_token: Option<String>,
+    network_id: u8,
+    cache_ttls: CacheTtls,
+    watched_policies: Vec<String>,
+    block_duplicate_asset_names: bool,
+    max_policy_lock_seconds: u32,
+    image_check: Option<ImageCheck>,
+    maintenance: Arc<Maintenance>,
+    features: Features,
+    build_limit: Arc<BuildLimit>,
+    keystore: Keystore,
+    build_settings: BuildSettings,
+    price_oracle: PriceOracle,
+}
+
+/// Parses an address given by a user, refusing one for another network
+pub fn parse_network_address(address: &str, network_id: u8) -> Result<Address> {
+    let address = parse_address(address)?;
+    if address.network_id()? != network_id {
+        return Err(Error::Message(
+            "Address is on the wrong network".to_string(),
+        ));
+    }
+    Ok(address)
 }
 
 pub fn parse_address(address: &str) -> Result<Address> {
@@ -38,10 +104,71 @@ pub fn parse_address(address: &str) -> Result<Address> {
     }
 }
 
-pub fn respond_with_transaction(tx: &Transaction) -> HttpResponse {
-    HttpResponse::Ok().json(json!({
-        "transaction": hex::encode(tx.to_bytes())
-    }))
+/// The project under `slug`, answering with 404 when there is none
+fn find_project(data: &AppState, slug: &str) -> Result<Projects> {
+    data.projects
+        .read()
+        .unwrap()
+        .get(slug)
+        .cloned()
+        .ok_or_else(|| Error::NotFound(format!("No project named {}", slug)))
+}
+
+/// The marketplace tenant a request is for, by path prefix or host
+fn find_tenant(req: &HttpRequest, data: &AppState) -> Result<Arc<Tenant>> {
+    data.tenants
+        .resolve(req.path(), request_host(req.head()))
+        .ok_or_else(|| Error::NotFound("No such marketplace".to_string()))
+}
+
+fn request_host(head: &RequestHead) -> Option<&str> {
+    head.headers()
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| head.uri.host())
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Compares in constant time so response timing does not leak how much of a
+/// token was guessed right
+fn tokens_match(provided: &str, token: &str) -> bool {
+    provided.as_bytes().ct_eq(token.as_bytes()).into()
+}
+
+/// Checks the bearer token of a request against the configured admin token.
+/// Admin endpoints are disabled entirely when no token is configured.
+fn authorize_admin(req: &HttpRequest, data: &AppState) -> Result<()> {
+    let token = data.admin_token.as_deref().ok_or(Error::Unauthorized)?;
+    match bearer_token(req) {
+        Some(provided) if tokens_match(provided, token) => Ok(()),
+        _ => Err(Error::Unauthorized),
+    }
+}
+
+/// Admin endpoints scoped to the tenant of a request take its own admin
+/// token as well as the configured one
+fn authorize_tenant_admin(req: &HttpRequest, data: &AppState) -> Result<Arc<Tenant>> {
+    let tenant = find_tenant(req, data)?;
+    if authorize_admin(req, data).is_ok() {
+        return Ok(tenant);
+    }
+    match (bearer_token(req), tenant.admin_token.as_deref()) {
+        (Some(provided), Some(token)) if tokens_match(provided, token) => Ok(tenant),
+        _ => Err(Error::Unauthorized),
+    }
+}
+
+pub fn respond_with_transaction(tx: &Transaction) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(json!({
+        "transaction": hex::encode(tx.to_bytes()),
+        "summary": tx::summarize_transaction(tx)?,
+    })))
 }
 
 #[derive(Deserialize)]
@@ -51,50 +178,361 @@ struct Signature {
 }
 #[post("/sign")]
 async fn sign_transaction(
+    req: HttpRequest,
     signature: web::Json<Signature>,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let tenant = find_tenant(&req, &data)?;
     let Signature {
         signature,
         transaction,
     } = signature.into_inner();
 
     let transaction = Transaction::from_bytes(hex::decode(transaction)?)?;
-    let tx_witness_set = TransactionWitnessSet::from_bytes(hex::decode(signature)?)?;
+    let tx_witness_set = decode_witnesses(hex::decode(signature)?)?;
 
     let tx = combine_witness_set(transaction, tx_witness_set)?;
 
     let tx_id = data.submitter.submit_tx(&tx).await?;
+    AuditEntry::new("sign")
+        .submitted(&data.pool, &tx, &tx_id)
+        .await;
+    // Listed right away, instead of once db-sync has caught up
+    if let Err(e) =
+        track_pending_sale(&data.pool, &tenant.marketplace.holder.address, &tx, &tx_id).await
+    {
+        println!("Failed to track pending sale {}: {}", tx_id, e);
+    }
+    if let Err(e) = redeem_coupon(&data.pool, &tx).await {
+        println!("Failed to redeem the coupon of {}: {}", tx_id, e);
+    }
     Ok(HttpResponse::Ok().json(json!({ "tx_id": tx_id })))
 }
 
+/// Limits JSON request bodies to `limit` bytes, answering larger ones with
+/// 413 before they are read in full
+fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(move |err, _| match err {
+            JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+                Error::PayloadTooLarge(limit).into()
+            }
+            err => err.into(),
+        })
+}
+
+/// Routes of the first API version. Breaking changes to response shapes go
+/// into a new version instead of changing these.
+fn configure_v1(cfg: &mut web::ServiceConfig, max_mint_payload_bytes: usize) {
+    cfg.service(address::create_address_service())
+        .service(nft::create_nft_service().app_data(json_config(max_mint_payload_bytes)))
+        .service(marketplace::create_marketplace_service())
+        .service(offers::create_offers_service())
+        .service(project::create_project_service())
+        .service(drops::create_drops_service())
+        .service(
+            collections::create_collections_service().app_data(json_config(max_mint_payload_bytes)),
+        )
+        .service(datum::create_datum_service())
+        .service(metadata::create_metadata_service())
+        .service(search::create_search_service())
+        .service(tx::create_tx_service())
+        .service(chain::create_chain_service())
+        .service(tx_session::create_tx_session_service())
+        .service(admin::create_admin_service())
+        .service(ws::create_ws_service())
+        .service(sign_transaction);
+}
+
 pub async fn start_server(config: Config) -> Result<()> {
-    let tax_address = Address::from_bech32(&config.nft_bech32_tax_address)?;
+    let tax_address = configured_address(&config.nft_bech32_tax_address, config.is_testnet)?;
     let db_pool = PgPool::connect(&config.database_url).await?;
+    sqlx::migrate!("./migrations").run(&db_pool).await?;
     let address = format!("0.0.0.0:{}", config.port);
     let marketplace = Marketplace::from_config(&config)?;
     let project = Projects::from_config(&config)?;
+    let projects = Arc::new(RwLock::new(Projects::load_all(&config, &db_pool).await?));
+    let submitter = submitter_from_config(&config)?;
+    let build_settings = BuildSettings::from_config(&config);
+    let network_id = if config.is_testnet {
+        NetworkInfo::testnet().network_id()
+    } else {
+        NetworkInfo::mainnet().network_id()
+    };
+    let cache_ttls = CacheTtls {
+        marketplace: config.cache_ttl_marketplace_seconds,
+        nft: config.cache_ttl_nft_seconds,
+        projects: config.cache_ttl_projects_seconds,
+    };
+    let watched_policies: Vec<String> = config
+        .watched_policies
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(|policy| policy.trim().to_lowercase())
+        .filter(|policy| !policy.is_empty())
+        .collect();
+    let image_check = if config.check_nft_images {
+        Some(ImageCheck::new(
+            &config.ipfs_gateway_url,
+            config.max_nft_image_bytes,
+            config.block_invalid_nft_images,
+        )?)
+    } else {
+        None
+    };
+    let slo_thresholds = SloThresholds {
+        p95_latency_ms: config.slo_p95_latency_ms,
+        error_rate: config.slo_error_rate,
+    };
+    let metrics = Arc::new(Metrics::new(slo_thresholds));
+    let maintenance = Arc::new(Maintenance::new(config.maintenance_mode));
+    let build_limit = Arc::new(BuildLimit::new(
+        config.max_concurrent_tx_builds,
+        config.tx_build_retry_after_seconds,
+    ));
+    let features = Features::from_config(&config.disabled_features)?;
+    let keystore = Keystore::from_config(&config);
+    let sealed = seal_stored_keys(&db_pool, &keystore).await?;
+    if sealed > 0 {
+        println!("Sealed {} stored signing keys", sealed);
+    }
+    let (settled, retried) = crate::drops::watcher::reconcile_pending_payments(&db_pool).await?;
+    if settled + retried > 0 {
+        println!(
+            "Settled {} pending drop payments, {} are served again",
+            settled, retried
+        );
+    }
+    if let Some(grpc_port) = config.grpc_port {
+        crate::grpc::spawn(
+            grpc_port,
+            db_pool.clone(),
+            marketplace.clone(),
+            tax_address.clone(),
+            maintenance.clone(),
+            features.clone(),
+            build_settings.clone(),
+        );
+    }
+    crate::drops::watcher::spawn(
+        db_pool.clone(),
+        submitter.clone(),
+        tax_address.clone(),
+        keystore.clone(),
+        build_settings.clone(),
+        features.clone(),
+        config.drop_watcher_interval_seconds,
+    );
+    jobs::worker::spawn(db_pool.clone(), config.job_worker_interval_seconds);
+    let watched_balances: Vec<WatchedBalance> = vec![
+        (
+            "marketplace",
+            &marketplace.holder.address,
+            config.marketplace_holder_min_balance_lovelace,
+        ),
+        (
+            "projects",
+            &project.holder.address,
+            config.projects_holder_min_balance_lovelace,
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, address, min_lovelace)| {
+        Some(WatchedBalance {
+            name,
+            address: address.clone(),
+            min_lovelace: min_lovelace?,
+        })
+    })
+    .collect();
+    if !watched_balances.is_empty() {
+        balance_monitor::spawn(
+            db_pool.clone(),
+            watched_balances,
+            metrics.clone(),
+            config.balance_alert_webhook_url.clone(),
+            config.balance_check_interval_minutes,
+        );
+    }
+    let price_oracle = PriceOracle::default();
+    if let Some(sources) = &config.price_oracle_sources {
+        price_oracle.spawn(
+            price_oracle::parse_sources(sources)?,
+            config.price_oracle_interval_seconds,
+            config.price_oracle_max_age_seconds,
+        );
+    }
+    if let Some(cold_storage_address) = &config.cold_storage_address {
+        let mut wallets = vec![];
+        if let Some(key_file) = &config.marketplace_revenue_key_file {
+            wallets.push(RevenueWallet::from_key_file(
+                marketplace.revenue_address.clone(),
+                key_file,
+            )?);
+        }
+        if let Some(key_file) = &config.projects_revenue_key_file {
+            wallets.push(RevenueWallet::from_key_file(
+                project.revenue_address.clone(),
+                key_file,
+            )?);
+        }
+        revenue_sweep::spawn(
+            db_pool.clone(),
+            submitter.clone(),
+            wallets,
+            configured_address(cold_storage_address, config.is_testnet)?,
+            config.revenue_sweep_threshold_lovelace,
+            revenue_sweep::parse_schedule(&config.revenue_sweep_schedule)?,
+        );
+    }
+    if let Some(min_utxos) = config.holder_consolidation_min_utxos {
+        consolidate::spawn(
+            db_pool.clone(),
+            submitter.clone(),
+            marketplace.holder.clone(),
+            min_utxos,
+            config.holder_consolidation_interval_seconds,
+        );
+    }
+    let tenants = Arc::new(Tenants::load(
+        &config,
+        &db_pool,
+        marketplace.clone(),
+        metrics.clone(),
+        slo_thresholds,
+    )?);
+    crate::marketplace::offers::spawn(
+        db_pool.clone(),
+        submitter.clone(),
+        tenants
+            .iter()
+            .map(|tenant| tenant.marketplace.holder.clone())
+            .collect(),
+        config.offer_refund_interval_seconds,
+    );
+    if let Some(interval_seconds) = config.holder_watch_interval_seconds {
+        let mut holders: Vec<WatchedHolder> = tenants
+            .iter()
+            .map(|tenant| WatchedHolder {
+                name: if tenant.is_default() {
+                    "marketplace".to_string()
+                } else {
+                    format!("{} marketplace", tenant.id)
+                },
+                address: tenant.marketplace.holder.address.clone(),
+            })
+            .collect();
+        holders.push(WatchedHolder {
+            name: "projects".to_string(),
+            address: project.holder.address.clone(),
+        });
+        // Projects registered later are not watched until a restart
+        for (slug, project) in projects.read().unwrap().iter() {
+            holders.push(WatchedHolder {
+                name: format!("{} project", slug),
+                address: project.holder.address.clone(),
+            });
+        }
+        holder_watch::spawn(
+            db_pool.clone(),
+            holders,
+            maintenance.clone(),
+            config.security_alert_webhook_url.clone(),
+            config.holder_watch_auto_maintenance,
+            interval_seconds,
+        );
+    }
+    cleanup::spawn(
+        db_pool.clone(),
+        cleanup::parse_schedule(&config.cleanup_schedule)?,
+        config.job_retention_days,
+        config.audit_retention_days,
+    );
     println!("Starting server on {}", &address);
     Ok(HttpServer::new(move || {
         App::new()
             .wrap(
                 Cors::default()
-                    .allow_any_origin()
+                    .allowed_origin_fn({
+                        let tenants = tenants.clone();
+                        move |origin, head| {
+                            let origin = origin.to_str().unwrap_or_default();
+                            tenants
+                                .resolve(head.uri.path(), request_host(head))
+                                .map_or(false, |tenant| tenant.allows_origin(origin))
+                        }
+                    })
                     .allow_any_method()
                     .allow_any_header(),
             )
+            .wrap(middleware::Compress::default())
+            .wrap_fn({
+                let metrics = metrics.clone();
+                let tenants = tenants.clone();
+                move |req, srv| {
+                    let metrics = metrics.clone();
+                    let tenant = tenants.resolve(req.path(), request_host(req.head()));
+                    let started = Instant::now();
+                    let method = req.method().to_string();
+                    let res = srv.call(req);
+                    async move {
+                        let res = res.await?;
+                        // Routing has filled in the pattern by the time the response is back
+                        let route = res
+                            .request()
+                            .match_pattern()
+                            .unwrap_or_else(|| "unmatched".to_string());
+                        let status = res.status().as_u16();
+                        metrics.record(&route, &method, status, started.elapsed());
+                        if let Some(tenant) = tenant.filter(|tenant| !tenant.is_default()) {
+                            tenant
+                                .metrics
+                                .record(&route, &method, status, started.elapsed());
+                        }
+                        Ok(res)
+                    }
+                }
+            })
             .app_data(Data::new(AppState {
                 pool: db_pool.clone(),
-                submitter: Submitter::for_url(&config.submit_api_base_url),
+                submitter: submitter.clone(),
                 tax_address: tax_address.clone(),
-                marketplace: marketplace.clone(),
+                tenants: tenants.clone(),
                 project: project.clone(),
+                projects: projects.clone(),
+                projects_fee: config.projects_fee_lovelace,
+                admin_token: config.admin_token.clone(),
+                network_id,
+                cache_ttls,
+                watched_policies: watched_policies.clone(),
+                block_duplicate_asset_names: config.block_duplicate_asset_names,
+                max_policy_lock_seconds: config.max_policy_lock_seconds,
+                image_check: image_check.clone(),
+                maintenance: maintenance.clone(),
+                features: features.clone(),
+                build_limit: build_limit.clone(),
+                keystore: keystore.clone(),
+                build_settings: build_settings.clone(),
+                price_oracle: price_oracle.clone(),
             }))
-            .service(address::create_address_service())
-            .service(nft::create_nft_service())
-            .service(marketplace::create_marketplace_service())
-            .service(project::create_project_service())
-            .service(sign_transaction)
+            .app_data(json_config(config.max_json_payload_bytes))
+            .service(
+                web::scope("/v1").configure(|cfg| configure_v1(cfg, config.max_mint_payload_bytes)),
+            )
+            // Tenants not told apart by their host
+            .service(
+                web::scope("/t/{tenant}")
+                    .service(
+                        web::scope("/v1")
+                            .configure(|cfg| configure_v1(cfg, config.max_mint_payload_bytes)),
+                    )
+                    .configure(|cfg| configure_v1(cfg, config.max_mint_payload_bytes)),
+            )
+            // Unversioned aliases of v1, kept until existing frontends move over
+            .configure(|cfg| configure_v1(cfg, config.max_mint_payload_bytes))
     })
     .bind(address)?
     .run()
@@ -1,15 +1,36 @@
+use crate::features::Feature;
 use crate::{
-    cardano_db_sync::{get_protocol_params, get_slot_number, query_user_address_utxo},
-    nft::{NftTransactionBuilder, WottleNftMetadata},
+    audit::AuditEntry,
+    nft::{mint_nft, WottleNftMetadata, DEFAULT_POLICY_LOCK_SECONDS},
     Result,
 };
-use actix_web::{get, post, web, HttpResponse, Scope};
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Scope};
 use serde::Deserialize;
 use serde_json::json;
 
-use crate::cardano_db_sync::{query_if_nft_minted, query_single_nft};
-use crate::rest::AppState;
+use crate::burn::burn_nft;
+use crate::cardano_db_sync::ScriptUtxoJson;
+use crate::cardano_db_sync::{
+    query_duplicate_assets, query_if_nft_minted, query_single_nft, DuplicateAsset,
+};
+use crate::cip68::plan_datum_update;
+use crate::drops::get_drop_policy;
+use crate::error::Error;
+use crate::marketplace::estimate::estimate_price;
+use crate::marketplace::royalty::RoyaltyRecipient;
+use crate::nft::PolicyWitness;
+use crate::policies::{
+    check_password, encrypt_policy_key, get_collection, get_managed_policy, keep_policy,
+};
+use crate::remint::{update_nft_metadata, UPDATE_WARNINGS};
+use crate::rest::cache::cached_json;
+use crate::rest::tx::summarize_transaction;
+use crate::rest::{find_tenant, respond_with_transaction, AppState};
+use cardano_serialization_lib::address::Address;
 use cardano_serialization_lib::crypto::TransactionHash;
+use cardano_serialization_lib::plutus::PlutusData;
+use cardano_serialization_lib::utils::hash_plutus_data;
+use cardano_serialization_lib::{AssetName, NativeScript, PolicyID};
 
 #[derive(Deserialize)]
 struct TransactionHashQuery {
@@ -29,6 +50,17 @@ async fn check_nft_exists(
 #[derive(Deserialize)]
 struct CreateNft {
     address: String,
+    /// CIP-27 royalty of the new collection
+    royalty: Option<RoyaltyRecipient>,
+    /// Receives the change, for wallets keeping it apart from payments
+    change_address: Option<String>,
+    /// Seconds the policy stays open for minting, one hour when not given
+    policy_lock_seconds: Option<u32>,
+    /// Keep the policy key here to mint more under the same policy later
+    #[serde(default)]
+    keep_policy: bool,
+    /// Return the policy key encrypted with this password
+    policy_key_password: Option<String>,
     #[serde(flatten)]
     nft: WottleNftMetadata,
 }
@@ -38,25 +70,289 @@ async fn create_nft_transaction(
     create_nft: web::Json<CreateNft>,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    data.features.check(&data.pool, Feature::Minting).await?;
+    let _permit = data.build_limit.acquire()?;
     let create_nft = create_nft.into_inner();
     let address = super::parse_address(&create_nft.address)?;
-    let utxos = query_user_address_utxo(&data.pool, &address).await?;
-    let slot = get_slot_number(&data.pool).await?;
-    let params = get_protocol_params(&data.pool).await?;
-
-    let nft_tx_builder = NftTransactionBuilder::new(create_nft.nft, slot, params)?;
-
-    let tx = nft_tx_builder.create_transaction(&address, &data.tax_address, utxos)?;
+    let change_address = create_nft
+        .change_address
+        .as_deref()
+        .map(super::parse_address)
+        .transpose()?;
+    let policy_lock_seconds = create_nft
+        .policy_lock_seconds
+        .unwrap_or(DEFAULT_POLICY_LOCK_SECONDS);
+    if let Some(password) = &create_nft.policy_key_password {
+        check_password(password)?;
+    }
+    if create_nft.keep_policy {
+        data.keystore.check()?;
+    }
+    if policy_lock_seconds == 0 || policy_lock_seconds > data.max_policy_lock_seconds {
+        return Err(Error::Message(format!(
+            "The policy lock window must be between 1 and {} seconds",
+            data.max_policy_lock_seconds
+        )));
+    }
+    let duplicates = query_duplicate_assets(
+        &data.pool,
+        create_nft.nft.name(),
+        &data.watched_policies,
+        &address,
+    )
+    .await?;
+    if data.block_duplicate_asset_names && !duplicates.is_empty() {
+        return Err(Error::Message(duplicate_message(&duplicates)));
+    }
+    let image_warning = match &data.image_check {
+        Some(image_check) => match image_check.problem(create_nft.nft.image()).await {
+            Some(problem) if image_check.block => return Err(Error::Message(problem)),
+            problem => problem,
+        },
+        None => None,
+    };
+    let (nft_tx_builder, tx) = mint_nft(
+        &data.pool,
+        &data.pool,
+        create_nft.nft,
+        create_nft.royalty,
+        policy_lock_seconds,
+        &address,
+        change_address.as_ref(),
+        &data.tax_address,
+        &data.build_settings,
+    )
+    .await?;
+    let policy_key = create_nft
+        .policy_key_password
+        .as_deref()
+        .map(|password| encrypt_policy_key(nft_tx_builder.policy(), password))
+        .transpose()?;
+    if create_nft.keep_policy {
+        keep_policy(
+            &data.pool,
+            &data.keystore,
+            nft_tx_builder.policy(),
+            &address,
+            None,
+        )
+        .await?;
+    }
+    AuditEntry::new("mint")
+        .address(&address)
+        .built(&data.pool, &tx)
+        .await?;
 
     Ok(HttpResponse::Ok().json(json!({
         "transaction": hex::encode(tx.to_bytes()),
+        "summary": summarize_transaction(&tx)?,
         "policy": {
             "id": nft_tx_builder.policy_id(),
-            "json": nft_tx_builder.policy_json()
+            "json": nft_tx_builder.policy_json(),
+            "lockSlot": nft_tx_builder.policy_ttl(),
+            "encryptedKey": policy_key,
+            "managed": create_nft.keep_policy,
+        },
+        "duplicates": duplicates,
+        "imageWarning": image_warning,
+    })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Burn {
+    address: String,
+    policy_id: String,
+    asset_name: String,
+    /// CBOR hex of the native script, not needed for policies managed here
+    policy_script: Option<String>,
+}
+
+#[post("/burn")]
+async fn burn_nft_transaction(
+    req: HttpRequest,
+    burn: web::Json<Burn>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let burn = burn.into_inner();
+    let address = super::parse_address(&burn.address)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(&burn.policy_id)?)?;
+    let asset_name = AssetName::new(burn.asset_name.into_bytes())?;
+    let policy = policy_witness(&req, &data, &address, &policy_id, burn.policy_script).await?;
+
+    let tx = burn_nft(
+        &data.pool,
+        &address,
+        &policy_id,
+        &asset_name,
+        policy,
+        &data.build_settings,
+    )
+    .await?;
+    AuditEntry::new("burn")
+        .address(&address)
+        .asset(&policy_id, &asset_name)
+        .built(&data.pool, &tx)
+        .await?;
+    respond_with_transaction(&tx)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetadataUpdate {
+    address: String,
+    policy_id: String,
+    /// CBOR hex of the native script, not needed for policies managed here
+    policy_script: Option<String>,
+    /// The asset to update is the one named in here
+    metadata: WottleNftMetadata,
+}
+
+/// Replaces the CIP-25 metadata of an asset whose policy is still open
+#[post("/update-metadata")]
+async fn update_metadata_transaction(
+    req: HttpRequest,
+    update: web::Json<MetadataUpdate>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let update = update.into_inner();
+    let address = super::parse_address(&update.address)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(&update.policy_id)?)?;
+    if query_single_nft(&data.pool, &update.policy_id, update.metadata.name())
+        .await?
+        .is_none()
+    {
+        return Err(Error::NotFound("No such asset has been minted".to_string()));
+    }
+    let policy = policy_witness(&req, &data, &address, &policy_id, update.policy_script).await?;
+
+    let tx = update_nft_metadata(
+        &data.pool,
+        &address,
+        &policy_id,
+        &update.metadata,
+        policy,
+        &data.build_settings,
+    )
+    .await?;
+    AuditEntry::new("update_metadata")
+        .address(&address)
+        .asset(
+            &policy_id,
+            &AssetName::new(update.metadata.name().as_bytes().to_vec())?,
+        )
+        .built(&data.pool, &tx)
+        .await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "transaction": hex::encode(tx.to_bytes()),
+        "summary": summarize_transaction(&tx)?,
+        "warnings": UPDATE_WARNINGS,
+    })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DatumUpdateRequest {
+    policy_id: String,
+    /// Name of the asset, without the CIP-67 label
+    asset_name: String,
+    /// CBOR hex of the new datum
+    datum: String,
+}
+
+/// Parts of the transaction moving a CIP-68 reference token to a new datum,
+/// see `cip68` for why the transaction itself is not built here
+#[post("/update-datum")]
+async fn update_datum(
+    update: web::Json<DatumUpdateRequest>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let update = update.into_inner();
+    let policy_id = PolicyID::from_bytes(hex::decode(&update.policy_id)?)?;
+    let datum = PlutusData::from_bytes(hex::decode(&update.datum)?)?;
+    let plan = plan_datum_update(&data.pool, &policy_id, &update.asset_name, datum).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "referenceUtxo": ScriptUtxoJson::from(&plan.reference_utxo),
+        "datum": {
+            "hash": hex::encode(hash_plutus_data(&plan.datum).to_bytes()),
+            "cbor": hex::encode(plan.datum.to_bytes()),
+        },
+        "referenceScript": plan.reference_script,
+    })))
+}
+
+/// The script given by the user, or a policy managed here. Drop policies
+/// sign for admins only and collection policies for their owner only.
+async fn policy_witness(
+    req: &HttpRequest,
+    data: &AppState,
+    address: &Address,
+    policy_id: &PolicyID,
+    policy_script: Option<String>,
+) -> Result<PolicyWitness> {
+    if let Some(script) = policy_script {
+        return Ok(PolicyWitness::Script(NativeScript::from_bytes(
+            hex::decode(script)?,
+        )?));
+    }
+    if let Some(policy) = get_drop_policy(&data.pool, &data.keystore, &policy_id.to_bytes()).await?
+    {
+        super::authorize_admin(req, data)?;
+        return Ok(PolicyWitness::Managed(policy));
+    }
+    match get_managed_policy(&data.pool, &data.keystore, &policy_id.to_bytes()).await? {
+        Some(policy) => {
+            let collection = get_collection(&data.pool, &policy_id.to_bytes()).await?;
+            if collection.owner_address != address.to_bech32(None)? {
+                return Err(Error::Forbidden(
+                    "Only the owner of a collection can use its policy".to_string(),
+                ));
+            }
+            Ok(PolicyWitness::Managed(policy))
         }
+        None => Err(Error::Message(
+            "A policy script is required for policies not managed here".to_string(),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+struct NameCheck {
+    name: String,
+    address: String,
+}
+
+/// Looks for existing assets a new mint named `name` would duplicate
+#[get("/check-name")]
+async fn check_name(
+    query: web::Query<NameCheck>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let address = super::parse_address(&query.address)?;
+    let duplicates =
+        query_duplicate_assets(&data.pool, &query.name, &data.watched_policies, &address).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "blocked": data.block_duplicate_asset_names && !duplicates.is_empty(),
+        "duplicates": duplicates,
     })))
 }
 
+fn duplicate_message(duplicates: &[DuplicateAsset]) -> String {
+    let policies: Vec<&str> = duplicates
+        .iter()
+        .map(|duplicate| duplicate.policy_id.as_str())
+        .collect();
+    format!(
+        "An asset named {} already exists under policy {}",
+        duplicates[0].asset_name,
+        policies.join(", ")
+    )
+}
+
 #[derive(Deserialize)]
 struct NftDetails {
     policy_id: String,
@@ -65,17 +361,44 @@ struct NftDetails {
 
 #[get("/single/{policy_id}/{asset_name}")]
 async fn get_single_nft(
+    req: HttpRequest,
     details: web::Path<NftDetails>,
     data: web::Data<AppState>,
 ) -> Result<HttpResponse> {
     let details = details.into_inner();
     let json = query_single_nft(&data.pool, &details.policy_id, &details.asset_name).await?;
-    Ok(HttpResponse::Ok().json(json))
+    cached_json(&req, &json, data.cache_ttls.nft)
+}
+
+/// Last sale, collection floor and a rarity-adjusted estimate of an asset,
+/// listed or not
+#[get("/{policy_id}/{asset_name}/estimate")]
+async fn get_price_estimate(
+    req: HttpRequest,
+    details: web::Path<NftDetails>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let details = details.into_inner();
+    let tenant = find_tenant(&req, &data)?;
+    let estimate = estimate_price(
+        &data.pool,
+        &tenant.marketplace.holder.address.to_bech32(None)?,
+        &tenant.marketplace.revenue_address.to_bech32(None)?,
+        &hex::decode(&details.policy_id)?,
+        details.asset_name.as_bytes(),
+    )
+    .await?;
+    cached_json(&req, &estimate, data.cache_ttls.nft)
 }
 
 pub fn create_nft_service() -> Scope {
     web::scope("/nft")
         .service(create_nft_transaction)
         .service(check_nft_exists)
+        .service(check_name)
+        .service(burn_nft_transaction)
+        .service(update_metadata_transaction)
+        .service(update_datum)
         .service(get_single_nft)
+        .service(get_price_estimate)
 }
@@ -0,0 +1,187 @@
+use crate::audit::AuditEntry;
+use crate::error::Error;
+use crate::features::Feature;
+use crate::marketplace::offers::{get_bidder_offers, get_open_offers};
+use crate::marketplace::MIN_SALE_PRICE;
+use crate::rest::tx::summarize_transaction;
+use crate::rest::{find_tenant, parse_address, respond_with_transaction, AppState};
+use crate::Result;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Scope};
+use cardano_serialization_lib::{AssetName, PolicyID};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OffersQuery {
+    policy_id: String,
+    asset_name: String,
+}
+
+/// Escrowed offers on an NFT that can still be accepted, highest first
+#[get("")]
+async fn get_offers(
+    req: HttpRequest,
+    query: web::Query<OffersQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.features.check(&data.pool, Feature::Offers).await?;
+    let tenant = find_tenant(&req, &data)?;
+    let query = query.into_inner();
+    let policy_id = PolicyID::from_bytes(hex::decode(query.policy_id)?)?;
+    let asset_name = AssetName::new(query.asset_name.into_bytes())?;
+    let offers = get_open_offers(
+        &data.pool,
+        &tenant.marketplace.holder.address,
+        &policy_id,
+        &asset_name,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(offers))
+}
+
+/// Escrowed offers of a bidder, including expired ones not refunded yet
+#[get("/bidder/{address}")]
+async fn get_offers_of_bidder(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let tenant = find_tenant(&req, &data)?;
+    let bidder_address = parse_address(&path.into_inner())?;
+    let offers = get_bidder_offers(
+        &data.pool,
+        &tenant.marketplace.holder.address,
+        &bidder_address,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(offers))
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaceOffer {
+    bidder_address: String,
+    policy_id: String,
+    asset_name: String,
+    price: u64,
+    /// Slot from which the offer can no longer be accepted and is refunded
+    expires_at: u64,
+}
+
+#[post("")]
+async fn place_offer(
+    req: HttpRequest,
+    offer_details: web::Json<PlaceOffer>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    data.features.check(&data.pool, Feature::Offers).await?;
+    let tenant = find_tenant(&req, &data)?;
+    let _permit = data.build_limit.acquire()?;
+    let offer_details = offer_details.into_inner();
+    if offer_details.price < MIN_SALE_PRICE {
+        return Err(Error::Message(
+            "Offer cannot be less than 5 ADA".to_string(),
+        ));
+    }
+    let bidder_address = parse_address(&offer_details.bidder_address)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(offer_details.policy_id)?)?;
+    let asset_name = AssetName::new(offer_details.asset_name.into_bytes())?;
+    let (tx, offer) = tenant
+        .marketplace
+        .offer(
+            bidder_address.clone(),
+            policy_id.clone(),
+            asset_name.clone(),
+            offer_details.price,
+            offer_details.expires_at,
+            &data.pool,
+            &data.pool,
+        )
+        .await?;
+    AuditEntry::new("offer")
+        .address(&bidder_address)
+        .asset(&policy_id, &asset_name)
+        .price(offer_details.price)
+        .built(&data.pool, &tx)
+        .await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "transaction": hex::encode(tx.to_bytes()),
+        "summary": summarize_transaction(&tx)?,
+        "offer": offer,
+    })))
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AcceptOffer {
+    seller_address: String,
+}
+
+/// Signed by the holder, the seller signs and submits it
+#[post("/{txHash}/accept")]
+async fn accept_offer(
+    req: HttpRequest,
+    path: web::Path<String>,
+    accept_details: web::Json<AcceptOffer>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    data.features.check(&data.pool, Feature::Offers).await?;
+    let tenant = find_tenant(&req, &data)?;
+    let _permit = data.build_limit.acquire()?;
+    let seller_address = parse_address(&accept_details.seller_address)?;
+    let (tx, fee) = tenant
+        .marketplace
+        .accept_offer(seller_address.clone(), &path, &data.pool, &data.pool)
+        .await?;
+    AuditEntry::new("accept_offer")
+        .address(&seller_address)
+        .fee_bps(fee.fee_bps)
+        .built(&data.pool, &tx)
+        .await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "transaction": hex::encode(tx.to_bytes()),
+        "summary": summarize_transaction(&tx)?,
+        "fee": fee,
+    })))
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WithdrawOffer {
+    bidder_address: String,
+}
+
+/// Left open with the offers feature switched off, the bids are the
+/// bidders' ADA
+#[post("/{txHash}/withdraw")]
+async fn withdraw_offer(
+    req: HttpRequest,
+    path: web::Path<String>,
+    withdraw_details: web::Json<WithdrawOffer>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let tenant = find_tenant(&req, &data)?;
+    let bidder_address = parse_address(&withdraw_details.bidder_address)?;
+    let tx = tenant
+        .marketplace
+        .withdraw_offer(bidder_address.clone(), &path, &data.pool, &data.pool)
+        .await?;
+    AuditEntry::new("withdraw_offer")
+        .address(&bidder_address)
+        .built(&data.pool, &tx)
+        .await?;
+    respond_with_transaction(&tx)
+}
+
+pub fn create_offers_service() -> Scope {
+    web::scope("/offers")
+        .service(get_offers)
+        .service(get_offers_of_bidder)
+        .service(place_offer)
+        .service(accept_offer)
+        .service(withdraw_offer)
+}
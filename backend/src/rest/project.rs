@@ -1,24 +1,104 @@
+use crate::audit::AuditEntry;
+use crate::cardano_db_sync::query_project_stats;
 use crate::error::Error;
+use crate::features::Feature;
 use crate::marketplace::holder::Filters;
+use crate::project::phase::get_sale_phases;
+use crate::project::Projects;
+use crate::rest::cache::cached_json;
 use crate::rest::marketplace::WebFilter;
-use crate::rest::{parse_address, respond_with_transaction, AppState};
+use crate::rest::{find_project, parse_address, respond_with_transaction, AppState};
 use crate::Result;
-use actix_web::{get, post, web, HttpResponse, Scope};
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Scope};
 use cardano_serialization_lib::{AssetName, PolicyID};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+async fn list_sales(
+    req: &HttpRequest,
+    data: &AppState,
+    project: &Projects,
+    query: WebFilter,
+) -> Result<HttpResponse> {
+    let filters = query.into_filters()?;
+    let sales = project
+        .holder
+        .get_nfts_for_sale(&data.pool, filters)
+        .await?;
+    cached_json(
+        req,
+        &data.price_oracle.price_all(&sales),
+        data.cache_ttls.projects,
+    )
+}
 
 #[get("")]
 async fn get_all_sales(
+    req: HttpRequest,
     data: web::Data<AppState>,
     query: web::Query<WebFilter>,
 ) -> Result<HttpResponse> {
-    let filters = query.into_inner().into_filters()?;
-    let sales = data
-        .project
-        .holder
-        .get_nfts_for_sale(&data.pool, filters)
-        .await?;
-    Ok(HttpResponse::Ok().json(sales))
+    data.features.check(&data.pool, Feature::Projects).await?;
+    list_sales(&req, &data, &data.project, query.into_inner()).await
+}
+
+#[get("/{slug}")]
+async fn get_project_sales(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+    query: web::Query<WebFilter>,
+) -> Result<HttpResponse> {
+    data.features.check(&data.pool, Feature::Projects).await?;
+    let project = find_project(&data, &path)?;
+    list_sales(&req, &data, &project, query.into_inner()).await
+}
+
+async fn sale_status(data: &AppState, project: &Projects, policy_id: &str) -> Result<HttpResponse> {
+    let policy_id = PolicyID::from_bytes(hex::decode(policy_id)?)?;
+    let phases = get_sale_phases(&data.pool, &policy_id).await?;
+    let remaining = project.remaining_supply(&data.pool, &policy_id).await?;
+    Ok(HttpResponse::Ok().json(json!({
+        "phases": phases,
+        "remaining": remaining,
+    })))
+}
+
+#[get("/phases/{policy_id}")]
+async fn get_sale_status(
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.features.check(&data.pool, Feature::Projects).await?;
+    sale_status(&data, &data.project, &path).await
+}
+
+#[get("/{slug}/phases/{policy_id}")]
+async fn get_project_sale_status(
+    path: web::Path<(String, String)>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.features.check(&data.pool, Feature::Projects).await?;
+    let (slug, policy_id) = path.into_inner();
+    let project = find_project(&data, &slug)?;
+    sale_status(&data, &project, &policy_id).await
+}
+
+#[get("/{slug}/stats")]
+async fn get_project_stats(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.features.check(&data.pool, Feature::Projects).await?;
+    let project = find_project(&data, &path)?;
+    let stats = query_project_stats(
+        &data.pool,
+        &project.holder.address.to_bech32(None)?,
+        &project.revenue_address.to_bech32(None)?,
+    )
+    .await?;
+    cached_json(&req, &stats, data.cache_ttls.projects)
 }
 
 #[derive(Deserialize, Debug, Serialize)]
@@ -29,23 +109,55 @@ struct Buy {
     asset_name: String,
 }
 
-#[post("/buy")]
-async fn buy_nft(buy_details: web::Json<Buy>, data: web::Data<AppState>) -> Result<HttpResponse> {
-    let buy_details = buy_details.into_inner();
-
+async fn buy(data: &AppState, project: &Projects, buy_details: Buy) -> Result<HttpResponse> {
     let buyer_address = parse_address(&buy_details.buyer_address)?;
     let policy_id = PolicyID::from_bytes(hex::decode(buy_details.policy_id)?)?;
     let asset_name = AssetName::new(buy_details.asset_name.into_bytes())?;
 
-    let tx = data
-        .project
-        .buy(buyer_address, policy_id, asset_name, &data.pool)
+    let tx = project
+        .buy(
+            buyer_address.clone(),
+            policy_id.clone(),
+            asset_name.clone(),
+            &data.pool,
+            &data.pool,
+            &data.build_settings,
+        )
+        .await?;
+    AuditEntry::new("project_buy")
+        .address(&buyer_address)
+        .asset(&policy_id, &asset_name)
+        .built(&data.pool, &tx)
         .await?;
-    Ok(respond_with_transaction(&tx))
+    respond_with_transaction(&tx)
+}
+
+#[post("/buy")]
+async fn buy_nft(buy_details: web::Json<Buy>, data: web::Data<AppState>) -> Result<HttpResponse> {
+    data.features.check(&data.pool, Feature::Projects).await?;
+    data.maintenance.check()?;
+    buy(&data, &data.project, buy_details.into_inner()).await
+}
+
+#[post("/{slug}/buy")]
+async fn buy_project_nft(
+    path: web::Path<String>,
+    buy_details: web::Json<Buy>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.features.check(&data.pool, Feature::Projects).await?;
+    data.maintenance.check()?;
+    let project = find_project(&data, &path)?;
+    buy(&data, &project, buy_details.into_inner()).await
 }
 
 pub fn create_project_service() -> Scope {
     web::scope("/projects")
         .service(buy_nft)
         .service(get_all_sales)
+        .service(buy_project_nft)
+        .service(get_project_sales)
+        .service(get_sale_status)
+        .service(get_project_sale_status)
+        .service(get_project_stats)
 }
@@ -0,0 +1,87 @@
+use crate::cardano_db_sync::{
+    query_assets_by_name, query_handle_address, query_policies_by_prefix, query_transaction_exists,
+};
+use crate::marketplace::holder::Filters;
+use crate::rest::AppState;
+use crate::{Error, Result};
+use actix_web::{get, web, HttpResponse, Scope};
+use cardano_serialization_lib::PolicyID;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+}
+
+/// Searches listings, collections, assets, ADA Handles and transactions at
+/// once for a global search bar. Groups that cannot match the query, e.g.
+/// transactions for a query that is not hex, come back empty.
+#[get("")]
+async fn search(query: web::Query<SearchQuery>, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let q = query.q.trim();
+    if q.len() < 2 {
+        return Err(Error::Message(
+            "Search needs at least 2 characters".to_string(),
+        ));
+    }
+    let is_hex = q.chars().all(|c| c.is_ascii_hexdigit());
+
+    let mut listings = data
+        .marketplace
+        .holder
+        .get_nfts_for_sale(
+            &data.pool,
+            Filters {
+                asset_name: Some(q.to_string()),
+                ..Default::default()
+            },
+        )
+        .await?;
+    let mut collections = vec![];
+    if is_hex {
+        collections = query_policies_by_prefix(&data.pool, q).await?;
+        if let Ok(Ok(policy)) = hex::decode(q).map(PolicyID::from_bytes) {
+            let policy_listings = data
+                .marketplace
+                .holder
+                .get_nfts_for_sale(
+                    &data.pool,
+                    Filters {
+                        policy: Some(policy),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            listings.extend(policy_listings);
+        }
+    }
+
+    let assets = query_assets_by_name(&data.pool, q).await?;
+
+    let handle = q.strip_prefix('$').unwrap_or(q);
+    let handles = match query_handle_address(&data.pool, handle).await? {
+        Some(address) => vec![json!({ "handle": format!("${}", handle), "address": address })],
+        None => vec![],
+    };
+
+    let mut transactions = vec![];
+    if is_hex && q.len() == 64 {
+        let hash = hex::decode(q)?;
+        if query_transaction_exists(&data.pool, &hash).await? {
+            transactions.push(q.to_lowercase());
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "listings": data.price_oracle.price_all(&listings),
+        "collections": collections,
+        "assets": assets,
+        "handles": handles,
+        "transactions": transactions,
+    })))
+}
+
+pub fn create_search_service() -> Scope {
+    web::scope("/search").service(search)
+}
@@ -0,0 +1,310 @@
+use crate::audit::{was_submitted, AuditEntry};
+use crate::cardano_db_sync::{query_transaction_depth, TransactionDepth};
+use crate::rest::{parse_address, parse_network_address, respond_with_transaction, AppState};
+use crate::transfer::transfer_nft;
+use crate::{Error, Result};
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{get, post, web, HttpResponse, Scope};
+use cardano_serialization_lib::crypto::TransactionHash;
+use cardano_serialization_lib::metadata::{decode_metadatum_to_json_str, MetadataJsonSchema};
+use cardano_serialization_lib::utils::{from_bignum, hash_transaction, Int, Value};
+use cardano_serialization_lib::{AssetName, PolicyID, Transaction};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// How often db-sync is checked while a client follows a transaction
+const EVENTS_POLL_SECONDS: u64 = 5;
+/// Transactions not on chain by then have most likely expired
+const EVENTS_TIMEOUT_SECONDS: u64 = 3600;
+const DEFAULT_CONFIRMATIONS: i64 = 10;
+const MAX_CONFIRMATIONS: i64 = 100;
+
+#[derive(Deserialize)]
+struct EncodedTransaction {
+    transaction: String,
+}
+
+/// Renders a transaction as JSON, for debugging what is handed to wallets
+#[post("/decode")]
+async fn decode_transaction(tx: web::Json<EncodedTransaction>) -> Result<HttpResponse> {
+    let tx = Transaction::from_bytes(hex::decode(&tx.transaction)?)?;
+    Ok(HttpResponse::Ok().json(summarize_transaction(&tx)?))
+}
+
+/// What a transaction spends, creates, mints and carries as metadata, so
+/// wallets can show users what they sign without decoding the CBOR
+pub(crate) fn summarize_transaction(tx: &Transaction) -> Result<serde_json::Value> {
+    let body = tx.body();
+
+    let inputs = body.inputs();
+    let inputs: Vec<_> = (0..inputs.len())
+        .map(|i| inputs.get(i))
+        .map(|input| {
+            json!({
+                "tx_hash": hex::encode(input.transaction_id().to_bytes()),
+                "tx_idx": input.index(),
+            })
+        })
+        .collect();
+
+    let outputs = body.outputs();
+    let mut output_jsons = Vec::with_capacity(outputs.len());
+    for i in 0..outputs.len() {
+        let output = outputs.get(i);
+        output_jsons.push(json!({
+            "address": output.address().to_bech32(None)?,
+            "lovelace": from_bignum(&output.amount().coin()),
+            "assets": assets_json(&output.amount()),
+            "data_hash": output.data_hash().map(|h| hex::encode(h.to_bytes())),
+        }));
+    }
+
+    let mut mint_jsons = vec![];
+    if let Some(mint) = body.multiassets() {
+        let policies = mint.keys();
+        for i in 0..policies.len() {
+            let policy_id = policies.get(i);
+            if let Some(assets) = mint.get(&policy_id) {
+                let asset_names = assets.keys();
+                for j in 0..asset_names.len() {
+                    let asset_name = asset_names.get(j);
+                    if let Some(qty) = assets.get(&asset_name) {
+                        mint_jsons.push(json!({
+                            "policy_id": hex::encode(policy_id.to_bytes()),
+                            "asset_name": String::from_utf8(asset_name.name())
+                                .unwrap_or_else(|_| hex::encode(asset_name.name())),
+                            "qty": int_to_i64(&qty),
+                        }));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut metadata_json = serde_json::Map::new();
+    if let Some(metadata) = tx.auxiliary_data().and_then(|aux| aux.metadata()) {
+        let labels = metadata.keys();
+        for i in 0..labels.len() {
+            let label = labels.get(i);
+            if let Some(metadatum) = metadata.get(&label) {
+                let json =
+                    decode_metadatum_to_json_str(&metadatum, MetadataJsonSchema::BasicConversions)?;
+                metadata_json.insert(
+                    from_bignum(&label).to_string(),
+                    serde_json::from_str(&json)?,
+                );
+            }
+        }
+    }
+
+    let required_signers: Vec<String> = body
+        .required_signers()
+        .map(|signers| {
+            (0..signers.len())
+                .map(|i| hex::encode(signers.get(i).to_bytes()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(json!({
+        "tx_hash": hex::encode(hash_transaction(&body).to_bytes()),
+        "inputs": inputs,
+        "outputs": output_jsons,
+        "fee": from_bignum(&body.fee()),
+        "ttl": body.ttl(),
+        "validity_start": body.validity_start_interval(),
+        "mint": mint_jsons,
+        "metadata": metadata_json,
+        "required_signers": required_signers,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Transfer {
+    sender_address: String,
+    recipient_address: String,
+    policy_id: String,
+    asset_name: String,
+}
+
+#[post("/transfer-nft")]
+async fn transfer(
+    transfer: web::Json<Transfer>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let transfer = transfer.into_inner();
+    let sender = parse_address(&transfer.sender_address)?;
+    let recipient = parse_network_address(&transfer.recipient_address, data.network_id)?;
+    let policy_id = PolicyID::from_bytes(hex::decode(transfer.policy_id)?)?;
+    let asset_name = AssetName::new(transfer.asset_name.into_bytes())?;
+
+    let tx = transfer_nft(
+        &data.pool,
+        &sender,
+        &recipient,
+        &policy_id,
+        &asset_name,
+        &data.build_settings,
+    )
+    .await?;
+    AuditEntry::new("transfer")
+        .address(&sender)
+        .asset(&policy_id, &asset_name)
+        .built(&data.pool, &tx)
+        .await?;
+    respond_with_transaction(&tx)
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    /// Confirmations after which the stream ends
+    confirmations: Option<i64>,
+}
+
+/// Server-sent events following a transaction onto the chain: `submitted`
+/// once the service handed it to the node, `in-block` when db-sync indexes
+/// it, then `confirmed` for every new block on top until the requested depth
+/// is reached. `rolled-back` is sent should the block be rolled back, and
+/// `timeout` when the transaction never shows up.
+#[get("/{hash}/events")]
+async fn transaction_events(
+    path: web::Path<String>,
+    query: web::Query<EventsQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let hash = path.into_inner().to_lowercase();
+    let tx_hash = TransactionHash::from_bytes(hex::decode(&hash)?)?;
+    let target = query
+        .confirmations
+        .unwrap_or(DEFAULT_CONFIRMATIONS)
+        .clamp(1, MAX_CONFIRMATIONS);
+    let pool = data.pool.clone();
+
+    let (sender, receiver) = mpsc::channel(16);
+    actix_web::rt::spawn(async move {
+        let send = |event: &str, data: serde_json::Value| {
+            sender.send(Ok::<_, Error>(Bytes::from(format!(
+                "event: {}\ndata: {}\n\n",
+                event, data
+            ))))
+        };
+        let started = Instant::now();
+        let mut interval = tokio::time::interval(Duration::from_secs(EVENTS_POLL_SECONDS));
+        let mut submitted = false;
+        let mut last_depth: Option<TransactionDepth> = None;
+        loop {
+            interval.tick().await;
+            let depth = match query_transaction_depth(&pool, &tx_hash).await {
+                Ok(depth) => depth,
+                Err(e) => {
+                    println!("Failed to follow transaction {}: {}", hash, e);
+                    continue;
+                }
+            };
+            let sent = match (&last_depth, &depth) {
+                (None, None) => {
+                    if !submitted && was_submitted(&pool, &hash).await.unwrap_or(false) {
+                        submitted = true;
+                        send("submitted", json!({ "txHash": hash })).await
+                    } else if started.elapsed().as_secs() > EVENTS_TIMEOUT_SECONDS {
+                        let _ = send("timeout", json!({ "txHash": hash })).await;
+                        break;
+                    } else {
+                        Ok(())
+                    }
+                }
+                (Some(_), None) => send("rolled-back", json!({ "txHash": hash })).await,
+                (last, Some(depth)) => {
+                    let moved = last.as_ref().map(|last| last.block_no) != Some(depth.block_no);
+                    let in_block = if moved {
+                        send("in-block", json!({ "blockNo": depth.block_no })).await
+                    } else {
+                        Ok(())
+                    };
+                    if in_block.is_ok()
+                        && (moved
+                            || last.as_ref().map(|last| last.confirmations)
+                                != Some(depth.confirmations))
+                    {
+                        send(
+                            "confirmed",
+                            json!({
+                                "blockNo": depth.block_no,
+                                "confirmations": depth.confirmations,
+                            }),
+                        )
+                        .await
+                    } else {
+                        in_block
+                    }
+                }
+            };
+            // The client is gone
+            if sent.is_err() {
+                break;
+            }
+            if depth
+                .as_ref()
+                .map_or(false, |depth| depth.confirmations >= target)
+            {
+                break;
+            }
+            last_depth = depth;
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Compression would hold events back until enough of them pile up
+        .insert_header((header::CONTENT_ENCODING, "identity"))
+        .streaming(ReceiverStream::new(receiver)))
+}
+
+fn assets_json(value: &Value) -> Vec<serde_json::Value> {
+    let mut asset_jsons = vec![];
+    if let Some(asset) = value.multiasset() {
+        let policies = asset.keys();
+        for i in 0..policies.len() {
+            let policy_id = policies.get(i);
+            if let Some(assets) = asset.get(&policy_id) {
+                let asset_names = assets.keys();
+                for j in 0..asset_names.len() {
+                    let asset_name = asset_names.get(j);
+                    if let Some(qty) = assets.get(&asset_name) {
+                        asset_jsons.push(json!({
+                            "policy_id": hex::encode(policy_id.to_bytes()),
+                            "asset_name": String::from_utf8(asset_name.name())
+                                .unwrap_or_else(|_| hex::encode(asset_name.name())),
+                            "qty": from_bignum(&qty),
+                        }));
+                    }
+                }
+            }
+        }
+    }
+    asset_jsons
+}
+
+fn int_to_i64(int: &Int) -> i64 {
+    match int.as_positive() {
+        Some(n) => from_bignum(&n) as i64,
+        None => int
+            .as_negative()
+            .map(|n| -(from_bignum(&n) as i64))
+            .unwrap_or(0),
+    }
+}
+
+pub fn create_tx_service() -> Scope {
+    web::scope("/tx")
+        .service(decode_transaction)
+        .service(transaction_events)
+        .service(transfer)
+}
@@ -0,0 +1,78 @@
+use crate::coin::decode_witnesses;
+use crate::rest::AppState;
+use crate::tx_session::{add_witnesses, create_session, get_session, submit_session};
+use crate::Result;
+use actix_web::{get, post, web, HttpResponse, Scope};
+use cardano_serialization_lib::crypto::Ed25519KeyHash;
+use cardano_serialization_lib::Transaction;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NewSession {
+    transaction: String,
+    /// Hex key hashes of everyone who has to sign
+    required_signers: Vec<String>,
+}
+
+#[post("")]
+async fn create(
+    new_session: web::Json<NewSession>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let new_session = new_session.into_inner();
+    let tx = Transaction::from_bytes(hex::decode(&new_session.transaction)?)?;
+    let signers = new_session
+        .required_signers
+        .iter()
+        .map(|signer| Ok(Ed25519KeyHash::from_bytes(hex::decode(signer)?)?))
+        .collect::<Result<Vec<_>>>()?;
+    let session = create_session(&data.pool, &tx, &signers).await?;
+    Ok(HttpResponse::Ok().json(session))
+}
+
+#[get("/{id}")]
+async fn get(path: web::Path<i64>, data: web::Data<AppState>) -> Result<HttpResponse> {
+    let session = get_session(&data.pool, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(session))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Witness {
+    witness_set: String,
+}
+
+#[post("/{id}/witness")]
+async fn witness(
+    path: web::Path<i64>,
+    witness: web::Json<Witness>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let witness_set = decode_witnesses(hex::decode(&witness.witness_set)?)?;
+    let session = add_witnesses(
+        &data.pool,
+        data.submitter.as_ref(),
+        path.into_inner(),
+        witness_set,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(session))
+}
+
+#[post("/{id}/submit")]
+async fn submit(path: web::Path<i64>, data: web::Data<AppState>) -> Result<HttpResponse> {
+    data.maintenance.check()?;
+    let session = submit_session(&data.pool, data.submitter.as_ref(), path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(session))
+}
+
+pub fn create_tx_session_service() -> Scope {
+    web::scope("/tx-sessions")
+        .service(create)
+        .service(get)
+        .service(witness)
+        .service(submit)
+}
@@ -0,0 +1,92 @@
+// Live updates over WebSocket
+
+use crate::error::Error;
+use crate::marketplace::ticker::{get_floor, CollectionEvent};
+use crate::rest::{find_tenant, AppState};
+use crate::Result;
+use actix_http::ws::{hash_key, verify_handshake};
+use actix_web::http::{header, StatusCode};
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpRequest, HttpResponse, Scope};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Streams listings, sales and floor price changes of one collection as JSON
+/// text messages, starting with its current floor. Messages from the client
+/// are not read; it leaves by closing the connection.
+#[get("/collections/{policy_id}")]
+async fn collection_ticker(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let policy_id = path.into_inner().to_lowercase();
+    hex::decode(&policy_id)?;
+    verify_handshake(req.head()).map_err(|e| Error::Message(e.to_string()))?;
+    let key = req
+        .headers()
+        .get(header::SEC_WEBSOCKET_KEY)
+        .ok_or_else(|| Error::Message("Missing WebSocket key".to_string()))?;
+    let accept = hash_key(key.as_bytes());
+
+    let tenant = find_tenant(&req, &data)?;
+    let holder = tenant.marketplace.holder.address.to_bech32(None)?;
+    let floor = get_floor(&data.pool, &holder, &policy_id).await?;
+    let mut events = tenant.ticker.subscribe();
+    let (sender, receiver) = mpsc::channel(16);
+    actix_web::rt::spawn(async move {
+        let mut next = Some(CollectionEvent::FloorChanged {
+            policy_id: policy_id.clone(),
+            floor,
+        });
+        loop {
+            let event = match next.take() {
+                Some(event) => event,
+                None => match events.recv().await {
+                    Ok(event) if event.policy_id() == policy_id => event,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            };
+            let message = match serde_json::to_vec(&event) {
+                Ok(message) => message,
+                Err(_) => continue,
+            };
+            if sender
+                .send(Ok::<_, Error>(text_frame(&message)))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    Ok(HttpResponse::build(StatusCode::SWITCHING_PROTOCOLS)
+        .upgrade("websocket")
+        .insert_header((header::SEC_WEBSOCKET_ACCEPT, &accept[..]))
+        .streaming(ReceiverStream::new(receiver)))
+}
+
+/// An unmasked text frame, as servers send them
+fn text_frame(payload: &[u8]) -> Bytes {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x81);
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    Bytes::from(frame)
+}
+
+pub fn create_ws_service() -> Scope {
+    web::scope("/ws").service(collection_ticker)
+}
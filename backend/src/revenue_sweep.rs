@@ -0,0 +1,201 @@
+// Moves the ADA collected at the revenue addresses to cold storage
+
+use crate::audit::AuditEntry;
+use crate::chain::ChainQuery;
+use crate::coin::{build_sweep, is_ada_only};
+use crate::transaction::TxSubmitter;
+use crate::{decode_private_key, Error, Result};
+use cardano_serialization_lib::address::{Address, BaseAddress, EnterpriseAddress};
+use cardano_serialization_lib::crypto::{PrivateKey, Vkeywitnesses};
+use cardano_serialization_lib::utils::{from_bignum, hash_transaction, make_vkey_witness};
+use cardano_serialization_lib::{Transaction, TransactionWitnessSet};
+use chrono::Utc;
+use cron::Schedule;
+use sqlx::PgPool;
+use std::str::FromStr;
+use std::sync::Arc;
+
+const ONE_HOUR: u32 = 3600;
+/// Inputs swept per transaction, well within the transaction size limit
+const MAX_SWEEP_INPUTS: usize = 100;
+
+/// A revenue address along with the key spending from it
+pub struct RevenueWallet {
+    address: Address,
+    key: PrivateKey,
+}
+
+impl RevenueWallet {
+    /// Refuses a key that does not spend from `address`
+    pub fn from_key_file(address: Address, key_file_path: &str) -> Result<Self> {
+        let key = decode_private_key(key_file_path)?;
+        let payment_cred = BaseAddress::from_address(&address)
+            .map(|base| base.payment_cred())
+            .or_else(|| EnterpriseAddress::from_address(&address).map(|ent| ent.payment_cred()))
+            .and_then(|cred| cred.to_keyhash());
+        match payment_cred {
+            Some(key_hash) if key_hash.to_bytes() == key.to_public().hash().to_bytes() => {
+                Ok(Self { address, key })
+            }
+            _ => Err(Error::Message(format!(
+                "Key file {} does not spend from revenue address {}",
+                key_file_path,
+                address.to_bech32(None)?
+            ))),
+        }
+    }
+}
+
+pub fn parse_schedule(expression: &str) -> Result<Schedule> {
+    Schedule::from_str(expression)
+        .map_err(|e| Error::Message(format!("Invalid revenue sweep schedule: {}", e)))
+}
+
+/// Sweeps every wallet holding at least `threshold` lovelace in ADA-only
+/// UTxOs to `cold_address` on each run of `schedule`. UTxOs carrying assets
+/// are left where they are.
+pub fn spawn(
+    pool: PgPool,
+    submitter: Arc<dyn TxSubmitter>,
+    wallets: Vec<RevenueWallet>,
+    cold_address: Address,
+    threshold: u64,
+    schedule: Schedule,
+) {
+    actix_web::rt::spawn(async move {
+        for next in schedule.upcoming(Utc) {
+            if let Ok(wait) = (next - Utc::now()).to_std() {
+                tokio::time::sleep(wait).await;
+            }
+            for wallet in &wallets {
+                let swept = sweep(
+                    &pool,
+                    &pool,
+                    submitter.as_ref(),
+                    wallet,
+                    &cold_address,
+                    threshold,
+                )
+                .await;
+                match swept {
+                    Ok(Some(tx_hash)) => println!("Swept revenue to cold storage in {}", tx_hash),
+                    Ok(None) => {}
+                    Err(e) => println!("Revenue sweep failed: {}", e),
+                }
+            }
+        }
+    });
+}
+
+/// Chain data is read from `chain`, the sweep is audited in `pool`
+async fn sweep<C: ChainQuery>(
+    chain: &C,
+    pool: &PgPool,
+    submitter: &dyn TxSubmitter,
+    wallet: &RevenueWallet,
+    cold_address: &Address,
+    threshold: u64,
+) -> Result<Option<String>> {
+    let mut utxos: Vec<_> = chain
+        .address_utxos(&wallet.address)
+        .await?
+        .into_iter()
+        .filter(is_ada_only)
+        .collect();
+    // The largest first, so a capped sweep still moves the most
+    utxos.sort_by_key(|utxo| std::cmp::Reverse(from_bignum(&utxo.output().amount().coin())));
+    utxos.truncate(MAX_SWEEP_INPUTS);
+    let total: u64 = utxos
+        .iter()
+        .map(|utxo| from_bignum(&utxo.output().amount().coin()))
+        .sum();
+    if utxos.is_empty() || total < threshold {
+        return Ok(None);
+    }
+
+    let slot = chain.slot_number().await?;
+    let params = chain.protocol_params().await?;
+    let tx_body = match build_sweep(&utxos, cold_address, &params, slot + ONE_HOUR)? {
+        Some(tx_body) => tx_body,
+        None => return Ok(None),
+    };
+
+    let mut witness_set = TransactionWitnessSet::new();
+    let mut vkeys = Vkeywitnesses::new();
+    vkeys.add(&make_vkey_witness(&hash_transaction(&tx_body), &wallet.key));
+    witness_set.set_vkeys(&vkeys);
+    let tx = Transaction::new(&tx_body, &witness_set, None);
+
+    let tx_hash = submitter.submit_tx(&tx).await?;
+    AuditEntry::new("revenue_sweep")
+        .address(&wallet.address)
+        .price(total)
+        .submitted(pool, &tx, &tx_hash)
+        .await;
+    Ok(Some(tx_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::memory::{new_address, unreachable_pool, MemoryChain};
+    use crate::transaction::MockSubmitter;
+    use cardano_serialization_lib::address::StakeCredential;
+    use cardano_serialization_lib::utils::{to_bignum, Value};
+
+    fn wallet() -> RevenueWallet {
+        let key = PrivateKey::generate_ed25519().unwrap();
+        let address =
+            EnterpriseAddress::new(0, &StakeCredential::from_keyhash(&key.to_public().hash()))
+                .to_address();
+        RevenueWallet { address, key }
+    }
+
+    #[tokio::test]
+    async fn sweeps_wallets_from_the_threshold_on() {
+        let wallet = wallet();
+        let cold_address = new_address();
+        let mut chain = MemoryChain::mary(50_000_000);
+        chain.pay(&wallet.address, &Value::new(&to_bignum(30_000_000)));
+        chain.pay(&wallet.address, &Value::new(&to_bignum(20_000_000)));
+        let submitter = MockSubmitter::default();
+        let pool = unreachable_pool();
+
+        let swept = sweep(
+            &chain,
+            &pool,
+            &submitter,
+            &wallet,
+            &cold_address,
+            60_000_000,
+        )
+        .await
+        .unwrap();
+        assert_eq!(swept, None);
+        assert!(submitter.submitted.lock().unwrap().is_empty());
+
+        let tx_hash = sweep(
+            &chain,
+            &pool,
+            &submitter,
+            &wallet,
+            &cold_address,
+            50_000_000,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let submitted = submitter.submitted.lock().unwrap();
+        assert_eq!(submitted.len(), 1);
+        let body = submitted[0].body();
+        assert_eq!(tx_hash, hex::encode(hash_transaction(&body).to_bytes()));
+        assert_eq!(body.inputs().len(), 2);
+        assert_eq!(body.outputs().len(), 1);
+        let output = body.outputs().get(0);
+        assert_eq!(output.address().to_bytes(), cold_address.to_bytes());
+        assert_eq!(
+            from_bignum(&output.amount().coin()) + from_bignum(&body.fee()),
+            50_000_000
+        );
+    }
+}
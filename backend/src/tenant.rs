@@ -0,0 +1,180 @@
+// Whitelabel marketplaces served by the same process next to the default one
+
+use crate::config::Config;
+use crate::marketplace::holder::MarketplaceHolder;
+use crate::marketplace::listing_cache::{self, ListingCache};
+use crate::marketplace::ticker::{self, Ticker};
+use crate::marketplace::Marketplace;
+use crate::rest::metrics::{Metrics, SloThresholds};
+use crate::{configured_address, Error, Result};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Id of the marketplace configured through the environment
+pub const DEFAULT_TENANT: &str = "default";
+/// Routes of a tenant are also served under `/t/{tenant}`
+const PATH_PREFIX: &str = "/t/";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TenantConfig {
+    private_key_file: String,
+    revenue_address: String,
+    fee_bps: Option<u64>,
+    min_fee_lovelace: Option<u64>,
+    /// Host names the tenant is served under, without the port
+    #[serde(default)]
+    hosts: Vec<String>,
+    /// Origins browsers may call the tenant from, any when empty
+    #[serde(default)]
+    cors_origins: Vec<String>,
+    /// Bearer token of the tenant's own admin endpoints
+    admin_token: Option<String>,
+}
+
+pub struct Tenant {
+    pub id: String,
+    pub marketplace: Marketplace,
+    pub admin_token: Option<String>,
+    /// Requests to this tenant only, all requests for the default one
+    pub metrics: Arc<Metrics>,
+    pub ticker: Ticker,
+    pub listings: ListingCache,
+    hosts: Vec<String>,
+    cors_origins: Vec<String>,
+}
+
+impl Tenant {
+    pub fn is_default(&self) -> bool {
+        self.id == DEFAULT_TENANT
+    }
+
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.cors_origins.is_empty() || self.cors_origins.iter().any(|o| o == origin)
+    }
+}
+
+pub struct Tenants {
+    default: Arc<Tenant>,
+    by_id: HashMap<String, Arc<Tenant>>,
+}
+
+impl Tenants {
+    /// The default marketplace along with the tenants of the tenants file.
+    /// Each tenant gets its own ticker and listing cache, polling db-sync for
+    /// its holder.
+    pub fn load(
+        config: &Config,
+        pool: &PgPool,
+        marketplace: Marketplace,
+        metrics: Arc<Metrics>,
+        thresholds: SloThresholds,
+    ) -> Result<Self> {
+        let default = Arc::new(Tenant {
+            id: DEFAULT_TENANT.to_string(),
+            ticker: spawn_ticker(config, pool, &marketplace)?,
+            listings: spawn_listing_cache(config, pool, &marketplace),
+            marketplace: marketplace.clone(),
+            admin_token: config.admin_token.clone(),
+            metrics,
+            hosts: vec![],
+            cors_origins: vec![],
+        });
+        let tenant_configs: HashMap<String, TenantConfig> = match &config.tenants_file {
+            Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+            None => HashMap::new(),
+        };
+        let mut by_id = HashMap::new();
+        for (id, tenant) in tenant_configs {
+            let tenant = Self::new_tenant(config, pool, &marketplace, &id, tenant, thresholds)
+                .map_err(|e| Error::Message(format!("Tenant {}: {}", id, e)))?;
+            by_id.insert(id, Arc::new(tenant));
+        }
+        if by_id.contains_key(DEFAULT_TENANT) {
+            return Err(Error::Message(format!(
+                "Tenant id {} is reserved",
+                DEFAULT_TENANT
+            )));
+        }
+        Ok(Self { default, by_id })
+    }
+
+    fn new_tenant(
+        config: &Config,
+        pool: &PgPool,
+        default: &Marketplace,
+        id: &str,
+        tenant: TenantConfig,
+        thresholds: SloThresholds,
+    ) -> Result<Tenant> {
+        let marketplace = default.for_tenant(
+            MarketplaceHolder::from_key_file(&tenant.private_key_file, config.is_testnet)?,
+            configured_address(&tenant.revenue_address, config.is_testnet)?,
+            tenant.fee_bps,
+            tenant.min_fee_lovelace,
+        )?;
+        Ok(Tenant {
+            id: id.to_string(),
+            ticker: spawn_ticker(config, pool, &marketplace)?,
+            listings: spawn_listing_cache(config, pool, &marketplace),
+            marketplace,
+            admin_token: tenant.admin_token,
+            metrics: Arc::new(Metrics::new(thresholds)),
+            hosts: tenant
+                .hosts
+                .iter()
+                .map(|host| host.to_lowercase())
+                .collect(),
+            cors_origins: tenant.cors_origins,
+        })
+    }
+
+    /// The default tenant first, then the others in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Tenant>> {
+        std::iter::once(&self.default).chain(self.by_id.values())
+    }
+
+    /// The tenant named by the `/t/{tenant}` prefix of `path`, or else the one
+    /// served under `host`, or else the default one. None for a prefix naming
+    /// no tenant.
+    pub fn resolve(&self, path: &str, host: Option<&str>) -> Option<Arc<Tenant>> {
+        if let Some(rest) = path.strip_prefix(PATH_PREFIX) {
+            let id = rest.split('/').next().unwrap_or_default();
+            return match id {
+                DEFAULT_TENANT => Some(self.default.clone()),
+                id => self.by_id.get(id).cloned(),
+            };
+        }
+        let host = host
+            .map(|host| host.split(':').next().unwrap_or_default().to_lowercase())
+            .unwrap_or_default();
+        Some(
+            self.by_id
+                .values()
+                .find(|tenant| tenant.hosts.contains(&host))
+                .cloned()
+                .unwrap_or_else(|| self.default.clone()),
+        )
+    }
+}
+
+fn spawn_ticker(config: &Config, pool: &PgPool, marketplace: &Marketplace) -> Result<Ticker> {
+    Ok(ticker::spawn(
+        pool.clone(),
+        marketplace.holder.address.to_bech32(None)?,
+        marketplace.revenue_address.to_bech32(None)?,
+        config.ticker_interval_seconds,
+    ))
+}
+
+fn spawn_listing_cache(config: &Config, pool: &PgPool, marketplace: &Marketplace) -> ListingCache {
+    listing_cache::spawn(
+        pool.clone(),
+        marketplace.holder.clone(),
+        config.listing_cache_pages,
+        config.listing_cache_popular_policies,
+        config.listing_cache_interval_seconds,
+    )
+}
@@ -1,12 +1,143 @@
+use crate::config::Config;
 use crate::Result;
+use async_trait::async_trait;
+#[cfg(test)]
+use cardano_serialization_lib::utils::hash_transaction;
 use cardano_serialization_lib::{crypto::TransactionHash, Transaction};
 use reqwest::{
     header::{HeaderMap, HeaderValue},
     Client, Url,
 };
+use serde_json::{json, Value};
+use std::sync::Arc;
+#[cfg(test)]
+use std::sync::Mutex;
 
 use crate::error::Error;
 
+/// Hands signed transactions to the chain, returning their hash
+#[async_trait]
+pub trait TxSubmitter: Send + Sync {
+    async fn submit_tx(&self, tx: &Transaction) -> Result<String>;
+}
+
+/// Picks the submitter named by `TX_SUBMITTER`
+pub fn submitter_from_config(config: &Config) -> Result<Arc<dyn TxSubmitter>> {
+    match config.tx_submitter.as_str() {
+        "submit-api" => Ok(Arc::new(Submitter::for_url(&config.submit_api_base_url))),
+        "blockfrost" => {
+            let project_id = config.blockfrost_project_id.as_deref().ok_or_else(|| {
+                Error::Message("BLOCKFROST_PROJECT_ID is required for Blockfrost".to_string())
+            })?;
+            Ok(Arc::new(BlockfrostSubmitter::new(
+                &config.blockfrost_url,
+                project_id,
+            )?))
+        }
+        "ogmios" => {
+            let url = config
+                .ogmios_url
+                .as_deref()
+                .ok_or_else(|| Error::Message("OGMIOS_URL is required for Ogmios".to_string()))?;
+            Ok(Arc::new(OgmiosSubmitter::new(url)?))
+        }
+        other => Err(Error::Message(format!(
+            "Unknown transaction submitter {}",
+            other
+        ))),
+    }
+}
+
+/// Why the node refused a transaction, told apart so clients know whether to
+/// rebuild, re-sign or give up
+#[derive(Debug, thiserror::Error)]
+pub enum TxRejection {
+    #[error("Transaction inputs and outputs do not balance, build the transaction again")]
+    ValueNotConserved,
+    #[error("Some inputs of the transaction were already spent, build the transaction again")]
+    BadInputs,
+    #[error("Transaction expired or is not valid yet, build the transaction again")]
+    OutsideValidityInterval,
+    #[error("Transaction fee is too small, build the transaction again")]
+    FeeTooSmall,
+    #[error("Transaction is missing signatures, sign it with every required key")]
+    MissingWitnesses,
+    #[error("An output holds less than the minimum ADA, send more ADA with it")]
+    OutputTooSmall,
+    #[error("Transaction is too large, use fewer inputs")]
+    MaxTxSizeExceeded,
+    #[error("Transaction rejected by the node: {}", .0)]
+    Other(String),
+}
+
+impl TxRejection {
+    /// Recognises the ledger failure in the error body of a submission. The
+    /// first known failure wins, as the node often reports follow-on errors.
+    pub fn from_node_error(body: &str) -> Self {
+        const KNOWN: [&str; 9] = [
+            "badinputsutxo",
+            "outsidevalidityinterval",
+            "expiredutxo",
+            "valuenotconserved",
+            "feetoosmall",
+            "missingvkeywitnesses",
+            "missingscriptwitnesses",
+            "outputtoosmall",
+            "maxtxsizeutxo",
+        ];
+        let body_lower = body.to_lowercase();
+        let first = KNOWN
+            .iter()
+            .filter_map(|name| body_lower.find(name).map(|at| (at, *name)))
+            .min();
+        match first.map(|(_, name)| name) {
+            Some("badinputsutxo") => TxRejection::BadInputs,
+            Some("outsidevalidityinterval") | Some("expiredutxo") => {
+                TxRejection::OutsideValidityInterval
+            }
+            Some("valuenotconserved") => TxRejection::ValueNotConserved,
+            Some("feetoosmall") => TxRejection::FeeTooSmall,
+            Some("missingvkeywitnesses") | Some("missingscriptwitnesses") => {
+                TxRejection::MissingWitnesses
+            }
+            Some("outputtoosmall") => TxRejection::OutputTooSmall,
+            Some("maxtxsizeutxo") => TxRejection::MaxTxSizeExceeded,
+            _ => TxRejection::Other(body.chars().take(500).collect()),
+        }
+    }
+
+    /// Name of the failure, for clients to branch on
+    pub fn code(&self) -> &'static str {
+        match self {
+            TxRejection::ValueNotConserved => "ValueNotConserved",
+            TxRejection::BadInputs => "BadInputsUTxO",
+            TxRejection::OutsideValidityInterval => "OutsideValidityInterval",
+            TxRejection::FeeTooSmall => "FeeTooSmall",
+            TxRejection::MissingWitnesses => "MissingWitnesses",
+            TxRejection::OutputTooSmall => "OutputTooSmall",
+            TxRejection::MaxTxSizeExceeded => "MaxTxSizeExceeded",
+            TxRejection::Other(_) => "Other",
+        }
+    }
+}
+
+/// Turns a non-success submission response into the rejection it reports
+async fn rejection(res: reqwest::Response) -> Result<reqwest::Response> {
+    if res.status().is_success() {
+        return Ok(res);
+    }
+    let body = res.text().await?;
+    println!("Transaction rejected: {}", body);
+    Err(TxRejection::from_node_error(&body).into())
+}
+
+fn checked_tx_hash(text: &str) -> Result<String> {
+    TransactionHash::from_bytes(hex::decode(text.as_bytes())?)
+        .map_err(|_| Error::Message("Unsuccessful transaction. Please try again".to_string()))?;
+    Ok(text.to_string())
+}
+
+/// cardano-submit-api
 #[derive(Clone)]
 pub struct Submitter {
     submit_url: Url,
@@ -30,8 +161,54 @@ impl Submitter {
 
         Self { submit_url, client }
     }
+}
+
+#[async_trait]
+impl TxSubmitter for Submitter {
+    async fn submit_tx(&self, tx: &Transaction) -> Result<String> {
+        let res = self
+            .client
+            .post(self.submit_url.as_ref())
+            .body(tx.to_bytes())
+            .send()
+            .await?;
+
+        let text = rejection(res).await?.text().await?.replace("\"", "");
+
+        checked_tx_hash(&text)
+    }
+}
+
+pub struct BlockfrostSubmitter {
+    submit_url: Url,
+    client: Client,
+}
+
+impl BlockfrostSubmitter {
+    /// `base_url` includes the API version, e.g.
+    /// `https://cardano-mainnet.blockfrost.io/api/v0`
+    pub fn new(base_url: &str, project_id: &str) -> Result<Self> {
+        let submit_url = Url::parse(&format!("{}/tx/submit", base_url.trim_end_matches('/')))
+            .map_err(|e| Error::Message(format!("Invalid Blockfrost URL: {}", e)))?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", HeaderValue::from_static("application/cbor"));
+        headers.insert(
+            "project_id",
+            HeaderValue::from_str(project_id)
+                .map_err(|_| Error::Message("Invalid Blockfrost project id".to_string()))?,
+        );
+
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+        Ok(Self { submit_url, client })
+    }
+}
 
-    pub async fn submit_tx(&self, tx: &Transaction) -> Result<String> {
+#[async_trait]
+impl TxSubmitter for BlockfrostSubmitter {
+    async fn submit_tx(&self, tx: &Transaction) -> Result<String> {
         let res = self
             .client
             .post(self.submit_url.as_ref())
@@ -39,12 +216,74 @@ impl Submitter {
             .send()
             .await?;
 
-        let text = res.error_for_status()?.text().await?.replace("\"", "");
+        let text = rejection(res).await?.text().await?.replace("\"", "");
 
-        TransactionHash::from_bytes(hex::decode(text.as_bytes())?).map_err(|_| {
-            Error::Message("Unsuccessful transaction. Please try again".to_string())
-        })?;
+        checked_tx_hash(&text)
+    }
+}
+
+/// Ogmios over its HTTP JSON-RPC interface
+pub struct OgmiosSubmitter {
+    url: Url,
+    client: Client,
+}
+
+impl OgmiosSubmitter {
+    pub fn new(url: &str) -> Result<Self> {
+        let url =
+            Url::parse(url).map_err(|e| Error::Message(format!("Invalid Ogmios URL: {}", e)))?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+        Ok(Self { url, client })
+    }
+}
+
+#[async_trait]
+impl TxSubmitter for OgmiosSubmitter {
+    async fn submit_tx(&self, tx: &Transaction) -> Result<String> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "method": "submitTransaction",
+            "params": { "transaction": { "cbor": hex::encode(tx.to_bytes()) } },
+        });
+        let res = self
+            .client
+            .post(self.url.as_ref())
+            .body(request.to_string())
+            .send()
+            .await?;
+
+        let response: Value = serde_json::from_str(&res.text().await?)?;
+        if let Some(error) = response.get("error") {
+            println!("Ogmios rejected transaction: {}", error);
+            return Err(TxRejection::from_node_error(&error.to_string()).into());
+        }
+        let tx_id = response
+            .pointer("/result/transaction/id")
+            .and_then(|id| id.as_str())
+            .ok_or_else(|| Error::Message("Unexpected response from Ogmios".to_string()))?;
+
+        checked_tx_hash(tx_id)
+    }
+}
+
+/// Keeps submitted transactions in memory instead of sending them anywhere
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockSubmitter {
+    pub submitted: Mutex<Vec<Transaction>>,
+}
 
-        Ok(text)
+#[cfg(test)]
+#[async_trait]
+impl TxSubmitter for MockSubmitter {
+    async fn submit_tx(&self, tx: &Transaction) -> Result<String> {
+        self.submitted.lock().unwrap().push(tx.clone());
+        Ok(hex::encode(hash_transaction(&tx.body()).to_bytes()))
     }
 }
@@ -0,0 +1,70 @@
+// Plain NFT transfers between wallets
+
+use crate::chain::ChainQuery;
+use crate::coin::{
+    build_transaction_body, BuildSettings, LargestFirst, TransactionWitnessSetParams,
+};
+use crate::marketplace::{create_value_with_single_nft, find_nft};
+use crate::{Error, Result};
+use cardano_serialization_lib::address::Address;
+use cardano_serialization_lib::{
+    AssetName, PolicyID, Transaction, TransactionOutput, TransactionWitnessSet,
+};
+
+const ONE_HOUR: u32 = 3600;
+
+/// Builds an unsigned transaction sending one unit of the asset from
+/// `sender` to `recipient`. The recipient output carries the minimum ADA,
+/// other assets sharing the UTxO go back to the sender along with the change.
+pub async fn transfer_nft<C: ChainQuery>(
+    chain: &C,
+    sender: &Address,
+    recipient: &Address,
+    policy_id: &PolicyID,
+    asset_name: &AssetName,
+    build: &BuildSettings,
+) -> Result<Transaction> {
+    let sender_utxos = chain.address_utxos(sender).await?;
+    let (nft_utxo, sender_utxos) = find_nft(sender_utxos, policy_id, asset_name)
+        .map_err(|_| Error::Message("Address does not hold this NFT".to_string()))?;
+
+    let nft_value = create_value_with_single_nft(policy_id, asset_name);
+    let mut outputs = vec![TransactionOutput::new(recipient, &nft_value)];
+    let mut rest = nft_utxo.output().amount();
+    let remaining_assets = rest
+        .multiasset()
+        .unwrap()
+        .sub(&nft_value.multiasset().unwrap());
+    if remaining_assets.len() > 0 {
+        // Assets besides the NFT stay with the sender
+        rest.set_multiasset(&remaining_assets);
+        outputs.push(TransactionOutput::new(sender, &rest));
+    }
+
+    let slot = chain.slot_number().await?;
+    let protocol_params = chain.protocol_params().await?;
+    let tx_witness_params = TransactionWitnessSetParams {
+        vkey_count: 1,
+        ..Default::default()
+    };
+    let tx_body = build_transaction_body(
+        sender_utxos,
+        vec![nft_utxo],
+        outputs,
+        slot + ONE_HOUR,
+        &protocol_params,
+        None,
+        None,
+        &tx_witness_params,
+        None,
+        &LargestFirst,
+        None,
+        build,
+    )?;
+
+    Ok(Transaction::new(
+        &tx_body,
+        &TransactionWitnessSet::new(),
+        None,
+    ))
+}
@@ -0,0 +1,201 @@
+// Coordination of transactions signed by several parties
+
+use crate::audit::AuditEntry;
+use crate::coin::combine_witness_set;
+use crate::marketplace::coupon::redeem_coupon;
+use crate::transaction::TxSubmitter;
+use crate::{Error, Result};
+use cardano_serialization_lib::crypto::{Ed25519KeyHash, Vkeywitnesses};
+use cardano_serialization_lib::utils::hash_transaction;
+use cardano_serialization_lib::{Transaction, TransactionWitnessSet};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TxSession {
+    pub id: i64,
+    #[serde(with = "hex")]
+    pub transaction: Vec<u8>,
+    pub required_signers: Vec<String>,
+    pub signed_by: Vec<String>,
+    pub status: String,
+    pub tx_hash: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const TX_SESSION_COLUMNS: &str = r#"
+    id, transaction, required_signers, signed_by, status, tx_hash, last_error,
+    created_at, updated_at
+"#;
+
+pub async fn create_session(
+    pool: &PgPool,
+    tx: &Transaction,
+    required_signers: &[Ed25519KeyHash],
+) -> Result<TxSession> {
+    if required_signers.is_empty() {
+        return Err(Error::Message(
+            "A session needs at least one required signer".to_string(),
+        ));
+    }
+    let mut signers: Vec<String> = required_signers
+        .iter()
+        .map(|signer| hex::encode(signer.to_bytes()))
+        .collect();
+    signers.sort();
+    signers.dedup();
+
+    Ok(sqlx::query_as::<_, TxSession>(&format!(
+        r#"
+        INSERT INTO marketplace.tx_session (transaction, required_signers)
+        VALUES ($1, $2)
+        RETURNING {}
+        "#,
+        TX_SESSION_COLUMNS
+    ))
+    .bind(tx.to_bytes())
+    .bind(signers)
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn get_session(pool: &PgPool, id: i64) -> Result<TxSession> {
+    sqlx::query_as::<_, TxSession>(&format!(
+        "SELECT {} FROM marketplace.tx_session WHERE id = $1",
+        TX_SESSION_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("No transaction session {}", id)))
+}
+
+/// Merges the witnesses of one party into the session transaction. Every
+/// witness has to be a valid signature by one of the required signers. Once
+/// all have signed the transaction is submitted.
+pub async fn add_witnesses(
+    pool: &PgPool,
+    submitter: &dyn TxSubmitter,
+    id: i64,
+    witness_set: TransactionWitnessSet,
+) -> Result<TxSession> {
+    let mut db_tx = pool.begin().await?;
+    // Locked so witnesses posted at the same time are not lost
+    let session = sqlx::query_as::<_, TxSession>(&format!(
+        "SELECT {} FROM marketplace.tx_session WHERE id = $1 FOR UPDATE",
+        TX_SESSION_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&mut db_tx)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("No transaction session {}", id)))?;
+    if session.status != "collecting" {
+        return Err(Error::Message(format!(
+            "Transaction session {} is no longer collecting witnesses",
+            id
+        )));
+    }
+
+    let tx = Transaction::from_bytes(session.transaction.clone())?;
+    let tx_hash = hash_transaction(&tx.body());
+    let vkeys = witness_set.vkeys().unwrap_or_else(Vkeywitnesses::new);
+    if vkeys.len() == 0 {
+        return Err(Error::Message("No witnesses were provided".to_string()));
+    }
+    let mut signed_by = session.signed_by.clone();
+    for i in 0..vkeys.len() {
+        let vkey = vkeys.get(i);
+        let public_key = vkey.vkey().public_key();
+        let signer = hex::encode(public_key.hash().to_bytes());
+        if !session.required_signers.contains(&signer) {
+            return Err(Error::Forbidden(format!(
+                "{} is not a signer of this transaction",
+                signer
+            )));
+        }
+        if !public_key.verify(&tx_hash.to_bytes(), &vkey.signature()) {
+            return Err(Error::Message(format!(
+                "Witness of {} does not sign this transaction",
+                signer
+            )));
+        }
+        if !signed_by.contains(&signer) {
+            signed_by.push(signer);
+        }
+    }
+
+    let tx = combine_witness_set(tx, witness_set)?;
+    let complete = session
+        .required_signers
+        .iter()
+        .all(|signer| signed_by.contains(signer));
+    let session = sqlx::query_as::<_, TxSession>(&format!(
+        r#"
+        UPDATE marketplace.tx_session
+        SET transaction = $2, signed_by = $3, status = $4, updated_at = now()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        TX_SESSION_COLUMNS
+    ))
+    .bind(id)
+    .bind(tx.to_bytes())
+    .bind(&signed_by)
+    .bind(if complete { "signed" } else { "collecting" })
+    .fetch_one(&mut db_tx)
+    .await?;
+    db_tx.commit().await?;
+
+    if complete {
+        return submit_session(pool, submitter, id).await;
+    }
+    Ok(session)
+}
+
+/// Submits a fully signed session, recording the error when the submission
+/// fails so it can be retried.
+pub async fn submit_session(
+    pool: &PgPool,
+    submitter: &dyn TxSubmitter,
+    id: i64,
+) -> Result<TxSession> {
+    let session = get_session(pool, id).await?;
+    if session.status != "signed" {
+        return Err(Error::Message(format!(
+            "Transaction session {} is not ready to submit",
+            id
+        )));
+    }
+    let tx = Transaction::from_bytes(session.transaction)?;
+    let (status, tx_hash, last_error) = match submitter.submit_tx(&tx).await {
+        Ok(tx_hash) => ("submitted", Some(tx_hash), None),
+        Err(e) => ("signed", None, Some(e.to_string())),
+    };
+    if let Some(tx_hash) = &tx_hash {
+        AuditEntry::new("tx_session")
+            .submitted(pool, &tx, tx_hash)
+            .await;
+        if let Err(e) = redeem_coupon(pool, &tx).await {
+            println!("Failed to redeem the coupon of {}: {}", tx_hash, e);
+        }
+    }
+    Ok(sqlx::query_as::<_, TxSession>(&format!(
+        r#"
+        UPDATE marketplace.tx_session
+        SET status = $2, tx_hash = $3, last_error = $4, updated_at = now()
+        WHERE id = $1 AND status = 'signed'
+        RETURNING {}
+        "#,
+        TX_SESSION_COLUMNS
+    ))
+    .bind(id)
+    .bind(status)
+    .bind(tx_hash)
+    .bind(last_error)
+    .fetch_one(pool)
+    .await?)
+}
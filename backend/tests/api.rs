@@ -0,0 +1,896 @@
+// End-to-end tests of the API, run against a Postgres container seeded
+// with the cardano-db-sync 11 tables the backend queries. Needs a Docker
+// daemon.
+
+use cardano_serialization_lib::address::{
+    Address, BaseAddress, EnterpriseAddress, NetworkInfo, StakeCredential,
+};
+use cardano_serialization_lib::crypto::{PrivateKey, ScriptHash};
+use cardano_serialization_lib::plutus::PlutusData;
+use cardano_serialization_lib::utils::{from_bignum, hash_plutus_data, to_bignum, BigInt, Value};
+use cardano_serialization_lib::{AssetName, Assets, MultiAsset, PolicyID, Transaction};
+use serde_json::{json, Value as Json};
+use sqlx::{Executor, PgPool};
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::Duration;
+use testcontainers::{clients, images, Docker};
+
+const POLICY_ID: &str = "e9cdb3b2fd1d1c8b5ed53e3d4ea4bdb8b25d7c32a0e1e6b7eb1a5f0b";
+const LISTED_ASSET: &str = "Listed";
+const OWNED_ASSET: &str = "Owned";
+const LISTING_PRICE: u64 = 10_000_000;
+const ADMIN_TOKEN: &str = "test-admin-token";
+
+struct Wallet {
+    key: PrivateKey,
+}
+
+impl Wallet {
+    fn generate() -> Self {
+        Self {
+            key: PrivateKey::generate_ed25519().unwrap(),
+        }
+    }
+
+    fn enterprise_address(&self) -> Address {
+        EnterpriseAddress::new(
+            NetworkInfo::testnet().network_id(),
+            &StakeCredential::from_keyhash(&self.key.to_public().hash()),
+        )
+        .to_address()
+    }
+
+    fn base_address(&self) -> Address {
+        let credential = StakeCredential::from_keyhash(&self.key.to_public().hash());
+        BaseAddress::new(
+            NetworkInfo::testnet().network_id(),
+            &credential,
+            &credential,
+        )
+        .to_address()
+    }
+
+    fn bech32(&self) -> String {
+        self.enterprise_address().to_bech32(None).unwrap()
+    }
+
+    fn write_key_file(&self, path: &Path) {
+        let envelope = json!({
+            "type": "PaymentSigningKeyShelley_ed25519",
+            "description": "Payment Signing Key",
+            "cborHex": format!("5820{}", hex::encode(self.key.as_bytes())),
+        });
+        std::fs::write(path, envelope.to_string()).unwrap();
+    }
+}
+
+struct Output<'a> {
+    address: &'a str,
+    lovelace: u64,
+    asset: Option<&'a str>,
+    datum: Option<&'a PlutusData>,
+}
+
+struct Seeder {
+    pool: PgPool,
+    next_tx: i64,
+}
+
+impl Seeder {
+    /// Inserts a confirmed transaction with the given outputs, optionally
+    /// minting one of each of their assets and carrying metadata.
+    async fn insert_tx(
+        &mut self,
+        outputs: &[Output<'_>],
+        mint: bool,
+        metadata: Option<(u64, Json)>,
+    ) {
+        let tx_id = self.next_tx;
+        self.next_tx += 1;
+        let policy = hex::decode(POLICY_ID).unwrap();
+
+        let out_sum: u64 = outputs.iter().map(|output| output.lovelace).sum();
+        sqlx::query(
+            r#"
+            INSERT INTO tx (
+                id, hash, block_id, block_index, out_sum, fee, deposit, size,
+                valid_contract, script_size
+            )
+            VALUES ($1, $2, 1, 0, $3, 0, 0, 0, true, 0)
+            "#,
+        )
+        .bind(tx_id)
+        .bind([tx_id as u8; 32].to_vec())
+        .bind(out_sum as i64)
+        .execute(&self.pool)
+        .await
+        .unwrap();
+        for (index, output) in outputs.iter().enumerate() {
+            let address = Address::from_bech32(output.address).unwrap();
+            let data_hash = output.datum.map(|datum| hash_plutus_data(datum).to_bytes());
+            let (tx_out_id,): (i64,) = sqlx::query_as(
+                r#"
+                INSERT INTO tx_out (
+                    tx_id, index, address, address_raw, address_has_script, value, data_hash
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                RETURNING id
+                "#,
+            )
+            .bind(tx_id)
+            .bind(index as i16)
+            .bind(output.address)
+            .bind(address.to_bytes())
+            .bind(data_hash.is_some())
+            .bind(output.lovelace as i64)
+            .bind(&data_hash)
+            .fetch_one(&self.pool)
+            .await
+            .unwrap();
+            if let (Some(datum), Some(data_hash)) = (output.datum, &data_hash) {
+                sqlx::query("INSERT INTO datum (hash, tx_id, value) VALUES ($1, $2, $3)")
+                    .bind(data_hash)
+                    .bind(tx_id)
+                    .bind(datum_json(datum))
+                    .execute(&self.pool)
+                    .await
+                    .unwrap();
+            }
+            if let Some(asset) = output.asset {
+                sqlx::query(
+                    r#"
+                    INSERT INTO ma_tx_out (policy, name, quantity, tx_out_id)
+                    VALUES ($1, $2, 1, $3)
+                    "#,
+                )
+                .bind(&policy)
+                .bind(asset.as_bytes())
+                .bind(tx_out_id)
+                .execute(&self.pool)
+                .await
+                .unwrap();
+                if mint {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO ma_tx_mint (policy, name, quantity, tx_id)
+                        VALUES ($1, $2, 1, $3)
+                        "#,
+                    )
+                    .bind(&policy)
+                    .bind(asset.as_bytes())
+                    .bind(tx_id)
+                    .execute(&self.pool)
+                    .await
+                    .unwrap();
+                }
+            }
+        }
+        if let Some((key, json)) = metadata {
+            sqlx::query(
+                "INSERT INTO tx_metadata (key, json, bytes, tx_id) VALUES ($1, $2, '', $3)",
+            )
+            .bind(key as i64)
+            .bind(json)
+            .bind(tx_id)
+            .execute(&self.pool)
+            .await
+            .unwrap();
+        }
+    }
+}
+
+/// A datum in db-sync's detailed schema JSON, integers being all the tests
+/// need
+fn datum_json(datum: &PlutusData) -> Json {
+    let int = datum.as_integer().expect("integer datum").to_str();
+    json!({ "int": int.parse::<i64>().unwrap() })
+}
+
+fn script_address() -> Address {
+    EnterpriseAddress::new(
+        NetworkInfo::testnet().network_id(),
+        &StakeCredential::from_scripthash(&ScriptHash::from_bytes(vec![7; 28]).unwrap()),
+    )
+    .to_address()
+}
+
+struct Server {
+    process: Child,
+    url: String,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+    }
+}
+
+struct Fixture {
+    server: Server,
+    pool: PgPool,
+    database_url: String,
+    /// Holds the key files of the holders
+    dir: PathBuf,
+    seller: Wallet,
+    buyer: Wallet,
+    script_datum: PlutusData,
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Runs the backend against `database_url` with the holder keys in `dir`,
+/// once it answers
+async fn start_server(database_url: &str, dir: &Path) -> Server {
+    let port = free_port();
+    let process = Command::new(env!("CARGO_BIN_EXE_backend"))
+        .env("IS_TESTNET", "true")
+        .env("SUBMIT_API_BASE_URL", "http://127.0.0.1:1")
+        .env("PORT", port.to_string())
+        .env("NFT_BECH32_TAXATION_ADDRESS", Wallet::generate().bech32())
+        .env("DATABASE_URL", database_url)
+        .env("MARKETPLACE_PRIVATE_KEY_FILE", dir.join("marketplace.skey"))
+        .env(
+            "MARKETPLACE_REVENUE_ADDRESS",
+            Wallet::generate().base_address().to_bech32(None).unwrap(),
+        )
+        .env("PROJECTS_PRIVATE_KEY_FILE", dir.join("projects.skey"))
+        .env("ADMIN_TOKEN", ADMIN_TOKEN)
+        .env("POLICY_KEYSTORE_SECRET", "test-keystore-secret")
+        .env(
+            "PROJECTS_REVENUE_ADDRESS",
+            Wallet::generate().base_address().to_bech32(None).unwrap(),
+        )
+        .spawn()
+        .unwrap();
+    let server = Server {
+        process,
+        url: format!("http://127.0.0.1:{}", port),
+    };
+
+    let client = reqwest::Client::new();
+    for _ in 0..100 {
+        let ready = client
+            .get(format!("{}/marketplace", server.url))
+            .send()
+            .await
+            .map(|res| res.status().is_success())
+            .unwrap_or(false);
+        if ready {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    server
+}
+
+async fn setup(database_url: &str) -> Fixture {
+    let pool = PgPool::connect(database_url).await.unwrap();
+    pool.execute(include_str!("fixtures/db_sync_schema.sql"))
+        .await
+        .unwrap();
+
+    let dir = std::env::temp_dir().join(format!("backend-test-{}", free_port()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let marketplace = Wallet::generate();
+    let projects = Wallet::generate();
+    marketplace.write_key_file(&dir.join("marketplace.skey"));
+    projects.write_key_file(&dir.join("projects.skey"));
+
+    let seller = Wallet::generate();
+    let buyer = Wallet::generate();
+    let nft_metadata = json!({
+        POLICY_ID: {
+            LISTED_ASSET: { "name": LISTED_ASSET, "image": "ipfs://listed" },
+            OWNED_ASSET: { "name": OWNED_ASSET, "image": "ipfs://owned" },
+        }
+    });
+    let holder = marketplace.bech32();
+    let seller_address = seller.bech32();
+    let seller_chunks: Vec<String> = seller_address
+        .chars()
+        .collect::<Vec<char>>()
+        .chunks(64)
+        .map(|c| c.iter().collect())
+        .collect();
+
+    let mut seeder = Seeder {
+        pool: pool.clone(),
+        next_tx: 1,
+    };
+    seeder
+        .insert_tx(
+            &[
+                Output {
+                    address: &seller_address,
+                    lovelace: 2_000_000,
+                    asset: Some(OWNED_ASSET),
+                    datum: None,
+                },
+                Output {
+                    address: &seller_address,
+                    lovelace: 2_000_000,
+                    asset: Some(LISTED_ASSET),
+                    datum: None,
+                },
+                Output {
+                    address: &seller_address,
+                    lovelace: 100_000_000,
+                    asset: None,
+                    datum: None,
+                },
+                Output {
+                    address: &buyer.bech32(),
+                    lovelace: 200_000_000,
+                    asset: None,
+                    datum: None,
+                },
+            ],
+            true,
+            Some((721, nft_metadata)),
+        )
+        .await;
+    // The seller lists the second NFT, spending it to the holder
+    seeder
+        .insert_tx(
+            &[Output {
+                address: &holder,
+                lovelace: 2_000_000,
+                asset: Some(LISTED_ASSET),
+                datum: None,
+            }],
+            false,
+            Some((
+                888,
+                json!({ "seller_address": seller_chunks, "price": LISTING_PRICE }),
+            )),
+        )
+        .await;
+    sqlx::query("INSERT INTO tx_in (tx_in_id, tx_out_id, tx_out_index) VALUES (2, 1, 1)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    // Locked at a script with its datum revealed
+    let script_datum = PlutusData::new_integer(&BigInt::from_str("42").unwrap());
+    let script_address = script_address().to_bech32(None).unwrap();
+    seeder
+        .insert_tx(
+            &[Output {
+                address: &script_address,
+                lovelace: 5_000_000,
+                asset: None,
+                datum: Some(&script_datum),
+            }],
+            false,
+            None,
+        )
+        .await;
+
+    let server = start_server(database_url, &dir).await;
+
+    Fixture {
+        server,
+        pool,
+        database_url: database_url.to_string(),
+        dir,
+        seller,
+        buyer,
+        script_datum,
+    }
+}
+
+/// Posts `body` to `path`, as the admin when `admin` is set
+async fn post_json(fixture: &Fixture, path: &str, body: Json, admin: bool) -> Json {
+    let mut req = reqwest::Client::new()
+        .post(format!("{}{}", fixture.server.url, path))
+        .json(&body);
+    if admin {
+        req = req.bearer_auth(ADMIN_TOKEN);
+    }
+    let res = req.send().await.unwrap();
+    let status = res.status();
+    let json: Json = res.json().await.unwrap();
+    assert!(status.is_success(), "{} failed: {}", path, json);
+    json
+}
+
+async fn post_for_transaction(fixture: &Fixture, path: &str, body: Json) -> Transaction {
+    let json = post_json(fixture, path, body, false).await;
+    let cbor = hex::decode(json["transaction"].as_str().unwrap()).unwrap();
+    Transaction::from_bytes(cbor).expect("transaction deserializes")
+}
+
+async fn get_json(fixture: &Fixture, path: &str) -> Json {
+    let res = reqwest::get(format!("{}{}", fixture.server.url, path))
+        .await
+        .unwrap();
+    let status = res.status();
+    let json: Json = res.json().await.unwrap();
+    assert!(status.is_success(), "{} failed: {}", path, json);
+    json
+}
+
+/// Totals of lovelace and of every asset, keyed by `policy.name`.
+fn add_value(totals: &mut HashMap<String, i128>, value: &Value, sign: i128) {
+    *totals.entry("lovelace".to_string()).or_default() += sign * from_bignum(&value.coin()) as i128;
+    if let Some(multiasset) = value.multiasset() {
+        let policies = multiasset.keys();
+        for i in 0..policies.len() {
+            let policy = policies.get(i);
+            let assets = multiasset.get(&policy).unwrap();
+            let names = assets.keys();
+            for j in 0..names.len() {
+                let name = names.get(j);
+                let key = format!(
+                    "{}.{}",
+                    hex::encode(policy.to_bytes()),
+                    hex::encode(name.name())
+                );
+                *totals.entry(key).or_default() +=
+                    sign * from_bignum(&assets.get(&name).unwrap()) as i128;
+            }
+        }
+    }
+}
+
+async fn input_value(pool: &PgPool, hash: &[u8], index: u32) -> Value {
+    let (value,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT tx_out.value::bigint
+        FROM tx_out JOIN tx ON tx.id = tx_out.tx_id
+        WHERE tx.hash = $1 AND tx_out.index = $2
+        "#,
+    )
+    .bind(hash)
+    .bind(index as i16)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    let mut value = Value::new(&to_bignum(value as u64));
+    let assets: Vec<(Vec<u8>, Vec<u8>)> = sqlx::query_as(
+        r#"
+        SELECT ma_tx_out.policy, ma_tx_out.name
+        FROM ma_tx_out
+        JOIN tx_out ON tx_out.id = ma_tx_out.tx_out_id
+        JOIN tx ON tx.id = tx_out.tx_id
+        WHERE tx.hash = $1 AND tx_out.index = $2
+        "#,
+    )
+    .bind(hash)
+    .bind(index as i16)
+    .fetch_all(pool)
+    .await
+    .unwrap();
+    if !assets.is_empty() {
+        let mut multiasset = MultiAsset::new();
+        for (policy, name) in assets {
+            let policy = PolicyID::from_bytes(policy).unwrap();
+            let mut policy_assets = multiasset.get(&policy).unwrap_or_else(Assets::new);
+            policy_assets.insert(&AssetName::new(name).unwrap(), &to_bignum(1));
+            multiasset.insert(&policy, &policy_assets);
+        }
+        value.set_multiasset(&multiasset);
+    }
+    value
+}
+
+/// Inputs plus minted assets have to equal outputs plus the fee.
+async fn assert_balanced(pool: &PgPool, tx: &Transaction) {
+    let body = tx.body();
+    let mut totals = HashMap::new();
+    let inputs = body.inputs();
+    for i in 0..inputs.len() {
+        let input = inputs.get(i);
+        let value = input_value(pool, &input.transaction_id().to_bytes(), input.index()).await;
+        add_value(&mut totals, &value, 1);
+    }
+    if let Some(mint) = body.multiassets() {
+        let policies = mint.keys();
+        for i in 0..policies.len() {
+            let policy = policies.get(i);
+            let assets = mint.get(&policy).unwrap();
+            let names = assets.keys();
+            for j in 0..names.len() {
+                let name = names.get(j);
+                let qty = assets.get(&name).unwrap();
+                let qty = match qty.as_positive() {
+                    Some(n) => from_bignum(&n) as i128,
+                    None => -(from_bignum(&qty.as_negative().unwrap()) as i128),
+                };
+                let key = format!(
+                    "{}.{}",
+                    hex::encode(policy.to_bytes()),
+                    hex::encode(name.name())
+                );
+                *totals.entry(key).or_default() += qty;
+            }
+        }
+    }
+    let outputs = body.outputs();
+    for i in 0..outputs.len() {
+        add_value(&mut totals, &outputs.get(i).amount(), -1);
+    }
+    *totals.get_mut("lovelace").unwrap() -= from_bignum(&body.fee()) as i128;
+
+    for (unit, total) in totals {
+        assert_eq!(total, 0, "{} does not balance", unit);
+    }
+}
+
+#[tokio::test]
+async fn serves_chain_data() {
+    let docker = clients::Cli::default();
+    let postgres = docker.run(images::postgres::Postgres::default());
+    let database_url = format!(
+        "postgres://postgres@127.0.0.1:{}/postgres",
+        postgres.get_host_port(5432).unwrap()
+    );
+    let fixture = setup(&database_url).await;
+
+    let params = get_json(&fixture, "/chain/protocol-params").await;
+    assert_eq!(params["slot"], 50_000_000);
+    assert_eq!(params["era"], "Alonzo");
+    assert_eq!(params["collateralPercent"], 150);
+    assert_eq!(params["maxCollateralInputs"], 3);
+    assert_eq!(params["maxTxExUnits"]["mem"], 10_000_000);
+    assert_eq!(params["priceMem"]["numerator"], 57_700_000);
+    assert_ne!(
+        params["costModels"], "a0",
+        "the PlutusV1 cost model is read"
+    );
+
+    let listings = get_json(&fixture, &format!("/marketplace?policy={}", POLICY_ID)).await;
+    assert!(
+        listings.to_string().contains(LISTED_ASSET),
+        "the listing is found: {}",
+        listings
+    );
+
+    let datum_hash = hex::encode(hash_plutus_data(&fixture.script_datum).to_bytes());
+    let cbor = hex::encode(fixture.script_datum.to_bytes());
+    let datum = get_json(&fixture, &format!("/datum/{}", datum_hash)).await;
+    assert_eq!(datum["cbor"], cbor.as_str());
+    assert_eq!(datum["json"], json!({ "int": 42 }));
+
+    let script_address = script_address().to_bech32(None).unwrap();
+    let script_utxos = get_json(
+        &fixture,
+        &format!("/address/{}/script-utxo", script_address),
+    )
+    .await;
+    assert_eq!(script_utxos.as_array().unwrap().len(), 1);
+    assert_eq!(script_utxos[0]["datum"]["hash"], datum_hash.as_str());
+    assert_eq!(script_utxos[0]["datum"]["cbor"], cbor.as_str());
+}
+
+#[tokio::test]
+async fn builds_balanced_transactions() {
+    let docker = clients::Cli::default();
+    let postgres = docker.run(images::postgres::Postgres::default());
+    let database_url = format!(
+        "postgres://postgres@127.0.0.1:{}/postgres",
+        postgres.get_host_port(5432).unwrap()
+    );
+    let fixture = setup(&database_url).await;
+
+    let sell = post_for_transaction(
+        &fixture,
+        "/marketplace/sell",
+        json!({
+            "sellerAddress": fixture.seller.bech32(),
+            "policyId": POLICY_ID,
+            "assetName": OWNED_ASSET,
+            "price": 20_000_000,
+        }),
+    )
+    .await;
+    assert_balanced(&fixture.pool, &sell).await;
+    assert!(sell.auxiliary_data().is_some(), "sale metadata is attached");
+
+    let buy = post_for_transaction(
+        &fixture,
+        "/marketplace/buy",
+        json!({
+            "buyerAddress": fixture.buyer.bech32(),
+            "policyId": POLICY_ID,
+            "assetName": LISTED_ASSET,
+        }),
+    )
+    .await;
+    assert_balanced(&fixture.pool, &buy).await;
+
+    let cancel = post_for_transaction(
+        &fixture,
+        "/marketplace/cancel",
+        json!({
+            "sellerAddress": fixture.seller.bech32(),
+            "policyId": POLICY_ID,
+            "assetName": LISTED_ASSET,
+        }),
+    )
+    .await;
+    assert_balanced(&fixture.pool, &cancel).await;
+
+    let offer = post_for_transaction(
+        &fixture,
+        "/offers",
+        json!({
+            "bidderAddress": fixture.buyer.bech32(),
+            "policyId": POLICY_ID,
+            "assetName": OWNED_ASSET,
+            "price": 15_000_000,
+            "expiresAt": 50_100_000,
+        }),
+    )
+    .await;
+    assert_balanced(&fixture.pool, &offer).await;
+
+    let mint = post_for_transaction(
+        &fixture,
+        "/nft/create",
+        json!({
+            "address": fixture.buyer.bech32(),
+            "name": "Fresh",
+            "description": "Minted in a test",
+            "image": "ipfs://fresh",
+        }),
+    )
+    .await;
+    assert_balanced(&fixture.pool, &mint).await;
+    assert!(mint.body().multiassets().is_some(), "mint is set");
+}
+
+#[tokio::test]
+async fn reserves_drop_items() {
+    let docker = clients::Cli::default();
+    let postgres = docker.run(images::postgres::Postgres::default());
+    let database_url = format!(
+        "postgres://postgres@127.0.0.1:{}/postgres",
+        postgres.get_host_port(5432).unwrap()
+    );
+    let fixture = setup(&database_url).await;
+
+    let drop = post_json(
+        &fixture,
+        "/admin/drops",
+        json!({
+            "name": "Test drop",
+            "price": 10_000_000,
+            "startSlot": 49_000_000,
+            "policyLockSlot": 60_000_000,
+            "paymentAddress": fixture.seller.bech32(),
+            "items": [
+                { "name": "First", "description": "First item", "image": "ipfs://first" },
+                { "name": "Second", "description": "Second item", "image": "ipfs://second" },
+            ],
+        }),
+        true,
+    )
+    .await;
+    let path = format!("/drops/{}", drop["id"]);
+    assert_eq!(get_json(&fixture, &path).await["remaining"], 2);
+
+    let mint = post_json(
+        &fixture,
+        &format!("{}/mint", path),
+        json!({ "address": fixture.buyer.bech32() }),
+        false,
+    )
+    .await;
+    assert_eq!(mint["assetName"], "First");
+    let cbor = hex::decode(mint["transaction"].as_str().unwrap()).unwrap();
+    assert_balanced(&fixture.pool, &Transaction::from_bytes(cbor).unwrap()).await;
+    assert_eq!(get_json(&fixture, &path).await["remaining"], 1);
+}
+
+#[tokio::test]
+async fn reconciles_pending_drop_payments() {
+    let docker = clients::Cli::default();
+    let postgres = docker.run(images::postgres::Postgres::default());
+    let database_url = format!(
+        "postgres://postgres@127.0.0.1:{}/postgres",
+        postgres.get_host_port(5432).unwrap()
+    );
+    let fixture = setup(&database_url).await;
+
+    let drop = post_json(
+        &fixture,
+        "/admin/drops",
+        json!({
+            "name": "Watched drop",
+            "price": 10_000_000,
+            "startSlot": 49_000_000,
+            "policyLockSlot": 60_000_000,
+            "paymentWatcher": true,
+            "items": [
+                { "name": "First", "description": "First item", "image": "ipfs://first" },
+                { "name": "Second", "description": "Second item", "image": "ipfs://second" },
+            ],
+        }),
+        true,
+    )
+    .await;
+    let drop_id = drop["id"].as_i64().unwrap() as i32;
+    let policy_id = hex::decode(drop["policyId"].as_str().unwrap()).unwrap();
+    let landed = hex::encode([1u8; 32]);
+    let lost = hex::encode([0xee; 32]);
+
+    // Both crashed between recording and submitting, only the first one's
+    // transaction made it on chain
+    for (payment, result_tx_hash) in [(0xa1u8, &landed), (0xa2, &lost)].iter() {
+        sqlx::query(
+            r#"
+            INSERT INTO marketplace.drop_payment
+                (tx_hash, tx_index, drop_id, payer, amount, outcome, result_tx_hash, status)
+            VALUES ($1, 0, $2, $3, 10000000, 'minted', $4, 'pending')
+            "#,
+        )
+        .bind(vec![*payment; 32])
+        .bind(drop_id)
+        .bind(fixture.buyer.bech32())
+        .bind(result_tx_hash.as_str())
+        .execute(&fixture.pool)
+        .await
+        .unwrap();
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO marketplace.minted_name (policy_id, asset_name, tx_hash, valid_until)
+        VALUES ($1, 'First', $2, 50003600)
+        "#,
+    )
+    .bind(&policy_id)
+    .bind(&lost)
+    .execute(&fixture.pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        UPDATE marketplace.drop_item
+        SET reserved_by = $2, reserved_until = 50003600
+        WHERE drop_id = $1 AND asset_name = 'First'
+        "#,
+    )
+    .bind(drop_id)
+    .bind(fixture.buyer.bech32())
+    .execute(&fixture.pool)
+    .await
+    .unwrap();
+    let path = format!("/drops/{}", drop_id);
+    assert_eq!(get_json(&fixture, &path).await["remaining"], 1);
+
+    let _restarted = start_server(&fixture.database_url, &fixture.dir).await;
+
+    let statuses: Vec<(Vec<u8>, String)> =
+        sqlx::query_as("SELECT tx_hash, status FROM marketplace.drop_payment WHERE drop_id = $1")
+            .bind(drop_id)
+            .fetch_all(&fixture.pool)
+            .await
+            .unwrap();
+    assert_eq!(statuses, vec![(vec![0xa1; 32], "done".to_string())]);
+    let (claims,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM marketplace.minted_name WHERE policy_id = $1")
+            .bind(&policy_id)
+            .fetch_one(&fixture.pool)
+            .await
+            .unwrap();
+    assert_eq!(claims, 0);
+    assert_eq!(get_json(&fixture, &path).await["remaining"], 2);
+}
+
+#[tokio::test]
+async fn accepts_and_withdraws_offers() {
+    let docker = clients::Cli::default();
+    let postgres = docker.run(images::postgres::Postgres::default());
+    let database_url = format!(
+        "postgres://postgres@127.0.0.1:{}/postgres",
+        postgres.get_host_port(5432).unwrap()
+    );
+    let fixture = setup(&database_url).await;
+
+    let placed = post_json(
+        &fixture,
+        "/offers",
+        json!({
+            "bidderAddress": fixture.buyer.bech32(),
+            "policyId": POLICY_ID,
+            "assetName": OWNED_ASSET,
+            "price": 15_000_000,
+            "expiresAt": 50_100_000,
+        }),
+        false,
+    )
+    .await;
+    let offer = &placed["offer"];
+    let tx_hash = offer["txHash"].as_str().unwrap();
+    let output_index = offer["outputIndex"].as_u64().unwrap() as u32;
+    let cbor = hex::decode(placed["transaction"].as_str().unwrap()).unwrap();
+    let escrow = Transaction::from_bytes(cbor)
+        .unwrap()
+        .body()
+        .outputs()
+        .get(output_index as usize);
+    assert_eq!(from_bignum(&escrow.amount().coin()), 15_000_000);
+
+    // The bidder submits, the escrow lands in a new block
+    fixture
+        .pool
+        .execute(
+            r#"
+            INSERT INTO block (
+                id, hash, epoch_no, slot_no, epoch_slot_no, block_no, previous_id,
+                slot_leader_id, size, time, tx_count, proto_major, proto_minor
+            )
+            VALUES (2, decode(repeat('02', 32), 'hex'), 200, 50000020, 20, 2, 1, 1, 0, now(), 1, 6, 0)
+            "#,
+        )
+        .await
+        .unwrap();
+    sqlx::query(
+        r#"
+        INSERT INTO tx (
+            id, hash, block_id, block_index, out_sum, fee, deposit, size,
+            valid_contract, script_size
+        )
+        VALUES (100, $1, 2, 0, 15000000, 0, 0, 0, true, 0)
+        "#,
+    )
+    .bind(hex::decode(tx_hash).unwrap())
+    .execute(&fixture.pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        INSERT INTO tx_out (tx_id, index, address, address_raw, address_has_script, value)
+        VALUES (100, $1, $2, $3, false, 15000000)
+        "#,
+    )
+    .bind(output_index as i16)
+    .bind(escrow.address().to_bech32(None).unwrap())
+    .bind(escrow.address().to_bytes())
+    .execute(&fixture.pool)
+    .await
+    .unwrap();
+
+    let offers = get_json(
+        &fixture,
+        &format!("/offers?policyId={}&assetName={}", POLICY_ID, OWNED_ASSET),
+    )
+    .await;
+    assert_eq!(offers.as_array().unwrap().len(), 1);
+    assert_eq!(offers[0]["txHash"], tx_hash);
+    assert_eq!(offers[0]["assetName"], hex::encode(OWNED_ASSET));
+
+    let accept = post_for_transaction(
+        &fixture,
+        &format!("/offers/{}/accept", tx_hash),
+        json!({ "sellerAddress": fixture.seller.bech32() }),
+    )
+    .await;
+    assert_balanced(&fixture.pool, &accept).await;
+    let outputs = accept.body().outputs();
+    let buyer = fixture.buyer.enterprise_address().to_bytes();
+    assert!(
+        (0..outputs.len()).any(|i| {
+            let output = outputs.get(i);
+            output.address().to_bytes() == buyer && output.amount().multiasset().is_some()
+        }),
+        "the NFT goes to the bidder"
+    );
+
+    let withdraw = post_for_transaction(
+        &fixture,
+        &format!("/offers/{}/withdraw", tx_hash),
+        json!({ "bidderAddress": fixture.buyer.bech32() }),
+    )
+    .await;
+    assert_balanced(&fixture.pool, &withdraw).await;
+}
@@ -0,0 +1,187 @@
+-- The tables of the cardano-db-sync 11.0 schema queried by the backend,
+-- column for column, see
+-- https://github.com/input-output-hk/cardano-db-sync/blob/11.0.4/doc/schema.md
+--
+-- db-sync 11 is the release the queries are written against: the last one
+-- keeping multi-assets as ma_tx_out.policy and ma_tx_mint.policy, with
+-- cost models in epoch_param and datums only as JSON.
+
+CREATE DOMAIN hash28type AS BYTEA CONSTRAINT hash28type_check CHECK (octet_length(VALUE) = 28);
+CREATE DOMAIN hash32type AS BYTEA CONSTRAINT hash32type_check CHECK (octet_length(VALUE) = 32);
+CREATE DOMAIN addr29type AS BYTEA CONSTRAINT addr29type_check CHECK (octet_length(VALUE) = 29);
+CREATE DOMAIN asset32type AS BYTEA CONSTRAINT asset32type_check CHECK (octet_length(VALUE) <= 32);
+CREATE DOMAIN lovelace AS NUMERIC(20, 0) CONSTRAINT lovelace_check CHECK (VALUE >= 0 AND VALUE <= 18446744073709551615);
+CREATE DOMAIN txindex AS SMALLINT CONSTRAINT txindex_check CHECK (VALUE >= 0);
+CREATE DOMAIN word31type AS INTEGER CONSTRAINT word31type_check CHECK (VALUE >= 0);
+CREATE DOMAIN word63type AS BIGINT CONSTRAINT word63type_check CHECK (VALUE >= 0);
+CREATE DOMAIN word64type AS NUMERIC(20, 0) CONSTRAINT word64type_check CHECK (VALUE >= 0 AND VALUE <= 18446744073709551615);
+CREATE DOMAIN int65type AS NUMERIC(20, 0) CONSTRAINT int65type_check CHECK (VALUE >= -18446744073709551615 AND VALUE <= 18446744073709551615);
+
+CREATE TABLE slot_leader (
+    id BIGSERIAL PRIMARY KEY,
+    hash hash28type NOT NULL UNIQUE,
+    pool_hash_id BIGINT,
+    description VARCHAR NOT NULL
+);
+
+CREATE TABLE block (
+    id BIGSERIAL PRIMARY KEY,
+    hash hash32type NOT NULL UNIQUE,
+    epoch_no word31type,
+    slot_no word63type,
+    epoch_slot_no word31type,
+    block_no word31type,
+    previous_id BIGINT REFERENCES block (id),
+    slot_leader_id BIGINT NOT NULL REFERENCES slot_leader (id),
+    size word31type NOT NULL,
+    time TIMESTAMP NOT NULL,
+    tx_count BIGINT NOT NULL,
+    proto_major word31type NOT NULL,
+    proto_minor word31type NOT NULL,
+    vrf_key VARCHAR,
+    op_cert hash32type,
+    op_cert_counter word63type
+);
+
+CREATE TABLE tx (
+    id BIGSERIAL PRIMARY KEY,
+    hash hash32type NOT NULL UNIQUE,
+    block_id BIGINT NOT NULL REFERENCES block (id),
+    block_index word31type NOT NULL,
+    out_sum lovelace NOT NULL,
+    fee lovelace NOT NULL,
+    deposit BIGINT NOT NULL,
+    size word31type NOT NULL,
+    invalid_before word64type,
+    invalid_hereafter word64type,
+    valid_contract BOOLEAN NOT NULL,
+    script_size word31type NOT NULL
+);
+
+CREATE TABLE stake_address (
+    id BIGSERIAL PRIMARY KEY,
+    hash_raw addr29type NOT NULL UNIQUE,
+    view VARCHAR NOT NULL,
+    script_hash hash28type,
+    registered_tx_id BIGINT NOT NULL REFERENCES tx (id)
+);
+
+CREATE TABLE tx_out (
+    id BIGSERIAL PRIMARY KEY,
+    tx_id BIGINT NOT NULL REFERENCES tx (id),
+    index txindex NOT NULL,
+    address VARCHAR NOT NULL,
+    address_raw BYTEA NOT NULL,
+    address_has_script BOOLEAN NOT NULL,
+    payment_cred hash28type,
+    stake_address_id BIGINT REFERENCES stake_address (id),
+    value lovelace NOT NULL,
+    data_hash hash32type,
+    UNIQUE (tx_id, index)
+);
+
+CREATE TABLE tx_in (
+    id BIGSERIAL PRIMARY KEY,
+    tx_in_id BIGINT NOT NULL REFERENCES tx (id),
+    tx_out_id BIGINT NOT NULL REFERENCES tx (id),
+    tx_out_index txindex NOT NULL,
+    redeemer_id BIGINT,
+    UNIQUE (tx_out_id, tx_out_index)
+);
+
+CREATE TABLE ma_tx_mint (
+    id BIGSERIAL PRIMARY KEY,
+    policy hash28type NOT NULL,
+    name asset32type NOT NULL,
+    quantity int65type NOT NULL,
+    tx_id BIGINT NOT NULL REFERENCES tx (id),
+    UNIQUE (policy, name, tx_id)
+);
+
+CREATE TABLE ma_tx_out (
+    id BIGSERIAL PRIMARY KEY,
+    policy hash28type NOT NULL,
+    name asset32type NOT NULL,
+    quantity word64type NOT NULL,
+    tx_out_id BIGINT NOT NULL REFERENCES tx_out (id),
+    UNIQUE (policy, name, tx_out_id)
+);
+
+CREATE TABLE tx_metadata (
+    id BIGSERIAL PRIMARY KEY,
+    key word64type NOT NULL,
+    json JSONB,
+    bytes BYTEA NOT NULL,
+    tx_id BIGINT NOT NULL REFERENCES tx (id),
+    UNIQUE (key, tx_id)
+);
+
+CREATE TABLE datum (
+    id BIGSERIAL PRIMARY KEY,
+    hash hash32type NOT NULL UNIQUE,
+    tx_id BIGINT NOT NULL REFERENCES tx (id),
+    value JSONB
+);
+
+CREATE TABLE epoch_param (
+    id BIGSERIAL PRIMARY KEY,
+    epoch_no word31type NOT NULL,
+    min_fee_a word31type NOT NULL,
+    min_fee_b word31type NOT NULL,
+    max_block_size word31type NOT NULL,
+    max_tx_size word31type NOT NULL,
+    max_bh_size word31type NOT NULL,
+    key_deposit lovelace NOT NULL,
+    pool_deposit lovelace NOT NULL,
+    max_epoch word31type NOT NULL,
+    optimal_pool_count word31type NOT NULL,
+    influence DOUBLE PRECISION NOT NULL,
+    monetary_expand_rate DOUBLE PRECISION NOT NULL,
+    treasury_growth_rate DOUBLE PRECISION NOT NULL,
+    decentralisation DOUBLE PRECISION NOT NULL,
+    entropy hash32type,
+    protocol_major word31type NOT NULL,
+    protocol_minor word31type NOT NULL,
+    min_utxo_value lovelace NOT NULL,
+    min_pool_cost lovelace NOT NULL,
+    nonce hash32type,
+    coins_per_utxo_word lovelace,
+    cost_models JSONB,
+    price_mem DOUBLE PRECISION,
+    price_step DOUBLE PRECISION,
+    max_tx_ex_mem word64type,
+    max_tx_ex_steps word64type,
+    max_block_ex_mem word64type,
+    max_block_ex_steps word64type,
+    max_val_size word64type,
+    collateral_percent word31type,
+    max_collateral_inputs word31type,
+    block_id BIGINT NOT NULL REFERENCES block (id),
+    UNIQUE (epoch_no, block_id)
+);
+
+INSERT INTO slot_leader (id, hash, description)
+VALUES (1, decode(repeat('01', 28), 'hex'), 'Test slot leader');
+
+INSERT INTO block (
+    id, hash, epoch_no, slot_no, epoch_slot_no, block_no, slot_leader_id, size,
+    time, tx_count, proto_major, proto_minor
+)
+VALUES (1, decode(repeat('01', 32), 'hex'), 200, 50000000, 0, 1, 1, 0, now(), 0, 6, 0);
+
+INSERT INTO epoch_param (
+    epoch_no, min_fee_a, min_fee_b, max_block_size, max_tx_size, max_bh_size,
+    key_deposit, pool_deposit, max_epoch, optimal_pool_count, influence,
+    monetary_expand_rate, treasury_growth_rate, decentralisation,
+    protocol_major, protocol_minor, min_utxo_value, min_pool_cost,
+    coins_per_utxo_word, cost_models, price_mem, price_step, max_tx_ex_mem,
+    max_tx_ex_steps, max_block_ex_mem, max_block_ex_steps, max_val_size,
+    collateral_percent, max_collateral_inputs, block_id
+)
+VALUES (
+    200, 44, 155381, 65536, 16384, 1100, 2000000, 500000000, 18, 500, 0.3,
+    0.003, 0.2, 0, 6, 0, 1000000, 340000000, 34482,
+    '{"PlutusV1": {"addInteger-cpu-arguments-intercept": 197209, "addInteger-cpu-arguments-slope": 0}}',
+    0.0577, 0.0000721, 10000000, 10000000000, 50000000, 40000000000, 5000, 150,
+    3, 1
+);